@@ -0,0 +1,319 @@
+//! Benchmarks for the depth-first traversals
+//!
+//! The `tall_tree` group compares the plain and depth-hinted traversals on a
+//! long chain. The `binary_tree` and `strings` groups measure the overhead of
+//! the traversal itself, comparing `for_tree!` and `traverse_tree` with
+//! hand-written recursion over a large `BinaryNode` and over the generated
+//! strings of the crate docs. The `small_fanout` group compares `traverse_tree`
+//! returning a `Vec` of children with `traverse_tree_iter` returning a
+//! `SmallVec` that keeps them inline. The `wide_tree` group compares
+//! `traverse_tree` with `traverse_tree_push`, whose branch function pushes the
+//! children of a wide `NAryNode` tree into a callback.
+//!
+//! Besides the timings reported by criterion, each benchmark group prints the
+//! number of heap allocations a single traversal makes, as counted by a global
+//! allocator wrapper.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use arboriter::{
+    BinaryNode, NAryNode, TreeControl, for_tree, prune, traverse_tree, traverse_tree_hinted,
+    traverse_tree_iter, traverse_tree_push,
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+use smallvec::{SmallVec, smallvec};
+
+/// Counts allocations and reallocations, then defers to the system allocator.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Height of the benchmarked tree: a single chain, the worst case for stack growth
+const HEIGHT: u32 = 100_000;
+
+fn chain(n: &u32) -> Vec<u32> {
+    vec![n + 1]
+}
+
+fn plain() {
+    traverse_tree(
+        0u32,
+        |n| *n < HEIGHT,
+        chain,
+        |n| {
+            black_box(n);
+            TreeControl::Continue
+        },
+    );
+}
+
+fn hinted() {
+    traverse_tree_hinted(
+        0u32,
+        |n| *n < HEIGHT,
+        chain,
+        HEIGHT as usize,
+        |n| {
+            black_box(n);
+            TreeControl::Continue
+        },
+    );
+}
+
+fn allocations(f: fn()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn tall_tree(c: &mut Criterion) {
+    println!(
+        "allocations per traversal of a {}-node chain: plain {}, hinted {}",
+        HEIGHT,
+        allocations(plain),
+        allocations(hinted)
+    );
+
+    let mut group = c.benchmark_group("tall_tree");
+    group.bench_function("traverse_tree", |b| b.iter(plain));
+    group.bench_function("traverse_tree_hinted", |b| b.iter(hinted));
+    group.finish();
+}
+
+/// Levels of the complete binary tree, which has `2^LEVELS - 1` nodes
+const LEVELS: u32 = 16;
+
+/// Length of the longest generated string
+const MAX_LEN: usize = 12;
+
+/// Builds a complete binary tree whose values are the node's pre-order index
+fn complete_tree(levels: u32, next: &mut u64) -> Option<Box<BinaryNode<u64>>> {
+    if levels == 0 {
+        return None;
+    }
+    let value = *next;
+    *next += 1;
+    let left = complete_tree(levels - 1, next);
+    let right = complete_tree(levels - 1, next);
+    Some(Box::new(BinaryNode::with_children(value, left, right)))
+}
+
+fn sum_macro(root: &BinaryNode<u64>) -> u64 {
+    let mut sum = 0;
+    for_tree!(node in root; |_| true; |node| node.children() => {
+        sum += node.value;
+    });
+    sum
+}
+
+fn sum_function(root: &BinaryNode<u64>) -> u64 {
+    let mut sum = 0;
+    traverse_tree(
+        root,
+        |_| true,
+        |node| node.children(),
+        |node| {
+            sum += node.value;
+            TreeControl::Continue
+        },
+    );
+    sum
+}
+
+fn sum_recursive(node: &BinaryNode<u64>) -> u64 {
+    node.value
+        + node.left.as_deref().map_or(0, sum_recursive)
+        + node.right.as_deref().map_or(0, sum_recursive)
+}
+
+fn strings_macro() -> usize {
+    let mut count = 0;
+    for_tree!(s in String::new(); |s| s.len() <= MAX_LEN; |s| {
+        vec![format!("{}a", s), format!("{}b", s)]
+    } => {
+        count += black_box(s).len();
+        if s.len() == MAX_LEN {
+            prune!();
+        }
+    });
+    count
+}
+
+fn strings_recursive() -> usize {
+    fn generate(s: &str) -> usize {
+        let mut count = black_box(s).len();
+        if s.len() < MAX_LEN {
+            count += generate(&format!("{}a", s));
+            count += generate(&format!("{}b", s));
+        }
+        count
+    }
+    generate("")
+}
+
+/// Number of nodes in the generated binary tree of numbers, `1..FANOUT_NODES`
+const FANOUT_NODES: u32 = 1 << 16;
+
+fn fanout_vec() {
+    traverse_tree(
+        1u32,
+        |n| *n < FANOUT_NODES,
+        |n| vec![n * 2, n * 2 + 1],
+        |n| {
+            black_box(n);
+            TreeControl::Continue
+        },
+    );
+}
+
+fn fanout_smallvec() {
+    traverse_tree_iter(
+        1u32,
+        |n| *n < FANOUT_NODES,
+        |n| -> SmallVec<[u32; 2]> { smallvec![n * 2, n * 2 + 1] },
+        |n| {
+            black_box(n);
+            TreeControl::Continue
+        },
+    );
+}
+
+/// Children of every inner node of the wide tree
+const FANOUT: u32 = 32;
+
+/// Levels of the wide tree below the root
+const WIDE_LEVELS: u32 = 3;
+
+fn wide_tree(levels: u32) -> NAryNode<u32> {
+    let children = if levels == 0 {
+        Vec::new()
+    } else {
+        (0..FANOUT).map(|_| wide_tree(levels - 1)).collect()
+    };
+    NAryNode::with_children(levels, children)
+}
+
+fn wide_vec(root: &NAryNode<u32>) -> u32 {
+    let mut sum = 0;
+    traverse_tree(
+        root,
+        |_| true,
+        |node| node.children(),
+        |node| {
+            sum += node.value;
+            TreeControl::Continue
+        },
+    );
+    sum
+}
+
+fn wide_push(root: &NAryNode<u32>) -> u32 {
+    let mut sum = 0;
+    traverse_tree_push(
+        root,
+        |_| true,
+        |node, push| {
+            for child in &node.children {
+                push(child);
+            }
+        },
+        |node| {
+            sum += node.value;
+            TreeControl::Continue
+        },
+    );
+    sum
+}
+
+fn binary_tree(c: &mut Criterion) {
+    let root = *complete_tree(LEVELS, &mut 0).expect("the tree has at least one level");
+    assert_eq!(sum_macro(&root), sum_recursive(&root));
+    assert_eq!(sum_function(&root), sum_recursive(&root));
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    sum_macro(&root);
+    println!(
+        "allocations per traversal of a {}-node binary tree: for_tree! {}, recursive 0",
+        (1u32 << LEVELS) - 1,
+        ALLOCATIONS.load(Ordering::Relaxed) - before
+    );
+
+    let mut group = c.benchmark_group("binary_tree");
+    group.bench_function("for_tree", |b| b.iter(|| sum_macro(black_box(&root))));
+    group.bench_function("traverse_tree", |b| {
+        b.iter(|| sum_function(black_box(&root)))
+    });
+    group.bench_function("recursive", |b| b.iter(|| sum_recursive(black_box(&root))));
+    group.finish();
+}
+
+fn strings(c: &mut Criterion) {
+    assert_eq!(strings_macro(), strings_recursive());
+
+    let mut group = c.benchmark_group("strings");
+    group.bench_function("for_tree", |b| b.iter(strings_macro));
+    group.bench_function("recursive", |b| b.iter(strings_recursive));
+    group.finish();
+}
+
+fn small_fanout(c: &mut Criterion) {
+    println!(
+        "allocations per traversal of a {}-node binary tree: Vec {}, SmallVec {}",
+        FANOUT_NODES - 1,
+        allocations(fanout_vec),
+        allocations(fanout_smallvec)
+    );
+
+    let mut group = c.benchmark_group("small_fanout");
+    group.bench_function("traverse_tree", |b| b.iter(fanout_vec));
+    group.bench_function("traverse_tree_iter", |b| b.iter(fanout_smallvec));
+    group.finish();
+}
+
+fn wide(c: &mut Criterion) {
+    let root = wide_tree(WIDE_LEVELS);
+    assert_eq!(wide_vec(&root), wide_push(&root));
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    wide_vec(&root);
+    let vec_allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    wide_push(&root);
+    println!(
+        "allocations per traversal of a tree with fan-out {}: Vec {}, push {}",
+        FANOUT,
+        vec_allocations,
+        ALLOCATIONS.load(Ordering::Relaxed) - before
+    );
+
+    let mut group = c.benchmark_group("wide_tree");
+    group.bench_function("traverse_tree", |b| b.iter(|| wide_vec(black_box(&root))));
+    group.bench_function("traverse_tree_push", |b| {
+        b.iter(|| wide_push(black_box(&root)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tall_tree, binary_tree, strings, small_fanout, wide);
+criterion_main!(benches);
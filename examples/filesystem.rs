@@ -3,7 +3,7 @@
 //! This example demonstrates how to use for_tree to traverse a hierarchical
 //! file system structure, supporting both files and directories.
 
-use arboriter::for_tree;
+use arboriter::{TreeControl, for_tree, traverse_leaves};
 
 /// Represents a file system node (file or directory)
 #[derive(Clone)]
@@ -112,22 +112,23 @@ fn traverse_fs(root: &FsNode) {
 /// Find all files larger than a given size
 fn find_large_files(root: &FsNode, min_size: usize) {
     println!("Finding files larger than {} bytes:", min_size);
-    
+
     let mut large_file_count = 0;
-    
-    for_tree!(node in root; |_| true; |node| {
-        if node.is_dir {
-            node.children.iter().collect()
-        } else {
-            Vec::new()
-        }
-    } => {
-        if !node.is_dir && node.size > min_size {
-            println!("Large file found: {} ({} bytes)", node.name, node.size);
-            large_file_count += 1;
-        }
-    });
-    
+
+    // Only files are leaves, so there is no need to check `is_dir`
+    traverse_leaves(
+        root,
+        |_| true,
+        |node| node.children.iter().collect(),
+        |node| {
+            if node.size > min_size {
+                println!("Large file found: {} ({} bytes)", node.name, node.size);
+                large_file_count += 1;
+            }
+            TreeControl::Continue
+        },
+    );
+
     println!("Total large files found: {}", large_file_count);
 }
 
@@ -90,7 +90,7 @@ fn generate_binary_numbers(max_bits: usize) {
         // Skip the empty string
         if !num.is_empty() {
             // Convert to integer for display
-            if let Ok(value) = u32::from_str_radix(&num, 2) {
+            if let Ok(value) = u32::from_str_radix(num, 2) {
                 println!("Binary: {}, Decimal: {}", num, value);
             }
         }
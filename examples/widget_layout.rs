@@ -0,0 +1,144 @@
+//! Example of a two-pass layout over a GUI widget tree
+//!
+//! This example demonstrates how traverse_events can drive a layout engine.
+//! A measuring pass computes every widget's size from the sizes of its
+//! children, which are only known once the children have been left, and an
+//! arranging pass then hands out positions from the top down. The two passes
+//! are written out here; `Widget::layout` runs the same ones.
+
+use std::collections::HashMap;
+
+use arboriter::{Event, Rect, Size, TreeControl, Widget, traverse_events};
+
+/// Space between a container's border and its children
+const PADDING: u32 = 4;
+/// Space between two children of the same container
+const SPACING: u32 = 2;
+
+fn main() {
+    println!("Widget Layout Example");
+    println!("=====================");
+
+    let window = Widget::column(
+        "window",
+        vec![
+            Widget::leaf("title", Size::new(120, 20)),
+            Widget::column(
+                "form",
+                vec![
+                    Widget::leaf("name", Size::new(100, 16)),
+                    Widget::leaf("email", Size::new(140, 16)),
+                ],
+            ),
+            Widget::leaf("ok", Size::new(40, 16)),
+        ],
+    );
+
+    println!("\n1. Measure (bottom-up)");
+    println!("---------------------");
+    let sizes = measure(&window);
+    for (name, size) in &sizes {
+        println!("{:>8}: {}x{}", name, size.width, size.height);
+    }
+
+    println!("\n2. Arrange (top-down)");
+    println!("--------------------");
+    let rects = arrange(&window, &sizes);
+    for (depth, name, rect) in &rects {
+        println!(
+            "{}{} at ({}, {}) size {}x{}",
+            "  ".repeat(*depth),
+            name,
+            rect.x,
+            rect.y,
+            rect.size.width,
+            rect.size.height
+        );
+    }
+
+    // The crate's own layout pass gives the same rectangles
+    let same = window
+        .layout(PADDING, SPACING)
+        .iter()
+        .zip(&rects)
+        .all(|((widget, rect), (_, name, arranged))| widget.name == *name && rect == arranged);
+    println!("\nMatches Widget::layout: {}", same);
+}
+
+/// Compute the size of every widget; a container's size is only known once all
+/// of its children have been measured, so the work happens on `Leave`
+fn measure(root: &Widget) -> Vec<(&str, Size)> {
+    let mut sizes = Vec::new();
+    // The sizes of the children measured so far, one list per open container
+    let mut open: Vec<Vec<Size>> = Vec::new();
+
+    traverse_events(
+        root,
+        |_| true,
+        |w| w.children(),
+        |event| {
+            match event {
+                Event::Enter(_) => open.push(Vec::new()),
+                Event::Leave(widget) => {
+                    let children = open.pop().expect("every Leave follows an Enter");
+
+                    let size = if children.is_empty() {
+                        widget.preferred
+                    } else {
+                        let gaps = SPACING * (children.len() as u32 - 1);
+                        Size {
+                            width: children.iter().map(|s| s.width).max().unwrap_or(0)
+                                + 2 * PADDING,
+                            height: children.iter().map(|s| s.height).sum::<u32>()
+                                + gaps
+                                + 2 * PADDING,
+                        }
+                    };
+
+                    if let Some(parent) = open.last_mut() {
+                        parent.push(size);
+                    }
+                    sizes.push((widget.name.as_str(), size));
+                }
+            }
+            TreeControl::Continue
+        },
+    );
+
+    sizes
+}
+
+/// Give every widget its position, stacking each container's children from the
+/// container's top-left corner downwards
+fn arrange<'a>(root: &'a Widget, sizes: &[(&str, Size)]) -> Vec<(usize, &'a str, Rect)> {
+    let sizes: HashMap<_, _> = sizes.iter().copied().collect();
+    let mut rects = Vec::new();
+    // Where the next child of each open container goes
+    let mut cursors: Vec<(u32, u32)> = Vec::new();
+
+    traverse_events(
+        root,
+        |_| true,
+        |w| w.children(),
+        |event| {
+            match event {
+                Event::Enter(widget) => {
+                    let (x, y) = cursors.last().copied().unwrap_or((0, 0));
+                    let size = sizes[widget.name.as_str()];
+
+                    rects.push((cursors.len(), widget.name.as_str(), Rect::new(x, y, size)));
+                    cursors.push((x + PADDING, y + PADDING));
+                }
+                Event::Leave(widget) => {
+                    cursors.pop();
+                    if let Some((_, y)) = cursors.last_mut() {
+                        *y += sizes[widget.name.as_str()].height + SPACING;
+                    }
+                }
+            }
+            TreeControl::Continue
+        },
+    );
+
+    rects
+}
@@ -0,0 +1,400 @@
+//! Arena-backed mutable tree container
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{TreeControl, walk};
+
+/// Identifier of a node in an [`ArenaTree`].
+///
+/// Ids are indices into the tree's arena. They stay valid until the node is
+/// removed, and are never handed out again afterwards, so a stale id can't
+/// silently refer to a different node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Node<T> {
+    value: T,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// A mutable tree whose nodes can have any number of children.
+///
+/// Nodes live in a single `Vec` and refer to each other by [`NodeId`], so the
+/// tree can be grown and pruned in place without fighting the borrow checker,
+/// and appending a child is O(1). The tree always has a root, created by
+/// [`ArenaTree::new`].
+///
+/// To run any of the traversal functions over the tree, start from
+/// [`node`](ArenaTree::node), which returns a cheap, copyable [`NodeRef`]
+/// handle whose [`children`](NodeRef::children) work as a branch function.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree, ArenaTree, TreeControl};
+///
+/// let mut tree = ArenaTree::new("root");
+/// let docs = tree.add_child(tree.root(), "docs");
+/// tree.add_child(docs, "guide.md");
+/// let tmp = tree.add_child(tree.root(), "tmp");
+/// tree.add_child(tmp, "scratch.txt");
+///
+/// tree.remove_subtree(tmp);
+///
+/// let mut names = Vec::new();
+/// traverse_tree(tree.node(tree.root()), |_| true, |n| n.children(), |n| {
+///     names.push(*n.value());
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(names, vec!["root", "docs", "guide.md"]);
+/// assert_eq!(tree.iter().copied().collect::<Vec<_>>(), names);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArenaTree<T> {
+    nodes: Vec<Option<Node<T>>>,
+    len: usize,
+}
+
+impl<T> ArenaTree<T> {
+    /// Creates a tree containing only a root node with the given value.
+    pub fn new(root_value: T) -> Self {
+        ArenaTree {
+            nodes: vec![Some(Node {
+                value: root_value,
+                parent: None,
+                children: Vec::new(),
+            })],
+            len: 1,
+        }
+    }
+
+    /// Returns the id of the root node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Returns the number of nodes in the tree, which is always at least 1.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if `id` refers to a node that is still in the tree.
+    pub fn contains(&self, id: NodeId) -> bool {
+        matches!(self.nodes.get(id.0), Some(Some(_)))
+    }
+
+    /// Returns the value of a node, or `None` if it has been removed.
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.slot(id).map(|node| &node.value)
+    }
+
+    /// Returns a mutable reference to the value of a node, or `None` if it has been removed.
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.nodes
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .map(|node| &mut node.value)
+    }
+
+    /// Returns the parent of a node, or `None` for the root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been removed.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.expect_node(id).parent
+    }
+
+    /// Returns the ids of a node's children, in insertion order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been removed.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.expect_node(id).children
+    }
+
+    /// Appends a new child with the given value to `parent` and returns its id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` has been removed.
+    pub fn add_child(&mut self, parent: NodeId, value: T) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.expect_node_mut(parent).children.push(id);
+        self.nodes.push(Some(Node {
+            value,
+            parent: Some(parent),
+            children: Vec::new(),
+        }));
+        self.len += 1;
+        id
+    }
+
+    /// Removes a node together with all of its descendants.
+    ///
+    /// The ids of the removed nodes are not reused.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is the root or has already been removed.
+    pub fn remove_subtree(&mut self, id: NodeId) {
+        let parent = self
+            .expect_node(id)
+            .parent
+            .expect("the root of an ArenaTree can't be removed");
+        self.expect_node_mut(parent)
+            .children
+            .retain(|&child| child != id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes[id.0].take() {
+                stack.extend(node.children);
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Returns a handle to a node that can be used with the traversal functions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been removed.
+    pub fn node(&self, id: NodeId) -> NodeRef<'_, T> {
+        self.expect_node(id);
+        NodeRef { tree: self, id }
+    }
+
+    /// Depth-first traversal of the subtree rooted at `start`, by node id.
+    ///
+    /// Behaves like [`traverse_tree`](crate::traverse_tree), with every
+    /// [`TreeControl`] value meaning the same, but walks the arena's own child
+    /// lists with a stack of ids, so no [`NodeRef`] handles or child vectors
+    /// are built along the way. The visitor gets each node's id together with
+    /// its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` has been removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{ArenaTree, TreeControl};
+    ///
+    /// let mut tree = ArenaTree::new("root");
+    /// let docs = tree.add_child(tree.root(), "docs");
+    /// tree.add_child(docs, "guide.md");
+    /// tree.add_child(tree.root(), "tmp");
+    ///
+    /// let mut names = Vec::new();
+    /// tree.traverse(tree.root(), |id, name| {
+    ///     names.push(*name);
+    ///     TreeControl::from_prune(id == docs)
+    /// });
+    ///
+    /// assert_eq!(names, vec!["root", "docs", "tmp"]);
+    /// ```
+    pub fn traverse<F>(&self, start: NodeId, mut visit: F)
+    where
+        F: FnMut(NodeId, &T) -> TreeControl,
+    {
+        self.expect_node(start);
+
+        walk(
+            start,
+            |_, _| true,
+            |&id, _| self.children(id).iter().copied(),
+            |&id, _| visit(id, &self.expect_node(id).value),
+        );
+    }
+
+    /// Returns an iterator over the values of the tree, in depth-first pre-order.
+    pub fn iter(&self) -> ArenaIter<'_, T> {
+        ArenaIter {
+            tree: self,
+            stack: vec![self.root()],
+        }
+    }
+
+    fn slot(&self, id: NodeId) -> Option<&Node<T>> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    fn expect_node(&self, id: NodeId) -> &Node<T> {
+        self.slot(id).expect("node is not in the tree")
+    }
+
+    fn expect_node_mut(&mut self, id: NodeId) -> &mut Node<T> {
+        self.nodes
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .expect("node is not in the tree")
+    }
+}
+
+/// A borrowed handle to a node of an [`ArenaTree`], returned by [`ArenaTree::node`].
+///
+/// Handles are `Copy`, so they can be used directly as the node type of the
+/// traversal functions, with [`NodeRef::children`] as the branch function.
+#[derive(Debug)]
+pub struct NodeRef<'a, T> {
+    tree: &'a ArenaTree<T>,
+    id: NodeId,
+}
+
+// Derived impls would require `T: Clone`
+impl<T> Clone for NodeRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeRef<'_, T> {}
+
+impl<'a, T> NodeRef<'a, T> {
+    /// Returns the id of this node.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Returns the value stored in this node.
+    pub fn value(&self) -> &'a T {
+        &self.tree.expect_node(self.id).value
+    }
+
+    /// Returns handles to this node's children, in insertion order.
+    pub fn children(&self) -> Vec<NodeRef<'a, T>> {
+        self.tree
+            .children(self.id)
+            .iter()
+            .map(|&id| NodeRef {
+                tree: self.tree,
+                id,
+            })
+            .collect()
+    }
+}
+
+/// Depth-first iterator over the values of an [`ArenaTree`], returned by [`ArenaTree::iter`].
+#[derive(Debug)]
+pub struct ArenaIter<'a, T> {
+    tree: &'a ArenaTree<T>,
+    stack: Vec<NodeId>,
+}
+
+impl<'a, T> Iterator for ArenaIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.tree.expect_node(self.stack.pop()?);
+        // Pushed in reverse, so the first child is visited first
+        self.stack.extend(node.children.iter().rev());
+        Some(&node.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traverse_tree;
+
+    #[test]
+    fn test_arena_tree_add_and_remove() {
+        let mut tree = ArenaTree::new(1);
+        let a = tree.add_child(tree.root(), 2);
+        let b = tree.add_child(tree.root(), 3);
+        let c = tree.add_child(a, 4);
+        let d = tree.add_child(c, 5);
+        tree.add_child(b, 6);
+
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.children(tree.root()), &[a, b]);
+        assert_eq!(tree.parent(d), Some(c));
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 4, 5, 3, 6]
+        );
+
+        tree.remove_subtree(a);
+
+        assert_eq!(tree.len(), 3);
+        assert!(!tree.contains(a) && !tree.contains(c) && !tree.contains(d));
+        assert_eq!(tree.get(d), None);
+        assert_eq!(tree.children(tree.root()), &[b]);
+
+        // Ids of removed nodes are not handed out again
+        let e = tree.add_child(b, 7);
+        assert!(![a, c, d].contains(&e));
+        *tree.get_mut(e).unwrap() += 10;
+
+        let mut visited = Vec::new();
+        traverse_tree(
+            tree.node(tree.root()),
+            |_| true,
+            |n| n.children(),
+            |n| {
+                visited.push((n.id(), *n.value()));
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(
+            visited.iter().map(|&(_, value)| value).collect::<Vec<_>>(),
+            vec![1, 3, 6, 17]
+        );
+        assert_eq!(visited[3].0, e);
+    }
+
+    #[test]
+    fn test_arena_tree_traverse_by_id() {
+        let mut tree = ArenaTree::new(1);
+        let a = tree.add_child(tree.root(), 2);
+        let b = tree.add_child(tree.root(), 3);
+        let c = tree.add_child(a, 4);
+        tree.add_child(a, 5);
+        tree.add_child(c, 6);
+        tree.add_child(b, 7);
+
+        // Starting below the root only walks that subtree
+        let mut visited = Vec::new();
+        tree.traverse(a, |id, value| {
+            visited.push((id, *value));
+            TreeControl::Continue
+        });
+        assert_eq!(
+            visited.iter().map(|&(_, v)| v).collect::<Vec<_>>(),
+            vec![2, 4, 6, 5]
+        );
+        assert_eq!(visited[1].0, c);
+
+        tree.remove_subtree(c);
+        let mut values = Vec::new();
+        tree.traverse(tree.root(), |_, value| {
+            values.push(*value);
+            if *value == 5 {
+                TreeControl::Break
+            } else {
+                TreeControl::Continue
+            }
+        });
+        assert_eq!(values, vec![1, 2, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "root of an ArenaTree")]
+    fn test_arena_tree_remove_root_panics() {
+        let mut tree = ArenaTree::new(1);
+        let root = tree.root();
+        tree.remove_subtree(root);
+    }
+}
@@ -0,0 +1,330 @@
+//! Asynchronous tree traversal
+
+use alloc::vec::{self, Vec};
+use core::future::Future;
+
+use crate::{MAX_SUBTREE_RESTARTS, Pending, TreeControl};
+
+/// Depth-first traversal whose branch function and visitor are asynchronous.
+///
+/// This is the `async` counterpart of [`traverse_tree`](crate::traverse_tree), for
+/// trees whose children have to be fetched with `.await`, for example from a
+/// database or the filesystem. The traversal is driven by an explicit stack
+/// inside a single future, so it neither recurses nor boxes a future per node,
+/// and it works with any executor.
+///
+/// Each future returned by `branch_fn` or `visit_fn` is awaited to completion
+/// before the traversal moves on, so nodes are visited in exactly the same order
+/// as with the synchronous version. The futures cannot borrow the node they were
+/// created from; clone what they need into an `async move` block.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function returning a future that resolves to the branches of a node
+/// * `visit_fn` - A function returning a future that resolves to the control flow
+///   instruction for a node
+///
+/// # Control Flow
+///
+/// * `TreeControl::Continue` - Continue into this node's children
+/// * `TreeControl::Prune` - Skip this node's children
+/// * `TreeControl::Break` - Stop the entire traversal
+/// * `TreeControl::Repeat` - Visit this node again
+/// * `TreeControl::ContinueWithout(n)` - Continue into this node's children, skipping the first `n`
+/// * `TreeControl::RestartSubtree` - Regenerate the parent's children, awaiting
+///   `branch_fn` again
+/// * `TreeControl::Defer` - Continue into this node's children, then visit this
+///   node again
+/// * `TreeControl::PruneSiblings` - Skip this node's children and its remaining
+///   siblings
+/// * `TreeControl::PruneAfter(n)` - Continue into this node's children, but no
+///   further than `n` levels below this node
+/// * `TreeControl::BreakAfterSubtree` - Continue into this node's children, then
+///   stop the traversal once they are done
+///
+/// # Example
+///
+/// ```
+/// use std::future::Future;
+/// use std::pin::pin;
+/// use std::task::{Context, Poll, Waker};
+/// use arboriter::{traverse_tree_async, TreeControl};
+///
+/// // A trivial executor for futures that never wait on anything external
+/// fn block_on<F: Future>(future: F) -> F::Output {
+///     let mut future = pin!(future);
+///     let mut cx = Context::from_waker(Waker::noop());
+///     loop {
+///         if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+///             return output;
+///         }
+///     }
+/// }
+///
+/// let mut visited = Vec::new();
+///
+/// block_on(traverse_tree_async(
+///     1,
+///     |n| *n < 16,
+///     |n| {
+///         let n = *n;
+///         async move { vec![n * 2, n * 2 + 1] }
+///     },
+///     |n| {
+///         visited.push(*n);
+///         let n = *n;
+///         async move {
+///             if n == 2 {
+///                 TreeControl::Prune
+///             } else {
+///                 TreeControl::Continue
+///             }
+///         }
+///     },
+/// ));
+///
+/// assert_eq!(visited, vec![1, 2, 3, 6, 12, 13, 7, 14, 15]);
+/// ```
+pub async fn traverse_tree_async<T, C, B, BFut, F, Fut>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> BFut,
+    BFut: Future<Output = Vec<T>>,
+    F: FnMut(&T) -> Fut,
+    Fut: Future<Output = TreeControl>,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    // Same layout as the synchronous traversal: one frame per node on the
+    // current path, holding the node, its pending children, and whether it is
+    // visited again once they are done.
+    let mut stack: Vec<(T, Pending<vec::IntoIter<T>>, bool)> = Vec::new();
+    let mut node = initial;
+    let mut root_restarts = 0;
+    // The depth of the node whose subtree ends the traversal once it is done
+    let mut break_after = None;
+
+    loop {
+        loop {
+            match visit_fn(&node).await {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return,
+                TreeControl::Prune => break,
+                TreeControl::PruneSiblings => {
+                    if let Some((_, siblings, _)) = stack.last_mut() {
+                        siblings.skip_rest();
+                    }
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => {
+                    let depth = stack.len();
+                    if control == TreeControl::BreakAfterSubtree {
+                        break_after = Some(depth);
+                    }
+                    let inherited = stack
+                        .last()
+                        .map_or(usize::MAX, |(_, siblings, _)| siblings.depth_limit());
+                    // `Pending::descend` takes a synchronous closure, so the
+                    // children are awaited first unless the node is at its depth limit
+                    let children = if depth < control.depth_limit(depth, inherited) {
+                        branch_fn(&node).await
+                    } else {
+                        Vec::new()
+                    };
+                    let children = Pending::descend(control, depth, inherited, || children);
+                    stack.push((node, children, control == TreeControl::Defer));
+                    break;
+                }
+                TreeControl::RestartSubtree => match stack.last_mut() {
+                    Some((parent, children, _)) => {
+                        // `Pending::restart` takes a synchronous closure, so the
+                        // children are awaited first when a restart is allowed
+                        if children.restarts < MAX_SUBTREE_RESTARTS {
+                            let regenerated = branch_fn(parent).await;
+                            children.restart(|| regenerated);
+                        }
+                        break;
+                    }
+                    None if root_restarts < MAX_SUBTREE_RESTARTS => {
+                        root_restarts += 1;
+                        continue;
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        node = loop {
+            let Some((_, children, _)) = stack.last_mut() else {
+                return;
+            };
+
+            match children.next() {
+                Some(child) if condition(&child) => break child,
+                Some(_) => {}
+                None => {
+                    if let Some((finished, _, true)) = stack.pop()
+                        && matches!(
+                            visit_fn(&finished).await,
+                            TreeControl::Break | TreeControl::BreakAfterSubtree
+                        )
+                    {
+                        return;
+                    }
+                    if break_after == Some(stack.len()) {
+                        return;
+                    }
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::pin::{Pin, pin};
+    use core::task::{Context, Poll, Waker};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// Future that is pending once before resolving, like a real I/O call.
+    struct YieldOnce<V> {
+        value: Option<V>,
+        yielded: bool,
+    }
+
+    impl<V: Unpin> Future for YieldOnce<V> {
+        type Output = V;
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<V> {
+            if self.yielded {
+                Poll::Ready(self.value.take().expect("polled after completion"))
+            } else {
+                self.yielded = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    fn yield_once<V>(value: V) -> YieldOnce<V> {
+        YieldOnce {
+            value: Some(value),
+            yielded: false,
+        }
+    }
+
+    #[test]
+    fn test_traverse_tree_async_pending_futures() {
+        let mut visited = Vec::new();
+        let mut branch_calls = 0;
+
+        block_on(traverse_tree_async(
+            1,
+            |n| *n < 8,
+            |n| {
+                branch_calls += 1;
+                yield_once(vec![*n * 2, *n * 2 + 1])
+            },
+            |n| {
+                visited.push(*n);
+                yield_once(TreeControl::Continue)
+            },
+        ));
+
+        assert_eq!(visited, vec![1, 2, 4, 5, 3, 6, 7]);
+        assert_eq!(branch_calls, 7);
+    }
+
+    #[test]
+    fn test_traverse_tree_async_break_and_repeat() {
+        let mut visited = Vec::new();
+        let mut repeated = false;
+
+        block_on(traverse_tree_async(
+            1,
+            |n| *n < 16,
+            |n| yield_once(vec![*n * 2, *n * 2 + 1]),
+            |n| {
+                visited.push(*n);
+                let control = match *n {
+                    4 if !repeated => {
+                        repeated = true;
+                        TreeControl::Repeat
+                    }
+                    5 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                };
+                yield_once(control)
+            },
+        ));
+
+        assert_eq!(visited, vec![1, 2, 4, 4, 8, 9, 5]);
+    }
+
+    #[test]
+    fn test_traverse_tree_async_defer() {
+        let mut visited = Vec::new();
+
+        block_on(traverse_tree_async(
+            1,
+            |n| *n < 8,
+            |n| yield_once(vec![*n * 2, *n * 2 + 1]),
+            |n| {
+                let control = if visited.contains(n) {
+                    TreeControl::Continue
+                } else if *n < 4 {
+                    TreeControl::Defer
+                } else {
+                    TreeControl::Continue
+                };
+                visited.push(*n);
+                yield_once(control)
+            },
+        ));
+
+        assert_eq!(visited, vec![1, 2, 4, 5, 2, 3, 6, 7, 3, 1]);
+    }
+
+    #[test]
+    fn test_traverse_tree_async_prune_siblings() {
+        let mut visited = Vec::new();
+
+        block_on(traverse_tree_async(
+            1,
+            |n| *n < 16,
+            |n| yield_once(vec![*n * 2, *n * 2 + 1]),
+            |n| {
+                visited.push(*n);
+                yield_once(if *n == 4 {
+                    TreeControl::PruneSiblings
+                } else {
+                    TreeControl::Continue
+                })
+            },
+        ));
+
+        assert_eq!(visited, vec![1, 2, 4, 3, 6, 12, 13, 7, 14, 15]);
+    }
+}
@@ -1,6 +1,6 @@
 //! Additional examples of using the for_tree macro
 
-use crate::{BinaryNode, break_tree, for_tree, prune};
+use crate::{BinaryNode, break_tree, for_tree, for_tree_depth, prune};
 
 /// Example: Finding a value in a binary tree
 pub fn find_value_example() {
@@ -69,30 +69,22 @@ pub fn fibonacci_example() {
     });
 }
 
-/// Example: Emulating a breadth-first search by level tracking
+/// Example: Tracking the level of each node with for_tree_depth
 pub fn bfs_emulation_example() {
     let root = create_sample_tree();
 
-    println!("BFS-like traversal by tracking levels:");
+    println!("Traversal with levels tracked by the traversal itself:");
 
-    // We use a tuple of (node, level) to track depth
-    for_tree!(item in (&root, 0); |_| true; |item| {
-        let (node, level) = *item;
+    for_tree_depth!(node, level in &root; |_| true; |node| {
         let mut children = Vec::new();
-
-        // We sort children by level to process same-level nodes together
         if let Some(left) = &node.left {
-            children.push((left.as_ref(), level + 1));
+            children.push(left.as_ref());
         }
         if let Some(right) = &node.right {
-            children.push((right.as_ref(), level + 1));
+            children.push(right.as_ref());
         }
-
-        // Sort by level to approximate BFS
-        children.sort_by_key(|&(_, l)| l);
         children
     } => {
-        let (node, level) = *item;
         println!("Level {}: value {}", level, node.value);
     });
 }
@@ -145,7 +137,7 @@ pub fn filesystem_example() {
 
     println!("File system traversal:");
 
-    for_tree!(node in &fs_root; |_| true; |node| {
+    for_tree_depth!(node, depth in &fs_root; |_| true; |node| {
         // Only directories have children to traverse
         if node.is_directory {
             node.children.iter().collect()
@@ -153,36 +145,12 @@ pub fn filesystem_example() {
             Vec::new()
         }
     } => {
-        let indent = "  ".repeat(get_depth(node, &fs_root));
+        let indent = "  ".repeat(depth);
         let node_type = if node.is_directory { "DIR" } else { "FILE" };
         println!("{}{}: {}", indent, node_type, node.name);
     });
 }
 
-// Helper function to calculate depth in the filesystem tree
-fn get_depth(node: &FileSystemNode, root: &FileSystemNode) -> usize {
-    if node.name == root.name {
-        0
-    } else {
-        let mut current = Some(root);
-        let mut depth = 0;
-
-        while let Some(n) = current {
-            for child in &n.children {
-                if child.name == node.name {
-                    return depth + 1;
-                }
-            }
-
-            // This is simplified and not correct for a real implementation
-            depth += 1;
-            current = n.children.first();
-        }
-
-        depth
-    }
-}
-
 // Helper function to create a sample binary tree
 fn create_sample_tree() -> BinaryNode<i32> {
     BinaryNode::with_children(
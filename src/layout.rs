@@ -0,0 +1,242 @@
+//! Widget tree with a two-pass layout
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Event, TreeControl, traverse_events};
+
+/// Width and height of a [`Widget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    /// Creates a size from a width and a height.
+    pub fn new(width: u32, height: u32) -> Self {
+        Size { width, height }
+    }
+}
+
+/// Position and size of a [`Widget`], with the root's top-left corner at the origin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub size: Size,
+}
+
+impl Rect {
+    /// Creates a rectangle from its top-left corner and its size.
+    pub fn new(x: u32, y: u32, size: Size) -> Self {
+        Rect { x, y, size }
+    }
+
+    /// The x coordinate just past the right edge.
+    pub fn right(&self) -> u32 {
+        self.x + self.size.width
+    }
+
+    /// The y coordinate just past the bottom edge.
+    pub fn bottom(&self) -> u32 {
+        self.y + self.size.height
+    }
+
+    /// Returns `true` if `other` lies entirely inside this rectangle.
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+}
+
+/// A widget of a user interface: either a leaf with a preferred size, or a
+/// column that stacks its children vertically.
+///
+/// Laying out such a tree takes two passes in opposite directions. A column's
+/// size depends on the sizes of its children, so sizes are measured bottom-up,
+/// when [`traverse_events`] leaves each widget. Positions depend on where the
+/// parent was put, so they are handed out top-down, when it enters each widget.
+/// [`measure`](Widget::measure) runs the first pass and
+/// [`layout`](Widget::layout) both.
+///
+/// A column surrounds its children with `padding` on every side and puts
+/// `spacing` between two neighbours. A column without children has the
+/// preferred size it was created with.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{Rect, Size, Widget};
+///
+/// let window = Widget::column("window", vec![
+///     Widget::leaf("title", Size::new(120, 20)),
+///     Widget::leaf("ok", Size::new(40, 16)),
+/// ]);
+///
+/// let rects = window.layout(4, 2);
+/// let names: Vec<_> = rects.iter().map(|(widget, _)| widget.name.as_str()).collect();
+///
+/// assert_eq!(names, vec!["window", "title", "ok"]);
+/// assert_eq!(rects[0].1, Rect::new(0, 0, Size::new(128, 46)));
+/// assert_eq!(rects[2].1, Rect::new(4, 26, Size::new(40, 16)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Widget {
+    pub name: String,
+    /// The size of a leaf, which a column with children ignores
+    pub preferred: Size,
+    pub children: Vec<Widget>,
+}
+
+impl Widget {
+    /// Creates a widget without children that has a fixed size.
+    pub fn leaf(name: impl Into<String>, size: Size) -> Self {
+        Widget {
+            name: name.into(),
+            preferred: size,
+            children: Vec::new(),
+        }
+    }
+
+    /// Creates a column that is sized by its children.
+    pub fn column(name: impl Into<String>, children: Vec<Widget>) -> Self {
+        Widget {
+            name: name.into(),
+            preferred: Size::default(),
+            children,
+        }
+    }
+
+    /// Returns references to the children, for use as a branch function.
+    pub fn children(&self) -> Vec<&Widget> {
+        self.children.iter().collect()
+    }
+
+    /// Returns the size of every widget in the tree, in depth-first pre-order.
+    pub fn measure(&self, padding: u32, spacing: u32) -> Vec<Size> {
+        let mut sizes = Vec::new();
+        // Per open column: its index in `sizes` and the sizes of its children so far
+        let mut open: Vec<(usize, Vec<Size>)> = Vec::new();
+
+        traverse_events(
+            self,
+            |_| true,
+            |widget| widget.children(),
+            |event| {
+                match event {
+                    Event::Enter(_) => {
+                        open.push((sizes.len(), Vec::new()));
+                        sizes.push(Size::default());
+                    }
+                    Event::Leave(widget) => {
+                        let (index, children) = open.pop().expect("every Leave follows an Enter");
+                        let size = match children.len() {
+                            0 => widget.preferred,
+                            count => Size {
+                                width: children.iter().map(|s| s.width).max().unwrap_or(0)
+                                    + 2 * padding,
+                                height: children.iter().map(|s| s.height).sum::<u32>()
+                                    + spacing * (count as u32 - 1)
+                                    + 2 * padding,
+                            },
+                        };
+
+                        sizes[index] = size;
+                        if let Some((_, siblings)) = open.last_mut() {
+                            siblings.push(size);
+                        }
+                    }
+                }
+                TreeControl::Continue
+            },
+        );
+
+        sizes
+    }
+
+    /// Returns every widget in the tree with its rectangle, in depth-first pre-order.
+    pub fn layout(&self, padding: u32, spacing: u32) -> Vec<(&Widget, Rect)> {
+        let sizes = self.measure(padding, spacing);
+        let mut rects = Vec::with_capacity(sizes.len());
+        // Per open widget: its index in `rects` and where its next child goes
+        let mut open: Vec<(usize, u32, u32)> = Vec::new();
+
+        traverse_events(
+            self,
+            |_| true,
+            |widget| widget.children(),
+            |event| {
+                match event {
+                    Event::Enter(widget) => {
+                        let (x, y) = open.last().map_or((0, 0), |&(_, x, y)| (x, y));
+                        let index = rects.len();
+
+                        rects.push((*widget, Rect::new(x, y, sizes[index])));
+                        open.push((index, x + padding, y + padding));
+                    }
+                    Event::Leave(_) => {
+                        let (index, _, _) = open.pop().expect("every Leave follows an Enter");
+                        if let Some((_, _, y)) = open.last_mut() {
+                            *y += sizes[index].height + spacing;
+                        }
+                    }
+                }
+                TreeControl::Continue
+            },
+        );
+
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_widget_layout_nested_columns() {
+        let window = Widget::column(
+            "window",
+            vec![
+                Widget::leaf("title", Size::new(120, 20)),
+                Widget::column(
+                    "form",
+                    vec![
+                        Widget::leaf("name", Size::new(100, 16)),
+                        Widget::leaf("email", Size::new(140, 16)),
+                    ],
+                ),
+                Widget::leaf("ok", Size::new(40, 16)),
+                Widget::column("empty", Vec::new()),
+            ],
+        );
+
+        let rects: Vec<_> = window
+            .layout(4, 2)
+            .into_iter()
+            .map(|(widget, rect)| (widget.name.as_str(), rect))
+            .collect();
+
+        assert_eq!(
+            rects,
+            vec![
+                ("window", Rect::new(0, 0, Size::new(156, 92))),
+                ("title", Rect::new(4, 4, Size::new(120, 20))),
+                ("form", Rect::new(4, 26, Size::new(148, 42))),
+                ("name", Rect::new(8, 30, Size::new(100, 16))),
+                ("email", Rect::new(8, 48, Size::new(140, 16))),
+                ("ok", Rect::new(4, 70, Size::new(40, 16))),
+                ("empty", Rect::new(4, 88, Size::new(0, 0))),
+            ]
+        );
+        assert!(rects[1..].iter().all(|(_, rect)| rects[0].1.contains(rect)));
+        assert!(!rects[3].1.contains(&rects[4].1));
+    }
+}
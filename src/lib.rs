@@ -13,7 +13,8 @@
 //! - **Control Flow**: Support for `break`, `continue`, and a special `prune!` operation
 //! - **Flexible**: Works with both in-memory tree structures and imperative tree generation
 //! - **Type Safe**: Fully leverages Rust's type system for safety and clarity
-//! - **Zero Cost**: Compiles to efficient code equivalent to hand-written recursion
+//! - **Low Overhead**: Compiles to a plain loop, with no clones or boxed closures
+//! - **Parallel**: Optional `rayon` feature for traversing subtrees concurrently
 //!
 //! ## Quick Example
 //!
@@ -97,14 +98,82 @@
 //! - `continue` - Standard Rust continue, skip to the next iteration
 //! - `prune!()` - Skip traversing children of the current node
 //! - `break_tree!()` - Exit the entire traversal (unwinding the recursion stack)
+//! - `prune_if!(cond)` / `break_tree_if!(cond)` - Conditional forms of the above
+//! - `prune_depth!(depth, max)` - Prune once the depth bound by `for_tree_depth!` reaches `max`
+//! - `prune_after!(n)` - Visit the current node's subtree, but only down to `n` levels below it
+//! - `skip_body!()` - Skip the rest of the body for the current node, but still visit its children
+//! - `defer!()` - Visit the children, then run the body for the current node again
+//!
+//! ## Closures
+//!
+//! Every callback taken by the traversal functions and helpers, whether a
+//! condition, branch function, visitor, key or cost function, is an `FnMut`,
+//! so any of them can update counters, caches or buffers they capture. The only
+//! exception is `par_traverse_tree` from the `rayon` feature, whose callbacks run
+//! concurrently and must therefore be `Fn + Sync`.
 //!
 //! ## Performance
 //!
-//! The `for_tree!` macro is a zero-cost abstraction - it compiles down to efficient
-//! recursive code and introduces no runtime overhead compared to hand-written
-//! recursive traversal functions.
+//! The `for_tree!` macro compiles down to an efficient loop over an explicit
+//! stack, never cloning nodes or boxing closures, and never overflowing the call
+//! stack on deep trees. The remaining cost compared to hand-written recursion is
+//! the `Vec` that the branch function returns for every node: on a large
+//! `BinaryNode` tree, where visiting a node is nearly free, that allocation
+//! dominates, while on generated trees whose nodes are built anyway the two are
+//! close. `benches/traversal.rs` measures both cases (`cargo bench`).
+//!
+//! ## `no_std` Support
+//!
+//! The crate only needs `alloc`. Disable the default `std` feature to use
+//! [`traverse_tree`], [`TreeControl`] and the macros in `no_std` environments;
+//! the printing example functions are only available with `std`.
 //!
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::fmt::{Debug, Display, Write};
+use core::ops::ControlFlow;
+
+#[cfg(feature = "rayon")]
+mod parallel;
+
+#[cfg(feature = "rayon")]
+pub use parallel::par_traverse_tree;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+#[cfg(feature = "async")]
+pub use asynchronous::traverse_tree_async;
+
+mod arena;
+mod layout;
+mod resumable;
+mod rng;
+mod trie;
+mod walker;
+
+pub use arena::{ArenaIter, ArenaTree, NodeId, NodeRef};
+pub use layout::{Rect, Size, Widget};
+use resumable::WalkState;
+pub use resumable::{StepResult, TraversalState};
+pub use rng::SeededRng;
+pub use trie::Trie;
+pub use walker::TreeWalker;
+
 /// Enum representing control flow options within a tree traversal.
 ///
 /// This enum allows controlling how traversal proceeds after visiting a node:
@@ -114,6 +183,16 @@
 /// * `Continue` - Continue normal traversal, visiting this node's children
 /// * `Prune` - Skip traversing children of the current node, but continue with sibling nodes
 /// * `Break` - Stop the entire traversal immediately
+/// * `Repeat` - Visit the current node again before moving on
+/// * `RestartSubtree` - Regenerate the children of the current node's parent and continue with those
+/// * `ContinueWithout(n)` - Continue into this node's children, skipping the first `n`
+/// * `Defer` - Continue into this node's children, then visit this node again once they are done
+/// * `PruneSiblings` - Skip the children of the current node and its remaining siblings
+/// * `PruneAfter(n)` - Continue into this node's children, but no further than `n` levels below it
+/// * `BreakAfterSubtree` - Continue into this node's children, then stop the traversal once they are done
+///
+/// More variants may be added in later versions, so a `match` on a
+/// `TreeControl` outside this crate needs a wildcard arm.
 ///
 /// # Usage
 ///
@@ -121,13 +200,16 @@
 /// * `continue` - (implicit) Normal Rust continue behavior (built-in keyword)
 /// * [`prune!`] - Skip children of the current node
 /// * [`break_tree!`] - Exit the entire traversal
+/// * [`skip_body!`] - End the body for this node, but still visit its children
+/// * [`defer!`] - Run the body for this node again once its children are done
+/// * [`prune_after!`] - Visit no further than a number of levels below the current node
 ///
 /// When using [`traverse_tree`] directly, return the appropriate variant from your visitor function.
 ///
 /// # Example
 ///
 /// ```
-/// use arboriter::{traverse_tree, TreeControl};
+/// use arboriter::{traverse_forest, TreeControl};
 ///
 /// // Generate a sequence of numbers with pruning
 /// // We'll manually build up a tree traversal that skips even numbers' children
@@ -140,33 +222,32 @@
 /// // Each has a single odd child: +1
 /// let root_nodes = vec![0, 2, 4, 6, 8, 10];
 /// 
-/// for &start in &root_nodes {
-///     traverse_tree(
-///         start,
-///         |_| true, // Visit all nodes
-///         |&n| {
-///             // Each node branches to n+1, but we'll prune this in the visitor
-///             vec![n + 1]
-///         },
-///         |&n| {
-///             sequence.push(n);
-///             
-///             if n % 2 == 0 {
-///                 // Skip children of even numbers
-///                 TreeControl::Prune
-///             } else {
-///                 // Process children of odd numbers
-///                 TreeControl::Continue
-///             }
+/// traverse_forest(
+///     root_nodes,
+///     |_| true, // Visit all nodes
+///     |&n| {
+///         // Each node branches to n+1, but we'll prune this in the visitor
+///         vec![n + 1]
+///     },
+///     |&n| {
+///         sequence.push(n);
+///         
+///         if n % 2 == 0 {
+///             // Skip children of even numbers
+///             TreeControl::Prune
+///         } else {
+///             // Process children of odd numbers
+///             TreeControl::Continue
 ///         }
-///     );
-/// }
+///     }
+/// );
 /// 
 /// // We should see all root nodes (the evens) but none of their children (odds)
 /// // because we pruned at even numbers
 /// assert_eq!(sequence, vec![0, 2, 4, 6, 8, 10]);
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum TreeControl {
     /// Continue traversal normally, including this node's children
     Continue,
@@ -174,6 +255,280 @@ pub enum TreeControl {
     Prune,
     /// Break out of the entire traversal immediately
     Break,
+    /// Call the visitor on the current node again instead of moving on.
+    ///
+    /// This is useful when the visitor changes state that affects how the node
+    /// should be handled, for example expanding a node in an iterative search
+    /// before deciding whether to descend into it. The visitor is called
+    /// repeatedly for as long as it returns `Repeat`, so it must eventually return
+    /// another variant or the traversal will never finish.
+    Repeat,
+    /// Discard the children still queued for this node's parent and call
+    /// `branch_fn` on the parent again, then continue with the fresh children.
+    ///
+    /// This is for generative algorithms where visiting a node changes state that
+    /// the parent's children were generated from, so they need to be re-expanded
+    /// from scratch. The current node's own children are not visited. On the root,
+    /// which has no parent, it visits the root again like `Repeat`.
+    ///
+    /// To guard against endless restart loops, the children of one node are
+    /// regenerated at most [`MAX_SUBTREE_RESTARTS`] times; after that,
+    /// `RestartSubtree` is treated like `Prune`. Traversals that cannot regenerate
+    /// children, such as breadth-first or parallel ones, also treat it like `Prune`.
+    RestartSubtree,
+    /// Continue into this node's children, but skip the first `n` of them.
+    ///
+    /// The skipped children are the first `n` returned by the branch function,
+    /// counted before the condition is checked, so they are never passed to the
+    /// condition or visited. `ContinueWithout(0)` is the same as `Continue`.
+    ContinueWithout(usize),
+    /// Continue into this node's children, then call the visitor on this node
+    /// again once all of them are done.
+    ///
+    /// This gives a single visitor both a pre-order and a post-order call for
+    /// the nodes that need one, for example to open and close a scope around a
+    /// subtree. The second call comes before any of the node's later siblings
+    /// are visited, and its result only matters if it is `Break` or
+    /// `BreakAfterSubtree`, which stop the traversal.
+    /// [`traverse_tree_deferred`] tells the visitor which of the two calls it
+    /// is handling.
+    ///
+    /// Traversals that don't keep a node around until its subtree is done,
+    /// such as breadth-first or best-first ones, treat `Defer` like
+    /// `Continue`.
+    Defer,
+    /// Skip traversing children of the current node, and also skip all of its
+    /// siblings that have not been visited yet.
+    ///
+    /// This is for "found what I was looking for on this level": the traversal
+    /// gives up on the rest of the parent's children and goes on with the
+    /// parent's own next sibling, unlike `Break`, which stops everything. A
+    /// parent that returned `Defer` still gets its second visit. On the root,
+    /// which has no siblings, it is the same as `Prune`.
+    ///
+    /// Traversals that don't visit siblings one after another, such as
+    /// breadth-first, best-first or parallel ones, treat `PruneSiblings` like
+    /// `Prune`.
+    PruneSiblings,
+    /// Continue into this node's children, but only down to `n` levels below
+    /// this node.
+    ///
+    /// Nodes `n` levels below are still visited, but their children are never
+    /// generated, whatever their visitor returns. This caps the depth of one
+    /// subtree relative to where the cap was set, for example to show only the
+    /// first two levels under a selected node, while the rest of the tree is
+    /// traversed as usual. A cap set inside a capped subtree can only tighten
+    /// it, and a deferred node at the cap still gets its second visit.
+    /// `PruneAfter(0)` is the same as `Prune`.
+    PruneAfter(usize),
+    /// Continue into this node's children, then stop the traversal once this
+    /// node's whole subtree is done.
+    ///
+    /// Unlike `Break`, which stops immediately, this lets work that has already
+    /// started on a node run to completion, for example to finish processing a
+    /// transaction before shutting down, while no siblings of the node and no
+    /// other subtrees are started anymore. Nodes inside the subtree that
+    /// returned `Defer` still get their second visit, but the node's ancestors
+    /// don't. A `Break` returned inside the subtree still stops immediately.
+    ///
+    /// Traversals that don't finish a subtree before moving on to other nodes,
+    /// such as breadth-first or best-first ones, treat `BreakAfterSubtree` like
+    /// `Break`, and so does a node's second visit after `Defer`, when its
+    /// subtree is already done.
+    BreakAfterSubtree,
+}
+
+impl TreeControl {
+    /// Returns `Prune` if `prune` is `true`, and `Continue` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{traverse_tree, TreeControl};
+    ///
+    /// let mut visited = Vec::new();
+    ///
+    /// traverse_tree(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |n| {
+    ///     visited.push(*n);
+    ///     TreeControl::from_prune(*n == 2)
+    /// });
+    ///
+    /// assert_eq!(visited, vec![1, 2, 3, 6, 7]);
+    /// ```
+    pub fn from_prune(prune: bool) -> TreeControl {
+        if prune {
+            TreeControl::Prune
+        } else {
+            TreeControl::Continue
+        }
+    }
+
+    /// Merges the decisions of two analyses into one, keeping the stronger of the two.
+    ///
+    /// This lets a visitor run several independent checks and combine their
+    /// results. The variants take precedence in this order, strongest first:
+    ///
+    /// 1. `Break`
+    /// 2. `BreakAfterSubtree`
+    /// 3. `RestartSubtree`
+    /// 4. `Repeat`
+    /// 5. `PruneSiblings`
+    /// 6. `Prune`
+    /// 7. `PruneAfter(n)`, where two of them merge into the smaller `n`
+    /// 8. `Defer`
+    /// 9. `ContinueWithout(n)`, where two of them merge into the larger `n`
+    /// 10. `Continue`
+    ///
+    /// In particular `Break` beats `Prune`, which beats `Continue`. The operation
+    /// is commutative and associative, and `Continue` is its identity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::TreeControl;
+    ///
+    /// let too_deep = TreeControl::from_prune(true);
+    /// let found = TreeControl::Continue;
+    ///
+    /// assert_eq!(too_deep.and(found), TreeControl::Prune);
+    /// assert_eq!(too_deep.and(TreeControl::Break), TreeControl::Break);
+    /// ```
+    pub fn and(self, other: TreeControl) -> TreeControl {
+        fn precedence(control: TreeControl) -> u8 {
+            match control {
+                TreeControl::Continue => 0,
+                TreeControl::ContinueWithout(_) => 1,
+                TreeControl::Defer => 2,
+                TreeControl::PruneAfter(_) => 3,
+                TreeControl::Prune => 4,
+                TreeControl::PruneSiblings => 5,
+                TreeControl::Repeat => 6,
+                TreeControl::RestartSubtree => 7,
+                TreeControl::BreakAfterSubtree => 8,
+                TreeControl::Break => 9,
+            }
+        }
+
+        match (self, other) {
+            (TreeControl::ContinueWithout(a), TreeControl::ContinueWithout(b)) => {
+                TreeControl::ContinueWithout(a.max(b))
+            }
+            (TreeControl::PruneAfter(a), TreeControl::PruneAfter(b)) => {
+                TreeControl::PruneAfter(a.min(b))
+            }
+            _ if precedence(other) > precedence(self) => other,
+            _ => self,
+        }
+    }
+
+    /// How many leading children the traversal skips when descending after this
+    /// control value, which is only non-zero for `ContinueWithout`.
+    pub(crate) fn skipped_children(self) -> usize {
+        match self {
+            TreeControl::ContinueWithout(skip) => skip,
+            _ => 0,
+        }
+    }
+
+    /// The depth limit for a node at `depth` and its subtree, given the limit
+    /// `inherited` from its ancestors, which only `PruneAfter` tightens. The
+    /// node's children are only generated while `depth` is below the limit, and
+    /// `usize::MAX` means there is none.
+    pub(crate) fn depth_limit(self, depth: usize, inherited: usize) -> usize {
+        match self {
+            TreeControl::PruneAfter(levels) => inherited.min(depth.saturating_add(levels)),
+            _ => inherited,
+        }
+    }
+}
+
+/// How many times [`TreeControl::RestartSubtree`] regenerates the children of the
+/// same node before further restarts are treated like [`TreeControl::Prune`].
+pub const MAX_SUBTREE_RESTARTS: usize = 1024;
+
+/// Children of a node on the current path that are still waiting to be visited,
+/// in the explicit stack of a depth-first traversal. `I` iterates over the
+/// children, which come from a `Vec` for most traversals.
+pub(crate) struct Pending<I> {
+    // `None` once the remaining children have been dropped
+    children: Option<I>,
+    restarts: usize,
+    depth_limit: usize,
+}
+
+impl<I: Iterator> Pending<I> {
+    /// The children of a node at `depth` whose visitor returned the descending
+    /// `control`, below ancestors that set the depth limit `inherited`.
+    /// `generate` is only called if the node is above the limit that applies
+    /// to it, which the children then inherit.
+    pub(crate) fn descend<C>(
+        control: TreeControl,
+        depth: usize,
+        inherited: usize,
+        generate: impl FnOnce() -> C,
+    ) -> Self
+    where
+        C: IntoIterator<IntoIter = I>,
+    {
+        let depth_limit = control.depth_limit(depth, inherited);
+        let skip = control.skipped_children();
+
+        let children = (depth < depth_limit).then(|| {
+            let mut children = generate().into_iter();
+            if skip > 0 {
+                children.nth(skip - 1);
+            }
+            children
+        });
+
+        Pending {
+            children,
+            restarts: 0,
+            depth_limit,
+        }
+    }
+
+    /// The depth limit that applies to these children and their subtrees.
+    pub(crate) fn depth_limit(&self) -> usize {
+        self.depth_limit
+    }
+
+    pub(crate) fn next(&mut self) -> Option<I::Item> {
+        self.children.as_mut()?.next()
+    }
+
+    /// Drops the remaining children, for `TreeControl::PruneSiblings`.
+    pub(crate) fn skip_rest(&mut self) {
+        self.children = None;
+    }
+
+    /// Replaces the remaining children with the ones from `regenerate`, for
+    /// `TreeControl::RestartSubtree`, unless the restart limit has been reached.
+    pub(crate) fn restart<C>(&mut self, regenerate: impl FnOnce() -> C)
+    where
+        C: IntoIterator<IntoIter = I>,
+    {
+        if self.restarts < MAX_SUBTREE_RESTARTS {
+            self.restarts += 1;
+            self.children = Some(regenerate().into_iter());
+        }
+    }
+}
+
+/// Lets [`prune!`] and [`break_tree!`] be used inside [`for_tree_try!`] bodies,
+/// whose generated closure returns `Result<TreeControl, E>`.
+impl<E> From<TreeControl> for Result<TreeControl, E> {
+    fn from(control: TreeControl) -> Self {
+        Ok(control)
+    }
+}
+
+/// Lets [`prune!`] and [`break_tree!`] be used inside [`for_tree_value!`] bodies,
+/// whose generated closure returns `ControlFlow<V, TreeControl>`.
+impl<V> From<TreeControl> for ControlFlow<V, TreeControl> {
+    fn from(control: TreeControl) -> Self {
+        ControlFlow::Continue(control)
+    }
 }
 
 /// Core function that handles depth-first tree traversal of arbitrary tree-like structures.
@@ -190,6 +545,13 @@ pub enum TreeControl {
 ///    - Recursively traverse that branch to its full depth
 ///    - Only then proceed to the next branch
 ///
+/// The traversal keeps its own stack of pending branches instead of using
+/// recursion, so very deep trees cannot overflow the call stack.
+///
+/// `condition` is called exactly once for every candidate node, in traversal
+/// order, right before the node would be visited. It may be stateful, for example
+/// to accept only the first few matching subtrees.
+///
 /// # Parameters
 ///
 /// * `initial` - The root value to start traversal from
@@ -199,7 +561,7 @@ pub enum TreeControl {
 ///
 /// # Type Parameters
 ///
-/// * `T` - The type of values in the tree structure (must implement `Clone`)
+/// * `T` - The type of values in the tree structure
 /// * `C` - The type of the condition function
 /// * `B` - The type of the branching function
 /// * `F` - The type of the visitor function
@@ -210,6 +572,16 @@ pub enum TreeControl {
 /// * `TreeControl::Continue` - Continue normal traversal
 /// * `TreeControl::Prune` - Skip traversing children of the current node
 /// * `TreeControl::Break` - Stop the entire traversal
+/// * `TreeControl::Repeat` - Visit the current node again
+/// * `TreeControl::RestartSubtree` - Regenerate the children of the current node's parent
+/// * `TreeControl::ContinueWithout(n)` - Continue into the children, skipping the first `n`
+/// * `TreeControl::Defer` - Continue into the children, then visit the current node again
+/// * `TreeControl::PruneSiblings` - Skip the children of the current node and its
+///   remaining siblings
+/// * `TreeControl::PruneAfter(n)` - Continue into the children, but don't generate
+///   the children of nodes `n` levels below the current node
+/// * `TreeControl::BreakAfterSubtree` - Continue into the children, then stop the
+///   traversal once the current node's subtree is done
 ///
 /// # Example
 ///
@@ -249,532 +621,14133 @@ pub enum TreeControl {
 ///
 /// assert_eq!(values, vec![1, 2, 3]);
 /// ```
-pub fn traverse_tree<T, C, B, F>(
+pub fn traverse_tree<T, C, B, F>(initial: T, mut condition: C, mut branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal whose `branch_fn` may return any collection of children.
+///
+/// This is [`traverse_tree`] without the `Vec`: `branch_fn` can return a
+/// `SmallVec`, an `ArrayVec`, a `Box<[T]>`, an array or a plain iterator,
+/// anything that implements `IntoIterator<Item = T>`. The children are consumed
+/// straight from it, so a collection that keeps small fan-outs inline saves the
+/// heap allocation `traverse_tree` makes for every node with children.
+/// Everything else, including the handling of every `TreeControl` variant, is
+/// the same as in `traverse_tree`.
+///
+/// `traverse_tree` itself keeps returning `Vec` so that closures ending in
+/// `.collect()` still infer their collection type.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_iter, TreeControl};
+/// use smallvec::{SmallVec, smallvec};
+///
+/// // A binary tree of numbers, without allocating for the children
+/// let mut visited = Vec::new();
+///
+/// traverse_tree_iter(
+///     1,
+///     |n| *n < 8,
+///     |n| -> SmallVec<[u32; 2]> { smallvec![n * 2, n * 2 + 1] },
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec![1, 2, 4, 5, 3, 6, 7]);
+/// ```
+pub fn traverse_tree_iter<T, C, B, I, F>(
     initial: T,
-    condition: C,
-    branch_fn: B,
+    mut condition: C,
+    mut branch_fn: B,
     mut visit_fn: F,
 ) where
-    T: Clone,
-    C: Fn(&T) -> bool,
-    B: Fn(&T) -> Vec<T>,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> I,
+    I: IntoIterator<Item = T>,
     F: FnMut(&T) -> TreeControl,
 {
-    // Define the recursive traversal function
-    fn traverse_internal<T, C, B, F>(
-        node: &T,
-        condition: &C,
-        branch_fn: &B,
-        visit_fn: &mut F,
-    ) -> TreeControl
-    where
-        T: Clone,
-        C: Fn(&T) -> bool,
-        B: Fn(&T) -> Vec<T>,
-        F: FnMut(&T) -> TreeControl,
-    {
-        // Visit the current node
-        let result = visit_fn(node);
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| visit_fn(node),
+    );
+}
 
-        // Handle control flow
-        match result {
-            TreeControl::Break => return TreeControl::Break,
-            TreeControl::Prune => return TreeControl::Continue,
-            TreeControl::Continue => {}
-        }
+/// Depth-first traversal whose `branch_fn` pushes children into a callback instead of returning them.
+///
+/// `branch_fn` is called with a node and a `push` callback, and calls `push`
+/// once for every child, in order. The children are pushed into buffers that
+/// the traversal reuses once a node's children are done, one for each level of
+/// the current path, so once they have grown to fit the widest nodes of the
+/// tree, no node causes an allocation, however many children it has. This
+/// suits wide trees, and children that are produced by loops or filters more
+/// easily pushed than collected. A branch function that can return an iterator
+/// over the children can use [`traverse_tree_iter`] instead.
+///
+/// Visiting order and control flow are the same as for [`traverse_tree`].
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that pushes the branches of a given node into the callback
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_push, NAryNode, TreeControl};
+///
+/// let root = NAryNode::with_children(1, vec![
+///     NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+///     NAryNode::new(3),
+/// ]);
+///
+/// let mut values = Vec::new();
+///
+/// traverse_tree_push(
+///     &root,
+///     |_| true,
+///     |node, push| {
+///         for child in &node.children {
+///             push(child);
+///         }
+///     },
+///     |node| {
+///         values.push(node.value);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(values, vec![1, 2, 4, 5, 3]);
+/// ```
+pub fn traverse_tree_push<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T, &mut dyn FnMut(T)),
+    F: FnMut(&T) -> TreeControl,
+{
+    // The children of one node, reversed so that the first child is popped
+    // first. The buffer goes back to `spare` once the traversal drops it.
+    struct Pushed<'s, T> {
+        children: Vec<T>,
+        spare: &'s RefCell<Vec<Vec<T>>>,
+    }
 
-        // Get branches and continue traversal if condition is met
-        for child in branch_fn(node) {
-            if condition(&child) {
-                let child_result = traverse_internal(&child, condition, branch_fn, visit_fn);
-                if child_result == TreeControl::Break {
-                    return TreeControl::Break;
-                }
-            }
-        }
+    impl<T> Iterator for Pushed<'_, T> {
+        type Item = T;
 
-        TreeControl::Continue
+        fn next(&mut self) -> Option<T> {
+            self.children.pop()
+        }
     }
 
-    // Only traverse if the initial node meets the condition
-    if condition(&initial) {
-        traverse_internal(&initial, &condition, &branch_fn, &mut visit_fn);
+    impl<T> Drop for Pushed<'_, T> {
+        fn drop(&mut self) {
+            let mut children = core::mem::take(&mut self.children);
+            children.clear();
+            self.spare.borrow_mut().push(children);
+        }
     }
+
+    let spare = RefCell::new(Vec::new());
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let mut children: Vec<T> = spare.borrow_mut().pop().unwrap_or_default();
+            branch_fn(node, &mut |child| children.push(child));
+            children.reverse();
+            Pushed {
+                children,
+                spare: &spare,
+            }
+        },
+        |node, _| visit_fn(node),
+    );
 }
 
-/// Skips traversing the children of the current node.
+/// Depth-first traversal of a forest: every root in `roots`, one tree after another.
 ///
-/// This macro is used within a [`for_tree!`] block to prevent traversal
-/// of the current node's children. The traversal will continue with
-/// sibling nodes.
+/// Each tree is traversed exactly like [`traverse_tree`] would, in the order the
+/// roots are yielded, with the same `condition`, `branch_fn` and `visit_fn`. A
+/// root that fails `condition` is skipped along with its tree.
+///
+/// `TreeControl::Break` stops the whole walk, not just the current tree: no
+/// further roots are taken from `roots`. The other variants only affect the
+/// tree they are returned in.
 ///
 /// # Example
 ///
 /// ```
-/// use arboriter::{for_tree, prune};
-///
-/// // Generate numbers in a simple tree structure, starting with 1
-/// // Each number branches to [n*2, n*2+1]
-/// // We'll prune at even numbers
-/// let mut values = Vec::new();
+/// use arboriter::{traverse_forest, TreeControl};
 ///
-/// for_tree!(n in 1; |n| *n < 8; |n| {
-///     // Each node branches to [n*2, n*2+1]
-///     vec![*n * 2, *n * 2 + 1]
-/// } => {
-///     values.push(*n);
-///     
-///     if *n % 2 == 0 {
-///         prune!(); // Don't process children of even numbers
+/// // Each number branches to the numbers ten times larger
+/// let mut visited = Vec::new();
+/// traverse_forest(1..=4, |n| *n < 100, |n| vec![n * 10], |n| {
+///     visited.push(*n);
+///     if *n == 30 {
+///         TreeControl::Break
+///     } else {
+///         TreeControl::Continue
 ///     }
 /// });
 ///
-/// // With this traversal and pruning, we should see:
-/// // 1 → 2 (prune) → 3 → 6 (prune) → 7
-/// assert_eq!(values, vec![1, 2, 3, 6, 7]);
+/// // The tree rooted at 4 is never reached
+/// assert_eq!(visited, vec![1, 10, 2, 20, 3, 30]);
 /// ```
-#[macro_export]
-macro_rules! prune {
-    () => {
-        return $crate::TreeControl::Prune;
-    };
+pub fn traverse_forest<T, I, C, B, F>(roots: I, mut condition: C, mut branch_fn: B, mut visit_fn: F)
+where
+    I: IntoIterator<Item = T>,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    for root in roots {
+        let control = walk(
+            root,
+            |node, _| condition(node),
+            |node, _| branch_fn(node),
+            |node, _| visit_fn(node),
+        );
+        if control == TreeControl::Break {
+            break;
+        }
+    }
 }
 
-/// Breaks out of the entire tree traversal.
+/// Depth-first traversal that keeps its pending nodes in a caller-provided buffer.
 ///
-/// This macro is used within a [`for_tree!`] block to immediately stop
-/// the entire traversal, unwinding the traversal stack and returning
-/// control to the point after the [`for_tree!`] macro.
+/// Behaves like [`traverse_tree`], but instead of allocating a new stack on every
+/// call, pending nodes are kept in `stack`. The buffer is cleared at the start and
+/// end of each call but keeps its capacity, so hot loops that run many traversals,
+/// such as the selection phase of a tree search, stop reallocating once the buffer
+/// has grown to the largest size they need.
+///
+/// Since the buffer only holds nodes, not their parents or depths,
+/// `TreeControl::RestartSubtree`, `TreeControl::PruneSiblings` and
+/// `TreeControl::PruneAfter` are treated like `TreeControl::Prune`, and
+/// `TreeControl::Defer` like `TreeControl::Continue`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+/// * `stack` - The buffer to keep pending nodes in; its contents are discarded
 ///
 /// # Example
 ///
 /// ```
-/// use arboriter::{for_tree, break_tree};
+/// use arboriter::{traverse_tree_with_stack, TreeControl};
 ///
-/// // Find a specific value in a tree-like structure
-/// let mut found = false;
-/// let target = 7;
+/// let mut stack = Vec::new();
+/// let mut total = 0;
 ///
-/// for_tree!(n in 0; |n| *n <= 10; |n| vec![*n + 1] => {
-///     println!("Checking {}", n);
-///     
-///     if *n == target {
-///         found = true;
-///         break_tree!(); // Exit the traversal - we found what we're looking for
-///     }
-/// });
+/// for limit in 1..100 {
+///     traverse_tree_with_stack(
+///         1,
+///         |n| *n < limit,
+///         |n| vec![*n * 2, *n * 2 + 1],
+///         |_| {
+///             total += 1;
+///             TreeControl::Continue
+///         },
+///         &mut stack,
+///     );
+/// }
 ///
-/// assert!(found);
+/// assert_eq!(total, (0..99).sum::<i32>());
+/// assert!(stack.is_empty());
 /// ```
-#[macro_export]
-macro_rules! break_tree {
-    () => {
-        return $crate::TreeControl::Break;
-    };
+pub fn traverse_tree_with_stack<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+    stack: &mut Vec<T>,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    stack.clear();
+    stack.push(initial);
+    // The buffer length below which nodes are outside the subtree that ends
+    // the traversal
+    let mut break_after = None;
+
+    // Children are pushed in reverse so the first child is popped first
+    while let Some(node) = stack.pop() {
+        if break_after.is_some_and(|len| stack.len() < len) {
+            stack.clear();
+            return;
+        }
+        if !condition(&node) {
+            continue;
+        }
+
+        loop {
+            match visit_fn(&node) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => {
+                    stack.clear();
+                    return;
+                }
+                TreeControl::Prune
+                | TreeControl::PruneSiblings
+                | TreeControl::RestartSubtree
+                | TreeControl::PruneAfter(_) => {
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::BreakAfterSubtree) => {
+                    if control == TreeControl::BreakAfterSubtree {
+                        break_after = Some(stack.len());
+                    }
+                    let children = branch_fn(&node)
+                        .into_iter()
+                        .skip(control.skipped_children());
+                    stack.extend(children.rev());
+                    break;
+                }
+            }
+        }
+    }
 }
 
-/// A macro for traversing tree-like structures or generating tree-like data.
+/// Depth-first traversal that reserves its stack for an expected tree depth up front.
 ///
-/// # Syntax
+/// The traversal keeps one stack frame per node on the path from the root to
+/// the current node, and the stack grows by reallocating as the traversal goes
+/// deeper. For tall trees whose depth is roughly known in advance, passing it
+/// as `expected_depth` allocates the stack once instead. The hint only affects
+/// performance: the traversal behaves exactly like [`traverse_tree`] whether
+/// the actual depth turns out smaller or larger.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `expected_depth` - The number of levels to reserve room for
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
 ///
-/// ```rust,ignore
-/// // This is just syntax illustration, not meant to be compiled
-/// for_tree!(var in initial; condition; branches => {
-///     // body
-///     // You can use special control flow:
-///     // - break_tree!(); - exits the entire traversal
-///     // - prune!(); - skips traversing children of the current node
-/// });
 /// ```
+/// use arboriter::{traverse_tree_hinted, TreeControl};
 ///
-/// # Examples
+/// // A chain 10,000 nodes tall
+/// let mut deepest = 0;
 ///
-/// Traverse a binary tree:
-/// ```rust,no_run
-/// use arboriter::{for_tree, prune, break_tree, BinaryNode};
+/// traverse_tree_hinted(0, |n| *n < 10_000, |n| vec![*n + 1], 10_000, |n| {
+///     deepest = *n;
+///     TreeControl::Continue
+/// });
 ///
-/// // Create a simple binary tree for demonstration
-/// let root = BinaryNode::with_children(
-///     10,
-///     Some(Box::new(BinaryNode::new(5))),
-///     Some(Box::new(BinaryNode::new(15)))
-/// );
+/// assert_eq!(deepest, 9_999);
+/// ```
+pub fn traverse_tree_hinted<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    expected_depth: usize,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk_with_capacity(
+        initial,
+        expected_depth,
+        usize::MAX,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, _| match event {
+            Event::Enter(node) | Event::Leave(node) => visit_fn(node),
+        },
+    );
+}
+
+/// Depth up to which [`traverse_tree_hybrid`] uses native recursion before
+/// switching to an explicit stack.
+pub const RECURSION_LIMIT: usize = 64;
+
+/// Counts how often the hybrid traversal falls back to the explicit stack, so
+/// tests can tell which path a traversal took.
+#[cfg(test)]
+static HYBRID_FALLBACKS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// Depth-first traversal that recurses for shallow trees and uses an explicit stack for deep ones.
 ///
-/// for_tree!(node in &root; |_| true; |node| {
-///     // Explicitly declare the type of branches
-///     let mut branches: Vec<&BinaryNode<i32>> = Vec::new();
-///     if let Some(left) = &node.left {
-///         branches.push(left.as_ref());
-///     }
-///     if let Some(right) = &node.right {
-///         branches.push(right.as_ref());
-///     }
-///     branches
-/// } => {
-///     println!("Node value: {}", node.value);
+/// Equivalent to [`traverse_tree_limit`] with a limit of [`RECURSION_LIMIT`].
 ///
-///     if node.value == 10 {
-///         break_tree!(); // Exit the traversal
-///     }
+/// # Example
 ///
-///     if node.value < 0 {
-///         prune!(); // Don't traverse children of negative nodes
-///     }
+/// ```
+/// use arboriter::{traverse_tree_hybrid, TreeControl};
+///
+/// // A chain far deeper than the recursion limit
+/// let mut count = 0;
+///
+/// traverse_tree_hybrid(0, |n| *n < 100_000, |n| vec![*n + 1], |_| {
+///     count += 1;
+///     TreeControl::Continue
 /// });
+///
+/// assert_eq!(count, 100_000);
 /// ```
+pub fn traverse_tree_hybrid<T, C, B, F>(initial: T, condition: C, branch_fn: B, visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    traverse_tree_limit(initial, condition, branch_fn, RECURSION_LIMIT, visit_fn);
+}
+
+/// Depth-first traversal that recurses up to `recursion_limit` levels deep and
+/// continues with an explicit stack below that.
 ///
-/// Generate strings of "a", "b", and "c" with length <= 8:
-/// ```rust,no_run
-/// use arboriter::{for_tree, prune};
+/// Native recursion keeps the traversal state on the call stack, which avoids
+/// heap allocations and is fast for shallow trees, but overflows the call
+/// stack on very deep ones. This traversal recurses for the top
+/// `recursion_limit` levels of the tree and traverses any subtree below that
+/// depth with the explicit-stack algorithm of [`traverse_tree`], so it is safe
+/// for trees of any depth. Visiting order and control flow are exactly the same
+/// as for [`traverse_tree`], whichever path handles a node.
 ///
-/// for_tree!(s in String::new(); |s| s.len() <= 8; |s| {
-///     // Create branches with explicit type
-///     let mut branches: Vec<String> = Vec::new();
-///     branches.push(format!("{}a", s));
-///     branches.push(format!("{}b", s));
-///     branches.push(format!("{}c", s));
-///     branches
-/// } => {
-///     println!("{}", s);
+/// # Parameters
 ///
-///     if s.len() == 8 {
-///         prune!(); // Don't generate longer strings
-///     }
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `recursion_limit` - The number of levels handled by recursion
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_limit, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// // Only the root and its children are handled recursively
+/// traverse_tree_limit(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], 2, |n| {
+///     visited.push(*n);
+///     TreeControl::Continue
 /// });
+///
+/// assert_eq!(visited, vec![1, 2, 4, 8, 9, 5, 10, 11, 3, 6, 12, 13, 7, 14, 15]);
 /// ```
-#[macro_export]
-macro_rules! for_tree {
-    // Main pattern with => separator
-    ($var:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+pub fn traverse_tree_limit<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    recursion_limit: usize,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    /// What a finished subtree asks of its parent.
+    enum Step {
+        Done,
+        Break,
+        /// The subtree's root returned `RestartSubtree`
+        Restart,
+        /// The subtree's root returned `PruneSiblings`
+        SkipSiblings,
+    }
+
+    // `depth_left` is how many levels below `node` may still be visited,
+    // after the `PruneAfter` caps of its ancestors
+    fn recurse<T, C, B, F>(
+        node: &T,
+        levels_left: usize,
+        depth_left: usize,
+        condition: &mut C,
+        branch_fn: &mut B,
+        visit_fn: &mut F,
+    ) -> Step
+    where
+        C: FnMut(&T) -> bool,
+        B: FnMut(&T) -> Vec<T>,
+        F: FnMut(&T) -> TreeControl,
+    {
+        let control = loop {
+            match visit_fn(node) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return Step::Break,
+                TreeControl::Prune => return Step::Done,
+                TreeControl::PruneSiblings => return Step::SkipSiblings,
+                TreeControl::RestartSubtree => return Step::Restart,
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => break control,
+            }
+        };
+
+        let mut children = Pending::descend(control, 0, depth_left, || branch_fn(node));
+        let child_depth_left = children.depth_limit().saturating_sub(1);
+
+        while let Some(child) = children.next() {
+            if !condition(&child) {
+                continue;
+            }
+
+            let step = if levels_left > 1 {
+                recurse(
+                    &child,
+                    levels_left - 1,
+                    child_depth_left,
+                    condition,
+                    branch_fn,
+                    visit_fn,
+                )
+            } else {
+                fall_back(child, child_depth_left, condition, branch_fn, visit_fn)
+            };
+
+            match step {
+                Step::Done => {}
+                Step::Break => return Step::Break,
+                Step::Restart => children.restart(|| branch_fn(node)),
+                Step::SkipSiblings => children.skip_rest(),
+            }
+        }
+
+        if control == TreeControl::BreakAfterSubtree
+            || control == TreeControl::Defer
+                && matches!(
+                    visit_fn(node),
+                    TreeControl::Break | TreeControl::BreakAfterSubtree
+                )
         {
-            $crate::traverse_tree(
-                $init,
-                $cond,
-                $branch,
-                |$var| {
-                    let result = {
-                        $body
-                        $crate::TreeControl::Continue
-                    };
-                    result
-                }
-            );
+            return Step::Break;
         }
-    };
+        Step::Done
+    }
 
-    // Alternative syntax with semicolons instead of =>
-    ($var:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
-        $crate::for_tree!($var in $init; $cond; $branch => $body);
-    };
+    /// Traverses a subtree whose root has already passed `condition` with the
+    /// explicit-stack algorithm.
+    fn fall_back<T, C, B, F>(
+        root: T,
+        depth_left: usize,
+        condition: &mut C,
+        branch_fn: &mut B,
+        visit_fn: &mut F,
+    ) -> Step
+    where
+        C: FnMut(&T) -> bool,
+        B: FnMut(&T) -> Vec<T>,
+        F: FnMut(&T) -> TreeControl,
+    {
+        #[cfg(test)]
+        HYBRID_FALLBACKS.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
 
-    // Allows shorter syntax when the closures are simple - uses = like in the blog post
-    ($var:ident = $init:expr; $cond:expr; $branch:expr => $body:block) => {
-        {
-            let initial_value = $init;
-            $crate::for_tree!(
-                $var in initial_value; 
-                |$var| $cond; 
-                |$var| $branch;
-                $body
-            );
+        // `walk` would restart the root itself, but this root's children
+        // belong to the recursive caller, which has to regenerate them. The
+        // same goes for skipping the root's siblings.
+        let mut restart = false;
+        let mut skip_siblings = false;
+
+        let result = walk_with_capacity(
+            root,
+            0,
+            depth_left,
+            |node, depth| depth == 0 || condition(node),
+            |node, _| branch_fn(node),
+            |event, depth| match event {
+                Event::Enter(node) => match visit_fn(node) {
+                    TreeControl::RestartSubtree if depth == 0 => {
+                        restart = true;
+                        TreeControl::Break
+                    }
+                    TreeControl::PruneSiblings if depth == 0 => {
+                        skip_siblings = true;
+                        TreeControl::Prune
+                    }
+                    control => control,
+                },
+                Event::Leave(node) => visit_fn(node),
+            },
+        );
+
+        match result {
+            _ if restart => Step::Restart,
+            _ if skip_siblings => Step::SkipSiblings,
+            TreeControl::Break => Step::Break,
+            _ => Step::Done,
         }
-    };
+    }
 
-    // Very similar to for loop syntax with semicolons
-    ($var:ident = $init:expr; $cond:expr; $branch:expr; $body:block) => {
-        $crate::for_tree!($var = $init; $cond; $branch => $body);
-    };
-}
+    if recursion_limit == 0 {
+        walk(
+            initial,
+            |node, _| condition(node),
+            |node, _| branch_fn(node),
+            |node, _| visit_fn(node),
+        );
+        return;
+    }
 
-// Examples
+    if !condition(&initial) {
+        return;
+    }
 
-/// Tree node example for binary trees
-pub struct BinaryNode<T> {
-    pub value: T,
-    pub left: Option<Box<BinaryNode<T>>>,
-    pub right: Option<Box<BinaryNode<T>>>,
-}
+    // There is nothing above the root to regenerate, so restarting the root
+    // visits it again, as in `walk`
+    let mut root_restarts = 0;
 
-impl<T> BinaryNode<T> {
-    /// Creates a new `BinaryNode` with the given value and no children.
-    ///
-    /// # Parameters
-    ///
-    /// * `value` - The value to store in this node
-    ///
-    /// # Returns
-    ///
-    /// A new `BinaryNode` with the specified value and `None` for both children.
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use arboriter::BinaryNode;
-    ///
-    /// let node = BinaryNode::new(42);
-    /// assert_eq!(node.value, 42);
-    /// assert!(node.left.is_none());
-    /// assert!(node.right.is_none());
-    /// ```
-    pub fn new(value: T) -> Self {
-        BinaryNode {
-            value,
-            left: None,
-            right: None,
+    while let Step::Restart = recurse(
+        &initial,
+        recursion_limit,
+        usize::MAX,
+        &mut condition,
+        &mut branch_fn,
+        &mut visit_fn,
+    ) {
+        if root_restarts == MAX_SUBTREE_RESTARTS {
+            break;
         }
+        root_restarts += 1;
     }
+}
 
-    /// Creates a new `BinaryNode` with the given value and child nodes.
-    ///
-    /// # Parameters
-    ///
-    /// * `value` - The value to store in this node
-    /// * `left` - The left child of this node, if any
-    /// * `right` - The right child of this node, if any
-    ///
-    /// # Returns
-    ///
-    /// A new `BinaryNode` with the specified value and children.
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use arboriter::BinaryNode;
-    ///
-    /// let node = BinaryNode::with_children(
-    ///     1,
-    ///     Some(Box::new(BinaryNode::new(2))),
-    ///     Some(Box::new(BinaryNode::new(3)))
-    /// );
-    ///
-    /// assert_eq!(node.value, 1);
-    /// assert_eq!(node.left.as_ref().unwrap().value, 2);
-    /// assert_eq!(node.right.as_ref().unwrap().value, 3);
-    /// ```
-    pub fn with_children(
-        value: T,
-        left: Option<Box<BinaryNode<T>>>,
-        right: Option<Box<BinaryNode<T>>>,
-    ) -> Self {
-        BinaryNode { value, left, right }
+/// Depth-first traversal for generated trees, kept for compatibility.
+///
+/// This is the same as [`traverse_tree`], which now moves every node through
+/// the traversal and never clones it, so it no longer needs `T: Clone` either.
+#[deprecated(
+    since = "0.2.0",
+    note = "use `traverse_tree`, which doesn't need `T: Clone` either"
+)]
+pub fn generate_tree<T, C, B, F>(initial: T, condition: C, branch_fn: B, visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    traverse_tree(initial, condition, branch_fn, visit_fn);
+}
+
+/// Generates every sequence over `alphabet` of length at most `max_len`.
+///
+/// This is the generative pattern of the string examples packaged up: the tree
+/// whose root is the empty sequence and whose children extend a sequence by
+/// one element, in the order of `alphabet`, traversed with [`traverse_tree`].
+/// The sequences come out in depth-first order, which for a sorted alphabet is
+/// lexicographic order, starting with the empty sequence. Sequences of length
+/// `max_len` are pruned, so nothing longer is ever built.
+///
+/// The traversal runs before the iterator is returned, so all
+/// `1 + n + n^2 + ... + n^max_len` sequences for an alphabet of `n` elements
+/// are held in memory at once. For very large products, traverse the tree
+/// directly and prune what isn't needed.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::product;
+///
+/// let words: Vec<String> = product(&['a', 'b'], 2)
+///     .map(|chars| chars.into_iter().collect())
+///     .collect();
+///
+/// assert_eq!(words, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+/// ```
+pub fn product<T: Clone>(alphabet: &[T], max_len: usize) -> impl Iterator<Item = Vec<T>> {
+    let mut sequences = Vec::new();
+
+    traverse_tree(
+        Vec::new(),
+        |_| true,
+        |sequence: &Vec<T>| {
+            alphabet
+                .iter()
+                .map(|element| {
+                    let mut longer = sequence.clone();
+                    longer.push(element.clone());
+                    longer
+                })
+                .collect()
+        },
+        |sequence| {
+            sequences.push(sequence.clone());
+            if sequence.len() >= max_len {
+                TreeControl::Prune
+            } else {
+                TreeControl::Continue
+            }
+        },
+    );
+
+    sequences.into_iter()
+}
+
+/// Depth-first traversal of a tree of shared, mutable `Rc<RefCell<T>>` nodes.
+///
+/// Trees such as scene graphs or UI hierarchies often hold their children as
+/// `Rc<RefCell<T>>`. This function takes care of the borrows: each node is
+/// borrowed immutably only for the duration of a single call to `visit_fn` or
+/// `branch_fn`, and never while its children are being traversed. A visitor is
+/// therefore free to `borrow_mut` any other node it holds a handle to,
+/// including ancestors of the current node, without risking a `BorrowMutError` panic.
+///
+/// Because `Rc` makes sharing easy, it also makes cycles easy. This traversal
+/// has no cycle detection, so a node that is reachable from itself will be
+/// visited forever. For shared structures that may contain cycles, track the
+/// nodes already seen, for example by [`Rc::as_ptr`], and prune at repeats, or
+/// use [`traverse_graph_checked`].
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns the children of a given node
+/// * `visit_fn` - A function that is called for each node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use arboriter::{traverse_shared, TreeControl};
+///
+/// struct Widget {
+///     name: &'static str,
+///     children: Vec<Rc<RefCell<Widget>>>,
+/// }
+///
+/// let leaf = |name| Rc::new(RefCell::new(Widget { name, children: Vec::new() }));
+/// let button = leaf("button");
+/// let root = Rc::new(RefCell::new(Widget {
+///     name: "window",
+///     children: vec![leaf("label"), button.clone()],
+/// }));
+///
+/// let mut names = Vec::new();
+///
+/// traverse_shared(root.clone(), |w| w.children.clone(), |w| {
+///     names.push(w.name);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(names, vec!["window", "label", "button"]);
+///
+/// // The same node can be shared, and mutated, through any of its handles
+/// button.borrow_mut().name = "ok";
+/// assert_eq!(root.borrow().children[1].borrow().name, "ok");
+/// ```
+pub fn traverse_shared<T, B, F>(root: Rc<RefCell<T>>, mut branch_fn: B, mut visit_fn: F)
+where
+    B: FnMut(&T) -> Vec<Rc<RefCell<T>>>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        root,
+        |_, _| true,
+        // Each borrow ends when the call returns
+        |node, _| branch_fn(&node.borrow()),
+        |node, _| visit_fn(&node.borrow()),
+    );
+}
+
+/// The order in which [`traverse_tree_in`] visits nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// Depth-first, each node before its children, as in [`traverse_tree`]
+    PreOrderDfs,
+    /// Depth-first, each node after all of its children
+    PostOrderDfs,
+    /// Breadth-first, level by level, each level from first to last child
+    Bfs,
+}
+
+/// Traverses a tree in an order chosen at runtime.
+///
+/// This is a single entry point for the main traversal strategies, for
+/// callers that pick the order dynamically, for example from a command-line
+/// flag. The control flow instructions returned by `visit_fn` mean slightly
+/// different things in each order, because the visitor runs at a different
+/// point relative to the node's children:
+///
+/// | Control            | `PreOrderDfs`            | `PostOrderDfs`              | `Bfs`                         |
+/// |--------------------|--------------------------|-----------------------------|-------------------------------|
+/// | `Continue`         | Descend into children    | No effect                   | Queue the children            |
+/// | `Prune`            | Skip the children        | No effect, children are done | Don't queue the children     |
+/// | `Break`            | Stop the traversal       | Stop the traversal          | Stop the traversal            |
+/// | `Repeat`           | Visit the node again     | Visit the node again        | Visit the node again          |
+/// | `ContinueWithout(n)` | Skip the first `n` children | No effect                | Don't queue the first `n` children |
+/// | `RestartSubtree`   | Regenerate the parent's children | No effect           | Like `Prune`                  |
+/// | `Defer`            | Descend into children, then visit the node again | No effect | Like `Continue`   |
+/// | `PruneSiblings`    | Skip the children and the remaining siblings | Skip the remaining siblings | Like `Prune` |
+/// | `PruneAfter(n)`    | Descend, but no further than `n` levels below the node | No effect | Queue descendants down to `n` levels below the node |
+/// | `BreakAfterSubtree` | Stop once the children are done | Like `Break`          | Like `Break`                  |
+///
+/// In post-order a node is only visited once its whole subtree has been
+/// visited, so there is nothing left for `Prune` to skip. To avoid descending
+/// into a subtree in post-order, filter its root out with `condition`.
+///
+/// # Parameters
+///
+/// * `order` - The order in which to visit nodes
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_in, Order, TreeControl};
+///
+/// let run = |order| {
+///     let mut visited = Vec::new();
+///     traverse_tree_in(order, 1, |n| *n < 8, |n| vec![n * 2, n * 2 + 1], |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     });
+///     visited
+/// };
+///
+/// assert_eq!(run(Order::PreOrderDfs), vec![1, 2, 4, 5, 3, 6, 7]);
+/// assert_eq!(run(Order::PostOrderDfs), vec![4, 5, 2, 6, 7, 3, 1]);
+/// assert_eq!(run(Order::Bfs), vec![1, 2, 3, 4, 5, 6, 7]);
+/// ```
+pub fn traverse_tree_in<T, C, B, F>(
+    order: Order,
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    match order {
+        Order::PreOrderDfs => {
+            walk(
+                initial,
+                |node, _| condition(node),
+                |node, _| branch_fn(node),
+                |node, _| visit_fn(node),
+            );
+        }
+        Order::PostOrderDfs => {
+            traverse_events(initial, condition, branch_fn, |event| match event {
+                Event::Enter(_) => TreeControl::Continue,
+                Event::Leave(node) => loop {
+                    match visit_fn(node) {
+                        TreeControl::Repeat => continue,
+                        // The node's subtree is already done
+                        TreeControl::Break | TreeControl::BreakAfterSubtree => {
+                            break TreeControl::Break;
+                        }
+                        TreeControl::PruneSiblings => break TreeControl::PruneSiblings,
+                        _ => break TreeControl::Continue,
+                    }
+                },
+            })
+        }
+        Order::Bfs => {
+            if !condition(&initial) {
+                return;
+            }
+
+            // Each node is queued with its depth and the depth limit set by its ancestors
+            let mut queue = alloc::collections::VecDeque::new();
+            queue.push_back((initial, 0, usize::MAX));
+
+            while let Some((node, depth, inherited)) = queue.pop_front() {
+                let control = loop {
+                    match visit_fn(&node) {
+                        TreeControl::Repeat => continue,
+                        TreeControl::Break | TreeControl::BreakAfterSubtree => return,
+                        // Siblings are already queued, so there is nothing to restart
+                        // or skip
+                        TreeControl::Prune
+                        | TreeControl::PruneSiblings
+                        | TreeControl::RestartSubtree => break None,
+                        control @ (TreeControl::Continue
+                        | TreeControl::ContinueWithout(_)
+                        | TreeControl::Defer
+                        | TreeControl::PruneAfter(_)) => break Some(control),
+                    }
+                };
+
+                if let Some(control) = control {
+                    let mut children =
+                        Pending::descend(control, depth, inherited, || branch_fn(&node));
+                    while let Some(child) = children.next() {
+                        if condition(&child) {
+                            queue.push_back((child, depth + 1, children.depth_limit()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Depth-first traversal that visits siblings right-to-left.
+///
+/// Behaves like [`traverse_tree`], except that the branches returned by `branch_fn`
+/// are visited from last to first. This gives the mirror-image traversal order
+/// without having to reverse the output of every branch function.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_rev, TreeControl};
+///
+/// let mut values = Vec::new();
+///
+/// traverse_tree_rev(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     values.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(values, vec![1, 3, 7, 6, 2, 5, 4]);
+/// ```
+pub fn traverse_tree_rev<T, C, B, F>(initial: T, condition: C, mut branch_fn: B, visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    traverse_tree(
+        initial,
+        condition,
+        |node| {
+            let mut branches = branch_fn(node);
+            branches.reverse();
+            branches
+        },
+        visit_fn,
+    );
+}
+
+/// Depth-first traversal that visits each node's children in ascending key order.
+///
+/// Behaves like [`traverse_tree`], except that the branches returned by `branch_fn`
+/// are sorted by `key_fn` before they are visited. This makes "best first among
+/// siblings" explicit and keeps the sorting out of the branch function. The sort is
+/// stable, so siblings with equal keys keep the order `branch_fn` returned them in.
+///
+/// For descending order, wrap the key in [`core::cmp::Reverse`]. For full control
+/// over the comparison, use [`traverse_tree_ordered_by`].
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `key_fn` - A function that returns the key siblings are sorted by
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_ordered, TreeControl};
+///
+/// // Visit the cheapest move first
+/// let mut visited = Vec::new();
+///
+/// traverse_tree_ordered(
+///     ("start", 0),
+///     |_| true,
+///     |(name, _)| match *name {
+///         "start" => vec![("slow", 9), ("fast", 1), ("medium", 5)],
+///         _ => Vec::new(),
+///     },
+///     |(_, cost)| *cost,
+///     |(name, _)| {
+///         visited.push(*name);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec!["start", "fast", "medium", "slow"]);
+/// ```
+pub fn traverse_tree_ordered<T, C, B, F, K, KF>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut key_fn: KF,
+    visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    K: Ord,
+    KF: FnMut(&T) -> K,
+{
+    traverse_tree_ordered_by(
+        initial,
+        condition,
+        branch_fn,
+        |a, b| key_fn(a).cmp(&key_fn(b)),
+        visit_fn,
+    );
+}
+
+/// Depth-first traversal that visits each node's children in the order given by a
+/// comparator.
+///
+/// This is the comparator form of [`traverse_tree_ordered`]. The sort is stable, so
+/// siblings that compare equal keep the order `branch_fn` returned them in.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `compare` - A function that orders two siblings
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_ordered_by, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// // Larger children first
+/// traverse_tree_ordered_by(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |a, b| b.cmp(a), |n| {
+///     visited.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(visited, vec![1, 3, 7, 6, 2, 5, 4]);
+/// ```
+pub fn traverse_tree_ordered_by<T, C, B, F, O>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut compare: O,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    O: FnMut(&T, &T) -> Ordering,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let mut branches = branch_fn(node);
+            branches.sort_by(&mut compare);
+            branches
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Explicit-stack depth-first traversal shared by the sequential traversal functions.
+///
+/// All callbacks receive the depth of the node they are called with; for `condition`
+/// that is the depth the node will have once visited. Returns `TreeControl::Break`
+/// if the visitor stopped the traversal, and `TreeControl::Continue` otherwise.
+fn walk<T, C, B, I, F>(initial: T, condition: C, branch_fn: B, mut visit_fn: F) -> TreeControl
+where
+    C: FnMut(&T, usize) -> bool,
+    B: FnMut(&T, usize) -> I,
+    I: IntoIterator<Item = T>,
+    F: FnMut(&T, usize) -> TreeControl,
+{
+    walk_deferred(initial, condition, branch_fn, |event, depth| match event {
+        Event::Enter(node) | Event::Leave(node) => visit_fn(node, depth),
+    })
+}
+
+/// [`walk`] for callers that need to tell the second visit of a node that
+/// returned `TreeControl::Defer` apart from the first: the first visit of
+/// every node is an `Event::Enter`, the second visit an `Event::Leave`.
+fn walk_deferred<T, C, B, I, F>(initial: T, condition: C, branch_fn: B, visit_fn: F) -> TreeControl
+where
+    C: FnMut(&T, usize) -> bool,
+    B: FnMut(&T, usize) -> I,
+    I: IntoIterator<Item = T>,
+    F: FnMut(Event<&T>, usize) -> TreeControl,
+{
+    walk_with_capacity(initial, 0, usize::MAX, condition, branch_fn, visit_fn)
+}
+
+/// [`walk_deferred`] with room for `expected_depth` levels reserved on the stack up front,
+/// which never generates the children of nodes at `depth_limit` or deeper, as if
+/// the root's ancestors had returned `TreeControl::PruneAfter`.
+fn walk_with_capacity<T, C, B, I, F>(
+    initial: T,
+    expected_depth: usize,
+    depth_limit: usize,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) -> TreeControl
+where
+    C: FnMut(&T, usize) -> bool,
+    B: FnMut(&T, usize) -> I,
+    I: IntoIterator<Item = T>,
+    F: FnMut(Event<&T>, usize) -> TreeControl,
+{
+    if !condition(&initial, 0) {
+        return TreeControl::Continue;
+    }
+
+    let mut walk = WalkState::new(initial, expected_depth, depth_limit);
+    while !walk.is_done() {
+        walk.step(&mut condition, &mut branch_fn, &mut visit_fn, &mut |_| {});
+    }
+
+    if walk.broke() {
+        TreeControl::Break
+    } else {
+        TreeControl::Continue
+    }
+}
+
+/// Depth-first traversal that generates children one index at a time.
+///
+/// Instead of returning all branches in a `Vec`, `branch_fn` is called with the
+/// parent and increasing child indices 0, 1, 2, ... and returns `None` once there
+/// are no more children. Children are generated lazily, right before they are
+/// visited, so wide trees need no per-node allocation and children after a
+/// `TreeControl::Break` are never computed at all.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function called with a node and a child index that returns
+///   that child, or `None` when the index is past the last child
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_indexed, TreeControl};
+///
+/// // Each byte string has 256 children, but only the first few are ever built
+/// let mut found = None;
+/// let mut generated = 0;
+///
+/// traverse_tree_indexed(
+///     Vec::new(),
+///     |_| true,
+///     |bytes: &Vec<u8>, index| {
+///         let byte = u8::try_from(index).ok()?;
+///         generated += 1;
+///         let mut child = bytes.clone();
+///         child.push(byte);
+///         Some(child)
+///     },
+///     |bytes| {
+///         if bytes == &[0, 2] {
+///             found = Some(bytes.clone());
+///             TreeControl::Break
+///         } else if bytes.len() == 2 {
+///             TreeControl::Prune
+///         } else {
+///             TreeControl::Continue
+///         }
+///     },
+/// );
+///
+/// assert_eq!(found, Some(vec![0, 2]));
+/// assert_eq!(generated, 4);
+/// ```
+pub fn traverse_tree_indexed<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T, usize) -> Option<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    // One frame per node on the current path, holding the node, the index of its
+    // next child, how often its children were restarted, whether it is visited
+    // again once they are done, and the depth limit its subtree is under. The
+    // depth of a node is the number of frames above it.
+    let mut stack: Vec<(T, usize, usize, bool, usize)> = Vec::new();
+    let mut node = initial;
+    let mut root_restarts = 0;
+    // The depth of the node whose subtree ends the traversal once it is done
+    let mut break_after = None;
+
+    loop {
+        loop {
+            match visit_fn(&node) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return,
+                TreeControl::Prune => break,
+                // The parent has no children left to visit, so it is done
+                TreeControl::PruneSiblings => {
+                    if let Some((finished, _, _, true, _)) = stack.pop()
+                        && matches!(
+                            visit_fn(&finished),
+                            TreeControl::Break | TreeControl::BreakAfterSubtree
+                        )
+                    {
+                        return;
+                    }
+                    if break_after == Some(stack.len()) {
+                        return;
+                    }
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => {
+                    if control == TreeControl::BreakAfterSubtree {
+                        break_after = Some(stack.len());
+                    }
+                    let inherited = stack.last().map_or(usize::MAX, |frame| frame.4);
+                    let depth_limit = control.depth_limit(stack.len(), inherited);
+                    let deferred = control == TreeControl::Defer;
+                    stack.push((node, control.skipped_children(), 0, deferred, depth_limit));
+                    break;
+                }
+                TreeControl::RestartSubtree => match stack.last_mut() {
+                    Some((_, index, restarts, _, _)) => {
+                        if *restarts < MAX_SUBTREE_RESTARTS {
+                            *restarts += 1;
+                            *index = 0;
+                        }
+                        break;
+                    }
+                    None if root_restarts < MAX_SUBTREE_RESTARTS => {
+                        root_restarts += 1;
+                        continue;
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        node = loop {
+            let child_depth = stack.len();
+            let Some((parent, index, _, _, depth_limit)) = stack.last_mut() else {
+                return;
+            };
+
+            // A node at its depth limit has no children to generate
+            let child = if child_depth <= *depth_limit {
+                branch_fn(parent, *index)
+            } else {
+                None
+            };
+
+            match child {
+                Some(child) => {
+                    *index += 1;
+                    if condition(&child) {
+                        break child;
+                    }
+                }
+                None => {
+                    if let Some((finished, _, _, true, _)) = stack.pop()
+                        && matches!(
+                            visit_fn(&finished),
+                            TreeControl::Break | TreeControl::BreakAfterSubtree
+                        )
+                    {
+                        return;
+                    }
+                    if break_after == Some(stack.len()) {
+                        return;
+                    }
+                }
+            }
+        };
+    }
+}
+
+/// Depth-first traversal of binary trees whose branch function returns a pair.
+///
+/// Behaves like [`traverse_tree`], but `branch_fn` returns the left and right
+/// child as a `(left, right)` pair of options instead of a `Vec`, so walking a
+/// binary tree needs no allocation per node, and a missing child is spelled
+/// out as `None` rather than left out of a list. The left child and its
+/// subtree are visited before the right one.
+///
+/// `TreeControl::ContinueWithout(n)` only counts the children that are
+/// present, so `ContinueWithout(1)` skips the right child of a node that has
+/// no left one.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns the left and right child of a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_binary, BinaryNode, TreeControl};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::with_children(3, None, Some(Box::new(BinaryNode::new(4)))))),
+/// );
+///
+/// let mut values = Vec::new();
+///
+/// traverse_binary(&root, |_| true, |n| (n.left.as_deref(), n.right.as_deref()), |n| {
+///     values.push(n.value);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(values, vec![1, 2, 3, 4]);
+/// ```
+pub fn traverse_binary<T, C, B, F>(initial: T, mut condition: C, mut branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> (Option<T>, Option<T>),
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let (left, right) = branch_fn(node);
+            left.into_iter().chain(right)
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal that never visits nodes deeper than `max_depth`.
+///
+/// The root is at depth 0, its children at depth 1, and so on. Nodes at
+/// `max_depth` are still visited, but `branch_fn` is not called for them,
+/// whatever their visitor returns, so nothing below that level is generated or
+/// visited regardless of `condition`. This works like a `TreeControl::PruneAfter`
+/// returned by the root's parent, and keeps a depth cap separate from the
+/// value-based `condition`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `max_depth` - The deepest level that will be visited
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_max_depth, TreeControl};
+///
+/// // An unbounded string generator, capped at two characters by depth alone
+/// let mut strings = Vec::new();
+///
+/// traverse_tree_max_depth(
+///     String::new(),
+///     |_| true,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     2,
+///     |s| {
+///         strings.push(s.clone());
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(strings, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+/// ```
+pub fn traverse_tree_max_depth<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    max_depth: usize,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk_with_capacity(
+        initial,
+        0,
+        max_depth,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, _| match event {
+            Event::Enter(node) | Event::Leave(node) => visit_fn(node),
+        },
+    );
+}
+
+/// Iterative-deepening depth-first traversal.
+///
+/// Runs a depth-limited depth-first traversal with a limit of 0, then 1, then 2,
+/// and so on, like [`traverse_tree_max_depth`] in a loop. Every node at depth `d`
+/// is visited before any node at depth `d + 1` is first reached, which gives
+/// breadth-first completeness on very wide or infinite trees while only keeping a
+/// single root-to-leaf path in memory.
+///
+/// The loop ends when a round visits no more nodes than the previous one (the
+/// whole tree has been explored) or when `visit_fn` returns `TreeControl::Break`.
+///
+/// Because every round starts again from the root, `visit_fn` sees shallow nodes
+/// once per round. Callers who need each node only once should deduplicate, for
+/// example by only acting on nodes at the deepest level of the current round.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_iddfs, TreeControl};
+///
+/// // Find the shortest string over {a, b} containing "bb", even though the
+/// // tree of strings is infinite
+/// let mut found = None;
+///
+/// traverse_iddfs(
+///     String::new(),
+///     |_| true,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     |s| {
+///         if s.contains("bb") {
+///             found = Some(s.clone());
+///             TreeControl::Break
+///         } else {
+///             TreeControl::Continue
+///         }
+///     },
+/// );
+///
+/// assert_eq!(found, Some("bb".to_string()));
+/// ```
+pub fn traverse_iddfs<T, C, B, F>(initial: T, mut condition: C, mut branch_fn: B, mut visit_fn: F)
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut previous_visits = 0;
+
+    for max_depth in 0.. {
+        let mut visits = 0;
+
+        let result = walk_with_capacity(
+            initial.clone(),
+            max_depth,
+            max_depth,
+            |node, _| condition(node),
+            |node, _| branch_fn(node),
+            |event, _| match event {
+                Event::Enter(node) => {
+                    visits += 1;
+                    visit_fn(node)
+                }
+                Event::Leave(node) => visit_fn(node),
+            },
+        );
+
+        if result == TreeControl::Break || visits == previous_visits {
+            return;
+        }
+        previous_visits = visits;
+    }
+}
+
+/// Depth-first traversal that stops after visiting at most `max_visits` nodes.
+///
+/// This is a safety valve for generative trees whose `branch_fn` might never stop
+/// producing children. Once `max_visits` nodes have been visited, the traversal
+/// stops as if [`break_tree!`] had been used. Nodes are counted like
+/// [`TraversalStats::visited`]: a node visited again after returning `Repeat`
+/// or `Defer` still counts once.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `max_visits` - The maximum number of nodes to visit
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Returns
+///
+/// `true` if the budget was exhausted, meaning at least one more node would have
+/// been visited without it, or `false` if the traversal finished within the budget.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_budget, TreeControl};
+///
+/// // An infinite chain, cut off after five visits
+/// let mut visited = Vec::new();
+///
+/// let exhausted = traverse_tree_budget(0, |_| true, |n| vec![*n + 1], 5, |n| {
+///     visited.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert!(exhausted);
+/// assert_eq!(visited, vec![0, 1, 2, 3, 4]);
+/// ```
+pub fn traverse_tree_budget<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    max_visits: usize,
+    mut visit_fn: F,
+) -> bool
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut visits = 0;
+    let mut exhausted = false;
+    let mut repeating = false;
+
+    walk_deferred(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, _| match event {
+            Event::Enter(node) => {
+                if !repeating {
+                    if visits == max_visits {
+                        exhausted = true;
+                        return TreeControl::Break;
+                    }
+                    visits += 1;
+                }
+                let control = visit_fn(node);
+                repeating = control == TreeControl::Repeat;
+                control
+            }
+            // The second visit of a deferred node is part of the same node
+            Event::Leave(node) => visit_fn(node),
+        },
+    );
+
+    exhausted
+}
+
+/// Depth-first traversal of a possibly infinite tree, without a condition.
+///
+/// Generative trees such as the string generator of the crate docs are usually
+/// bounded by `condition`. Here the tree is deliberately unbounded: every child
+/// `branch_fn` returns is visited, and the traversal only ends where `visit_fn`
+/// returns `TreeControl::Prune` or `TreeControl::Break`, or where `branch_fn`
+/// returns no children. Deciding when to stop is the visitor's job, next to the
+/// rest of the logic that looks at the node.
+///
+/// **This never returns if the tree is infinite and the visitor never prunes or
+/// breaks along some branch.** Nodes are generated lazily, one branch at a time,
+/// so memory stays proportional to the depth reached, but the loop itself has
+/// no limit. When in doubt, use [`traverse_tree_budget`] to cap the number of
+/// visits or [`traverse_tree_max_depth`] to cap the depth, passing `|_| true` as
+/// the condition.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow
+///   instructions; it is responsible for ending the traversal
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_lazy, TreeControl};
+///
+/// // Every string over {a, b}: an infinite tree, cut off by the visitor
+/// let mut strings = Vec::new();
+///
+/// traverse_lazy(
+///     String::new(),
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     |s| {
+///         strings.push(s.clone());
+///         TreeControl::from_prune(s.len() == 2)
+///     },
+/// );
+///
+/// assert_eq!(strings, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+/// ```
+pub fn traverse_lazy<T, B, F>(initial: T, branch_fn: B, visit_fn: F)
+where
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    traverse_tree(initial, |_| true, branch_fn, visit_fn);
+}
+
+/// Depth-first traversal that catches branch functions returning their own node.
+///
+/// A generative `branch_fn` that accidentally includes the current node among
+/// its children, such as `|n| vec![*n]` instead of `|n| vec![*n + 1]`, makes
+/// the traversal loop forever on that node. This function behaves exactly like
+/// [`traverse_tree`], but panics with a descriptive message as soon as
+/// `branch_fn` returns a child equal to its parent, turning the hang into an
+/// error that points at the bug.
+///
+/// The check only runs when debug assertions are enabled, as in debug builds
+/// and `cargo test`. In release builds this is exactly [`traverse_tree`], so it
+/// can stay in production code at no cost.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Panics
+///
+/// With debug assertions enabled, panics if `branch_fn` returns a child equal
+/// to the node it was called with.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_guarded, TreeControl};
+///
+/// let mut visited = Vec::new();
+/// traverse_tree_guarded(0, |n| *n <= 3, |n| vec![*n + 1], |n| {
+///     visited.push(*n);
+///     TreeControl::Continue
+/// });
+/// assert_eq!(visited, vec![0, 1, 2, 3]);
+/// ```
+///
+/// A branch function that returns its own node is reported instead of looping:
+///
+/// ```should_panic
+/// use arboriter::{traverse_tree_guarded, TreeControl};
+///
+/// # if !cfg!(debug_assertions) { panic!() }
+/// traverse_tree_guarded(0, |n| *n <= 3, |n| vec![*n], |_| TreeControl::Continue);
+/// ```
+pub fn traverse_tree_guarded<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    T: PartialEq,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, depth| {
+            let children = branch_fn(node);
+            if cfg!(debug_assertions)
+                && let Some(index) = children.iter().position(|child| child == node)
+            {
+                panic!(
+                    "branch_fn returned the node it was called with as its child {} \
+                     (at depth {}), which would make the traversal loop forever",
+                    index, depth
+                );
+            }
+            children
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal that visits each distinct key at most once.
+///
+/// `key_fn` extracts a key from every node that passes `condition`. The first
+/// node with a given key is visited as usual; any later node with the same key
+/// is skipped together with its subtree, as if `condition` had rejected it.
+/// This memoizes generative traversals in which different branches produce
+/// the same states, without the cost of full graph cycle detection.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `key_fn` - A function that returns the key identifying a node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_dedup, TreeControl};
+///
+/// // Reachable totals when adding 2 or 3 at a time, up to 7
+/// let mut totals = Vec::new();
+///
+/// traverse_tree_dedup(0, |n| *n <= 7, |n| vec![n + 2, n + 3], |n| *n, |n| {
+///     totals.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(totals, vec![0, 2, 4, 6, 7, 5, 3]);
+/// ```
+#[cfg(feature = "std")]
+pub fn traverse_tree_dedup<T, C, B, F, K, KF>(
+    initial: T,
+    mut condition: C,
+    branch_fn: B,
+    mut key_fn: KF,
+    visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    K: core::hash::Hash + Eq,
+    KF: FnMut(&T) -> K,
+{
+    let mut seen = std::collections::HashSet::new();
+
+    traverse_tree(
+        initial,
+        |node| condition(node) && seen.insert(key_fn(node)),
+        branch_fn,
+        visit_fn,
+    );
+}
+
+/// Depth-first traversal that visits every node but expands each distinct node only once.
+///
+/// Generative traversals often reach equal states along different branches,
+/// and expanding them again repeats the whole subtree below. Here a node that
+/// is equal to one whose children were already generated is still visited,
+/// but its children are not generated a second time: a descending
+/// [`TreeControl`] from the visitor is turned into `TreeControl::Prune`, or
+/// `TreeControl::Break` for `BreakAfterSubtree`. Nodes the visitor pruned
+/// itself don't count as expanded.
+///
+/// Unlike [`traverse_tree_dedup`], which skips repeated nodes altogether, the
+/// visitor sees every occurrence, so it can count paths or record edges into
+/// a node that is shared, as in a DAG.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_memoized, TreeControl};
+///
+/// // Totals reached by adding 1 or 2 at a time: 2 and 3 are reached twice,
+/// // but only expanded the first time
+/// let mut totals = Vec::new();
+///
+/// traverse_memoized(0, |n| *n <= 3, |n| vec![n + 1, n + 2], |n| {
+///     totals.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(totals, vec![0, 1, 2, 3, 3, 2]);
+/// ```
+#[cfg(feature = "std")]
+pub fn traverse_memoized<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    T: core::hash::Hash + Eq + Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut expanded = std::collections::HashSet::new();
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| match visit_fn(node) {
+            control @ (TreeControl::Continue
+            | TreeControl::ContinueWithout(_)
+            | TreeControl::Defer
+            | TreeControl::PruneAfter(_)
+            | TreeControl::BreakAfterSubtree)
+                if !expanded.insert(node.clone()) =>
+            {
+                // An expanded subtree is already done, so there is nothing to wait for
+                if control == TreeControl::BreakAfterSubtree {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Prune
+                }
+            }
+            control => control,
+        },
+    );
+}
+
+/// Depth-first traversal whose branch function and visitor share a seeded random number generator.
+///
+/// Randomized tree algorithms, such as Monte Carlo tree search rollouts or
+/// random sampling of a generated tree, are hard to test when every run takes
+/// a different path. This function creates a [`SeededRng`] from `seed` and
+/// passes it to every call of `branch_fn` and `visit_fn`, in traversal order,
+/// so the same seed always produces the same traversal.
+///
+/// # Parameters
+///
+/// * `seed` - The seed for the random number generator
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node,
+///   using the random number generator
+/// * `visit_fn` - A function that is called for each visited node with the random
+///   number generator, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_seeded, TreeControl};
+///
+/// // A random walk down the implicit binary tree 1..64, taking one child per level
+/// let walk = |seed| {
+///     let mut path = Vec::new();
+///     traverse_tree_seeded(
+///         seed,
+///         1,
+///         |n| *n < 64,
+///         |n, rng| vec![n * 2 + rng.below(2) as u32],
+///         |n, _| {
+///             path.push(*n);
+///             TreeControl::Continue
+///         },
+///     );
+///     path
+/// };
+///
+/// assert_eq!(walk(7).len(), 6);
+/// assert_eq!(walk(7), walk(7));
+/// ```
+pub fn traverse_tree_seeded<T, C, B, F>(
+    seed: u64,
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T, &mut SeededRng) -> Vec<T>,
+    F: FnMut(&T, &mut SeededRng) -> TreeControl,
+{
+    // Shared by both callbacks, which are never running at the same time
+    let rng = RefCell::new(SeededRng::new(seed));
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node, &mut rng.borrow_mut()),
+        |node, _| visit_fn(node, &mut rng.borrow_mut()),
+    );
+}
+
+/// Walks from `root` to a leaf, taking a uniformly random child at every node,
+/// and returns the path taken.
+///
+/// This is the rollout of Monte Carlo tree search: from a node, play random
+/// moves until the game is over, then score the final position. Here a leaf is
+/// any node for which `branch_fn` returns no children. The random choices come
+/// from `rng`, so the same [`SeededRng`] state always produces the same path.
+///
+/// # Parameters
+///
+/// * `root` - The node to start the walk from, which is the first node of the path
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `rng` - The random number generator that picks the children
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{random_path, SeededRng};
+///
+/// // Count down from 10 by steps of 1 or 2 until reaching 0
+/// let steps = |n: &u32| (1..=2).filter(|s| s <= n).map(|s| n - s).collect();
+///
+/// let path = random_path(&10, steps, &mut SeededRng::new(3));
+///
+/// assert_eq!(path.first(), Some(&10));
+/// assert_eq!(path.last(), Some(&0));
+/// assert_eq!(path, random_path(&10, steps, &mut SeededRng::new(3)));
+/// ```
+pub fn random_path<T, B>(root: &T, mut branch_fn: B, rng: &mut SeededRng) -> Vec<T>
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut path = Vec::new();
+
+    traverse_tree(
+        root.clone(),
+        |_| true,
+        |node| {
+            let mut children = branch_fn(node);
+            if children.is_empty() {
+                children
+            } else {
+                let chosen = children.swap_remove(rng.below(children.len()));
+                vec![chosen]
+            }
+        },
+        |node| {
+            path.push(node.clone());
+            TreeControl::Continue
+        },
+    );
+
+    path
+}
+
+/// Depth-first traversal that explores at most `k` children of every node.
+///
+/// For very wide generative trees, pruning limits how deep the traversal goes
+/// but not how many siblings it has to consider. This function hands the full
+/// list of children of every node to `select`, which returns the indices of
+/// the ones worth exploring, typically the best few by some heuristic as in a
+/// beam search. Only the first `k` distinct indices are followed, in the order
+/// `select` returned them; the other children are dropped together with their
+/// subtrees.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `k` - The maximum number of children to explore per node
+/// * `select` - A function that picks the indices of the children to explore
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Panics
+///
+/// Panics if `select` returns an index that is out of bounds for the children
+/// it was given.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_sampled, TreeControl};
+///
+/// // Of the ten digits that can extend a number, follow only the two largest
+/// let mut visited = Vec::new();
+///
+/// traverse_sampled(
+///     0u32,
+///     |n| *n < 1000,
+///     |n| (0..10).map(|d| n * 10 + d).collect(),
+///     2,
+///     |children| (0..children.len()).rev().collect(),
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec![0, 9, 99, 999, 998, 98, 989, 988, 8, 89, 899, 898, 88, 889, 888]);
+/// ```
+pub fn traverse_sampled<T, C, B, S, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    k: usize,
+    mut select: S,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    S: FnMut(&[T]) -> Vec<usize>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let children = branch_fn(node);
+            let picks = select(&children);
+
+            // Taking each child out of its slot also skips repeated indices
+            let mut slots: Vec<Option<T>> = children.into_iter().map(Some).collect();
+            picks
+                .into_iter()
+                .filter_map(|index| slots[index].take())
+                .take(k)
+                .collect::<Vec<_>>()
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal that skips every node whose path cost exceeds a bound.
+///
+/// The cost of a path is the sum of `cost_fn` over all of its nodes, from the
+/// root down to and including the last one. A node whose path cost is greater
+/// than `bound` is skipped together with its subtree, as if a condition had
+/// rejected it. This is the pruning step of branch-and-bound search, which a
+/// plain condition can't express since it only sees one node at a time.
+/// `visit_fn` receives each node along with its path cost.
+///
+/// Pruning on the path cost is only sound if `cost_fn` never returns a
+/// negative value, since otherwise a path over the bound could come back
+/// under it further down.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `cost_fn` - A function that returns the cost a node adds to its path
+/// * `bound` - The highest path cost of the nodes to visit
+/// * `visit_fn` - A function that is called for each visited node and its path
+///   cost, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_bounded, TreeControl};
+///
+/// // Ways to climb stairs one or two steps at a time, paying per step taken
+/// let mut reached = Vec::new();
+///
+/// traverse_bounded(
+///     (0, 0),
+///     |&(position, _)| vec![(position + 1, 1), (position + 2, 3)],
+///     |&(_, price)| f64::from(price),
+///     4.0,
+///     |&(position, _), cost| {
+///         reached.push((position, cost));
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(
+///     reached,
+///     vec![(0, 0.0), (1, 1.0), (2, 2.0), (3, 3.0), (4, 4.0), (3, 4.0), (2, 3.0), (3, 4.0)],
+/// );
+/// ```
+pub fn traverse_bounded<T, B, CF, F>(
+    initial: T,
+    mut branch_fn: B,
+    mut cost_fn: CF,
+    bound: f64,
+    mut visit_fn: F,
+) where
+    B: FnMut(&T) -> Vec<T>,
+    CF: FnMut(&T) -> f64,
+    F: FnMut(&T, f64) -> TreeControl,
+{
+    // The path cost of every node on the current path, indexed by depth
+    let costs = RefCell::new(Vec::new());
+
+    walk(
+        initial,
+        |node, depth| {
+            let mut costs = costs.borrow_mut();
+            // Only the ancestors of the node are still on the path
+            costs.truncate(depth);
+            let cost = costs.last().copied().unwrap_or(0.0) + cost_fn(node);
+
+            if cost > bound {
+                return false;
+            }
+            costs.push(cost);
+            true
+        },
+        |node, _| branch_fn(node),
+        |node, depth| {
+            let cost = costs.borrow()[depth];
+            visit_fn(node, cost)
+        },
+    );
+}
+
+/// Depth-first traversal that reports the running visit count every `every` visits.
+///
+/// `progress_fn` is called with the number of visits so far after every
+/// `every`-th visit, which keeps progress reporting for long-running traversals
+/// out of the visitor. Nodes are counted like [`TraversalStats::visited`]: a
+/// node visited again after returning `Repeat` or `Defer` counts once, once
+/// its visitor returns anything but `Repeat`. An `every` of 0 disables
+/// progress reports.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `every` - How many visits to wait between progress reports
+/// * `progress_fn` - A function called with the running visit count
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_progress, TreeControl};
+///
+/// let mut reports = Vec::new();
+///
+/// traverse_tree_progress(
+///     1,
+///     |n| *n < 32,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     10,
+///     |visited| reports.push(visited),
+///     |_| TreeControl::Continue,
+/// );
+///
+/// assert_eq!(reports, vec![10, 20, 30]);
+/// ```
+pub fn traverse_tree_progress<T, C, B, F, P>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    every: usize,
+    mut progress_fn: P,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    P: FnMut(usize),
+{
+    let mut visits = 0;
+
+    walk_deferred(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, _| match event {
+            Event::Enter(node) => {
+                let control = visit_fn(node);
+
+                if control != TreeControl::Repeat {
+                    visits += 1;
+                    if every > 0 && visits % every == 0 {
+                        progress_fn(visits);
+                    }
+                }
+
+                control
+            }
+            // The second visit of a deferred node is part of the same node
+            Event::Leave(node) => visit_fn(node),
+        },
+    );
+}
+
+/// Depth-first traversal that stops for good once a running condition is met.
+///
+/// `stop_fn` is called for each visited node and the traversal ends, after that
+/// node, the first time it returns `true`. Unlike `condition`, which judges each
+/// node on its own, `stop_fn` is free to keep state across calls, such as a
+/// running total, and unlike [`traverse_tree_budget`] the limit doesn't have to
+/// be a node count.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `stop_fn` - A function called for each visited node, returning `true` to stop the traversal
+///
+/// # Returns
+///
+/// `true` if `stop_fn` stopped the traversal, or `false` if every node was visited.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::traverse_until;
+///
+/// // Collect nodes until their sum exceeds 10
+/// let mut visited = Vec::new();
+/// let mut total = 0;
+///
+/// let stopped = traverse_until(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     visited.push(*n);
+///     total += n;
+///     total > 10
+/// });
+///
+/// assert!(stopped);
+/// assert_eq!(visited, vec![1, 2, 4, 5]);
+/// ```
+pub fn traverse_until<T, C, B, S>(initial: T, condition: C, branch_fn: B, mut stop_fn: S) -> bool
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    S: FnMut(&T) -> bool,
+{
+    let mut stopped = false;
+
+    traverse_tree(initial, condition, branch_fn, |node| {
+        if stop_fn(node) {
+            stopped = true;
+            TreeControl::Break
+        } else {
+            TreeControl::Continue
+        }
+    });
+
+    stopped
+}
+
+/// Depth-first traversal that asks `should_descend` before generating a node's children.
+///
+/// `condition` filters individual nodes, while `should_descend` decides whether a
+/// visited node's subtree is worth expanding at all. Because it runs before
+/// `branch_fn`, an expensive subtree that would be discarded anyway is never
+/// generated. For each node the calls happen in this order:
+///
+/// 1. `visit_fn` is called with the node
+/// 2. `should_descend` is called, unless the visitor returned `Prune` or `Break`
+/// 3. `branch_fn` is called, unless `should_descend` returned `false`
+/// 4. `condition` is called for each child, as it is reached
+///
+/// Returning `false` from `should_descend` has the same effect as the visitor
+/// returning `TreeControl::Prune`, except that a node whose visitor returned
+/// `TreeControl::Defer` is still visited a second time. Inside a [`for_tree!`]
+/// body the same decision can be made with [`prune_if!`].
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `should_descend` - A function that determines whether a visited node's children should be generated
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use std::cell::Cell;
+/// use arboriter::{traverse_tree_descend_if, TreeControl};
+///
+/// let mut visited = Vec::new();
+/// let expanded = Cell::new(0);
+///
+/// // Multiples of 3 are visited, but their children are never generated
+/// traverse_tree_descend_if(
+///     1,
+///     |n| *n < 16,
+///     |n| n % 3 != 0,
+///     |n| {
+///         expanded.set(expanded.get() + 1);
+///         vec![n * 2, n * 2 + 1]
+///     },
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec![1, 2, 4, 8, 9, 5, 10, 11, 3]);
+/// assert_eq!(expanded.get(), 7);
+/// ```
+pub fn traverse_tree_descend_if<T, C, D, B, F>(
+    initial: T,
+    mut condition: C,
+    mut should_descend: D,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    D: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    // Asked only once the visitor has decided to descend, so a node that isn't
+    // descended into still gets the second visit it may have deferred
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            if should_descend(node) {
+                branch_fn(node)
+            } else {
+                Vec::new()
+            }
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal with separate filters for visiting a node and for descending into it.
+///
+/// The `condition` of [`traverse_tree`] makes one decision for both: a node it
+/// rejects is neither visited nor descended into, while its siblings are not
+/// affected. This function splits that decision in two, so that, for example,
+/// a node can be passed through without being visited:
+///
+/// | `visit_filter` | `descend_filter` | Effect on the node                                |
+/// |----------------|------------------|---------------------------------------------------|
+/// | `true`         | `true`           | Visited, then its children are traversed          |
+/// | `true`         | `false`          | Visited, but its children are skipped             |
+/// | `false`        | `true`           | Not visited, but its children are still traversed |
+/// | `false`        | `false`          | Skipped along with its whole subtree              |
+///
+/// Siblings are never affected by either filter. For each node, `visit_filter`
+/// is called first, then `visit_fn` if the node is visited, then
+/// `descend_filter` unless the visitor returned `Prune` or `Break`, and
+/// `branch_fn` last. Control flow from `visit_fn` works as in [`traverse_tree`],
+/// except that a `Continue` can't override a `descend_filter` of `false`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `visit_filter` - A function that determines whether a node is passed to `visit_fn`
+/// * `descend_filter` - A function that determines whether a node's children are traversed
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_filtered, TreeControl};
+///
+/// // Visit only the leaves of the implicit binary tree 1..16, and don't
+/// // descend past 6
+/// let mut visited = Vec::new();
+///
+/// traverse_tree_filtered(
+///     1,
+///     |n| *n >= 8,
+///     |n| *n < 8 && *n != 6,
+///     |n| vec![n * 2, n * 2 + 1],
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec![8, 9, 10, 11, 14, 15]);
+/// ```
+pub fn traverse_tree_filtered<T, V, D, B, F>(
+    initial: T,
+    mut visit_filter: V,
+    mut descend_filter: D,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    V: FnMut(&T) -> bool,
+    D: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |_, _| true,
+        |node, _| {
+            if descend_filter(node) {
+                branch_fn(node)
+            } else {
+                Vec::new()
+            }
+        },
+        |node, _| {
+            if visit_filter(node) {
+                visit_fn(node)
+            } else {
+                TreeControl::Continue
+            }
+        },
+    );
+}
+
+/// Statistics about a completed traversal, returned by [`traverse_tree_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraversalStats {
+    /// Number of nodes passed to the visitor. Each node counts once, however
+    /// often it is visited: neither the repeated visits from `Repeat` nor the
+    /// second visit of a node that returned `Defer` add to it. A node entered
+    /// again after a `RestartSubtree` counts once per entry.
+    pub visited: usize,
+    /// Number of nodes whose children were skipped because the visitor returned
+    /// `Prune` or `PruneSiblings`
+    pub pruned: usize,
+    /// Depth of the deepest visited node, with the root at depth 0
+    pub max_depth: usize,
+    /// Whether the visitor stopped the traversal by returning `Break` or
+    /// `BreakAfterSubtree`
+    pub broke_early: bool,
+    /// Whether `condition` rejected the root, in which case nothing was
+    /// visited at all
+    pub root_rejected: bool,
+}
+
+/// Depth-first traversal that also gathers [`TraversalStats`].
+///
+/// The traversal itself behaves exactly like [`traverse_tree`]. The statistics are
+/// useful for profiling branch functions and conditions, for example to see how
+/// much of a generated tree is actually explored. Where `traverse_tree` silently
+/// does nothing if `condition` rejects the root, the statistics record it in
+/// `root_rejected`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_stats, TraversalStats, TreeControl};
+///
+/// let stats = traverse_tree_stats(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     if *n == 2 {
+///         TreeControl::Prune
+///     } else {
+///         TreeControl::Continue
+///     }
+/// });
+///
+/// assert_eq!(
+///     stats,
+///     TraversalStats { visited: 9, pruned: 1, max_depth: 3, broke_early: false, root_rejected: false }
+/// );
+///
+/// let stats = traverse_tree_stats(16, |n| *n < 16, |n| vec![*n * 2], |_| TreeControl::Continue);
+/// assert!(stats.root_rejected);
+/// ```
+pub fn traverse_tree_stats<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) -> TraversalStats
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut stats = TraversalStats::default();
+    let mut root_rejected = false;
+    let mut repeating = false;
+
+    walk_deferred(
+        initial,
+        |node, depth| {
+            let accepted = condition(node);
+            // Only the root is checked at depth 0
+            if depth == 0 && !accepted {
+                root_rejected = true;
+            }
+            accepted
+        },
+        |node, _| branch_fn(node),
+        |event, depth| {
+            let control = match event {
+                Event::Enter(node) => {
+                    if !repeating {
+                        stats.visited += 1;
+                        stats.max_depth = stats.max_depth.max(depth);
+                    }
+                    let control = visit_fn(node);
+                    repeating = control == TreeControl::Repeat;
+                    control
+                }
+                // The second visit of a deferred node can only stop the traversal
+                Event::Leave(node) => match visit_fn(node) {
+                    TreeControl::Break | TreeControl::BreakAfterSubtree => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                },
+            };
+
+            match control {
+                TreeControl::Prune | TreeControl::PruneSiblings => stats.pruned += 1,
+                TreeControl::Break | TreeControl::BreakAfterSubtree => stats.broke_early = true,
+                TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::Repeat
+                | TreeControl::RestartSubtree => {}
+            }
+            control
+        },
+    );
+
+    stats.root_rejected = root_rejected;
+    stats
+}
+
+/// Depth-first traversal that only visits leaves.
+///
+/// The whole tree is still descended, but `visit_fn` is only called for nodes
+/// whose `branch_fn` returns an empty vector, in depth-first order. This makes a
+/// leaf-only intent explicit, for example processing only the files in a directory
+/// tree. A node with children is not a leaf even if `condition` rejects all of
+/// them, and an empty directory is a leaf just like a file.
+///
+/// `branch_fn` is called once per node, before the node is known to be a leaf.
+/// Since leaves have no children, `TreeControl::Continue` and `TreeControl::Prune`
+/// from the visitor behave the same; `TreeControl::Break` and
+/// `TreeControl::BreakAfterSubtree` stop the traversal, and
+/// `TreeControl::PruneSiblings` skips the leaf's remaining siblings.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each leaf, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_leaves, TreeControl};
+///
+/// let mut leaves = Vec::new();
+///
+/// traverse_leaves(1, |_| true, |n| if *n < 4 { vec![*n * 2, *n * 2 + 1] } else { vec![] }, |n| {
+///     leaves.push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(leaves, vec![4, 5, 6, 7]);
+/// ```
+pub fn traverse_leaves<T, C, B, F>(initial: T, mut condition: C, branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    // Children generated while checking whether a node is a leaf, handed back to
+    // the traversal when it asks for them right after the visit
+    let pending = Cell::new(Vec::new());
+    // Called from both closures, which are never running at the same time
+    let branch_fn = RefCell::new(branch_fn);
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            // Only empty when regenerating a parent's children after a restart
+            let children = pending.take();
+            if children.is_empty() {
+                (branch_fn.borrow_mut())(node)
+            } else {
+                children
+            }
+        },
+        |node, _| {
+            let children = (branch_fn.borrow_mut())(node);
+            if !children.is_empty() {
+                pending.set(children);
+                return TreeControl::Continue;
+            }
+
+            loop {
+                match visit_fn(node) {
+                    TreeControl::Repeat => continue,
+                    // A leaf's subtree is done as soon as it is visited
+                    TreeControl::Break | TreeControl::BreakAfterSubtree => {
+                        return TreeControl::Break;
+                    }
+                    TreeControl::Continue
+                    | TreeControl::ContinueWithout(_)
+                    | TreeControl::Defer
+                    | TreeControl::PruneAfter(_)
+                    | TreeControl::Prune => return TreeControl::Prune,
+                    control @ (TreeControl::RestartSubtree | TreeControl::PruneSiblings) => {
+                        return control;
+                    }
+                }
+            }
+        },
+    );
+}
+
+/// Traversal that always visits the highest-priority node found so far.
+///
+/// Instead of following depth-first or breadth-first order, every accepted child
+/// joins a frontier, and the node visited next is the one in the frontier with the
+/// largest `priority_fn` value. Nodes with equal priority are visited in the order
+/// they were discovered. This is the usual strategy for search problems where the
+/// most promising candidate should be expanded first.
+///
+/// The frontier is a [`BinaryHeap`](alloc::collections::BinaryHeap), so
+/// `priority_fn` is called once per accepted node, when it is discovered. For
+/// "lowest cost first", wrap the priority in [`core::cmp::Reverse`].
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `priority_fn` - A function that returns the priority of a node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Control Flow
+///
+/// * `TreeControl::Continue` - Add this node's accepted children to the frontier
+/// * `TreeControl::Prune` - Drop this node's children from consideration
+/// * `TreeControl::Break` - Stop the traversal
+/// * `TreeControl::Repeat` - Visit this node again
+/// * `TreeControl::RestartSubtree` - Treated like `Prune`, since siblings are
+///   already in the frontier
+/// * `TreeControl::Defer` - Treated like `Continue`, since the node's children
+///   are not visited right after it
+/// * `TreeControl::PruneSiblings` - Treated like `Prune`, since siblings are
+///   already in the frontier
+/// * `TreeControl::PruneAfter(n)` - Add this node's accepted children to the
+///   frontier, but never expand the nodes `n` levels below this one
+/// * `TreeControl::BreakAfterSubtree` - Treated like `Break`, since the node's
+///   children are not visited right after it
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_best_first, TreeControl};
+///
+/// // Always expand the largest number discovered so far
+/// let mut visited = Vec::new();
+///
+/// traverse_best_first(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], |n| *n, |n| {
+///     visited.push(*n);
+///     if *n == 6 {
+///         TreeControl::Break
+///     } else {
+///         TreeControl::Continue
+///     }
+/// });
+///
+/// assert_eq!(visited, vec![1, 3, 7, 15, 14, 6]);
+/// ```
+pub fn traverse_best_first<T, C, B, F, P, PF>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut priority_fn: PF,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    P: Ord,
+    PF: FnMut(&T) -> P,
+{
+    /// A frontier entry, ordered by priority and then by discovery order
+    struct Entry<T, P> {
+        priority: P,
+        discovered: usize,
+        node: T,
+        depth: usize,
+        /// The depth limit set by the node's ancestors
+        depth_limit: usize,
+    }
+
+    impl<T, P: Ord> Ord for Entry<T, P> {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.priority
+                .cmp(&other.priority)
+                .then_with(|| other.discovered.cmp(&self.discovered))
+        }
+    }
+
+    impl<T, P: Ord> PartialOrd for Entry<T, P> {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl<T, P: Ord> PartialEq for Entry<T, P> {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl<T, P: Ord> Eq for Entry<T, P> {}
+
+    if !condition(&initial) {
+        return;
+    }
+
+    let mut frontier = BinaryHeap::new();
+    let mut discovered = 0;
+    frontier.push(Entry {
+        priority: priority_fn(&initial),
+        discovered,
+        node: initial,
+        depth: 0,
+        depth_limit: usize::MAX,
+    });
+
+    while let Some(Entry {
+        node,
+        depth,
+        depth_limit,
+        ..
+    }) = frontier.pop()
+    {
+        loop {
+            match visit_fn(&node) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break | TreeControl::BreakAfterSubtree => return,
+                TreeControl::Prune | TreeControl::PruneSiblings | TreeControl::RestartSubtree => {
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)) => {
+                    let mut children =
+                        Pending::descend(control, depth, depth_limit, || branch_fn(&node));
+                    while let Some(child) = children.next() {
+                        if condition(&child) {
+                            discovered += 1;
+                            frontier.push(Entry {
+                                priority: priority_fn(&child),
+                                discovered,
+                                node: child,
+                                depth: depth + 1,
+                                depth_limit: children.depth_limit(),
+                            });
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Children added by the visitor of [`traverse_tree_dynamic`] for the current node.
+///
+/// By default, the children a node is expanded into are the ones `branch_fn`
+/// returns, followed by any added with [`add`](Branches::add). Calling
+/// [`set`](Branches::set) replaces them all instead, and `branch_fn` is then not
+/// called for the node. [`retain`](Branches::retain) narrows down whichever
+/// children that leaves.
+pub struct Branches<T> {
+    added: Vec<T>,
+    replace: bool,
+    keep: Vec<KeepFn<T>>,
+}
+
+type KeepFn<T> = Box<dyn FnMut(&T) -> bool>;
+
+impl<T> Branches<T> {
+    /// Adds a child to be visited after the ones from `branch_fn`.
+    pub fn add(&mut self, branch: T) {
+        self.added.push(branch);
+    }
+
+    /// Replaces every child of the current node, including the ones from
+    /// `branch_fn` and any added earlier.
+    pub fn set(&mut self, branches: Vec<T>) {
+        self.added = branches;
+        self.replace = true;
+    }
+
+    /// Only descends into the children of the current node for which `keep`
+    /// returns `true`.
+    ///
+    /// The filter applies to all of the node's children, whether they come
+    /// from `branch_fn`, [`add`](Branches::add) or [`set`](Branches::set), and
+    /// works like a `condition` for this node alone: the children it rejects
+    /// are not visited, and neither are their subtrees. Calling `retain` more
+    /// than once keeps only the children that pass every filter. The children
+    /// are only generated after the visit, so `keep` is stored until then and
+    /// can't borrow from the visitor's surroundings; values it needs are
+    /// moved into it.
+    pub fn retain<P>(&mut self, keep: P)
+    where
+        P: FnMut(&T) -> bool + 'static,
+    {
+        self.keep.push(Box::new(keep));
+    }
+}
+
+impl<T> Default for Branches<T> {
+    fn default() -> Self {
+        Branches {
+            added: Vec::new(),
+            replace: false,
+            keep: Vec::new(),
+        }
+    }
+}
+
+/// Depth-first traversal whose visitor can add to or replace the children of the
+/// node it is visiting.
+///
+/// The visitor receives a [`Branches`] buffer along with each node. Children added
+/// to it are visited after the ones `branch_fn` returns, which supports trees that
+/// grow while they are walked, such as pathfinding where neighbours are only
+/// discovered during a visit. `branch_fn` is only called once the visitor has
+/// returned `TreeControl::Continue`, and not at all if the visitor replaced the
+/// children with [`Branches::set`].
+///
+/// The visitor can also narrow down the children with [`Branches::retain`], so a
+/// single visitor both processes a node and decides which of its children to
+/// descend into, based on what it found at the node.
+///
+/// Children added during a visit that returns `Prune` or `Break` are discarded,
+/// and so are those added to a node at the depth cap of a `PruneAfter`, whose
+/// children are never generated. Across `Repeat` visits of the same node they
+/// accumulate, and so do filters.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each visited node and its branch buffer,
+///   returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_dynamic, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// traverse_tree_dynamic(1, |n| *n < 8, |n| vec![*n * 2], |n, branches| {
+///     visited.push(*n);
+///     if *n == 2 {
+///         branches.add(7);
+///     }
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(visited, vec![1, 2, 4, 7]);
+/// ```
+pub fn traverse_tree_dynamic<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T, &mut Branches<T>) -> TreeControl,
+{
+    // The current node's buffer, kept across repeated visits and handed to the
+    // traversal when it asks for the node's children right after the visit
+    let pending = Cell::new(Branches::default());
+    // Whether the last visit returned `Repeat`, so the next one is of the same node
+    let repeating = Cell::new(false);
+
+    walk_deferred(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let Branches {
+                mut added,
+                replace,
+                mut keep,
+            } = pending.take();
+
+            let mut branches = if replace {
+                added
+            } else {
+                let mut branches = branch_fn(node);
+                branches.append(&mut added);
+                branches
+            };
+            if !keep.is_empty() {
+                branches.retain(|child| keep.iter_mut().all(|keep| keep(child)));
+            }
+            branches
+        },
+        |event, _| match event {
+            Event::Enter(node) => {
+                // A node at a depth cap is never expanded, so its buffer may
+                // still be here and must not reach the next node
+                let carried = pending.take();
+                let mut branches = if repeating.get() {
+                    carried
+                } else {
+                    Branches::default()
+                };
+                let control = visit_fn(node, &mut branches);
+                repeating.set(control == TreeControl::Repeat);
+                if matches!(
+                    control,
+                    TreeControl::Continue
+                        | TreeControl::ContinueWithout(_)
+                        | TreeControl::Defer
+                        | TreeControl::PruneAfter(_)
+                        | TreeControl::BreakAfterSubtree
+                        | TreeControl::Repeat
+                ) {
+                    pending.set(branches);
+                }
+                control
+            }
+            // The children are done, so branches added now have nowhere to go
+            Event::Leave(node) => visit_fn(node, &mut Branches::default()),
+        },
+    );
+}
+
+/// Position of a node among its siblings, passed to the visitor by
+/// [`traverse_tree_child_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildInfo {
+    /// Index of the node among its parent's visited children, starting at 0
+    pub index: usize,
+    /// Whether the node is the last of its parent's visited children
+    pub is_last: bool,
+    /// Number of visited children of the node's parent, including the node itself
+    pub sibling_count: usize,
+}
+
+impl ChildInfo {
+    /// The position of the root, which is the only node at its level.
+    pub const ROOT: ChildInfo = ChildInfo {
+        index: 0,
+        is_last: true,
+        sibling_count: 1,
+    };
+}
+
+/// Depth-first traversal that tells the visitor where each node sits among its siblings.
+///
+/// This is the information needed to draw tree connectors such as `├──` and
+/// `└──`, which depend on whether a node is its parent's last child. The root
+/// gets [`ChildInfo::ROOT`].
+///
+/// Siblings are counted after filtering with `condition`, so `condition` is
+/// called for all of a node's children as soon as they are generated, rather
+/// than for each child just before it is visited.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each visited node and its [`ChildInfo`],
+///   returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_child_info, TreeControl};
+///
+/// let mut lines = Vec::new();
+///
+/// traverse_tree_child_info(
+///     1,
+///     |n| *n < 6,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     |n, info| {
+///         let connector = if info.is_last { "└── " } else { "├── " };
+///         lines.push(format!("{}{}", connector, n));
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(lines, vec!["└── 1", "├── 2", "├── 4", "└── 5", "└── 3"]);
+/// ```
+pub fn traverse_tree_child_info<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T, ChildInfo) -> TreeControl,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    // Each node carries its own position, computed when its parent's children
+    // are generated and filtered
+    walk(
+        (initial, ChildInfo::ROOT),
+        |_, _| true,
+        |(node, _), _| {
+            let children: Vec<T> = branch_fn(node)
+                .into_iter()
+                .filter(|child| condition(child))
+                .collect();
+            let sibling_count = children.len();
+
+            children
+                .into_iter()
+                .enumerate()
+                .map(|(index, child)| {
+                    let info = ChildInfo {
+                        index,
+                        is_last: index + 1 == sibling_count,
+                        sibling_count,
+                    };
+                    (child, info)
+                })
+                .collect::<Vec<_>>()
+        },
+        |(node, info), _| visit_fn(node, *info),
+    );
+}
+
+/// Depth-first traversal that numbers the visited nodes in pre-order.
+///
+/// `visit_fn` receives each node together with its index: 0 for the root, then
+/// 1, 2, ... in the order the nodes are actually visited. Nodes skipped by
+/// `condition`, by pruning or after a `TreeControl::Break` never get an index,
+/// so the indices always run from 0 without gaps and can be used directly as
+/// positions in an array or adjacency list. A node keeps its index when it is
+/// visited again, after `TreeControl::Repeat` or for the second visit of
+/// `TreeControl::Defer`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each visited node's index and the node,
+///   returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_enumerated, TreeControl};
+///
+/// let mut numbered = Vec::new();
+///
+/// traverse_enumerated(
+///     1,
+///     |n| *n < 8,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     |index, n| {
+///         numbered.push((index, *n));
+///         TreeControl::from_prune(*n == 2)
+///     },
+/// );
+///
+/// assert_eq!(numbered, vec![(0, 1), (1, 2), (2, 3), (3, 6), (4, 7)]);
+/// ```
+pub fn traverse_enumerated<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(usize, &T) -> TreeControl,
+{
+    let mut next_index = 0;
+    // The indices of the nodes on the current path, by depth
+    let mut path: Vec<usize> = Vec::new();
+    // Whether the next visit is the same node again
+    let mut revisit = false;
+
+    walk_deferred(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, depth| match event {
+            Event::Enter(node) => {
+                if !revisit {
+                    path.truncate(depth);
+                    path.push(next_index);
+                    next_index += 1;
+                }
+                let control = visit_fn(path[depth], node);
+                // Restarting the root's subtree visits the root itself again
+                revisit = control == TreeControl::Repeat
+                    || (control == TreeControl::RestartSubtree && depth == 0);
+                control
+            }
+            Event::Leave(node) => visit_fn(path[depth], node),
+        },
+    );
+}
+
+/// Depth-first traversal of a tree whose edges carry data.
+///
+/// `branch_fn` returns each child paired with the data of the edge leading to
+/// it, such as the character appended in a string generator or the move made
+/// in a game tree, and the visitor receives that edge along with the node.
+/// The root has no incoming edge, so it is visited with `None`. This models
+/// labeled trees and tries without having to store the label in every node.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns the branches of a given node, each with its edge data
+/// * `visit_fn` - A function called with each visited node and the edge that led to it,
+///   returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_edges, TreeControl};
+///
+/// let mut moves = Vec::new();
+///
+/// traverse_tree_edges(
+///     0,
+///     |n| *n <= 3,
+///     |n| vec![('+', n + 1), ('*', n * 3)],
+///     |n, edge| {
+///         moves.push((edge.copied(), *n));
+///         TreeControl::from_prune(*n == 0 && edge.is_some())
+///     },
+/// );
+///
+/// assert_eq!(
+///     moves,
+///     vec![(None, 0), (Some('+'), 1), (Some('+'), 2), (Some('+'), 3), (Some('*'), 3), (Some('*'), 0)]
+/// );
+/// ```
+pub fn traverse_tree_edges<T, E, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<(E, T)>,
+    F: FnMut(&T, Option<&E>) -> TreeControl,
+{
+    walk(
+        (None, initial),
+        |(_, node), _| condition(node),
+        |(_, node), _| {
+            branch_fn(node)
+                .into_iter()
+                .map(|(edge, child)| (Some(edge), child))
+                .collect::<Vec<_>>()
+        },
+        |(edge, node), _| visit_fn(node, edge.as_ref()),
+    );
+}
+
+/// Which of a node's children [`traverse_tree_grouped`] should traverse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SiblingAction {
+    /// Traverse all of the children
+    VisitAll,
+    /// Skip all of the children
+    VisitNone,
+    /// Traverse only the children at these indices, in their original order.
+    /// Indices that are out of range are ignored.
+    VisitOnly(Vec<usize>),
+}
+
+/// Depth-first traversal that decides which children to traverse by looking at all of them at once.
+///
+/// Pruning with [`TreeControl`] decides about one node at a time. Some rules
+/// need to see the whole sibling group instead, such as "if any move wins
+/// outright, don't explore the others". `group_fn` is called with all of a
+/// node's children, as returned by `branch_fn`, before any of them is visited,
+/// and returns a [`SiblingAction`] selecting the ones to traverse. The
+/// selected children then go through `condition` and are visited as usual,
+/// and `TreeControl::ContinueWithout(n)` skips the first `n` of them.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `group_fn` - A function that selects which of a node's children to traverse
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_grouped, SiblingAction, TreeControl};
+///
+/// // Among siblings, a multiple of 10 is a "win": only it is explored further
+/// let mut visited = Vec::new();
+///
+/// traverse_tree_grouped(
+///     1,
+///     |n| *n < 100,
+///     |n| vec![n * 3, n * 5, n * 10],
+///     |children| match children.iter().position(|n| n % 10 == 0) {
+///         Some(win) => SiblingAction::VisitOnly(vec![win]),
+///         None => SiblingAction::VisitAll,
+///     },
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(visited, vec![1, 10, 30, 90]);
+/// ```
+pub fn traverse_tree_grouped<T, C, B, G, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut group_fn: G,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    G: FnMut(&[T]) -> SiblingAction,
+    F: FnMut(&T) -> TreeControl,
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let children = branch_fn(node);
+            match group_fn(&children) {
+                SiblingAction::VisitAll => children,
+                SiblingAction::VisitNone => Vec::new(),
+                SiblingAction::VisitOnly(indices) => children
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| indices.contains(index))
+                    .map(|(_, child)| child)
+                    .collect(),
+            }
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal that also reports every pair of adjacent siblings.
+///
+/// `pair_fn` is called with each consecutive pair of a node's children, as
+/// returned by `branch_fn`, in order and before any of them is visited. This
+/// makes checks between neighbouring siblings, such as whether they are sorted
+/// or overlap, possible without generating the child lists a second time. All
+/// children returned by `branch_fn` are paired, including ones that are then
+/// rejected by `condition`. Nodes whose children are never generated, because
+/// the visitor pruned them, have no pairs reported.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+/// * `pair_fn` - A function that is called with every two adjacent siblings
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_sibling_pairs, TreeControl};
+///
+/// // Ranges that split into sub-ranges; find siblings that are out of order
+/// let mut unordered = Vec::new();
+///
+/// traverse_sibling_pairs(
+///     (0, 8),
+///     |&(start, end)| end - start > 1,
+///     |&(start, end)| {
+///         let middle = (start + end) / 2;
+///         let mut halves = vec![(start, middle), (middle, end)];
+///         if (start, end) == (4, 8) {
+///             halves.reverse();
+///         }
+///         halves
+///     },
+///     |_| TreeControl::Continue,
+///     |a, b| {
+///         if a.0 > b.0 {
+///             unordered.push((*a, *b));
+///         }
+///     },
+/// );
+///
+/// assert_eq!(unordered, vec![((6, 8), (4, 6))]);
+/// ```
+pub fn traverse_sibling_pairs<T, C, B, F, P>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+    mut pair_fn: P,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+    P: FnMut(&T, &T),
+{
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| {
+            let children = branch_fn(node);
+            for pair in children.windows(2) {
+                pair_fn(&pair[0], &pair[1]);
+            }
+            children
+        },
+        |node, _| visit_fn(node),
+    );
+}
+
+/// Depth-first traversal that passes each node's depth to the visitor.
+///
+/// The root is at depth 0, its children at depth 1, and so on. The depth is
+/// maintained by the traversal itself, so it is always correct regardless of
+/// how the tree is shaped or which subtrees are pruned.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each visited node and its depth, returning
+///   control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_depth, TreeControl};
+///
+/// let mut lines = Vec::new();
+///
+/// traverse_tree_depth(
+///     1,
+///     |n| *n < 8,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     |n, depth| {
+///         lines.push(format!("{}{}", "  ".repeat(depth), n));
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(lines, vec!["1", "  2", "    4", "    5", "  3", "    6", "    7"]);
+/// ```
+pub fn traverse_tree_depth<T, C, B, F>(initial: T, mut condition: C, mut branch_fn: B, visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T, usize) -> TreeControl,
+{
+    traverse_tree_ctx(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        visit_fn,
+    );
+}
+
+/// Depth-first traversal where the condition, branch function and visitor all
+/// observe the depth.
+///
+/// The root is at depth 0. `condition` is called with the depth the candidate
+/// node would have if visited, `branch_fn` is called with the depth of the node
+/// whose children are being generated, and `visit_fn` receives the depth of the
+/// node being visited. This makes it possible to stop generating or accepting
+/// nodes past a certain depth without storing the depth inside `T`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function called with a node and its depth that determines whether
+///   the node should be visited
+/// * `branch_fn` - A function called with a node and its depth that returns its branches
+/// * `visit_fn` - A function called with each visited node and its depth, returning
+///   control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_ctx, TreeControl};
+///
+/// let mut strings = Vec::new();
+///
+/// traverse_tree_ctx(
+///     String::new(),
+///     |_, _| true,
+///     // Only branch from the first two levels
+///     |s, depth| {
+///         if depth < 2 {
+///             vec![format!("{}a", s), format!("{}b", s)]
+///         } else {
+///             Vec::new()
+///         }
+///     },
+///     |s, _| {
+///         strings.push(s.clone());
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(strings, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+/// ```
+pub fn traverse_tree_ctx<T, C, B, F>(initial: T, condition: C, branch_fn: B, visit_fn: F)
+where
+    C: FnMut(&T, usize) -> bool,
+    B: FnMut(&T, usize) -> Vec<T>,
+    F: FnMut(&T, usize) -> TreeControl,
+{
+    walk(initial, condition, branch_fn, visit_fn);
+}
+
+/// Depth-first traversal that passes the path from the root to each node.
+///
+/// The visitor receives a slice whose last element is the current node and whose
+/// earlier elements are its ancestors, starting with the root. The slice is backed
+/// by a single vector that grows and shrinks as the traversal descends and ascends,
+/// so no allocation is needed per node once the deepest path has been reached.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with the path to each visited node, returning
+///   control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_path, TreeControl};
+///
+/// let mut paths = Vec::new();
+///
+/// traverse_tree_path(
+///     1,
+///     |n| *n < 6,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     |path| {
+///         let names: Vec<String> = path.iter().map(|n| n.to_string()).collect();
+///         paths.push(names.join("/"));
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(paths, vec!["1", "1/2", "1/2/4", "1/2/5", "1/3"]);
+/// ```
+pub fn traverse_tree_path<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&[T]) -> TreeControl,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    // `path[i]` is the node at depth `i`, and `stack[i]` holds its children
+    // that are still waiting to be visited, and whether it is visited again
+    // once they are done
+    let mut path = vec![initial];
+    let mut stack: Vec<(Pending<vec::IntoIter<T>>, bool)> = Vec::new();
+    let mut root_restarts = 0;
+    // The depth of the node whose subtree ends the traversal once it is done
+    let mut break_after = None;
+
+    loop {
+        loop {
+            match visit_fn(&path) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return,
+                TreeControl::Prune => {
+                    path.pop();
+                    break;
+                }
+                TreeControl::PruneSiblings => {
+                    path.pop();
+                    if let Some((siblings, _)) = stack.last_mut() {
+                        siblings.skip_rest();
+                    }
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => {
+                    if control == TreeControl::BreakAfterSubtree {
+                        break_after = Some(stack.len());
+                    }
+                    if let Some(node) = path.last() {
+                        let inherited = stack
+                            .last()
+                            .map_or(usize::MAX, |(siblings, _)| siblings.depth_limit());
+                        let children =
+                            Pending::descend(control, stack.len(), inherited, || branch_fn(node));
+                        stack.push((children, control == TreeControl::Defer));
+                    }
+                    break;
+                }
+                TreeControl::RestartSubtree => {
+                    if stack.is_empty() && root_restarts < MAX_SUBTREE_RESTARTS {
+                        root_restarts += 1;
+                        continue;
+                    }
+                    path.pop();
+                    if let (Some(parent), Some((children, _))) = (path.last(), stack.last_mut()) {
+                        children.restart(|| branch_fn(parent));
+                    }
+                    break;
+                }
+            }
+        }
+
+        // Move on to the next accepted child of the deepest unfinished node
+        let child = loop {
+            let Some((children, _)) = stack.last_mut() else {
+                return;
+            };
+
+            match children.next() {
+                Some(child) if condition(&child) => break child,
+                Some(_) => {}
+                None => {
+                    if let Some((_, true)) = stack.pop()
+                        && matches!(
+                            visit_fn(&path),
+                            TreeControl::Break | TreeControl::BreakAfterSubtree
+                        )
+                    {
+                        return;
+                    }
+                    if break_after == Some(stack.len()) {
+                        return;
+                    }
+                    path.pop();
+                }
+            }
+        };
+        path.push(child);
+    }
+}
+
+/// A visited node together with its ancestors, passed to the visitor by
+/// [`traverse_tree_ancestors`].
+///
+/// The context borrows the traversal's own path, so looking up the parent or
+/// any further ancestor is a constant-time index and copies nothing.
+#[derive(Debug)]
+pub struct NodeCtx<'a, T> {
+    // Never empty: the root first, the current node last
+    path: &'a [T],
+}
+
+// Derived impls would require `T: Clone`
+impl<T> Clone for NodeCtx<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeCtx<'_, T> {}
+
+impl<'a, T> NodeCtx<'a, T> {
+    /// Returns the node being visited.
+    pub fn current(&self) -> &'a T {
+        &self.path[self.path.len() - 1]
+    }
+
+    /// Returns the parent of the node being visited, or `None` for the root.
+    pub fn parent(&self) -> Option<&'a T> {
+        self.ancestor(1)
+    }
+
+    /// Returns the ancestor `n` levels above the node being visited.
+    ///
+    /// `ancestor(0)` is the node itself, `ancestor(1)` its parent, `ancestor(2)`
+    /// its grandparent, and so on. Returns `None` if `n` is greater than the
+    /// node's depth.
+    pub fn ancestor(&self, n: usize) -> Option<&'a T> {
+        self.depth().checked_sub(n).map(|index| &self.path[index])
+    }
+
+    /// Returns the depth of the node being visited; the root is at depth 0.
+    pub fn depth(&self) -> usize {
+        self.path.len() - 1
+    }
+}
+
+/// Depth-first traversal whose visitor can look up the ancestors of each node.
+///
+/// This is [`traverse_tree_path`] behind a [`NodeCtx`]: instead of indexing a
+/// path slice from the end, the visitor asks for the
+/// [`current`](NodeCtx::current) node, its [`parent`](NodeCtx::parent), any
+/// [`ancestor`](NodeCtx::ancestor) by distance, or the [`depth`](NodeCtx::depth).
+/// Ancestors are borrowed from the traversal, never copied.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with the context of each visited node, returning
+///   control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_ancestors, TreeControl};
+///
+/// let mut grandchildren = Vec::new();
+///
+/// traverse_tree_ancestors(
+///     1,
+///     |n| *n < 8,
+///     |n| vec![*n * 2, *n * 2 + 1],
+///     |ctx| {
+///         if let Some(grandparent) = ctx.ancestor(2) {
+///             grandchildren.push((*grandparent, *ctx.current()));
+///         }
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(grandchildren, vec![(1, 4), (1, 5), (1, 6), (1, 7)]);
+/// ```
+pub fn traverse_tree_ancestors<T, C, B, F>(initial: T, condition: C, branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&NodeCtx<'_, T>) -> TreeControl,
+{
+    traverse_tree_path(initial, condition, branch_fn, |path| {
+        visit_fn(&NodeCtx { path })
+    });
+}
+
+/// Depth-first traversal whose visitor receives the parent of each node.
+///
+/// The most common question a visitor asks about its surroundings is which
+/// node it was reached from, for example to compute a path segment relative
+/// to the parent directory. This is [`traverse_tree_ancestors`] narrowed to
+/// that question: `visit_fn` is called with the parent, or `None` for the
+/// root, and the node itself. The parent is borrowed from the traversal's
+/// own path, never copied.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with the parent of each visited node and the
+///   node, returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_with_parent, TreeControl};
+///
+/// let mut edges = Vec::new();
+///
+/// traverse_with_parent(1, |n| *n < 6, |n| vec![*n * 2, *n * 2 + 1], |parent, n| {
+///     edges.push((parent.copied(), *n));
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(edges, vec![(None, 1), (Some(1), 2), (Some(2), 4), (Some(2), 5), (Some(1), 3)]);
+/// ```
+pub fn traverse_with_parent<T, C, B, F>(initial: T, condition: C, branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(Option<&T>, &T) -> TreeControl,
+{
+    traverse_tree_ancestors(initial, condition, branch_fn, |ctx| {
+        visit_fn(ctx.parent(), ctx.current())
+    });
+}
+
+/// An event produced by [`traverse_events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<T> {
+    /// The traversal reached a node, before any of its descendants
+    Enter(T),
+    /// The traversal is done with a node, after all of its descendants
+    Leave(T),
+}
+
+/// Depth-first traversal that reports both entering and leaving each node.
+///
+/// Every visited node produces an `Event::Enter` before any of its descendants and
+/// an `Event::Leave` after all of them, much like SAX parsing of XML. Because both
+/// events go to the same closure, balanced begin/end work such as opening and
+/// closing brackets or indentation can share state without any extra plumbing.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each event, returning control flow instructions
+///
+/// # Control Flow
+///
+/// For `Event::Enter`, the result works like in [`traverse_tree`]:
+/// * `TreeControl::Continue` - Continue into this node's children
+/// * `TreeControl::Prune` - Skip this node's children; its `Event::Leave` still follows immediately
+/// * `TreeControl::Break` - Stop the traversal without any further events
+/// * `TreeControl::Repeat` - Enter this node again
+/// * `TreeControl::Defer` - Treated like `Continue`, since every node is left anyway
+/// * `TreeControl::PruneSiblings` - Skip this node's children and its remaining
+///   siblings; its `Event::Leave` still follows immediately
+/// * `TreeControl::PruneAfter(n)` - Continue into this node's children, but no
+///   further than `n` levels below this node
+/// * `TreeControl::BreakAfterSubtree` - Continue into this node's children, and
+///   stop the traversal right after this node's `Event::Leave`
+///
+/// For `Event::Leave`, `TreeControl::Break` and `TreeControl::BreakAfterSubtree`
+/// stop the traversal before the remaining `Leave` events of the node's
+/// ancestors, and `TreeControl::PruneSiblings` skips the node's remaining
+/// siblings. Any other result has no effect.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_events, Event, TreeControl};
+///
+/// let mut text = String::new();
+///
+/// traverse_events(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |event| {
+///     match event {
+///         Event::Enter(n) => text.push_str(&format!("({}", n)),
+///         Event::Leave(_) => text.push(')'),
+///     }
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(text, "(1(2(4)(5))(3(6)(7)))");
+/// ```
+pub fn traverse_events<T, C, B, F>(initial: T, mut condition: C, mut branch_fn: B, mut visit_fn: F)
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(Event<&T>) -> TreeControl,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    // Each frame keeps its node alongside the children still to be visited, so
+    // the node can be left once the children run out
+    let mut stack: Vec<(T, Pending<vec::IntoIter<T>>)> = Vec::new();
+    let mut node = initial;
+    let mut root_restarts = 0;
+    // The depth of the node whose `Leave` ends the traversal
+    let mut break_after = None;
+
+    loop {
+        loop {
+            match visit_fn(Event::Enter(&node)) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return,
+                control @ (TreeControl::Prune | TreeControl::PruneSiblings) => {
+                    let left = visit_fn(Event::Leave(&node));
+                    if matches!(left, TreeControl::Break | TreeControl::BreakAfterSubtree) {
+                        return;
+                    }
+                    if (control == TreeControl::PruneSiblings || left == TreeControl::PruneSiblings)
+                        && let Some((_, siblings)) = stack.last_mut()
+                    {
+                        siblings.skip_rest();
+                    }
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => {
+                    if control == TreeControl::BreakAfterSubtree {
+                        break_after = Some(stack.len());
+                    }
+                    let inherited = stack
+                        .last()
+                        .map_or(usize::MAX, |(_, siblings)| siblings.depth_limit());
+                    let children =
+                        Pending::descend(control, stack.len(), inherited, || branch_fn(&node));
+                    stack.push((node, children));
+                    break;
+                }
+                TreeControl::RestartSubtree => {
+                    if matches!(
+                        visit_fn(Event::Leave(&node)),
+                        TreeControl::Break | TreeControl::BreakAfterSubtree
+                    ) {
+                        return;
+                    }
+                    match stack.last_mut() {
+                        Some((parent, children)) => children.restart(|| branch_fn(parent)),
+                        None if root_restarts < MAX_SUBTREE_RESTARTS => {
+                            root_restarts += 1;
+                            continue;
+                        }
+                        None => {}
+                    }
+                    break;
+                }
+            }
+        }
+
+        node = loop {
+            let Some((_, children)) = stack.last_mut() else {
+                return;
+            };
+
+            match children.next() {
+                Some(child) if condition(&child) => break child,
+                Some(_) => {}
+                None => {
+                    let (finished, _) = stack.pop().expect("the stack is not empty");
+                    match visit_fn(Event::Leave(&finished)) {
+                        TreeControl::Break | TreeControl::BreakAfterSubtree => return,
+                        _ if break_after == Some(stack.len()) => return,
+                        TreeControl::PruneSiblings => {
+                            if let Some((_, siblings)) = stack.last_mut() {
+                                siblings.skip_rest();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        };
+    }
+}
+
+/// Depth-first traversal that measures how long each node's subtree takes.
+///
+/// Behaves like [`traverse_tree`], and additionally times every visited node
+/// from just before its visit until its last descendant is done, so the
+/// duration covers the node's own `visit_fn` and `branch_fn` calls plus those
+/// of its whole subtree. The timings come back as a tree of the same shape as
+/// the visited part of the input, which makes it easy to find the subtrees
+/// that dominate a traversal.
+///
+/// A node entered more than once through `TreeControl::RestartSubtree` on an
+/// ancestor appears once per entry. If the traversal is stopped with
+/// `TreeControl::Break`, the nodes on the current path are timed up to the
+/// break.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Returns
+///
+/// A tree of `(node, duration)` pairs mirroring the visited nodes, or `None`
+/// if the root doesn't satisfy `condition`.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_timed, TreeControl};
+///
+/// let timings = traverse_tree_timed(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |_| {
+///     TreeControl::Continue
+/// })
+/// .unwrap();
+///
+/// let (root, total) = timings.value;
+/// assert_eq!(root, 1);
+/// assert_eq!(timings.children.len(), 2);
+/// // A subtree never takes longer than its parent's
+/// assert!(timings.children.iter().all(|child| child.value.1 <= total));
+/// ```
+#[cfg(feature = "std")]
+pub fn traverse_tree_timed<T, C, B, F>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut visit_fn: F,
+) -> Option<NAryNode<(T, std::time::Duration)>>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    use std::time::Instant;
+
+    // One frame per node on the current path: the node, when it was entered,
+    // the timings of its finished children, and whether it returned `Defer`
+    let mut stack: Vec<(T, Instant, Vec<_>, bool)> = Vec::new();
+    let mut timed = None;
+    let mut repeating = false;
+
+    let mut close = |stack: &mut Vec<(T, Instant, Vec<_>, bool)>| {
+        if let Some((node, start, children, _)) = stack.pop() {
+            let finished = NAryNode::with_children((node, start.elapsed()), children);
+            match stack.last_mut() {
+                Some((_, _, siblings, _)) => siblings.push(finished),
+                None => timed = Some(finished),
+            }
+        }
+    };
+
+    traverse_events(initial, condition, branch_fn, |event| match event {
+        Event::Enter(node) => {
+            // A repeated visit is part of the same entry
+            if !repeating {
+                stack.push((node.clone(), Instant::now(), Vec::new(), false));
+            }
+            let control = visit_fn(node);
+            repeating = control == TreeControl::Repeat;
+            if let Some((_, _, _, deferred)) = stack.last_mut() {
+                *deferred = control == TreeControl::Defer;
+            }
+            control
+        }
+        Event::Leave(node) => {
+            // The second visit of a deferred node is part of its own time
+            let control = match stack.last() {
+                Some((_, _, _, true)) => visit_fn(node),
+                _ => TreeControl::Continue,
+            };
+            close(&mut stack);
+            match control {
+                TreeControl::Break | TreeControl::BreakAfterSubtree => TreeControl::Break,
+                _ => TreeControl::Continue,
+            }
+        }
+    });
+
+    while !stack.is_empty() {
+        close(&mut stack);
+    }
+
+    timed
+}
+
+/// Depth-first traversal where the visitor can ask to see a node again after its subtree.
+///
+/// Every visited node is first passed to `visit_fn` as an `Event::Enter`. If
+/// the visitor returns `TreeControl::Defer` for it, the node's children are
+/// traversed and the node is then passed again as an `Event::Leave`, before
+/// any of its later siblings. Nodes that don't ask for it get no `Leave`, which
+/// sets this apart from [`traverse_events`]: only the nodes that need
+/// post-order work pay for it.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called with each event, returning control flow instructions
+///
+/// # Control Flow
+///
+/// For `Event::Enter`, the result works like in [`traverse_tree`]. For
+/// `Event::Leave`, only `TreeControl::Break` and `TreeControl::BreakAfterSubtree`
+/// have an effect, both stopping the traversal.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_tree_deferred, Event, TreeControl};
+///
+/// // Sum up the subtrees of the inner nodes
+/// let mut sums = vec![0];
+/// let mut totals = Vec::new();
+///
+/// traverse_tree_deferred(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], |event| match event {
+///     Event::Enter(n) if *n < 4 => {
+///         sums.push(0);
+///         TreeControl::Defer
+///     }
+///     Event::Enter(n) => {
+///         *sums.last_mut().unwrap() += n;
+///         TreeControl::Continue
+///     }
+///     Event::Leave(n) => {
+///         let total = sums.pop().unwrap() + n;
+///         *sums.last_mut().unwrap() += total;
+///         totals.push((*n, total));
+///         TreeControl::Continue
+///     }
+/// });
+///
+/// assert_eq!(totals, vec![(2, 11), (3, 16), (1, 28)]);
+/// ```
+pub fn traverse_tree_deferred<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(Event<&T>) -> TreeControl,
+{
+    walk_deferred(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |event, _| visit_fn(event),
+    );
+}
+
+/// Depth-first traversal of a graph that fails if it finds a cycle.
+///
+/// Behaves like [`traverse_tree`], but before visiting a node it checks whether
+/// the same node is already on the path from the root, using `PartialEq`. If so,
+/// `branch_fn` produced a back-edge, and instead of looping forever or silently
+/// skipping it, the traversal stops and reports the cycle. This is useful for
+/// validating that a structure which is supposed to be acyclic really is a DAG.
+///
+/// Only ancestors are checked, so a node reachable along several paths (a shared
+/// descendant in a DAG) is not a cycle, and is visited once per path.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Returns
+///
+/// `Ok(())` if the traversal finished or was stopped with `TreeControl::Break`, or
+/// `Err(cycle)` where `cycle` is the path from the repeated node back to itself,
+/// so its first and last elements are equal.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_graph_checked, TreeControl};
+///
+/// // 0 -> 1 -> 2 -> 3 -> 1
+/// let edges = [vec![1], vec![2], vec![3], vec![1]];
+///
+/// let result = traverse_graph_checked(0, |_| true, |n| edges[*n].clone(), |_| {
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(result, Err(vec![1, 2, 3, 1]));
+/// ```
+pub fn traverse_graph_checked<T, C, B, F>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut visit_fn: F,
+) -> Result<(), Vec<T>>
+where
+    T: Clone + PartialEq,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut cycle = None;
+
+    traverse_tree_path(initial, condition, branch_fn, |path| {
+        // A node already on the path above itself closes a cycle
+        let (node, ancestors) = path.split_last().expect("the path is never empty");
+        if let Some(start) = ancestors.iter().position(|ancestor| ancestor == node) {
+            cycle = Some(path[start..].to_vec());
+            return TreeControl::Break;
+        }
+        visit_fn(node)
+    });
+
+    cycle.map_or(Ok(()), Err)
+}
+
+/// Walks two trees in lockstep, pairing up nodes at the same position.
+///
+/// Both trees are traversed together in depth-first order. The `i`-th child of a
+/// node in the first tree is paired with the `i`-th child of the corresponding
+/// node in the second tree. Where one tree has run out of children, its side of
+/// the pair is `None` and the traversal carries on into the other tree alone, so
+/// every node of both trees is visited exactly once, whatever their shapes.
+///
+/// This is useful for diffing two trees or comparing a generated tree against an
+/// expected one. Children are paired purely by position, so a branch function that
+/// skips missing children (like [`BinaryNode::children`]) pairs a lone right child
+/// with the other tree's left child.
+///
+/// # Parameters
+///
+/// * `root_a` - The root of the first tree
+/// * `root_b` - The root of the second tree
+/// * `branch_a` - A function that returns the children of a node in the first tree
+/// * `branch_b` - A function that returns the children of a node in the second tree
+/// * `visit_fn` - A function called with each pair of nodes, returning control flow
+///   instructions. `Prune` skips the children on both sides.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{zip_trees, TreeControl};
+///
+/// // Two "trees" of numbers whose children are their divisors below 10 and 5
+/// let mut pairs = Vec::new();
+///
+/// zip_trees(
+///     12,
+///     8,
+///     |n| (2..10).filter(|d| n % d == 0 && *d < *n).collect(),
+///     |n| (2..5).filter(|d| n % d == 0 && *d < *n).collect(),
+///     |a, b| {
+///         pairs.push((a.copied(), b.copied()));
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(
+///     pairs,
+///     vec![
+///         (Some(12), Some(8)),
+///         (Some(2), Some(2)),
+///         (Some(3), Some(4)),
+///         (None, Some(2)),
+///         (Some(4), None),
+///         (Some(2), None),
+///         (Some(6), None),
+///         (Some(2), None),
+///         (Some(3), None),
+///     ]
+/// );
+/// ```
+pub fn zip_trees<T, U, B1, B2, F>(
+    root_a: T,
+    root_b: U,
+    mut branch_a: B1,
+    mut branch_b: B2,
+    mut visit_fn: F,
+) where
+    B1: FnMut(&T) -> Vec<T>,
+    B2: FnMut(&U) -> Vec<U>,
+    F: FnMut(Option<&T>, Option<&U>) -> TreeControl,
+{
+    walk(
+        (Some(root_a), Some(root_b)),
+        |_, _| true,
+        |(a, b), _| {
+            let mut children_a = a.as_ref().map_or_else(Vec::new, &mut branch_a).into_iter();
+            let mut children_b = b.as_ref().map_or_else(Vec::new, &mut branch_b).into_iter();
+            let len = children_a.len().max(children_b.len());
+
+            (0..len)
+                .map(|_| (children_a.next(), children_b.next()))
+                .collect::<Vec<_>>()
+        },
+        |(a, b), _| visit_fn(a.as_ref(), b.as_ref()),
+    );
+}
+
+/// A node of a tree whose children can be of different types.
+///
+/// This is the object-safe counterpart of the generic traversal functions, for
+/// trees that store their children as trait objects such as `Box<dyn Component>`.
+/// Implement it for every node type and traverse the tree with [`traverse_dyn`].
+///
+/// `Any` is a supertrait so that a visitor can recover the concrete type of a
+/// node with `(node as &dyn Any).downcast_ref::<MyNode>()`.
+///
+/// # Example
+///
+/// ```
+/// use std::any::Any;
+/// use arboriter::{traverse_dyn, Tree, TreeControl};
+///
+/// struct Panel {
+///     children: Vec<Box<dyn Tree>>,
+/// }
+///
+/// struct Label(&'static str);
+///
+/// impl Tree for Panel {
+///     fn children(&self) -> Vec<&dyn Tree> {
+///         self.children.iter().map(|child| child.as_ref()).collect()
+///     }
+/// }
+///
+/// impl Tree for Label {
+///     fn children(&self) -> Vec<&dyn Tree> {
+///         Vec::new()
+///     }
+/// }
+///
+/// let root = Panel {
+///     children: vec![
+///         Box::new(Label("title")),
+///         Box::new(Panel { children: vec![Box::new(Label("body"))] }),
+///     ],
+/// };
+///
+/// let mut labels = Vec::new();
+/// traverse_dyn(&root, &mut |node| {
+///     if let Some(Label(text)) = (node as &dyn Any).downcast_ref::<Label>() {
+///         labels.push(*text);
+///     }
+///     TreeControl::Continue
+/// });
+///
+/// assert_eq!(labels, vec!["title", "body"]);
+/// ```
+pub trait Tree: Any {
+    /// Returns this node's children, in traversal order.
+    fn children(&self) -> Vec<&dyn Tree>;
+}
+
+/// Depth-first traversal of a tree of [`Tree`] trait objects.
+///
+/// This works like [`traverse_tree`] with [`Tree::children`] as the branch
+/// function, but takes the visitor as a trait object as well, so a single
+/// compiled copy serves every tree.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `visit_fn` - A function that is called for each node, returning control flow instructions
+pub fn traverse_dyn(root: &dyn Tree, visit_fn: &mut dyn FnMut(&dyn Tree) -> TreeControl) {
+    walk(
+        root,
+        |_, _| true,
+        |node, _| node.children(),
+        |node, _| visit_fn(*node),
+    );
+}
+
+/// Collects the nodes of a tree level by level.
+///
+/// The outer vector is indexed by depth, with the root alone at index 0, and each
+/// inner vector holds the nodes at that depth from left to right. Unlike a flat
+/// breadth-first stream, the level boundaries are preserved, which is what tree
+/// visualization and per-level processing need.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be included;
+///   excluded nodes are not expanded either
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Returns
+///
+/// One vector of nodes per level, or an empty vector if the root is excluded.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::level_order;
+///
+/// let levels = level_order(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1]);
+///
+/// assert_eq!(levels, vec![vec![1], vec![2, 3], vec![4, 5, 6, 7]]);
+/// ```
+pub fn level_order<T, C, B>(initial: T, mut condition: C, mut branch_fn: B) -> Vec<Vec<T>>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut levels = Vec::new();
+    if !condition(&initial) {
+        return levels;
+    }
+
+    let mut level = vec![initial];
+    while !level.is_empty() {
+        let mut next = Vec::new();
+        for node in &level {
+            next.extend(branch_fn(node).into_iter().filter(|child| condition(child)));
+        }
+        levels.push(level);
+        level = next;
+    }
+
+    levels
+}
+
+/// Breadth-first traversal that hands each complete level to `level_fn`.
+///
+/// This is the streaming form of [`level_order`]: `level_fn` is called once per
+/// level with its depth and its nodes, from left to right, and only the current
+/// level and the one being built are kept in memory, instead of the whole tree.
+///
+/// The next level is only generated after `level_fn` returns for the current
+/// one. `TreeControl::Break` stops the traversal there, before any children of
+/// the current level are generated. `TreeControl::Prune` and
+/// `TreeControl::PruneSiblings` do the same, since pruning a whole level leaves
+/// nothing below it. `TreeControl::Repeat` calls `level_fn` again with the same
+/// level, and every other variant moves on to the next level.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be included;
+///   excluded nodes are not expanded either
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `level_fn` - A function called with the depth and the nodes of each level,
+///   returning control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_by_level, TreeControl};
+///
+/// let mut sums = Vec::new();
+///
+/// traverse_by_level(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], |depth, level| {
+///     sums.push(level.iter().sum::<i32>());
+///     if depth == 2 {
+///         TreeControl::Break
+///     } else {
+///         TreeControl::Continue
+///     }
+/// });
+///
+/// assert_eq!(sums, vec![1, 2 + 3, 4 + 5 + 6 + 7]);
+/// ```
+pub fn traverse_by_level<T, C, B, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut level_fn: F,
+) where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(usize, &[T]) -> TreeControl,
+{
+    if !condition(&initial) {
+        return;
+    }
+
+    let mut level = vec![initial];
+    let mut depth = 0;
+
+    while !level.is_empty() {
+        loop {
+            match level_fn(depth, &level) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break | TreeControl::Prune | TreeControl::PruneSiblings => return,
+                _ => break,
+            }
+        }
+
+        let mut next = Vec::new();
+        for node in &level {
+            next.extend(branch_fn(node).into_iter().filter(|child| condition(child)));
+        }
+        level = next;
+        depth += 1;
+    }
+}
+
+/// Collects every visited node into a `Vec`, in depth-first order.
+///
+/// This is a shortcut for the common pattern of declaring a vector and
+/// pushing each node into it from inside a [`traverse_tree`] visitor. Every
+/// node that satisfies `condition` (and whose ancestors did too) is included.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::collect_tree;
+///
+/// // Walk a chain 0 -> 1 -> 2 -> 3
+/// let values = collect_tree(0, |n| *n <= 3, |n| vec![*n + 1]);
+///
+/// assert_eq!(values, vec![0, 1, 2, 3]);
+/// ```
+pub fn collect_tree<T, C, B>(initial: T, condition: C, branch_fn: B) -> Vec<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    collect_tree_with(initial, condition, branch_fn, |node| node.clone())
+}
+
+/// Collects a projection of every visited node into a `Vec`, in depth-first order.
+///
+/// Like [`collect_tree`], but each node is passed through `map_fn` before being
+/// stored. This avoids cloning whole nodes when only part of each node is needed,
+/// such as a `value` field.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `map_fn` - A function that turns each visited node into the collected value
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{collect_tree_with, BinaryNode};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3)))
+/// );
+///
+/// let values = collect_tree_with(
+///     &root,
+///     |_| true,
+///     |node| {
+///         let mut children: Vec<&BinaryNode<i32>> = Vec::new();
+///         if let Some(left) = &node.left {
+///             children.push(left.as_ref());
+///         }
+///         if let Some(right) = &node.right {
+///             children.push(right.as_ref());
+///         }
+///         children
+///     },
+///     |node| node.value,
+/// );
+///
+/// assert_eq!(values, vec![1, 2, 3]);
+/// ```
+pub fn collect_tree_with<T, C, B, M, R>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut map_fn: M,
+) -> Vec<R>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    M: FnMut(&T) -> R,
+{
+    let mut collected = Vec::new();
+
+    traverse_tree(initial, condition, branch_fn, |node| {
+        collected.push(map_fn(node));
+        TreeControl::Continue
+    });
+
+    collected
+}
+
+/// Collects every visited node into a `Vec` in depth-first post-order.
+///
+/// Every node comes after all of its descendants, and the subtrees of
+/// siblings come in the order `branch_fn` returned them, which is the order
+/// needed to resolve dependencies or evaluate expression trees. This is
+/// [`collect_tree`] for [`Order::PostOrderDfs`]. Nodes that fail `condition`
+/// are left out along with their subtrees.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::collect_post_order;
+///
+/// // The implicit binary tree 1..8
+/// let nodes = collect_post_order(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1]);
+///
+/// assert_eq!(nodes, vec![4, 5, 2, 6, 7, 3, 1]);
+/// ```
+pub fn collect_post_order<T, C, B>(initial: T, condition: C, branch_fn: B) -> Vec<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut collected = Vec::new();
+
+    traverse_tree_in(Order::PostOrderDfs, initial, condition, branch_fn, |node| {
+        collected.push(node.clone());
+        TreeControl::Continue
+    });
+
+    collected
+}
+
+/// Collects every visited node into a `Vec` in reverse depth-first post-order.
+///
+/// This is [`collect_post_order`] reversed: every node comes before all of its
+/// descendants, and a node's subtrees come in the reverse of the order
+/// `branch_fn` returned them, so the last child's subtree comes first.
+///
+/// Reverse post-order is the standard basis for topological sorting. For a
+/// DAG whose nodes are reachable along several paths, use `condition` to
+/// visit each node only the first time it is reached, for example with a
+/// `HashSet` of seen nodes: the result then lists every node reachable from
+/// `initial` once, each before all of its successors, which is a topological
+/// order. That only holds if the graph has no cycles, which
+/// [`traverse_graph_checked`] can confirm first.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashSet;
+/// use arboriter::collect_rev_post_order;
+///
+/// // Tasks and the tasks that can only run after them, as a DAG:
+/// // 0 -> 1, 0 -> 2, 1 -> 3, 2 -> 3
+/// let after = [vec![1, 2], vec![3], vec![3], vec![]];
+///
+/// let mut seen = HashSet::new();
+/// let order = collect_rev_post_order(0, |n| seen.insert(*n), |n| after[*n].clone());
+///
+/// assert_eq!(order, vec![0, 2, 1, 3]);
+/// ```
+pub fn collect_rev_post_order<T, C, B>(initial: T, condition: C, branch_fn: B) -> Vec<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut collected = collect_post_order(initial, condition, branch_fn);
+    collected.reverse();
+    collected
+}
+
+/// Reduces a tree to a single value by threading an accumulator through a
+/// depth-first traversal.
+///
+/// `fold_fn` receives the current accumulator and a node, and returns the new
+/// accumulator together with a [`TreeControl`] deciding how the traversal
+/// proceeds. Nodes are folded in the same order [`traverse_tree`] visits them.
+/// The accumulator produced by the last visited node is returned, including
+/// when the traversal is stopped early with `TreeControl::Break`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `acc` - The initial accumulator value
+/// * `fold_fn` - A function combining the accumulator with each visited node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{fold_tree, TreeControl};
+///
+/// // Sum the nodes of the implicit binary tree 1..8, skipping 2's subtree
+/// let total = fold_tree(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], 0, |sum, n| {
+///     let control = if *n == 2 { TreeControl::Prune } else { TreeControl::Continue };
+///     (sum + n, control)
+/// });
+///
+/// assert_eq!(total, 1 + 2 + 3 + 6 + 7);
+/// ```
+pub fn fold_tree<T, C, B, A, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    acc: A,
+    mut fold_fn: F,
+) -> A
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(A, &T) -> (A, TreeControl),
+{
+    // The accumulator is moved into `fold_fn` and back out on every visit
+    let mut slot = Some(acc);
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| match slot.take() {
+            Some(acc) => {
+                let (acc, control) = fold_fn(acc, node);
+                slot = Some(acc);
+                control
+            }
+            None => TreeControl::Break,
+        },
+    );
+
+    slot.expect("accumulator is returned to its slot after every visit")
+}
+
+/// Fallible variant of [`fold_tree`] whose fold function can return an error.
+///
+/// `fold_fn` threads the accumulator through the traversal exactly like in
+/// [`fold_tree`], and its [`TreeControl`] can prune or stop the traversal in the
+/// same way. If it returns `Err`, the traversal stops immediately, no further
+/// nodes are folded, and the error is returned. As with
+/// [`Iterator::try_fold`], the accumulator was moved into the failing call,
+/// so it is lost along with the partial result.
+///
+/// The three outcomes are kept apart: `TreeControl::Prune` only skips a
+/// subtree, `TreeControl::Break` stops the traversal but still returns
+/// `Ok` with the accumulator so far, and only `Err` makes the whole fold fail.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `acc` - The initial accumulator value
+/// * `fold_fn` - A function combining the accumulator with each visited node,
+///   or failing
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{try_fold_tree, TreeControl};
+///
+/// // Directory sizes, skipping hidden directories, until one can't be read
+/// let sizes = |name: &str| match name {
+///     "locked" => Err(format!("can't read {}", name)),
+///     _ => Ok(name.len()),
+/// };
+/// let children = |dir: &&str| match *dir {
+///     "home" => vec![".cache", "docs"],
+///     ".cache" => vec!["locked"],
+///     "docs" => vec!["a", "bb"],
+///     _ => vec![],
+/// };
+///
+/// let total: Result<usize, String> = try_fold_tree("home", |_| true, children, 0, |total, dir| {
+///     if dir.starts_with('.') {
+///         return Ok((total, TreeControl::Prune));
+///     }
+///     Ok((total + sizes(dir)?, TreeControl::Continue))
+/// });
+/// assert_eq!(total, Ok(4 + 4 + 1 + 2));
+///
+/// // Without the pruning, the locked directory is reached
+/// let total: Result<usize, String> = try_fold_tree("home", |_| true, children, 0, |total, dir| {
+///     Ok((total + sizes(dir)?, TreeControl::Continue))
+/// });
+/// assert_eq!(total, Err("can't read locked".to_string()));
+/// ```
+pub fn try_fold_tree<T, C, B, A, E, F>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    acc: A,
+    mut fold_fn: F,
+) -> Result<A, E>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(A, &T) -> Result<(A, TreeControl), E>,
+{
+    // Holds the accumulator between visits, or the error once there is one
+    let mut slot = Some(Ok(acc));
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| match slot.take() {
+            Some(Ok(acc)) => match fold_fn(acc, node) {
+                Ok((acc, control)) => {
+                    slot = Some(Ok(acc));
+                    control
+                }
+                Err(e) => {
+                    slot = Some(Err(e));
+                    TreeControl::Break
+                }
+            },
+            other => {
+                slot = other;
+                TreeControl::Break
+            }
+        },
+    );
+
+    slot.expect("accumulator or error is returned to its slot after every visit")
+}
+
+/// Computes a value for every node from the values of its children, bottom-up.
+///
+/// This is the post-order counterpart of [`fold_tree`]: `combine` is called
+/// for each node once all of its children have been reduced, with the node
+/// and its children's results in the order `branch_fn` returned them. Leaves
+/// receive an empty vector. The result for the root is returned. Typical uses
+/// are evaluating expression trees and computing sizes or heights.
+///
+/// The tree is walked with an explicit stack, so deep trees are fine.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `combine` - A function that computes a node's result from the node and its children's results
+///
+/// # Example
+///
+/// ```
+/// use arboriter::reduce_up;
+///
+/// // The number of nodes below each node of the implicit binary tree 1..8
+/// let size = reduce_up(
+///     &1,
+///     |n| if *n < 4 { vec![n * 2, n * 2 + 1] } else { vec![] },
+///     |_, children: Vec<usize>| 1 + children.iter().sum::<usize>(),
+/// );
+///
+/// assert_eq!(size, 7);
+/// ```
+pub fn reduce_up<T, B, F, R>(root: &T, mut branch_fn: B, mut combine: F) -> R
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T, Vec<R>) -> R,
+{
+    // One frame per node on the current path: the node, its children that have
+    // not been reduced yet, and the results of those that have
+    let mut stack = vec![(root.clone(), branch_fn(root).into_iter(), Vec::new())];
+
+    loop {
+        let (_, children, _) = stack.last_mut().expect("the root frame is popped last");
+
+        match children.next() {
+            Some(child) => {
+                let grandchildren = branch_fn(&child).into_iter();
+                stack.push((child, grandchildren, Vec::new()));
+            }
+            None => {
+                let (node, _, results) = stack.pop().expect("the stack is not empty");
+                let result = combine(&node, results);
+
+                match stack.last_mut() {
+                    Some((_, _, parent_results)) => parent_results.push(result),
+                    None => return result,
+                }
+            }
+        }
+    }
+}
+
+/// Visits every node together with the number of nodes in its subtree, itself included.
+///
+/// The size of a subtree is only known once all of it has been seen, so nodes
+/// are visited in post-order: a node's children, and their whole subtrees,
+/// are visited before the node itself, in the order `branch_fn` returned them.
+/// The sizes are accumulated with [`reduce_up`], so each node is generated
+/// once and deep trees are fine.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each node with the size of its subtree
+///
+/// # Example
+///
+/// ```
+/// use arboriter::traverse_with_subtree_size;
+///
+/// // The implicit binary tree 1..8
+/// let mut sizes = Vec::new();
+/// traverse_with_subtree_size(
+///     &1,
+///     |n| if *n < 4 { vec![n * 2, n * 2 + 1] } else { vec![] },
+///     |n, size| sizes.push((*n, size)),
+/// );
+///
+/// assert_eq!(
+///     sizes,
+///     vec![(4, 1), (5, 1), (2, 3), (6, 1), (7, 1), (3, 3), (1, 7)]
+/// );
+/// ```
+pub fn traverse_with_subtree_size<T, B, F>(root: &T, branch_fn: B, mut visit_fn: F)
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T, usize),
+{
+    reduce_up(root, branch_fn, |node, sizes: Vec<usize>| {
+        let size = 1 + sizes.iter().sum::<usize>();
+        visit_fn(node, size);
+        size
+    });
+}
+
+/// Counts the nodes a traversal would visit.
+///
+/// Only nodes that are actually visited are counted, so subtrees filtered out by
+/// `condition` do not contribute to the total.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::count_nodes;
+///
+/// // All strings over {a, b} of length <= 3
+/// let count = count_nodes(
+///     String::new(),
+///     |s| s.len() <= 3,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+/// );
+///
+/// assert_eq!(count, 1 + 2 + 4 + 8);
+/// ```
+pub fn count_nodes<T, C, B>(initial: T, condition: C, branch_fn: B) -> usize
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    fold_tree(initial, condition, branch_fn, 0, |count, _| {
+        (count + 1, TreeControl::Continue)
+    })
+}
+
+/// Returns the height of a tree: the number of edges on its longest root-to-leaf path.
+///
+/// A root without children has height 0. Together with [`count_nodes`] this
+/// gives a quick measure of a tree's shape, for example to decide whether a
+/// recursive algorithm risks overflowing the stack. The tree is walked with an
+/// explicit stack, so measuring a deep tree is always safe.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{count_nodes, tree_height};
+///
+/// // The implicit binary tree 1..16 has four levels
+/// assert_eq!(tree_height(&1, |n| if *n < 8 { vec![n * 2, n * 2 + 1] } else { vec![] }), 3);
+/// assert_eq!(count_nodes(1, |n| *n < 16, |n| vec![n * 2, n * 2 + 1]), 15);
+/// ```
+pub fn tree_height<T, B>(root: &T, mut branch_fn: B) -> usize
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut height = 0;
+
+    walk(
+        root.clone(),
+        |_, _| true,
+        |node, _| branch_fn(node),
+        |_, depth| {
+            height = height.max(depth);
+            TreeControl::Continue
+        },
+    );
+
+    height
+}
+
+/// Counts how many nodes of a tree have each number of children.
+///
+/// The result maps a number of children to the number of nodes with exactly
+/// that many, so leaves are counted under key 0 and the values add up to the
+/// size of the tree. For generated trees this shows at a glance how bushy the
+/// tree is, which [`TraversalStats`] does not record. `branch_fn` is called
+/// once per node.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use arboriter::branching_histogram;
+///
+/// // 1 has two children, 2 has one, and 3 and 4 are leaves
+/// let histogram = branching_histogram(&1, |n| match n {
+///     1 => vec![2, 3],
+///     2 => vec![4],
+///     _ => vec![],
+/// });
+///
+/// assert_eq!(histogram, BTreeMap::from([(0, 2), (1, 1), (2, 1)]));
+/// ```
+pub fn branching_histogram<T, B>(root: &T, mut branch_fn: B) -> BTreeMap<usize, usize>
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut histogram = BTreeMap::new();
+
+    walk(
+        root.clone(),
+        |_, _| true,
+        |node, _| {
+            let children = branch_fn(node);
+            *histogram.entry(children.len()).or_insert(0) += 1;
+            children
+        },
+        |_, _| TreeControl::Continue,
+    );
+
+    histogram
+}
+
+/// Returns the path from the root to every leaf, in depth-first order.
+///
+/// A leaf is a node for which `branch_fn` returns no children. Each path
+/// starts with the root and ends with the leaf, so for a trie or a file
+/// system the paths are exactly the complete words or file paths. A root
+/// without children is a leaf itself, giving a single path of length one.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::leaf_paths;
+///
+/// let paths = leaf_paths(&1, |n| if *n < 4 { vec![n * 2, n * 2 + 1] } else { vec![] });
+///
+/// assert_eq!(paths, vec![vec![1, 2, 4], vec![1, 2, 5], vec![1, 3, 6], vec![1, 3, 7]]);
+/// ```
+pub fn leaf_paths<T, B>(root: &T, branch_fn: B) -> Vec<Vec<T>>
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut paths = Vec::new();
+    let mut path = Vec::new();
+    // Set on entering a node and cleared on leaving one, so it is still set
+    // when a node is left only if no child was entered in between
+    let mut is_leaf = false;
+
+    traverse_events(
+        root.clone(),
+        |_| true,
+        branch_fn,
+        |event| {
+            match event {
+                Event::Enter(node) => {
+                    path.push(node.clone());
+                    is_leaf = true;
+                }
+                Event::Leave(_) => {
+                    if is_leaf {
+                        paths.push(path.clone());
+                    }
+                    is_leaf = false;
+                    path.pop();
+                }
+            }
+            TreeControl::Continue
+        },
+    );
+
+    paths
+}
+
+/// Returns the first node, in depth-first order, for which `predicate` holds.
+///
+/// The traversal stops as soon as a match is found, so no further nodes are
+/// visited and no further branches are generated.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `predicate` - A function that returns `true` for the node being searched for
+///
+/// # Example
+///
+/// ```
+/// use arboriter::find_tree;
+///
+/// // The first string over {a, b} containing "ba"
+/// let found = find_tree(
+///     String::new(),
+///     |s| s.len() <= 3,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     |s| s.contains("ba"),
+/// );
+///
+/// assert_eq!(found, Some("aba".to_string()));
+/// ```
+pub fn find_tree<T, C, B, P>(initial: T, condition: C, branch_fn: B, mut predicate: P) -> Option<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    P: FnMut(&T) -> bool,
+{
+    find_map_tree(initial, condition, branch_fn, |node| {
+        if predicate(node) {
+            Some(node.clone())
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the first `Some` produced by `map_fn`, in depth-first order.
+///
+/// Like [`find_tree`], but `map_fn` both tests and projects each node, which avoids
+/// cloning a whole node when only part of it is needed.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `map_fn` - A function returning `Some` for the node being searched for
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{find_map_tree, BinaryNode};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3)))
+/// );
+///
+/// let doubled = find_map_tree(
+///     &root,
+///     |_| true,
+///     |node| {
+///         let mut children: Vec<&BinaryNode<i32>> = Vec::new();
+///         if let Some(left) = &node.left {
+///             children.push(left.as_ref());
+///         }
+///         if let Some(right) = &node.right {
+///             children.push(right.as_ref());
+///         }
+///         children
+///     },
+///     |node| (node.value > 1).then(|| node.value * 2),
+/// );
+///
+/// assert_eq!(doubled, Some(4));
+/// ```
+pub fn find_map_tree<T, C, B, M, R>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut map_fn: M,
+) -> Option<R>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    M: FnMut(&T) -> Option<R>,
+{
+    let mut found = None;
+
+    traverse_tree(initial, condition, branch_fn, |node| match map_fn(node) {
+        Some(result) => {
+            found = Some(result);
+            TreeControl::Break
+        }
+        None => TreeControl::Continue,
+    });
+
+    found
+}
+
+/// Returns every node, in depth-first order, for which `predicate` holds.
+///
+/// Unlike [`find_tree`], the traversal doesn't stop at the first match: the
+/// whole tree is visited, including the subtrees of matching nodes, so matches
+/// nested inside other matches are returned too. Use [`collect_matching_prune`]
+/// to keep only the topmost matches.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `predicate` - A function that returns `true` for the nodes to collect
+///
+/// # Example
+///
+/// ```
+/// use arboriter::collect_matching;
+///
+/// // All strings over {a, b} up to length 3 that end in "ab"
+/// let found = collect_matching(
+///     String::new(),
+///     |s| s.len() <= 3,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     |s| s.ends_with("ab"),
+/// );
+///
+/// assert_eq!(found, vec!["aab", "ab", "bab"]);
+/// ```
+pub fn collect_matching<T, C, B, P>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut predicate: P,
+) -> Vec<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    P: FnMut(&T) -> bool,
+{
+    let mut matches = Vec::new();
+
+    traverse_tree(initial, condition, branch_fn, |node| {
+        if predicate(node) {
+            matches.push(node.clone());
+        }
+        TreeControl::Continue
+    });
+
+    matches
+}
+
+/// Returns the topmost nodes, in depth-first order, for which `predicate` holds.
+///
+/// Like [`collect_matching`], but the traversal doesn't descend into a matching
+/// node: its subtree is pruned, so no descendant of a match is returned, and
+/// branches below a match are never generated. This finds, for example, the
+/// outermost directories with a given name without also listing the ones nested
+/// inside them.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `predicate` - A function that returns `true` for the nodes to collect
+///
+/// # Example
+///
+/// ```
+/// use arboriter::collect_matching_prune;
+///
+/// // "ab" and everything below it start with "ab", but only "ab" is returned
+/// let found = collect_matching_prune(
+///     String::new(),
+///     |s| s.len() <= 3,
+///     |s| vec![format!("{}a", s), format!("{}b", s)],
+///     |s| s.starts_with("ab") || s.starts_with("ba"),
+/// );
+///
+/// assert_eq!(found, vec!["ab", "ba"]);
+/// ```
+pub fn collect_matching_prune<T, C, B, P>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut predicate: P,
+) -> Vec<T>
+where
+    T: Clone,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    P: FnMut(&T) -> bool,
+{
+    let mut matches = Vec::new();
+
+    traverse_tree(initial, condition, branch_fn, |node| {
+        let matched = predicate(node);
+        if matched {
+            matches.push(node.clone());
+        }
+        TreeControl::from_prune(matched)
+    });
+
+    matches
+}
+
+/// Collects the values produced by a visitor that also steers the traversal.
+///
+/// Each call to `visit_fn` returns a pair: an optional value, which is appended
+/// to the result, and the [`TreeControl`] for that node, with the same meaning
+/// as for [`traverse_tree`]. This fuses the common "push a value, and maybe
+/// prune" pattern into a single return, like a `filter_map` that can also cut
+/// branches. A node visited more than once, because of `Repeat` or `Defer`, can
+/// add a value on every visit.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function returning an optional value to collect and control flow instructions
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{traverse_collect, TreeControl};
+///
+/// // The odd numbers of the tree, without descending below 2
+/// let odd = traverse_collect(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     let value = if *n % 2 == 1 { Some(*n) } else { None };
+///     (value, TreeControl::from_prune(*n == 2))
+/// });
+///
+/// assert_eq!(odd, vec![1, 3, 13, 7, 15]);
+/// ```
+pub fn traverse_collect<T, C, B, F, R>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    mut visit_fn: F,
+) -> Vec<R>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> (Option<R>, TreeControl),
+{
+    let mut values = Vec::new();
+
+    traverse_tree(initial, condition, branch_fn, |node| {
+        let (value, control) = visit_fn(node);
+        values.extend(value);
+        control
+    });
+
+    values
+}
+
+/// Fallible variant of [`traverse_tree`] whose visitor can return an error.
+///
+/// The traversal order and the meaning of each [`TreeControl`] value are the same
+/// as for [`traverse_tree`]. If `visit_fn` returns `Err`, the traversal stops
+/// immediately and the error is returned to the caller; no further nodes are visited.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function called for each visited node, returning control flow
+///   instructions or an error
+///
+/// # Returns
+///
+/// `Ok(())` if the traversal completed (or was stopped with `TreeControl::Break`),
+/// or the first error returned by `visit_fn`.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{try_traverse_tree, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// let result = try_traverse_tree(
+///     0,
+///     |n| *n < 10,
+///     |n| vec![*n + 1],
+///     |n| {
+///         if *n == 3 {
+///             return Err(format!("failed at {}", n));
+///         }
+///         visited.push(*n);
+///         Ok(TreeControl::Continue)
+///     },
+/// );
+///
+/// assert_eq!(result, Err("failed at 3".to_string()));
+/// assert_eq!(visited, vec![0, 1, 2]);
+/// ```
+pub fn try_traverse_tree<T, C, B, F, E>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) -> Result<(), E>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> Result<TreeControl, E>,
+{
+    let mut error = None;
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| match visit_fn(node) {
+            Ok(control) => control,
+            Err(e) => {
+                error = Some(e);
+                TreeControl::Break
+            }
+        },
+    );
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Variant of [`traverse_tree`] whose branch function can return an error.
+///
+/// Use this when enumerating a node's children can fail, for example when
+/// listing a directory that can't be read. The traversal order and the meaning
+/// of each [`TreeControl`] value are the same as for [`traverse_tree`]. If
+/// `branch_fn` returns `Err`, the traversal stops immediately: no further nodes
+/// are passed to `condition` or `visit_fn`, and the error is returned.
+///
+/// Combine it with a visitor that returns errors of its own by recording them
+/// in the visitor and returning `TreeControl::Break`, or use
+/// [`try_traverse_tree`] when only the visitor is fallible.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns the branches of a given node, or an error
+/// * `visit_fn` - A function that is called for each visited node, returning control flow instructions
+///
+/// # Returns
+///
+/// `Ok(())` if the traversal completed (or was stopped with `TreeControl::Break`),
+/// or the first error returned by `branch_fn`.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{try_traverse_branches, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// let result = try_traverse_branches(
+///     1,
+///     |n| *n < 16,
+///     |n| {
+///         if *n == 5 {
+///             Err(format!("can't expand {}", n))
+///         } else {
+///             Ok(vec![n * 2, n * 2 + 1])
+///         }
+///     },
+///     |n| {
+///         visited.push(*n);
+///         TreeControl::Continue
+///     },
+/// );
+///
+/// assert_eq!(result, Err("can't expand 5".to_string()));
+/// assert_eq!(visited, vec![1, 2, 4, 8, 9, 5]);
+/// ```
+pub fn try_traverse_branches<T, C, B, F, E>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) -> Result<(), E>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Result<Vec<T>, E>,
+    F: FnMut(&T) -> TreeControl,
+{
+    let mut error = None;
+    // Checked by the other callbacks, which can't share `error` with `branch_fn`
+    let failed = Cell::new(false);
+
+    walk(
+        initial,
+        |node, _| !failed.get() && condition(node),
+        |node, _| match branch_fn(node) {
+            Ok(children) => children,
+            Err(e) => {
+                error = Some(e);
+                failed.set(true);
+                Vec::new()
+            }
+        },
+        |node, _| {
+            if failed.get() {
+                TreeControl::Break
+            } else {
+                visit_fn(node)
+            }
+        },
+    );
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Renders a tree as text, one node per line, with ASCII connectors.
+///
+/// Each node is printed with its `Debug` representation. Children are found with
+/// `branch_fn` and drawn below their parent with `├──` and `└──` connectors, and a
+/// `│` continues the line of every ancestor that still has siblings to come, so
+/// the vertical bars line up however unbalanced the tree is.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to render
+/// * `branch_fn` - A function that returns the children of a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::format_tree;
+///
+/// let text = format_tree(&1, |n| if *n < 3 { vec![*n * 2, *n * 2 + 1] } else { Vec::new() });
+///
+/// assert_eq!(
+///     text,
+///     "1\n\
+///      ├── 2\n\
+///      │   ├── 4\n\
+///      │   └── 5\n\
+///      └── 3\n"
+/// );
+/// ```
+pub fn format_tree<T, B>(root: &T, mut branch_fn: B) -> String
+where
+    T: Debug,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut text = format!("{:?}\n", root);
+
+    // One frame per node on the current path, holding its children that are
+    // still waiting to be printed. A frame with children left means that level
+    // still needs a vertical bar.
+    let mut stack: Vec<vec::IntoIter<T>> = vec![branch_fn(root).into_iter()];
+
+    while let Some(children) = stack.last_mut() {
+        let Some(child) = children.next() else {
+            stack.pop();
+            continue;
+        };
+        let is_last = children.len() == 0;
+
+        for ancestor in &stack[..stack.len() - 1] {
+            text.push_str(if ancestor.len() == 0 {
+                "    "
+            } else {
+                "│   "
+            });
+        }
+        text.push_str(if is_last { "└── " } else { "├── " });
+        text.push_str(&format!("{:?}\n", child));
+
+        stack.push(branch_fn(&child).into_iter());
+    }
+
+    text
+}
+
+/// Prints a tree to standard output using [`format_tree`].
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to print
+/// * `branch_fn` - A function that returns the children of a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::print_tree;
+///
+/// // Prints:
+/// // "a"
+/// // ├── "ab"
+/// // └── "ac"
+/// print_tree(&"a".to_string(), |s| {
+///     if s.len() < 2 {
+///         vec![format!("{}b", s), format!("{}c", s)]
+///     } else {
+///         Vec::new()
+///     }
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn print_tree<T, B>(root: &T, branch_fn: B)
+where
+    T: Debug,
+    B: FnMut(&T) -> Vec<T>,
+{
+    print!("{}", format_tree(root, branch_fn));
+}
+
+/// Renders a tree in the DOT language of Graphviz.
+///
+/// The result is a `digraph` with one node per tree node, labelled with its
+/// `Display` representation, and an edge from every node to each of its
+/// children. Nodes get generated ids `n0`, `n1`, ... in depth-first order, so
+/// nodes that display the same, such as the two `"a"` leaves below, still show
+/// up as separate nodes. Quotes, backslashes and line breaks in labels are
+/// escaped. Render the output with, for example, `dot -Tsvg`.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to render
+/// * `branch_fn` - A function that returns the children of a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::to_dot;
+///
+/// let dot = to_dot(&"root", |s| if *s == "root" { vec!["a", "a"] } else { vec![] });
+///
+/// assert_eq!(
+///     dot,
+///     "digraph {\n    \
+///          n0 [label=\"root\"];\n    \
+///          n1 [label=\"a\"];\n    \
+///          n0 -> n1;\n    \
+///          n2 [label=\"a\"];\n    \
+///          n0 -> n2;\n\
+///      }\n"
+/// );
+/// ```
+pub fn to_dot<T, B>(root: &T, mut branch_fn: B) -> String
+where
+    T: Display,
+    B: FnMut(&T) -> Vec<T>,
+{
+    fn push_node(dot: &mut String, id: usize, label: &dyn Display) {
+        let label = format!("{}", label);
+        let _ = write!(dot, "    n{} [label=\"", id);
+        for c in label.chars() {
+            match c {
+                '"' => dot.push_str("\\\""),
+                '\\' => dot.push_str("\\\\"),
+                '\n' => dot.push_str("\\n"),
+                '\r' => {}
+                c => dot.push(c),
+            }
+        }
+        dot.push_str("\"];\n");
+    }
+
+    let mut dot = String::from("digraph {\n");
+    push_node(&mut dot, 0, root);
+    let mut next_id = 1;
+
+    // One frame per node on the current path: its id and its children that
+    // are still waiting to be written
+    let mut stack: Vec<(usize, vec::IntoIter<T>)> = vec![(0, branch_fn(root).into_iter())];
+
+    while let Some((parent, children)) = stack.last_mut() {
+        let Some(child) = children.next() else {
+            stack.pop();
+            continue;
+        };
+        let id = next_id;
+        next_id += 1;
+
+        push_node(&mut dot, id, &child);
+        let _ = writeln!(dot, "    n{} -> n{};", parent, id);
+
+        stack.push((id, branch_fn(&child).into_iter()));
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Depth-first traversal that sends a clone of each visited node over a channel.
+///
+/// This decouples producing a tree from consuming it: the traversal runs on one
+/// thread while another thread receives the nodes, in depth-first order, from
+/// the other end of the channel. Once the receiver is dropped there is nobody
+/// left to send to, so the traversal stops as if it had been broken.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `tx` - The sending half of the channel that receives the visited nodes
+///
+/// # Example
+///
+/// ```
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use arboriter::traverse_to_channel;
+///
+/// let (tx, rx) = mpsc::channel();
+///
+/// let producer = thread::spawn(move || {
+///     traverse_to_channel(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], tx);
+/// });
+///
+/// // The channel is closed when the traversal finishes and drops `tx`
+/// let received: Vec<i32> = rx.iter().collect();
+/// producer.join().unwrap();
+///
+/// assert_eq!(received, vec![1, 2, 4, 5, 3, 6, 7]);
+/// ```
+#[cfg(feature = "std")]
+pub fn traverse_to_channel<T, C, B>(
+    initial: T,
+    condition: C,
+    branch_fn: B,
+    tx: std::sync::mpsc::Sender<T>,
+) where
+    T: Clone + Send,
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+{
+    traverse_tree(initial, condition, branch_fn, |node| {
+        match tx.send(node.clone()) {
+            Ok(()) => TreeControl::Continue,
+            // The receiver is gone, so nothing would see the remaining nodes
+            Err(_) => TreeControl::Break,
+        }
+    });
+}
+
+/// Depth-first traversal whose visitor can stop it with a value.
+///
+/// The visitor returns `ControlFlow::Continue(control)` to steer the traversal
+/// with a [`TreeControl`] as usual, or `ControlFlow::Break(value)` to stop it and
+/// hand `value` back to the caller. This is the function behind
+/// [`for_tree_value!`] and `break_tree!(value)`.
+///
+/// # Parameters
+///
+/// * `initial` - The root value to start traversal from
+/// * `condition` - A function that determines whether a node should be visited
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+/// * `visit_fn` - A function that is called for each visited node, returning either
+///   control flow instructions or a value to stop with
+///
+/// # Returns
+///
+/// `Some(value)` if the visitor broke with a value, and `None` otherwise,
+/// including when it returned `ControlFlow::Continue(TreeControl::Break)`.
+///
+/// # Example
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use arboriter::{traverse_tree_value, TreeControl};
+///
+/// let path = traverse_tree_value(String::new(), |s| s.len() <= 3, |s| {
+///     vec![format!("{}a", s), format!("{}b", s)]
+/// }, |s| {
+///     if s.ends_with("ba") {
+///         ControlFlow::Break(s.len())
+///     } else {
+///         ControlFlow::Continue(TreeControl::Continue)
+///     }
+/// });
+///
+/// assert_eq!(path, Some(3)); // "aba"
+/// ```
+pub fn traverse_tree_value<T, C, B, F, V>(
+    initial: T,
+    mut condition: C,
+    mut branch_fn: B,
+    mut visit_fn: F,
+) -> Option<V>
+where
+    C: FnMut(&T) -> bool,
+    B: FnMut(&T) -> Vec<T>,
+    F: FnMut(&T) -> ControlFlow<V, TreeControl>,
+{
+    let mut value = None;
+
+    walk(
+        initial,
+        |node, _| condition(node),
+        |node, _| branch_fn(node),
+        |node, _| match visit_fn(node) {
+            ControlFlow::Continue(control) => control,
+            ControlFlow::Break(v) => {
+                value = Some(v);
+                TreeControl::Break
+            }
+        },
+    );
+
+    value
+}
+
+/// Marks the closures generated by the `for_tree!` family of macros, so that
+/// [`prune!`], [`break_tree!`] and the other body macros only compile inside
+/// them.
+///
+/// `__for_tree_body!(mark)` declares a conversion function at the start of the
+/// generated closure, and `__for_tree_body!(return value)` returns through it.
+/// Outside a body the function does not exist, so the compiler reports it by
+/// name, instead of a confusing type error or a silent `return` from the
+/// enclosing function.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __for_tree_body {
+    (mark) => {
+        #[allow(dead_code)]
+        fn only_valid_inside_a_for_tree_body<C, R: ::core::convert::From<C>>(value: C) -> R {
+            R::from(value)
+        }
+    };
+    (return $value:expr) => {
+        return only_valid_inside_a_for_tree_body($value)
+    };
+}
+
+/// Skips traversing the children of the current node.
+///
+/// This macro is used within a [`for_tree!`] block to prevent traversal
+/// of the current node's children. The traversal will continue with
+/// sibling nodes.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, prune};
+///
+/// // Generate numbers in a simple tree structure, starting with 1
+/// // Each number branches to [n*2, n*2+1]
+/// // We'll prune at even numbers
+/// let mut values = Vec::new();
+///
+/// for_tree!(n in 1; |n| *n < 8; |n| {
+///     // Each node branches to [n*2, n*2+1]
+///     vec![*n * 2, *n * 2 + 1]
+/// } => {
+///     values.push(*n);
+///     
+///     if *n % 2 == 0 {
+///         prune!(); // Don't process children of even numbers
+///     }
+/// });
+///
+/// // With this traversal and pruning, we should see:
+/// // 1 → 2 (prune) → 3 → 6 (prune) → 7
+/// assert_eq!(values, vec![1, 2, 3, 6, 7]);
+/// ```
+#[macro_export]
+macro_rules! prune {
+    () => {
+        $crate::__for_tree_body!(return $crate::TreeControl::Prune);
+    };
+}
+
+/// Breaks out of the entire tree traversal.
+///
+/// This macro is used within a [`for_tree!`] block to immediately stop
+/// the entire traversal, unwinding the traversal stack and returning
+/// control to the point after the [`for_tree!`] macro.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, break_tree};
+///
+/// // Find a specific value in a tree-like structure
+/// let mut found = false;
+/// let target = 7;
+///
+/// for_tree!(n in 0; |n| *n <= 10; |n| vec![*n + 1] => {
+///     println!("Checking {}", n);
+///     
+///     if *n == target {
+///         found = true;
+///         break_tree!(); // Exit the traversal - we found what we're looking for
+///     }
+/// });
+///
+/// assert!(found);
+/// ```
+///
+/// Inside a [`for_tree_value!`] body, `break_tree!(value)` stops the traversal and
+/// makes `value` the result of the whole [`for_tree_value!`] expression.
+#[macro_export]
+macro_rules! break_tree {
+    () => {
+        $crate::__for_tree_body!(return $crate::TreeControl::Break);
+    };
+
+    ($value:expr) => {
+        $crate::__for_tree_body!(return ::core::ops::ControlFlow::Break($value));
+    };
+}
+
+/// Skips traversing the children of the current node if a condition holds.
+///
+/// `prune_if!(cond)` is shorthand for `if cond { prune!(); }`. The condition is
+/// evaluated in the body's own scope, so it can refer to the node binding and to
+/// any local variables, and it may use `?` inside a [`for_tree_try!`] body.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, prune_if};
+///
+/// let mut values = Vec::new();
+///
+/// for_tree!(n in 1; |n| *n < 8; |n| vec![*n * 2, *n * 2 + 1] => {
+///     values.push(*n);
+///     prune_if!(*n % 2 == 0);
+/// });
+///
+/// assert_eq!(values, vec![1, 2, 3, 6, 7]);
+/// ```
+#[macro_export]
+macro_rules! prune_if {
+    ($cond:expr) => {
+        if $cond {
+            $crate::prune!();
+        }
+    };
+}
+
+/// Breaks out of the entire tree traversal if a condition holds.
+///
+/// `break_tree_if!(cond)` is shorthand for `if cond { break_tree!(); }`, with
+/// the condition evaluated in the body's own scope.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, break_tree_if};
+///
+/// let mut count = 0;
+///
+/// for_tree!(n in 0; |n| *n < 10; |n| vec![*n + 1] => {
+///     count += 1;
+///     break_tree_if!(*n >= 5);
+/// });
+///
+/// assert_eq!(count, 6);
+/// ```
+#[macro_export]
+macro_rules! break_tree_if {
+    ($cond:expr) => {
+        if $cond {
+            $crate::break_tree!();
+        }
+    };
+}
+
+/// Skips traversing the children of the current node once it is at a given depth.
+///
+/// `prune_depth!(depth, max)` is shorthand for `prune_if!(depth >= max)`, the
+/// usual way of stopping a generative traversal at a fixed length. It is meant
+/// for the body of [`for_tree_depth!`], whose depth binding is passed as the
+/// first argument; nodes at depth `max` are still visited, but no deeper
+/// nodes are generated.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_depth, prune_depth};
+///
+/// let mut strings = Vec::new();
+///
+/// for_tree_depth!(s, depth in String::new(); |_| true; |s| {
+///     vec![format!("{}a", s), format!("{}b", s)]
+/// } => {
+///     strings.push(s.clone());
+///     prune_depth!(depth, 2);
+/// });
+///
+/// assert_eq!(strings, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+/// ```
+#[macro_export]
+macro_rules! prune_depth {
+    ($depth:expr, $max:expr) => {
+        $crate::prune_if!($depth >= $max);
+    };
+}
+
+/// Caps the traversal below the current node at a given number of levels.
+///
+/// `prune_after!(n)` returns [`TreeControl::PruneAfter`] from the body: the
+/// traversal still continues into the current node's children, but nodes `n`
+/// levels below it are visited without their children being generated. Unlike
+/// [`prune_depth!`], the cap is relative to the current node, so different
+/// subtrees can be cut off at different depths.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, prune_after};
+///
+/// let mut values = Vec::new();
+///
+/// for_tree!(n in 1; |n| *n < 16; |n| vec![*n * 2, *n * 2 + 1] => {
+///     values.push(*n);
+///     if *n == 2 {
+///         prune_after!(1); // Visit 4 and 5, but not their children
+///     }
+/// });
+///
+/// assert_eq!(values, vec![1, 2, 4, 5, 3, 6, 12, 13, 7, 14, 15]);
+/// ```
+#[macro_export]
+macro_rules! prune_after {
+    ($levels:expr) => {
+        $crate::__for_tree_body!(return $crate::TreeControl::PruneAfter($levels));
+    };
+}
+
+/// Builds a [`TreeControl`] value from a list of conditions.
+///
+/// `control!(break if a, prune if b)` evaluates to `TreeControl::Break` if `a`
+/// holds, to `TreeControl::Prune` if `b` holds, and to `TreeControl::Continue`
+/// otherwise. The clauses are checked in the order they are written, and the
+/// supported keywords are `break`, `prune`, `repeat`, `restart`, `defer`,
+/// `prune_siblings`, `prune_after(n)` and `break_after_subtree`. It is meant for visitors passed to
+/// the traversal functions, as a compact way of saying "continue unless one of
+/// these holds".
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{control, traverse_tree, TreeControl};
+///
+/// let mut visited = Vec::new();
+///
+/// traverse_tree(1, |n| *n < 32, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     visited.push(*n);
+///     control!(break if *n == 6, prune if *n % 2 == 0)
+/// });
+///
+/// assert_eq!(visited, vec![1, 2, 3, 6]);
+/// assert_eq!(control!(prune if false), TreeControl::Continue);
+/// ```
+#[macro_export]
+macro_rules! control {
+    (@variant break) => {
+        $crate::TreeControl::Break
+    };
+    (@variant prune) => {
+        $crate::TreeControl::Prune
+    };
+    (@variant repeat) => {
+        $crate::TreeControl::Repeat
+    };
+    (@variant restart) => {
+        $crate::TreeControl::RestartSubtree
+    };
+    (@variant defer) => {
+        $crate::TreeControl::Defer
+    };
+    (@variant prune_siblings) => {
+        $crate::TreeControl::PruneSiblings
+    };
+    (@variant prune_after($levels:expr)) => {
+        $crate::TreeControl::PruneAfter($levels)
+    };
+    (@variant break_after_subtree) => {
+        $crate::TreeControl::BreakAfterSubtree
+    };
+    ($($kind:tt $(($arg:expr))? if $cond:expr),* $(,)?) => {
+        $(
+            if $cond {
+                $crate::control!(@variant $kind $(($arg))?)
+            } else
+        )* {
+            $crate::TreeControl::Continue
+        }
+    };
+}
+
+/// Skips the rest of the body for the current node, but still visits its children.
+///
+/// This macro is used within a [`for_tree!`] block to end the body early for a
+/// node that needs no further work, without affecting the shape of the traversal.
+/// It differs from the other ways of leaving the body:
+///
+/// * [`prune!`] also ends the body, but additionally skips the node's children
+/// * `continue` only applies to a loop written inside the body itself, and cannot
+///   be used to leave the body, since the body is not a loop iteration
+/// * [`break_tree!`] ends the body and stops the whole traversal
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree, skip_body};
+///
+/// let mut reached = Vec::new();
+/// let mut processed = Vec::new();
+///
+/// for_tree!(n in 1; |n| *n < 8; |n| vec![*n * 2, *n * 2 + 1] => {
+///     reached.push(*n);
+///
+///     if *n % 2 == 0 {
+///         skip_body!(); // Even nodes need no processing, but their children do
+///     }
+///
+///     processed.push(*n);
+/// });
+///
+/// assert_eq!(reached, vec![1, 2, 4, 5, 3, 6, 7]);
+/// assert_eq!(processed, vec![1, 5, 3, 7]);
+/// ```
+#[macro_export]
+macro_rules! skip_body {
+    () => {
+        $crate::__for_tree_body!(return $crate::TreeControl::Continue);
+    };
+}
+
+/// Visits the children of the current node, then runs the body for it again.
+///
+/// This macro is used within a [`for_tree!`] block to get a second call for a
+/// node once its whole subtree is done, as described for
+/// [`TreeControl::Defer`]. Both calls run the same body, so the body has to
+/// tell them apart itself, for example by remembering the nodes it deferred.
+/// [`traverse_tree_deferred`] does that bookkeeping for you.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{defer, for_tree};
+///
+/// let mut open = Vec::new();
+/// let mut text = String::new();
+///
+/// for_tree!(n in 1; |n| *n < 8; |n| vec![*n * 2, *n * 2 + 1] => {
+///     if open.last() == Some(n) {
+///         // The second call, after the children
+///         open.pop();
+///         text.push(')');
+///     } else if *n < 4 {
+///         open.push(*n);
+///         text.push_str(&format!("({}", n));
+///         defer!();
+///     } else {
+///         text.push_str(&format!("({})", n));
+///     }
+/// });
+///
+/// assert_eq!(text, "(1(2(4)(5))(3(6)(7)))");
+/// ```
+#[macro_export]
+macro_rules! defer {
+    () => {
+        $crate::__for_tree_body!(return $crate::TreeControl::Defer);
+    };
+}
+
+/// A macro for traversing tree-like structures or generating tree-like data.
+///
+/// # Syntax
+///
+/// ```rust,ignore
+/// // This is just syntax illustration, not meant to be compiled
+/// for_tree!(var in initial; condition; branches => {
+///     // body
+///     // You can use special control flow:
+///     // - break_tree!(); - exits the entire traversal
+///     // - prune!(); - skips traversing children of the current node
+/// });
+///
+/// // The condition can be left out to visit every node
+/// for_tree!(var in initial; branches; => {
+///     // body
+/// });
+/// ```
+///
+/// # Examples
+///
+/// Traverse a binary tree:
+/// ```rust,no_run
+/// use arboriter::{for_tree, prune, break_tree, BinaryNode};
+///
+/// // Create a simple binary tree for demonstration
+/// let root = BinaryNode::with_children(
+///     10,
+///     Some(Box::new(BinaryNode::new(5))),
+///     Some(Box::new(BinaryNode::new(15)))
+/// );
+///
+/// for_tree!(node in &root; |_| true; |node| {
+///     // Explicitly declare the type of branches
+///     let mut branches: Vec<&BinaryNode<i32>> = Vec::new();
+///     if let Some(left) = &node.left {
+///         branches.push(left.as_ref());
+///     }
+///     if let Some(right) = &node.right {
+///         branches.push(right.as_ref());
+///     }
+///     branches
+/// } => {
+///     println!("Node value: {}", node.value);
+///
+///     if node.value == 10 {
+///         break_tree!(); // Exit the traversal
+///     }
+///
+///     if node.value < 0 {
+///         prune!(); // Don't traverse children of negative nodes
+///     }
+/// });
+/// ```
+///
+/// Generate strings of "a", "b", and "c" with length <= 8:
+/// ```rust,no_run
+/// use arboriter::{for_tree, prune};
+///
+/// for_tree!(s in String::new(); |s| s.len() <= 8; |s| {
+///     // Create branches with explicit type
+///     let mut branches: Vec<String> = Vec::new();
+///     branches.push(format!("{}a", s));
+///     branches.push(format!("{}b", s));
+///     branches.push(format!("{}c", s));
+///     branches
+/// } => {
+///     println!("{}", s);
+///
+///     if s.len() == 8 {
+///         prune!(); // Don't generate longer strings
+///     }
+/// });
+/// ```
+///
+/// Declare the node type once, so neither the closures nor the branch vector
+/// need their own annotations:
+/// ```rust
+/// use arboriter::{for_tree, BinaryNode};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3)))
+/// );
+///
+/// let mut values = Vec::new();
+///
+/// for_tree!(node: &BinaryNode<i32> in &root; |_| true; |node| {
+///     let mut children = Vec::new();
+///     if let Some(left) = &node.left {
+///         children.push(left.as_ref());
+///     }
+///     if let Some(right) = &node.right {
+///         children.push(right.as_ref());
+///     }
+///     children
+/// } => {
+///     values.push(node.value);
+/// });
+///
+/// assert_eq!(values, vec![1, 2, 3]);
+/// ```
+///
+/// Leave out the condition when every node should be visited:
+/// ```rust
+/// use arboriter::{for_tree, BinaryNode};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3)))
+/// );
+///
+/// let mut values = Vec::new();
+///
+/// for_tree!(node in &root; |node| node.children(); => {
+///     values.push(node.value);
+/// });
+///
+/// assert_eq!(values, vec![1, 2, 3]);
+/// ```
+///
+/// # Loops inside the body
+///
+/// The body is not a loop iteration, so `break` and `continue` always belong to
+/// a loop written inside the body, including labeled loops. They never affect
+/// the traversal; use [`break_tree!`], [`prune!`] or [`skip_body!`] for that.
+/// A `break` or `continue` that would have to leave the body, such as one
+/// targeting a loop around the `for_tree!`, is rejected at compile time rather
+/// than being silently reinterpreted:
+///
+/// ```rust,compile_fail
+/// use arboriter::for_tree;
+///
+/// 'outer: for _ in 0..3 {
+///     for_tree!(n in 1; |n| *n < 8; |n| vec![*n * 2, *n * 2 + 1] => {
+///         continue 'outer;
+///     });
+/// }
+/// ```
+///
+/// Loops inside the body work exactly as they do anywhere else:
+/// ```rust
+/// use arboriter::{for_tree, prune};
+///
+/// let mut first_divisors = Vec::new();
+///
+/// for_tree!(n in 12; |n| *n > 1; |n| vec![*n / 2] => {
+///     'search: for d in 2..=*n {
+///         for e in 2..=*n {
+///             if d * e == *n {
+///                 first_divisors.push(d);
+///                 break 'search;
+///             }
+///         }
+///     }
+///
+///     if *n == 3 {
+///         prune!();
+///     }
+/// });
+///
+/// assert_eq!(first_divisors, vec![2, 2]);
+/// ```
+#[macro_export]
+macro_rules! for_tree {
+    // Condition left out, visiting every node: `node in initial; branches; => body`.
+    // An explicit condition can't be followed by `; =>`, so this never
+    // captures input meant for the other patterns
+    ($var:ident in $init:expr; $branch:expr; => $body:block) => {
+        $crate::for_tree!($var in $init; |_| true; $branch => $body)
+    };
+
+    // Main pattern with => separator
+    ($var:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        {
+            $crate::traverse_tree(
+                $init,
+                $cond,
+                $branch,
+                |$var| {
+                    $crate::__for_tree_body!(mark);
+                    let result = {
+                        $body
+                        $crate::TreeControl::Continue
+                    };
+                    result
+                }
+            );
+        }
+    };
+
+    // Alternative syntax with semicolons instead of =>
+    ($var:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree!($var in $init; $cond; $branch => $body);
+    };
+
+    // Allows shorter syntax when the closures are simple - uses = like in the blog post
+    ($var:ident = $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        {
+            let initial_value = $init;
+            $crate::for_tree!(
+                $var in initial_value; 
+                |$var| $cond; 
+                |$var| $branch;
+                $body
+            );
+        }
+    };
+
+    // Very similar to for loop syntax with semicolons
+    ($var:ident = $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree!($var = $init; $cond; $branch => $body);
+    };
+
+    // Explicitly typed node: `node: Type in initial; ...`
+    ($var:ident : $($rest:tt)+) => {
+        $crate::for_tree!(@typed $var [] $($rest)+)
+    };
+
+    // A type can't be followed by `in` in a macro pattern, so the type tokens
+    // are collected one at a time until the `in` keyword is reached
+    (@typed $var:ident [$($ty:tt)+] in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        {
+            $crate::traverse_tree::<$($ty)+, _, _, _>(
+                $init,
+                $cond,
+                $branch,
+                |$var: &$($ty)+| {
+                    $crate::__for_tree_body!(mark);
+                    let result = {
+                        $body
+                        $crate::TreeControl::Continue
+                    };
+                    result
+                }
+            );
+        }
+    };
+
+    (@typed $var:ident [$($ty:tt)+] in $init:expr; $branch:expr; => $body:block) => {
+        $crate::for_tree!(@typed $var [$($ty)+] in $init; |_| true; $branch => $body)
+    };
+
+    (@typed $var:ident [$($ty:tt)+] in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree!(@typed $var [$($ty)+] in $init; $cond; $branch => $body);
+    };
+
+    (@typed $var:ident [$($ty:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::for_tree!(@typed $var [$($ty)* $next] $($rest)*)
+    };
+}
+
+/// A version of [`for_tree!`] whose body can add children to the current node.
+///
+/// The second identifier is bound to the node's [`Branches`] buffer. Children
+/// added with [`add_branch!`] are visited after the ones from the branch function,
+/// and [`set_branches!`] replaces them all. [`prune!`] and [`break_tree!`] work
+/// exactly as in [`for_tree!`]. See [`traverse_tree_dynamic`] for details.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_dynamic, add_branch};
+///
+/// // Discover extra neighbours while visiting
+/// let mut visited = Vec::new();
+///
+/// for_tree_dynamic!(n, branches in 1; |n| *n < 20; |n| vec![*n * 3] => {
+///     visited.push(*n);
+///     if *n % 3 == 0 {
+///         add_branch!(branches, *n + 1);
+///     }
+/// });
+///
+/// assert_eq!(visited, vec![1, 3, 9, 10, 4, 12, 13]);
+/// ```
+#[macro_export]
+macro_rules! for_tree_dynamic {
+    ($var:ident, $branches:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        $crate::traverse_tree_dynamic(
+            $init,
+            $cond,
+            $branch,
+            |$var, $branches| {
+                $crate::__for_tree_body!(mark);
+                $body
+                $crate::TreeControl::Continue
+            }
+        )
+    };
+
+    ($var:ident, $branches:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree_dynamic!($var, $branches in $init; $cond; $branch => $body)
+    };
+}
+
+/// Adds a child to the current node inside a [`for_tree_dynamic!`] body.
+///
+/// `add_branch!(branches, value)` is shorthand for `branches.add(value)`. The
+/// buffer has to be named because macros cannot see identifiers introduced by
+/// another macro's expansion.
+#[macro_export]
+macro_rules! add_branch {
+    ($branches:ident, $value:expr) => {
+        $branches.add($value)
+    };
+}
+
+/// Replaces the children of the current node inside a [`for_tree_dynamic!`] body.
+///
+/// `set_branches!(branches, vec)` is shorthand for `branches.set(vec)`; the
+/// branch function is then not called for this node.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_dynamic, set_branches};
+///
+/// let mut visited = Vec::new();
+///
+/// for_tree_dynamic!(n, branches in 1; |_| true; |n| vec![*n * 2, *n * 2 + 1] => {
+///     visited.push(*n);
+///     if *n == 1 {
+///         set_branches!(branches, vec![10]);
+///     }
+///     if *n >= 10 {
+///         set_branches!(branches, Vec::new());
+///     }
+/// });
+///
+/// assert_eq!(visited, vec![1, 10]);
+/// ```
+#[macro_export]
+macro_rules! set_branches {
+    ($branches:ident, $values:expr) => {
+        $branches.set($values)
+    };
+}
+
+/// A version of [`for_tree!`] that also binds the depth of the current node.
+///
+/// The root is at depth 0. Both identifiers are in scope inside the body, and
+/// [`prune!`] and [`break_tree!`] work exactly as in [`for_tree!`].
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_depth, prune};
+///
+/// let mut lines = Vec::new();
+///
+/// for_tree_depth!(s, depth in String::new(); |_| true; |s| {
+///     vec![format!("{}a", s), format!("{}b", s)]
+/// } => {
+///     lines.push(format!("{}{:?}", "  ".repeat(depth), s));
+///
+///     if depth == 1 {
+///         prune!(); // Only show the first level below the root
+///     }
+/// });
+///
+/// assert_eq!(lines, vec!["\"\"", "  \"a\"", "  \"b\""]);
+/// ```
+#[macro_export]
+macro_rules! for_tree_depth {
+    ($var:ident, $depth:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        {
+            $crate::traverse_tree_depth(
+                $init,
+                $cond,
+                $branch,
+                |$var, $depth| {
+                    $crate::__for_tree_body!(mark);
+                    let result = {
+                        $body
+                        $crate::TreeControl::Continue
+                    };
+                    result
+                }
+            );
+        }
+    };
+
+    ($var:ident, $depth:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree_depth!($var, $depth in $init; $cond; $branch => $body);
+    };
+}
+
+/// A fallible version of [`for_tree!`] whose body may use the `?` operator.
+///
+/// The macro evaluates to a `Result<(), E>`. Any error propagated with `?`
+/// from the body stops the traversal and becomes the result of the whole
+/// expression. [`prune!`] and [`break_tree!`] work exactly as in [`for_tree!`].
+///
+/// Because `?` converts errors with `From`, the error type usually needs to
+/// be pinned down by the surrounding code, for example with a type annotation
+/// on the binding that receives the result.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_try, prune};
+///
+/// fn parse_all(inputs: &[&str]) -> Result<Vec<i32>, std::num::ParseIntError> {
+///     let mut parsed = Vec::new();
+///
+///     for_tree_try!(i in 0; |i| *i < inputs.len(); |i| vec![*i + 1] => {
+///         let value: i32 = inputs[*i].parse()?;
+///         parsed.push(value);
+///
+///         if value < 0 {
+///             prune!(); // Stop at the first negative number
+///         }
+///     })?;
+///
+///     Ok(parsed)
+/// }
+///
+/// assert_eq!(parse_all(&["1", "2", "-3", "4"]), Ok(vec![1, 2, -3]));
+/// assert!(parse_all(&["1", "x", "3"]).is_err());
+/// ```
+#[macro_export]
+macro_rules! for_tree_try {
+    ($var:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        $crate::try_traverse_tree(
+            $init,
+            $cond,
+            $branch,
+            |$var| {
+                $crate::__for_tree_body!(mark);
+                $body
+                ::core::result::Result::Ok($crate::TreeControl::Continue)
+            }
+        )
+    };
+
+    ($var:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree_try!($var in $init; $cond; $branch => $body)
+    };
+}
+
+/// A version of [`for_tree!`] that evaluates to the value passed to
+/// `break_tree!(value)`.
+///
+/// The macro evaluates to an `Option<V>`: `Some(value)` if the body ran
+/// `break_tree!(value)`, or `None` if the traversal finished without one. This
+/// removes the need for a captured `found` variable when searching. [`prune!`]
+/// and a plain `break_tree!()` work exactly as in [`for_tree!`]; the latter stops
+/// the traversal with a result of `None`.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{for_tree_value, break_tree};
+///
+/// // The first multiple of 7 in depth-first order
+/// let found = for_tree_value!(n in 1; |n| *n < 64; |n| vec![*n * 2, *n * 2 + 1] => {
+///     if *n % 7 == 0 {
+///         break_tree!(*n);
+///     }
+/// });
+///
+/// assert_eq!(found, Some(35));
+/// ```
+#[macro_export]
+macro_rules! for_tree_value {
+    ($var:ident in $init:expr; $cond:expr; $branch:expr => $body:block) => {
+        $crate::traverse_tree_value(
+            $init,
+            $cond,
+            $branch,
+            |$var| {
+                $crate::__for_tree_body!(mark);
+                $body
+                ::core::ops::ControlFlow::Continue($crate::TreeControl::Continue)
+            }
+        )
+    };
+
+    ($var:ident in $init:expr; $cond:expr; $branch:expr; $body:block) => {
+        $crate::for_tree_value!($var in $init; $cond; $branch => $body)
+    };
+}
+
+/// Traverses a tree and collects one value per visited node into a `Vec`.
+///
+/// The part after `=>` is an expression rather than a block, evaluated for each
+/// node in depth-first order to produce the corresponding element. This is the
+/// macro form of [`collect_tree_with`], and replaces the common pattern of
+/// pushing to a vector from a [`for_tree!`] body. Since the traversal can't be
+/// controlled from an expression, every node that passes the condition is
+/// visited.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{collect_for_tree, BinaryNode};
+///
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3))),
+/// );
+///
+/// let values = collect_for_tree!(node in &root; |_| true; |n| n.children() => node.value * 10);
+///
+/// assert_eq!(values, vec![10, 20, 30]);
+/// ```
+#[macro_export]
+macro_rules! collect_for_tree {
+    ($var:ident in $init:expr; $cond:expr; $branch:expr => $item:expr) => {
+        $crate::collect_tree_with($init, $cond, $branch, |$var| $item)
+    };
+}
+
+/// Builds an [`NAryNode`] tree from a literal.
+///
+/// A leaf is written as its value, and any other node as `value => { children }`
+/// with its children separated by commas. Leaves and inner nodes can be mixed
+/// freely among siblings, and a trailing comma is allowed. Values are arbitrary
+/// expressions.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{tree, NAryNode};
+///
+/// let root = tree!(1 => { 2 => { 4, 5 }, 3 => { 6 } });
+///
+/// assert_eq!(
+///     root,
+///     NAryNode::with_children(1, vec![
+///         NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+///         NAryNode::with_children(3, vec![NAryNode::new(6)]),
+///     ])
+/// );
+/// assert_eq!(tree!("leaf"), NAryNode::new("leaf"));
+/// ```
+#[macro_export]
+macro_rules! tree {
+    // Children are collected one at a time, since a plain repetition can't
+    // tell a leaf from an inner node before parsing it
+    (@children [$($built:expr),*]) => {
+        [$($built),*].into()
+    };
+    (@children [$($built:expr),*] $value:expr => { $($children:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::tree!(@children [$($built,)* $crate::tree!($value => { $($children)* })] $($($rest)*)?)
+    };
+    (@children [$($built:expr),*] $value:expr $(, $($rest:tt)*)?) => {
+        $crate::tree!(@children [$($built,)* $crate::NAryNode::new($value)] $($($rest)*)?)
+    };
+    ($value:expr => { $($children:tt)* }) => {
+        $crate::NAryNode::with_children($value, $crate::tree!(@children [] $($children)*))
+    };
+    ($value:expr) => {
+        $crate::NAryNode::new($value)
+    };
+}
+
+// Examples
+
+/// Tree node example for binary trees
+///
+/// With the `serde` feature enabled, `BinaryNode` implements `Serialize` and
+/// `Deserialize`, using the `value`, `left` and `right` field names.
+///
+/// Two trees are equal when they have the same shape and equal values in the
+/// same positions.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinaryNode<T> {
+    pub value: T,
+    pub left: Option<Box<BinaryNode<T>>>,
+    pub right: Option<Box<BinaryNode<T>>>,
+}
+
+impl<T> BinaryNode<T> {
+    /// Creates a new `BinaryNode` with the given value and no children.
+    ///
+    /// # Parameters
+    ///
+    /// * `value` - The value to store in this node
+    ///
+    /// # Returns
+    ///
+    /// A new `BinaryNode` with the specified value and `None` for both children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let node = BinaryNode::new(42);
+    /// assert_eq!(node.value, 42);
+    /// assert!(node.left.is_none());
+    /// assert!(node.right.is_none());
+    /// ```
+    pub fn new(value: T) -> Self {
+        BinaryNode {
+            value,
+            left: None,
+            right: None,
+        }
+    }
+
+    /// Creates a new `BinaryNode` with the given value and child nodes.
+    ///
+    /// # Parameters
+    ///
+    /// * `value` - The value to store in this node
+    /// * `left` - The left child of this node, if any
+    /// * `right` - The right child of this node, if any
+    ///
+    /// # Returns
+    ///
+    /// A new `BinaryNode` with the specified value and children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let node = BinaryNode::with_children(
+    ///     1,
+    ///     Some(Box::new(BinaryNode::new(2))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// assert_eq!(node.value, 1);
+    /// assert_eq!(node.left.as_ref().unwrap().value, 2);
+    /// assert_eq!(node.right.as_ref().unwrap().value, 3);
+    /// ```
+    pub fn with_children(
+        value: T,
+        left: Option<Box<BinaryNode<T>>>,
+        right: Option<Box<BinaryNode<T>>>,
+    ) -> Self {
+        BinaryNode { value, left, right }
+    }
+
+    /// Returns references to this node's children, left before right.
+    ///
+    /// Missing children are skipped, so a leaf returns an empty vector. This is
+    /// exactly the shape a [`for_tree!`] branch closure needs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{for_tree, BinaryNode};
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     1,
+    ///     None,
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// assert_eq!(root.children().len(), 1);
+    ///
+    /// let mut values = Vec::new();
+    /// for_tree!(node in &root; |_| true; |node| node.children() => {
+    ///     values.push(node.value);
+    /// });
+    /// assert_eq!(values, vec![1, 3]);
+    /// ```
+    pub fn children(&self) -> Vec<&BinaryNode<T>> {
+        let mut children = Vec::new();
+        if let Some(left) = &self.left {
+            children.push(left.as_ref());
+        }
+        if let Some(right) = &self.right {
+            children.push(right.as_ref());
+        }
+        children
+    }
+
+    /// Returns mutable references to this node's children, left before right.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let mut root = BinaryNode::with_children(
+    ///     1,
+    ///     Some(Box::new(BinaryNode::new(2))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// for child in root.children_mut() {
+    ///     child.value *= 10;
+    /// }
+    ///
+    /// assert_eq!(root.left.unwrap().value, 20);
+    /// assert_eq!(root.right.unwrap().value, 30);
+    /// ```
+    pub fn children_mut(&mut self) -> Vec<&mut BinaryNode<T>> {
+        let mut children = Vec::new();
+        if let Some(left) = &mut self.left {
+            children.push(left.as_mut());
+        }
+        if let Some(right) = &mut self.right {
+            children.push(right.as_mut());
+        }
+        children
+    }
+
+    /// Visits every value in the tree in pre-order: node, left subtree, right subtree.
+    ///
+    /// This is the same order the generic [`traverse_tree`] produces when children
+    /// are returned left before right.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     2,
+    ///     Some(Box::new(BinaryNode::new(1))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// let mut values = Vec::new();
+    /// root.traverse_pre_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![2, 1, 3]);
+    /// ```
+    pub fn traverse_pre_order<F: FnMut(&T)>(&self, mut visit_fn: F) {
+        fn pre_order<T, F: FnMut(&T)>(node: &BinaryNode<T>, visit_fn: &mut F) {
+            visit_fn(&node.value);
+            if let Some(left) = &node.left {
+                pre_order(left, visit_fn);
+            }
+            if let Some(right) = &node.right {
+                pre_order(right, visit_fn);
+            }
+        }
+
+        pre_order(self, &mut visit_fn);
+    }
+
+    /// Visits every value in the tree in-order: left subtree, node, right subtree.
+    ///
+    /// For a binary search tree this visits the values in ascending order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     2,
+    ///     Some(Box::new(BinaryNode::new(1))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// let mut values = Vec::new();
+    /// root.traverse_in_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![1, 2, 3]);
+    /// ```
+    pub fn traverse_in_order<F: FnMut(&T)>(&self, mut visit_fn: F) {
+        fn in_order<T, F: FnMut(&T)>(node: &BinaryNode<T>, visit_fn: &mut F) {
+            if let Some(left) = &node.left {
+                in_order(left, visit_fn);
+            }
+            visit_fn(&node.value);
+            if let Some(right) = &node.right {
+                in_order(right, visit_fn);
+            }
+        }
+
+        in_order(self, &mut visit_fn);
+    }
+
+    /// Visits every value in the tree in post-order: left subtree, right subtree, node.
+    ///
+    /// Every value is visited after all of its descendants, which is the order
+    /// needed to evaluate expression trees or free resources bottom-up.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     2,
+    ///     Some(Box::new(BinaryNode::new(1))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// let mut values = Vec::new();
+    /// root.traverse_post_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![1, 3, 2]);
+    /// ```
+    pub fn traverse_post_order<F: FnMut(&T)>(&self, mut visit_fn: F) {
+        fn post_order<T, F: FnMut(&T)>(node: &BinaryNode<T>, visit_fn: &mut F) {
+            if let Some(left) = &node.left {
+                post_order(left, visit_fn);
+            }
+            if let Some(right) = &node.right {
+                post_order(right, visit_fn);
+            }
+            visit_fn(&node.value);
+        }
+
+        post_order(self, &mut visit_fn);
+    }
+
+    /// Builds a new tree with the same shape whose values are mapped with `f`.
+    ///
+    /// `f` is called once per value in pre-order: node, left subtree, right subtree.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     2,
+    ///     Some(Box::new(BinaryNode::new(1))),
+    ///     None
+    /// );
+    ///
+    /// let labels = root.map(|value| format!("#{}", value));
+    /// assert_eq!(labels.value, "#2");
+    /// assert_eq!(labels.left.unwrap().value, "#1");
+    /// assert!(labels.right.is_none());
+    /// ```
+    pub fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> BinaryNode<U> {
+        fn map_node<T, U, F: FnMut(&T) -> U>(node: &BinaryNode<T>, f: &mut F) -> BinaryNode<U> {
+            let value = f(&node.value);
+            let left = node.left.as_ref().map(|left| Box::new(map_node(left, f)));
+            let right = node
+                .right
+                .as_ref()
+                .map(|right| Box::new(map_node(right, f)));
+            BinaryNode::with_children(value, left, right)
+        }
+
+        map_node(self, &mut f)
+    }
+
+    /// Builds a copy of the tree without the nodes whose value fails `keep`.
+    ///
+    /// A node that fails `keep` is removed together with all of its descendants,
+    /// whether or not they would pass. A kept node whose children were all removed
+    /// stays in the copy as a leaf.
+    ///
+    /// # Returns
+    ///
+    /// The filtered copy, or `None` if the root itself fails `keep`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::with_children(
+    ///     2,
+    ///     Some(Box::new(BinaryNode::new(-1))),
+    ///     Some(Box::new(BinaryNode::new(3)))
+    /// );
+    ///
+    /// let positive = root.filter(|value| *value > 0).unwrap();
+    /// assert!(positive.left.is_none());
+    /// assert_eq!(positive.right.unwrap().value, 3);
+    ///
+    /// assert!(root.filter(|value| *value > 2).is_none());
+    /// ```
+    pub fn filter<P: FnMut(&T) -> bool>(&self, mut keep: P) -> Option<BinaryNode<T>>
+    where
+        T: Clone,
+    {
+        fn filter_node<T: Clone, P: FnMut(&T) -> bool>(
+            node: &BinaryNode<T>,
+            keep: &mut P,
+        ) -> Option<BinaryNode<T>> {
+            if !keep(&node.value) {
+                return None;
+            }
+
+            let left = node.left.as_ref().and_then(|left| filter_node(left, keep));
+            let right = node
+                .right
+                .as_ref()
+                .and_then(|right| filter_node(right, keep));
+            Some(BinaryNode::with_children(
+                node.value.clone(),
+                left.map(Box::new),
+                right.map(Box::new),
+            ))
+        }
+
+        filter_node(self, &mut keep)
+    }
+
+    /// Builds a height-balanced tree from a slice, keeping the slice order in-order.
+    ///
+    /// The middle element becomes the root, and the elements before and after it
+    /// build the left and right subtrees the same way. If the slice is sorted, the
+    /// result is a balanced binary search tree.
+    ///
+    /// # Returns
+    ///
+    /// The new tree, or `None` if `values` is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5]).unwrap();
+    /// assert_eq!(root.value, 3);
+    ///
+    /// let mut values = Vec::new();
+    /// root.traverse_in_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![1, 2, 3, 4, 5]);
+    /// ```
+    pub fn from_sorted_slice(values: &[T]) -> Option<BinaryNode<T>>
+    where
+        T: Clone,
+    {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mid = values.len() / 2;
+        Some(BinaryNode::with_children(
+            values[mid].clone(),
+            BinaryNode::from_sorted_slice(&values[..mid]).map(Box::new),
+            BinaryNode::from_sorted_slice(&values[mid + 1..]).map(Box::new),
+        ))
+    }
+
+    /// Builds a binary search tree by inserting values one at a time.
+    ///
+    /// Each value goes to the left of every node it is smaller than and to the
+    /// right otherwise, so equal values are kept and end up right of each other.
+    /// The shape depends on the insertion order; inserting sorted values produces
+    /// a chain.
+    ///
+    /// # Returns
+    ///
+    /// The new tree, or `None` if `values` is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_bst_insert([5, 3, 8, 1, 4]).unwrap();
+    /// assert_eq!(root.value, 5);
+    /// assert_eq!(root.left.as_ref().unwrap().value, 3);
+    /// assert_eq!(root.right.as_ref().unwrap().value, 8);
+    ///
+    /// let mut values = Vec::new();
+    /// root.traverse_in_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![1, 3, 4, 5, 8]);
+    /// ```
+    pub fn from_bst_insert<I>(values: I) -> Option<BinaryNode<T>>
+    where
+        T: Ord,
+        I: IntoIterator<Item = T>,
+    {
+        let mut root: Option<Box<BinaryNode<T>>> = None;
+
+        for value in values {
+            let mut slot = &mut root;
+            while let Some(node) = slot {
+                slot = if value < node.value {
+                    &mut node.left
+                } else {
+                    &mut node.right
+                };
+            }
+            *slot = Some(Box::new(BinaryNode::new(value)));
+        }
+
+        root.map(|root| *root)
+    }
+
+    /// Inserts a value into the binary search tree rooted at this node.
+    ///
+    /// Smaller values go to the left and larger values to the right, so the
+    /// tree works as an ordered set. Returns `false`, leaving the tree
+    /// unchanged, if an equal value is already present. The tree is not
+    /// rebalanced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let mut set = BinaryNode::new(5);
+    /// assert!(set.insert(3));
+    /// assert!(set.insert(8));
+    /// assert!(!set.insert(3));
+    ///
+    /// assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![3, 5, 8]);
+    /// ```
+    pub fn insert(&mut self, value: T) -> bool
+    where
+        T: Ord,
+    {
+        let mut node = self;
+
+        loop {
+            let slot = match value.cmp(&node.value) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => return false,
+            };
+
+            match slot {
+                Some(child) => node = child,
+                None => {
+                    *slot = Some(Box::new(BinaryNode::new(value)));
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Removes a value from the binary search tree rooted at this node.
+    ///
+    /// A leaf is simply removed, a node with one child is replaced by that
+    /// child, and a node with two children takes the value of its in-order
+    /// successor, which is removed from the right subtree instead. Returns
+    /// `true` if the value was found and removed.
+    ///
+    /// A node can't remove itself, so removing the value of a root without
+    /// children returns `false` and leaves the tree unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let mut set = BinaryNode::from_bst_insert([5, 3, 8, 1, 4, 9]).unwrap();
+    /// assert!(set.remove(&3));
+    /// assert!(set.remove(&5));
+    /// assert!(!set.remove(&7));
+    ///
+    /// assert_eq!(set.value, 8);
+    /// assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![1, 4, 8, 9]);
+    /// ```
+    pub fn remove(&mut self, value: &T) -> bool
+    where
+        T: Ord,
+    {
+        let mut node = self;
+
+        loop {
+            if *value == node.value {
+                return node.remove_own_value();
+            }
+
+            let slot = if *value < node.value {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+
+            match slot {
+                None => return false,
+                // A leaf is unlinked by its parent, since it can't remove itself
+                Some(child)
+                    if child.value == *value && child.left.is_none() && child.right.is_none() =>
+                {
+                    *slot = None;
+                    return true;
+                }
+                Some(child) => node = child,
+            }
+        }
+    }
+
+    /// Replaces this node's value with that of its only child or its in-order
+    /// successor. Returns `false` for a leaf, which can only be unlinked by its parent.
+    fn remove_own_value(&mut self) -> bool {
+        match (self.left.take(), self.right.take()) {
+            (None, None) => false,
+            (Some(child), None) | (None, Some(child)) => {
+                *self = *child;
+                true
+            }
+            (left, Some(mut right)) => {
+                self.left = left;
+
+                if right.left.is_none() {
+                    // The right child is the successor itself
+                    self.value = right.value;
+                    self.right = right.right;
+                    return true;
+                }
+
+                // Otherwise the successor is the leftmost node of the right subtree
+                let mut parent = &mut right;
+                while parent.left.as_ref().is_some_and(|next| next.left.is_some()) {
+                    parent = parent.left.as_mut().expect("checked by the loop condition");
+                }
+
+                let successor = parent
+                    .left
+                    .take()
+                    .expect("the loop stops above a left child");
+                parent.left = successor.right;
+                self.value = successor.value;
+                self.right = Some(right);
+                true
+            }
+        }
+    }
+
+    /// Compares the shape of two trees, and their values with `value_eq`.
+    ///
+    /// Returns `true` if both trees have children in the same positions and
+    /// `value_eq` holds for every pair of values in the same position. Pass
+    /// `|_, _| true` to compare only the shape.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let a = BinaryNode::with_children(1, Some(Box::new(BinaryNode::new(2))), None);
+    /// let b = BinaryNode::with_children(10, Some(Box::new(BinaryNode::new(20))), None);
+    /// let c = BinaryNode::with_children(1, None, Some(Box::new(BinaryNode::new(2))));
+    ///
+    /// assert!(a.structural_eq(&b, |_, _| true));
+    /// assert!(a.structural_eq(&b, |x, y| x * 10 == *y));
+    /// assert!(!a.structural_eq(&c, |_, _| true));
+    /// ```
+    pub fn structural_eq<U, F>(&self, other: &BinaryNode<U>, mut value_eq: F) -> bool
+    where
+        F: FnMut(&T, &U) -> bool,
+    {
+        fn eq<T, U, F: FnMut(&T, &U) -> bool>(
+            a: &BinaryNode<T>,
+            b: &BinaryNode<U>,
+            value_eq: &mut F,
+        ) -> bool {
+            fn child_eq<T, U, F: FnMut(&T, &U) -> bool>(
+                a: &Option<Box<BinaryNode<T>>>,
+                b: &Option<Box<BinaryNode<U>>>,
+                value_eq: &mut F,
+            ) -> bool {
+                match (a, b) {
+                    (Some(a), Some(b)) => eq(a, b, value_eq),
+                    (None, None) => true,
+                    _ => false,
+                }
+            }
+
+            value_eq(&a.value, &b.value)
+                && child_eq(&a.left, &b.left, value_eq)
+                && child_eq(&a.right, &b.right, value_eq)
+        }
+
+        eq(self, other, &mut value_eq)
+    }
+
+    /// Returns the number of edges on the longest path from this node to a leaf.
+    ///
+    /// A node without children has height 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+    /// assert_eq!(root.height(), 2);
+    /// assert_eq!(BinaryNode::new(1).height(), 0);
+    /// ```
+    pub fn height(&self) -> usize {
+        self.children()
+            .into_iter()
+            .map(|child| child.height() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of nodes in the tree rooted at this node, including itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+    /// assert_eq!(root.len(), 7);
+    /// ```
+    // A node always contains at least itself, so there is no `is_empty`
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(|child| child.len())
+            .sum::<usize>()
+    }
+
+    /// Returns `true` if any node in the tree has a value equal to `target`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3]).unwrap();
+    /// assert!(root.contains(&3));
+    /// assert!(!root.contains(&4));
+    /// ```
+    pub fn contains(&self, target: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.depth_of(target).is_some()
+    }
+
+    /// Returns the depth of the first node, in depth-first pre-order, whose value equals `target`.
+    ///
+    /// This node is at depth 0. Returns `None` if no node matches. The tree is
+    /// searched with an explicit stack and the search stops at the first match.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+    /// assert_eq!(root.depth_of(&4), Some(0));
+    /// assert_eq!(root.depth_of(&7), Some(2));
+    /// assert_eq!(root.depth_of(&8), None);
+    /// ```
+    pub fn depth_of(&self, target: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        let mut found = None;
+
+        walk(
+            self,
+            |_, _| true,
+            |node, _| node.children(),
+            |node, depth| {
+                if node.value == *target {
+                    found = Some(depth);
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        found
+    }
+
+    /// Returns an iterator over the values of the tree in in-order.
+    ///
+    /// Values are yielded lazily, left subtree first, then the node itself,
+    /// then the right subtree, so a binary search tree yields its values in
+    /// ascending order. The iterator keeps an explicit stack of the nodes
+    /// whose left subtrees are being iterated, which is never deeper than the
+    /// tree. `&BinaryNode` also implements `IntoIterator`, so a tree can be
+    /// used directly in a `for` loop.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let tree = BinaryNode::from_bst_insert([5, 3, 8, 1, 4]).unwrap();
+    ///
+    /// let values: Vec<_> = tree.iter().copied().collect();
+    /// assert_eq!(values, vec![1, 3, 4, 5, 8]);
+    ///
+    /// let mut sum = 0;
+    /// for value in &tree {
+    ///     sum += value;
+    /// }
+    /// assert_eq!(sum, 21);
+    /// ```
+    pub fn iter(&self) -> BinaryNodeIter<'_, T> {
+        let mut iter = BinaryNodeIter { stack: Vec::new() };
+        iter.push_left_spine(Some(self));
+        iter
+    }
+
+    /// Rotates the tree rooted at this node to the left.
+    ///
+    /// The right child takes this node's place and this node becomes its left
+    /// child, so in-order traversal still yields the same sequence. Returns
+    /// `false`, leaving the tree unchanged, if there is no right child.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// // 1 -> 2 -> 3, leaning right
+    /// let mut root = BinaryNode::from_bst_insert([1, 2, 3]).unwrap();
+    /// assert!(root.rotate_left());
+    ///
+    /// assert_eq!(root.value, 2);
+    /// assert_eq!(root.left.as_ref().unwrap().value, 1);
+    /// assert_eq!(root.right.as_ref().unwrap().value, 3);
+    /// ```
+    pub fn rotate_left(&mut self) -> bool {
+        let Some(mut pivot) = self.right.take() else {
+            return false;
+        };
+
+        self.right = pivot.left.take();
+        core::mem::swap(self, &mut pivot);
+        // `pivot` now holds the old root, which becomes the left child
+        self.left = Some(pivot);
+        true
+    }
+
+    /// Rotates the tree rooted at this node to the right.
+    ///
+    /// The left child takes this node's place and this node becomes its right
+    /// child, so in-order traversal still yields the same sequence. Returns
+    /// `false`, leaving the tree unchanged, if there is no left child.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// // 3 -> 2 -> 1, leaning left
+    /// let mut root = BinaryNode::from_bst_insert([3, 2, 1]).unwrap();
+    /// assert!(root.rotate_right());
+    ///
+    /// assert_eq!(root.value, 2);
+    /// assert_eq!(root.left.as_ref().unwrap().value, 1);
+    /// assert_eq!(root.right.as_ref().unwrap().value, 3);
+    /// ```
+    pub fn rotate_right(&mut self) -> bool {
+        let Some(mut pivot) = self.left.take() else {
+            return false;
+        };
+
+        self.left = pivot.right.take();
+        core::mem::swap(self, &mut pivot);
+        // `pivot` now holds the old root, which becomes the right child
+        self.right = Some(pivot);
+        true
+    }
+
+    /// Builds a copy of the tree with the left and right children of every node swapped.
+    ///
+    /// Pre-order traversal of the mirror visits right subtrees before left ones,
+    /// and in-order traversal yields the original sequence reversed. Use
+    /// [`mirror_mut`](BinaryNode::mirror_mut) to flip a tree in place instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root = BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5]).unwrap();
+    /// let mirror = root.mirror();
+    ///
+    /// let mut values = Vec::new();
+    /// mirror.traverse_in_order(|value| values.push(*value));
+    /// assert_eq!(values, vec![5, 4, 3, 2, 1]);
+    /// assert_eq!(mirror.mirror(), root);
+    /// ```
+    pub fn mirror(&self) -> BinaryNode<T>
+    where
+        T: Clone,
+    {
+        fn mirror_node<T: Clone>(node: &BinaryNode<T>) -> BinaryNode<T> {
+            let left = node
+                .right
+                .as_ref()
+                .map(|right| Box::new(mirror_node(right)));
+            let right = node.left.as_ref().map(|left| Box::new(mirror_node(left)));
+            BinaryNode::with_children(node.value.clone(), left, right)
+        }
+
+        mirror_node(self)
+    }
+
+    /// Swaps the left and right children of every node in the tree, in place.
+    ///
+    /// This is the in-place version of [`mirror`](BinaryNode::mirror).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let mut root = BinaryNode::with_children(1, Some(Box::new(BinaryNode::new(2))), None);
+    /// root.mirror_mut();
+    ///
+    /// assert!(root.left.is_none());
+    /// assert_eq!(root.right.unwrap().value, 2);
+    /// ```
+    pub fn mirror_mut(&mut self) {
+        core::mem::swap(&mut self.left, &mut self.right);
+        for child in self.children_mut() {
+            child.mirror_mut();
+        }
+    }
+
+    /// Returns `true` if the tree is equal to its own mirror image.
+    ///
+    /// The left subtree of the root must be the mirror of the right one, in
+    /// shape and in values. A single node is symmetric. The check compares the
+    /// two sides directly, without building the mirror.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let symmetric: BinaryNode<i32> = "(1 (2 (3) _) (2 _ (3)))".parse().unwrap();
+    /// let lopsided: BinaryNode<i32> = "(1 (2 (3) _) (2 (3) _))".parse().unwrap();
+    ///
+    /// assert!(symmetric.is_symmetric());
+    /// assert!(!lopsided.is_symmetric());
+    /// ```
+    pub fn is_symmetric(&self) -> bool
+    where
+        T: PartialEq,
+    {
+        fn mirrors<T: PartialEq>(
+            a: &Option<Box<BinaryNode<T>>>,
+            b: &Option<Box<BinaryNode<T>>>,
+        ) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => {
+                    a.value == b.value && mirrors(&a.left, &b.right) && mirrors(&a.right, &b.left)
+                }
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        mirrors(&self.left, &self.right)
+    }
+
+    /// Returns `true` if, at every node, the heights of the two subtrees differ by at most one.
+    ///
+    /// A missing child counts as one level lower than a leaf, so a node with a
+    /// single leaf child is balanced, but a node with a single child that has
+    /// children of its own is not.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// assert!(BinaryNode::from_sorted_slice(&[1, 2, 3, 4, 5]).unwrap().is_balanced());
+    /// assert!(!BinaryNode::from_bst_insert([1, 2, 3]).unwrap().is_balanced());
+    /// ```
+    pub fn is_balanced(&self) -> bool {
+        // Height counted in nodes, so an empty subtree is 0, or `None` if
+        // some node below is unbalanced
+        fn balanced_height<T>(node: &Option<Box<BinaryNode<T>>>) -> Option<usize> {
+            let Some(node) = node else {
+                return Some(0);
+            };
+
+            let left = balanced_height(&node.left)?;
+            let right = balanced_height(&node.right)?;
+            (left.abs_diff(right) <= 1).then(|| left.max(right) + 1)
+        }
+
+        balanced_height(&self.left)
+            .zip(balanced_height(&self.right))
+            .is_some_and(|(left, right)| left.abs_diff(right) <= 1)
+    }
+
+    /// Parses a tree from the S-expression format produced by its `Display` implementation.
+    ///
+    /// A node is written as `(value)` if it has no children, and as
+    /// `(value left right)` otherwise, with `_` standing for a missing child.
+    /// Values are parsed with `T::from_str` and may not contain whitespace,
+    /// parentheses, or consist of `_` alone. Whitespace between tokens is
+    /// ignored. The same parser backs the `FromStr` implementation, so
+    /// `input.parse::<BinaryNode<T>>()` works too.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SexprError`] pointing at the byte offset where the input
+    /// stopped making sense, for example on unbalanced parentheses, a node with
+    /// a single child, a value `T` can't parse, or text after the tree.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{BinaryNode, SexprError};
+    ///
+    /// let root: BinaryNode<i32> = BinaryNode::from_sexpr("(1 (2 (4) (5)) (3 _ (6)))").unwrap();
+    /// assert_eq!(root.right.as_ref().unwrap().left, None);
+    /// assert_eq!(root.to_string(), "(1 (2 (4) (5)) (3 _ (6)))");
+    ///
+    /// assert_eq!(
+    ///     BinaryNode::<i32>::from_sexpr("(1 (2)"),
+    ///     Err(SexprError::UnexpectedEnd)
+    /// );
+    /// ```
+    pub fn from_sexpr(input: &str) -> Result<BinaryNode<T>, SexprError>
+    where
+        T: core::str::FromStr,
+    {
+        let mut parser = SexprParser { input, position: 0 };
+        let root = parser.node()?;
+
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(root),
+            Some(_) => Err(SexprError::TrailingInput {
+                position: parser.position,
+            }),
+        }
+    }
+
+    /// Writes the tree out as its pre-order sequence of values, with `None` for every missing child.
+    ///
+    /// Each node is followed by the sequence of its left subtree and then its
+    /// right one, so a leaf `v` becomes `[Some(v), None, None]`. The markers make
+    /// the sequence describe the shape exactly, and
+    /// [`deserialize_preorder`](BinaryNode::deserialize_preorder) rebuilds the
+    /// same tree from it. This is a compact format for storing trees without
+    /// `serde`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let root: BinaryNode<i32> = "(1 (2) (3 _ (4)))".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     root.serialize_preorder(),
+    ///     vec![Some(1), Some(2), None, None, Some(3), None, Some(4), None, None]
+    /// );
+    /// ```
+    pub fn serialize_preorder(&self) -> Vec<Option<T>>
+    where
+        T: Clone,
+    {
+        let mut sequence = Vec::new();
+        let mut stack = vec![Some(self)];
+
+        while let Some(node) = stack.pop() {
+            match node {
+                Some(node) => {
+                    sequence.push(Some(node.value.clone()));
+                    stack.push(node.right.as_deref());
+                    stack.push(node.left.as_deref());
+                }
+                None => sequence.push(None),
+            }
+        }
+
+        sequence
+    }
+
+    /// Rebuilds a tree from the sequence produced by [`serialize_preorder`](BinaryNode::serialize_preorder).
+    ///
+    /// Reading stops as soon as the tree is complete, and the number of
+    /// entries it took is returned along with it, so several trees can be
+    /// stored one after another in the same sequence.
+    ///
+    /// Returns `None` if the sequence doesn't start with a tree: if it is
+    /// empty, starts with `None` or ends before every node has both of its
+    /// children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::BinaryNode;
+    ///
+    /// let sequence = [Some(1), Some(2), None, None, None, Some(5), None, None];
+    ///
+    /// let (root, used) = BinaryNode::deserialize_preorder(&sequence).unwrap();
+    /// assert_eq!(root.to_string(), "(1 (2) _)");
+    /// assert_eq!(used, 5);
+    ///
+    /// // The second tree, a single node
+    /// let (leaf, _) = BinaryNode::deserialize_preorder(&sequence[used..]).unwrap();
+    /// assert_eq!(leaf, BinaryNode::new(5));
+    ///
+    /// assert_eq!(BinaryNode::deserialize_preorder(&sequence[..4]), None);
+    /// ```
+    pub fn deserialize_preorder(sequence: &[Option<T>]) -> Option<(BinaryNode<T>, usize)>
+    where
+        T: Clone,
+    {
+        // Reads the subtree starting at `position`, which is `None` for a
+        // missing child, or fails if the sequence runs out
+        fn subtree<T: Clone>(
+            sequence: &[Option<T>],
+            position: &mut usize,
+        ) -> Option<Option<Box<BinaryNode<T>>>> {
+            let entry = sequence.get(*position)?;
+            *position += 1;
+
+            match entry {
+                Some(value) => {
+                    let left = subtree(sequence, position)?;
+                    let right = subtree(sequence, position)?;
+                    Some(Some(Box::new(BinaryNode::with_children(
+                        value.clone(),
+                        left,
+                        right,
+                    ))))
+                }
+                None => Some(None),
+            }
+        }
+
+        let mut position = 0;
+        let root = subtree(sequence, &mut position)??;
+        Some((*root, position))
+    }
+}
+
+/// In-order iterator over the values of a [`BinaryNode`] tree, returned by [`BinaryNode::iter`].
+#[derive(Debug)]
+pub struct BinaryNodeIter<'a, T> {
+    /// Nodes whose value comes next, once everything in their left subtree has been yielded
+    stack: Vec<&'a BinaryNode<T>>,
+}
+
+impl<'a, T> BinaryNodeIter<'a, T> {
+    /// Pushes `node` and its chain of left descendants, leftmost on top.
+    fn push_left_spine(&mut self, mut node: Option<&'a BinaryNode<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for BinaryNodeIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.right.as_deref());
+        Some(&node.value)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinaryNode<T> {
+    type Item = &'a T;
+    type IntoIter = BinaryNodeIter<'a, T>;
+
+    fn into_iter(self) -> BinaryNodeIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Consuming pre-order iterator over the values of a [`BinaryNode`] tree,
+/// returned by `BinaryNode::into_iter`.
+#[derive(Debug)]
+pub struct BinaryNodeIntoIter<T> {
+    /// Subtrees still to be yielded, the next one on top
+    stack: Vec<Box<BinaryNode<T>>>,
+}
+
+impl<T> Iterator for BinaryNodeIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let node = *self.stack.pop()?;
+        // Pushed in reverse, so the left subtree is yielded first
+        self.stack.extend(node.right);
+        self.stack.extend(node.left);
+        Some(node.value)
+    }
+}
+
+/// Moves the values out of a tree in depth-first pre-order: each node before
+/// its left subtree, followed by its right subtree.
+///
+/// Note that this differs from iterating over `&BinaryNode`, which yields
+/// references in in-order. Pre-order is the order in which [`traverse_tree`]
+/// visits the nodes, so a tree built by a traversal is drained in the order it
+/// was generated.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::BinaryNode;
+///
+/// let tree = BinaryNode::with_children(
+///     String::from("root"),
+///     Some(Box::new(BinaryNode::new(String::from("left")))),
+///     Some(Box::new(BinaryNode::new(String::from("right")))),
+/// );
+///
+/// let values: Vec<String> = tree.into_iter().collect();
+/// assert_eq!(values, vec!["root", "left", "right"]);
+/// ```
+impl<T> IntoIterator for BinaryNode<T> {
+    type Item = T;
+    type IntoIter = BinaryNodeIntoIter<T>;
+
+    fn into_iter(self) -> BinaryNodeIntoIter<T> {
+        BinaryNodeIntoIter {
+            stack: vec![Box::new(self)],
+        }
+    }
+}
+
+/// Error returned by [`BinaryNode::from_sexpr`] for input that is not a valid tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SexprError {
+    /// The input ended before the tree was complete
+    UnexpectedEnd,
+    /// A character that is not allowed at this point
+    UnexpectedChar {
+        /// Byte offset of the character
+        position: usize,
+        /// The character that was found
+        found: char,
+    },
+    /// A value that could not be parsed into the node type
+    InvalidValue {
+        /// Byte offset of the value
+        position: usize,
+    },
+    /// Input left over after a complete tree
+    TrailingInput {
+        /// Byte offset where the extra input starts
+        position: usize,
+    },
+}
+
+impl core::fmt::Display for SexprError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SexprError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            SexprError::UnexpectedChar { position, found } => {
+                write!(f, "unexpected {:?} at position {}", found, position)
+            }
+            SexprError::InvalidValue { position } => {
+                write!(f, "invalid value at position {}", position)
+            }
+            SexprError::TrailingInput { position } => {
+                write!(
+                    f,
+                    "unexpected input after the tree at position {}",
+                    position
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for SexprError {}
+
+/// Recursive-descent parser behind [`BinaryNode::from_sexpr`].
+struct SexprParser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl SexprParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SexprError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += c.len_utf8();
+                Ok(())
+            }
+            Some(found) => Err(SexprError::UnexpectedChar {
+                position: self.position,
+                found,
+            }),
+            None => Err(SexprError::UnexpectedEnd),
+        }
+    }
+
+    /// Reads a value or `_`, up to the next whitespace or parenthesis.
+    fn atom(&mut self) -> Result<(usize, &str), SexprError> {
+        self.skip_whitespace();
+        let start = self.position;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| !c.is_whitespace() && *c != '(' && *c != ')')
+        {
+            self.position += c.len_utf8();
+        }
+
+        match self.peek() {
+            _ if self.position > start => Ok((start, &self.input[start..self.position])),
+            Some(found) => Err(SexprError::UnexpectedChar {
+                position: start,
+                found,
+            }),
+            None => Err(SexprError::UnexpectedEnd),
+        }
+    }
+
+    fn node<T: core::str::FromStr>(&mut self) -> Result<BinaryNode<T>, SexprError> {
+        self.expect('(')?;
+
+        let (position, atom) = self.atom()?;
+        if atom == "_" {
+            return Err(SexprError::UnexpectedChar {
+                position,
+                found: '_',
+            });
+        }
+        let value = atom
+            .parse()
+            .map_err(|_| SexprError::InvalidValue { position })?;
+
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.position += 1;
+            return Ok(BinaryNode::new(value));
+        }
+
+        let left = self.child()?;
+        let right = self.child()?;
+        self.expect(')')?;
+
+        Ok(BinaryNode::with_children(value, left, right))
+    }
+
+    fn child<T: core::str::FromStr>(&mut self) -> Result<Option<Box<BinaryNode<T>>>, SexprError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => Ok(Some(Box::new(self.node()?))),
+            Some(_) => match self.atom()? {
+                (_, "_") => Ok(None),
+                (position, _) => Err(SexprError::UnexpectedChar {
+                    position,
+                    found: self.input[position..].chars().next().unwrap_or('_'),
+                }),
+            },
+            None => Err(SexprError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Formats the tree as an S-expression, such as `(1 (2) (3 _ (4)))`.
+///
+/// A node without children is written as `(value)`, and any other node as
+/// `(value left right)` with `_` for a missing child. The output can be read
+/// back with [`BinaryNode::from_sexpr`] as long as no value's `Display` output
+/// contains whitespace or parentheses.
+impl<T: core::fmt::Display> core::fmt::Display for BinaryNode<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fn child<T: core::fmt::Display>(
+            f: &mut core::fmt::Formatter<'_>,
+            node: &Option<Box<BinaryNode<T>>>,
+        ) -> core::fmt::Result {
+            match node {
+                Some(node) => write!(f, " {}", node),
+                None => write!(f, " _"),
+            }
+        }
+
+        write!(f, "({}", self.value)?;
+        if self.left.is_some() || self.right.is_some() {
+            child(f, &self.left)?;
+            child(f, &self.right)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<T: core::str::FromStr> core::str::FromStr for BinaryNode<T> {
+    type Err = SexprError;
+
+    /// Same as [`BinaryNode::from_sexpr`].
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        BinaryNode::from_sexpr(input)
+    }
+}
+
+/// Tree node example for trees with any number of children
+///
+/// With the `serde` feature enabled, `NAryNode` implements `Serialize` and
+/// `Deserialize`, using the `value` and `children` field names.
+///
+/// Two trees are equal when they have the same shape and equal values in the
+/// same positions.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NAryNode<T> {
+    pub value: T,
+    pub children: Vec<NAryNode<T>>,
+}
+
+impl<T> NAryNode<T> {
+    /// Creates a new `NAryNode` with the given value and no children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let node = NAryNode::new(42);
+    /// assert_eq!(node.value, 42);
+    /// assert!(node.children.is_empty());
+    /// ```
+    pub fn new(value: T) -> Self {
+        NAryNode {
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    /// Creates a new `NAryNode` with the given value and children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let node = NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(3)]);
+    /// assert_eq!(node.children.len(), 2);
+    /// ```
+    pub fn with_children(value: T, children: Vec<NAryNode<T>>) -> Self {
+        NAryNode { value, children }
+    }
+
+    /// Returns references to this node's children, in order.
+    ///
+    /// This is a ready-made branch function for the traversal functions and
+    /// [`for_tree!`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{for_tree, NAryNode};
+    ///
+    /// let root = NAryNode::with_children(1, vec![
+    ///     NAryNode::with_children(2, vec![NAryNode::new(4)]),
+    ///     NAryNode::new(3),
+    /// ]);
+    ///
+    /// let mut values = Vec::new();
+    /// for_tree!(node in &root; |_| true; |node| node.children() => {
+    ///     values.push(node.value);
+    /// });
+    ///
+    /// assert_eq!(values, vec![1, 2, 4, 3]);
+    /// ```
+    pub fn children(&self) -> Vec<&NAryNode<T>> {
+        self.children.iter().collect()
+    }
+
+    /// Builds a new tree with the same shape whose values are mapped with `f`.
+    ///
+    /// This is a method form of [`map_tree`].
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> NAryNode<U> {
+        map_tree(self, f)
+    }
+
+    /// Compares the shape of two trees, and their values with `value_eq`.
+    ///
+    /// Returns `true` if both trees have the same number of children at every
+    /// node and `value_eq` holds for every pair of values in the same position.
+    /// Pass `|_, _| true` to compare only the shape.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let a = NAryNode::with_children(1, vec![NAryNode::new(2)]);
+    /// let b = NAryNode::with_children("x", vec![NAryNode::new("y")]);
+    ///
+    /// assert!(a.structural_eq(&b, |_, _| true));
+    /// assert!(!a.structural_eq(&NAryNode::new("x"), |_, _| true));
+    /// ```
+    pub fn structural_eq<U, F>(&self, other: &NAryNode<U>, mut value_eq: F) -> bool
+    where
+        F: FnMut(&T, &U) -> bool,
+    {
+        fn eq<T, U, F: FnMut(&T, &U) -> bool>(
+            a: &NAryNode<T>,
+            b: &NAryNode<U>,
+            value_eq: &mut F,
+        ) -> bool {
+            value_eq(&a.value, &b.value)
+                && a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(&b.children)
+                    .all(|(a, b)| eq(a, b, value_eq))
+        }
+
+        eq(self, other, &mut value_eq)
+    }
+
+    /// Sorts the children of every node in the tree by a key extracted from their values.
+    ///
+    /// Unlike [`traverse_tree_ordered`], which sorts children each time they are
+    /// visited, this changes the tree itself, so every later traversal sees the
+    /// children in sorted order. The sort is stable, and the tree is walked
+    /// with an explicit stack, so deep trees are fine.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let mut root = NAryNode::with_children("root", vec![
+    ///     NAryNode::with_children("b", vec![NAryNode::new("z"), NAryNode::new("y")]),
+    ///     NAryNode::new("a"),
+    /// ]);
+    ///
+    /// root.sort_children_by_key(|name| *name);
+    ///
+    /// let order: Vec<_> = arboriter::collect_tree(&root, |_| true, |n| n.children())
+    ///     .iter()
+    ///     .map(|n| n.value)
+    ///     .collect();
+    /// assert_eq!(order, vec!["root", "a", "b", "y", "z"]);
+    /// ```
+    pub fn sort_children_by_key<K, F>(&mut self, mut f: F)
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            node.children.sort_by_key(|child| f(&child.value));
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Removes every descendant whose value fails `keep`, together with its subtree.
+    ///
+    /// This is the in-place counterpart of [`filter_tree`], in the way
+    /// `Vec::retain` is to `Iterator::filter`: nothing is cloned, and the
+    /// removed subtrees are dropped. A kept node whose children were all removed
+    /// stays in the tree as a leaf. The root is always kept, so `keep` is never
+    /// called for it, nor for the descendants of a removed node. The tree is
+    /// walked with an explicit stack, so deep trees are fine.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let mut root = NAryNode::with_children(1, vec![
+    ///     NAryNode::with_children(2, vec![NAryNode::new(-4)]),
+    ///     NAryNode::with_children(-3, vec![NAryNode::new(5)]),
+    /// ]);
+    ///
+    /// root.retain(|n| *n > 0);
+    ///
+    /// assert_eq!(root, NAryNode::with_children(1, vec![NAryNode::new(2)]));
+    /// ```
+    pub fn retain<P: FnMut(&T) -> bool>(&mut self, mut keep: P) {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            node.children.retain(|child| keep(&child.value));
+            stack.extend(node.children.iter_mut().rev());
+        }
+    }
+
+    /// Replaces the subtree of the first node whose value matches `pred` with `replacement`.
+    ///
+    /// Nodes are tested in depth-first pre-order, starting with the root, and
+    /// only the first match is replaced; `pred` is not called for the nodes
+    /// after it, nor for anything inside `replacement`. If the root matches,
+    /// the whole tree becomes `replacement`. The replaced subtree is dropped.
+    /// Returns `true` if a node matched, and `false` if the tree was left
+    /// unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::NAryNode;
+    ///
+    /// let mut root = NAryNode::with_children(1, vec![
+    ///     NAryNode::with_children(2, vec![NAryNode::new(4)]),
+    ///     NAryNode::new(3),
+    /// ]);
+    ///
+    /// let replacement = NAryNode::with_children(20, vec![NAryNode::new(40), NAryNode::new(50)]);
+    /// assert!(root.replace_subtree(|n| *n == 2, replacement));
+    /// assert!(!root.replace_subtree(|n| *n == 4, NAryNode::new(0)));
+    ///
+    /// assert_eq!(root, NAryNode::with_children(1, vec![
+    ///     NAryNode::with_children(20, vec![NAryNode::new(40), NAryNode::new(50)]),
+    ///     NAryNode::new(3),
+    /// ]));
+    /// ```
+    pub fn replace_subtree<P: FnMut(&T) -> bool>(
+        &mut self,
+        mut pred: P,
+        replacement: NAryNode<T>,
+    ) -> bool {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            if pred(&node.value) {
+                *node = replacement;
+                return true;
+            }
+            stack.extend(node.children.iter_mut().rev());
+        }
+
+        false
+    }
+}
+
+/// Error returned by [`NAryNode::from_parent_list`] when the list does not describe a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeBuildError {
+    /// No entry has `None` as its parent
+    NoRoot,
+    /// More than one entry has `None` as its parent
+    MultipleRoots {
+        /// Index of the first root
+        first: usize,
+        /// Index of the second root
+        second: usize,
+    },
+    /// An entry refers to a parent index that is out of bounds
+    MissingParent {
+        /// Index of the entry
+        node: usize,
+        /// The parent index it refers to
+        parent: usize,
+    },
+    /// An entry is not connected to the root because its parents form a cycle
+    Cycle {
+        /// Index of the lowest entry that is not connected to the root
+        node: usize,
+    },
+}
+
+impl core::fmt::Display for TreeBuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TreeBuildError::NoRoot => write!(f, "no node without a parent"),
+            TreeBuildError::MultipleRoots { first, second } => {
+                write!(f, "nodes {} and {} both have no parent", first, second)
+            }
+            TreeBuildError::MissingParent { node, parent } => {
+                write!(f, "node {} refers to missing parent {}", node, parent)
+            }
+            TreeBuildError::Cycle { node } => {
+                write!(f, "node {} is part of a parent cycle", node)
+            }
+        }
+    }
+}
+
+impl core::error::Error for TreeBuildError {}
+
+/// Error returned by [`NAryNode::to_binary`] when a node has more than two children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooManyChildren {
+    /// The number of children of the first such node, in depth-first order
+    pub children: usize,
+}
+
+impl core::fmt::Display for TooManyChildren {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "a node with {} children can't be part of a binary tree",
+            self.children
+        )
+    }
+}
+
+impl core::error::Error for TooManyChildren {}
+
+impl<T: Clone> NAryNode<T> {
+    /// Builds a tree from a flat list of `(parent_index, value)` entries.
+    ///
+    /// This is the shape trees usually have when they come out of a database
+    /// table with a parent column. Each entry names the index of its parent in
+    /// the list, and exactly one entry, the root, has no parent. Children keep
+    /// the relative order they have in the list, and parents may come before or
+    /// after their children.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`TreeBuildError`] if there is no root, more than one root, an
+    /// entry whose parent index is out of bounds, or entries whose parents form
+    /// a cycle instead of leading back to the root.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{NAryNode, TreeBuildError};
+    ///
+    /// let rows = [(Some(2), "b"), (None, "root"), (Some(1), "a"), (Some(1), "c")];
+    /// let tree = NAryNode::from_parent_list(&rows).unwrap();
+    ///
+    /// assert_eq!(
+    ///     tree,
+    ///     NAryNode::with_children("root", vec![
+    ///         NAryNode::with_children("a", vec![NAryNode::new("b")]),
+    ///         NAryNode::new("c"),
+    ///     ])
+    /// );
+    ///
+    /// let rows = [(None, "root"), (Some(7), "orphan")];
+    /// assert_eq!(
+    ///     NAryNode::from_parent_list(&rows),
+    ///     Err(TreeBuildError::MissingParent { node: 1, parent: 7 })
+    /// );
+    /// ```
+    pub fn from_parent_list(nodes: &[(Option<usize>, T)]) -> Result<NAryNode<T>, TreeBuildError> {
+        let mut root = None;
+        let mut children = vec![Vec::new(); nodes.len()];
+
+        for (index, (parent, _)) in nodes.iter().enumerate() {
+            match *parent {
+                None => match root {
+                    None => root = Some(index),
+                    Some(first) => {
+                        return Err(TreeBuildError::MultipleRoots {
+                            first,
+                            second: index,
+                        });
+                    }
+                },
+                Some(parent) if parent >= nodes.len() => {
+                    return Err(TreeBuildError::MissingParent {
+                        node: index,
+                        parent,
+                    });
+                }
+                Some(parent) => children[parent].push(index),
+            }
+        }
+
+        let root = root.ok_or(TreeBuildError::NoRoot)?;
+
+        // Every entry has a valid parent, so an entry that can't be reached
+        // from the root must have a cycle among its ancestors
+        let mut order = Vec::with_capacity(nodes.len());
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(&children[index]);
+        }
+
+        if order.len() < nodes.len() {
+            let mut reached = vec![false; nodes.len()];
+            for &index in &order {
+                reached[index] = true;
+            }
+            let node = reached.iter().position(|&r| !r).unwrap_or(0);
+            return Err(TreeBuildError::Cycle { node });
+        }
+
+        // Build the nodes bottom-up: every entry comes after its parent in
+        // `order`, so walking it backwards finishes children before parents
+        let mut built: Vec<Option<NAryNode<T>>> = (0..nodes.len()).map(|_| None).collect();
+        for &index in order.iter().rev() {
+            let node_children = children[index]
+                .iter()
+                .map(|&child| built[child].take().expect("children are built first"))
+                .collect();
+            built[index] = Some(NAryNode::with_children(
+                nodes[index].1.clone(),
+                node_children,
+            ));
+        }
+
+        Ok(built[root].take().expect("the root is built last"))
+    }
+
+    /// Converts the tree into a [`BinaryNode`] tree.
+    ///
+    /// The first child of every node becomes its left child and the second its
+    /// right child, so a node with a single child only has a left one. This is
+    /// the inverse of the `From<&BinaryNode<T>>` conversion for trees whose
+    /// nodes with one child only have a left one. The tree is walked with an
+    /// explicit stack, so deep trees are fine.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TooManyChildren`] if any node has more than two children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use arboriter::{BinaryNode, NAryNode, TooManyChildren};
+    ///
+    /// let tree = NAryNode::with_children(1, vec![
+    ///     NAryNode::with_children(2, vec![NAryNode::new(4)]),
+    ///     NAryNode::new(3),
+    /// ]);
+    ///
+    /// let binary = tree.to_binary().unwrap();
+    /// assert_eq!(binary.value, 1);
+    /// assert_eq!(binary.left.as_ref().unwrap().left.as_ref().unwrap().value, 4);
+    /// assert_eq!(NAryNode::from(&binary), tree);
+    ///
+    /// let wide = NAryNode::with_children(1, vec![NAryNode::new(2); 3]);
+    /// assert_eq!(wide.to_binary(), Err(TooManyChildren { children: 3 }));
+    /// ```
+    pub fn to_binary(&self) -> Result<BinaryNode<T>, TooManyChildren> {
+        let mut result = None;
+        // The finished children of every node on the current path
+        let mut open: Vec<Vec<BinaryNode<T>>> = Vec::new();
+
+        traverse_events(
+            self,
+            |_| true,
+            |n| n.children(),
+            |event| {
+                match event {
+                    Event::Enter(node) => {
+                        if node.children.len() > 2 {
+                            result = Some(Err(TooManyChildren {
+                                children: node.children.len(),
+                            }));
+                            return TreeControl::Break;
+                        }
+                        open.push(Vec::with_capacity(node.children.len()));
+                    }
+                    Event::Leave(node) => {
+                        let mut children = open
+                            .pop()
+                            .expect("every Leave follows an Enter")
+                            .into_iter();
+                        let finished = BinaryNode::with_children(
+                            node.value.clone(),
+                            children.next().map(Box::new),
+                            children.next().map(Box::new),
+                        );
+                        match open.last_mut() {
+                            Some(siblings) => siblings.push(finished),
+                            None => result = Some(Ok(finished)),
+                        }
+                    }
+                }
+                TreeControl::Continue
+            },
+        );
+
+        result.expect("the traversal either stops at a wide node or leaves the root")
+    }
+}
+
+impl<T: Clone> TryFrom<&[(Option<usize>, T)]> for NAryNode<T> {
+    type Error = TreeBuildError;
+
+    /// Same as [`NAryNode::from_parent_list`].
+    fn try_from(nodes: &[(Option<usize>, T)]) -> Result<Self, Self::Error> {
+        NAryNode::from_parent_list(nodes)
+    }
+}
+
+/// Copies the tree with an explicit stack, so cloning a deep tree can't
+/// overflow the call stack.
+impl<T: Clone> Clone for NAryNode<T> {
+    fn clone(&self) -> Self {
+        map_tree(self, T::clone)
+    }
+}
+
+/// Converts a [`BinaryNode`] tree, keeping the left child before the right one
+/// and leaving out missing children, so a node with only a right child ends up
+/// with a single child. The tree is walked with an explicit stack, so deep
+/// trees are fine.
+impl<T: Clone> From<&BinaryNode<T>> for NAryNode<T> {
+    fn from(root: &BinaryNode<T>) -> Self {
+        let mut result = None;
+        // The finished children of every node on the current path
+        let mut open: Vec<Vec<NAryNode<T>>> = Vec::new();
+
+        traverse_events(
+            root,
+            |_| true,
+            |n| n.children(),
+            |event| {
+                match event {
+                    Event::Enter(_) => open.push(Vec::new()),
+                    Event::Leave(node) => {
+                        let children = open.pop().expect("every Leave follows an Enter");
+                        let finished = NAryNode::with_children(node.value.clone(), children);
+                        match open.last_mut() {
+                            Some(siblings) => siblings.push(finished),
+                            None => result = Some(finished),
+                        }
+                    }
+                }
+                TreeControl::Continue
+            },
+        );
+
+        result.expect("the root is left last")
+    }
+}
+
+/// Builds a new [`NAryNode`] tree with the same shape whose values are mapped with `f`.
+///
+/// `f` is called once per value in depth-first pre-order. The new tree is built
+/// with an explicit stack rather than recursion, so very deep trees cannot
+/// overflow the call stack.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to map
+/// * `f` - A function that maps each value to the value of the new node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{map_tree, NAryNode};
+///
+/// let root = NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(3)]);
+/// let strings = map_tree(&root, |n| n.to_string());
+///
+/// assert_eq!(strings.value, "1");
+/// assert_eq!(strings.children[1].value, "3");
+/// ```
+pub fn map_tree<T, U, F>(root: &NAryNode<T>, mut f: F) -> NAryNode<U>
+where
+    F: FnMut(&T) -> U,
+{
+    // One frame per node on the current path: its mapped value, the source
+    // children still to be mapped, and the children mapped so far
+    let mut stack = vec![(f(&root.value), root.children.iter(), Vec::new())];
+    let mut mapped = None;
+
+    while let Some((_, remaining, _)) = stack.last_mut() {
+        if let Some(child) = remaining.next() {
+            let value = f(&child.value);
+            stack.push((
+                value,
+                child.children.iter(),
+                Vec::with_capacity(child.children.len()),
+            ));
+            continue;
+        }
+
+        if let Some((value, _, children)) = stack.pop() {
+            let node = NAryNode::with_children(value, children);
+            match stack.last_mut() {
+                Some((_, _, siblings)) => siblings.push(node),
+                None => mapped = Some(node),
+            }
+        }
+    }
+
+    mapped.expect("the root is mapped once the stack is empty")
+}
+
+/// Transforms some values of an [`NAryNode`] tree, copying it only if anything changed.
+///
+/// `f` is called once per value in depth-first pre-order and returns
+/// `Some(new_value)` to replace a value or `None` to keep it. When `f` keeps
+/// every value, the result is `Cow::Borrowed(root)` and nothing is allocated,
+/// which makes rewrite passes that usually find nothing to do almost free.
+/// Otherwise the result is an owned tree with the new values, in which every
+/// unchanged subtree is copied exactly once. Like [`map_tree`], this works
+/// with an explicit stack, so deep trees are fine.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to transform
+/// * `f` - A function that returns the new value of a node, or `None` to keep its value
+///
+/// # Example
+///
+/// ```
+/// use std::borrow::Cow;
+/// use arboriter::{map_tree_cow, NAryNode};
+///
+/// let root = NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(3)]);
+///
+/// let unchanged = map_tree_cow(&root, |n| (*n > 10).then(|| n * 10));
+/// assert!(matches!(unchanged, Cow::Borrowed(_)));
+///
+/// let changed = map_tree_cow(&root, |n| (*n == 3).then(|| 30));
+/// assert_eq!(
+///     changed.into_owned(),
+///     NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(30)])
+/// );
+/// ```
+pub fn map_tree_cow<T, F>(root: &NAryNode<T>, mut f: F) -> Cow<'_, NAryNode<T>>
+where
+    T: Clone,
+    F: FnMut(&T) -> Option<T>,
+{
+    // One frame per node on the current path: the source node, its new value
+    // if it changed, its children still to be transformed, and the children
+    // transformed so far
+    let mut stack = vec![(root, f(&root.value), root.children.iter(), Vec::new())];
+
+    loop {
+        let (_, _, remaining, _) = stack.last_mut().expect("the root frame is popped last");
+
+        if let Some(child) = remaining.next() {
+            let value = f(&child.value);
+            stack.push((child, value, child.children.iter(), Vec::new()));
+            continue;
+        }
+
+        let (node, value, _, children) = stack.pop().expect("the stack is not empty");
+        let unchanged = value.is_none() && children.iter().all(|c| matches!(c, Cow::Borrowed(_)));
+
+        let result = if unchanged {
+            Cow::Borrowed(node)
+        } else {
+            Cow::Owned(NAryNode::with_children(
+                value.unwrap_or_else(|| node.value.clone()),
+                children.into_iter().map(Cow::into_owned).collect(),
+            ))
+        };
+
+        match stack.last_mut() {
+            Some((_, _, _, siblings)) => siblings.push(result),
+            None => return result,
+        }
+    }
+}
+
+/// Builds a copy of an [`NAryNode`] tree without the nodes whose value fails `keep`.
+///
+/// Unlike pruning during a traversal, this produces a new, smaller tree. A node
+/// that fails `keep` is removed together with all of its descendants, whether or
+/// not they would pass. A kept node whose children were all removed stays in the
+/// copy as a leaf. `keep` is called in depth-first pre-order, and never for the
+/// descendants of a removed node.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to filter
+/// * `keep` - A function that returns `true` for the values to keep
+///
+/// # Returns
+///
+/// The filtered copy, or `None` if the root itself fails `keep`.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{filter_tree, NAryNode};
+///
+/// let root = NAryNode::with_children(1, vec![
+///     NAryNode::with_children(2, vec![NAryNode::new(4)]),
+///     NAryNode::with_children(-3, vec![NAryNode::new(5)]),
+/// ]);
+///
+/// let filtered = filter_tree(&root, |n| *n > 0).unwrap();
+///
+/// assert_eq!(filtered.children.len(), 1);
+/// assert_eq!(filtered.children[0].value, 2);
+/// assert_eq!(filtered.children[0].children[0].value, 4);
+/// ```
+pub fn filter_tree<T, P>(root: &NAryNode<T>, mut keep: P) -> Option<NAryNode<T>>
+where
+    T: Clone,
+    P: FnMut(&T) -> bool,
+{
+    if !keep(&root.value) {
+        return None;
+    }
+
+    // One frame per node on the current path: its value, the source children
+    // still to be checked, and the children kept so far
+    let mut stack = vec![(root.value.clone(), root.children.iter(), Vec::new())];
+    let mut filtered = None;
+
+    while let Some((_, remaining, _)) = stack.last_mut() {
+        if let Some(child) = remaining.next() {
+            if keep(&child.value) {
+                stack.push((child.value.clone(), child.children.iter(), Vec::new()));
+            }
+            continue;
+        }
+
+        if let Some((value, _, children)) = stack.pop() {
+            let node = NAryNode::with_children(value, children);
+            match stack.last_mut() {
+                Some((_, _, siblings)) => siblings.push(node),
+                None => filtered = Some(node),
+            }
+        }
+    }
+
+    filtered
+}
+
+/// Finds the deepest node of an [`NAryNode`] tree that has both `a` and `b` in its subtree.
+///
+/// A node counts as being in its own subtree, so if one value is an ancestor
+/// of the other, that value is returned, and if `a == b` the node holding it
+/// is. If a value occurs more than once, every occurrence counts: the result
+/// is a node whose subtree holds some occurrence of each value while none of
+/// its children's subtrees does, and the first such node in depth-first order
+/// if there are several. A [`BinaryNode`] tree can be searched by converting
+/// it with `NAryNode::from`.
+///
+/// The tree is reduced bottom-up with [`reduce_up`] in a single pass: each
+/// node learns from its children which of the two values they contain, and the
+/// first node found to contain both is the answer.
+///
+/// # Returns
+///
+/// The value of the lowest common ancestor, or `None` if `a` or `b` is not in
+/// the tree.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{lowest_common_ancestor, NAryNode};
+///
+/// let root = NAryNode::with_children(1, vec![
+///     NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+///     NAryNode::new(3),
+/// ]);
+///
+/// assert_eq!(lowest_common_ancestor(&root, &4, &5), Some(&2));
+/// assert_eq!(lowest_common_ancestor(&root, &4, &3), Some(&1));
+/// assert_eq!(lowest_common_ancestor(&root, &2, &5), Some(&2));
+/// assert_eq!(lowest_common_ancestor(&root, &4, &6), None);
+/// ```
+pub fn lowest_common_ancestor<'a, T: PartialEq>(
+    root: &'a NAryNode<T>,
+    a: &T,
+    b: &T,
+) -> Option<&'a T> {
+    // Per subtree: whether it contains `a`, whether it contains `b`, and the
+    // ancestor once one has been found below
+    reduce_up(
+        &root,
+        |node| node.children(),
+        |node, children: Vec<(bool, bool, Option<&'a T>)>| {
+            if let Some(found) = children.iter().find_map(|(_, _, lca)| *lca) {
+                return (true, true, Some(found));
+            }
+
+            let has_a = node.value == *a || children.iter().any(|(has_a, _, _)| *has_a);
+            let has_b = node.value == *b || children.iter().any(|(_, has_b, _)| *has_b);
+            let lca = (has_a && has_b).then_some(&node.value);
+            (has_a, has_b, lca)
+        },
+    )
+    .2
+}
+
+/// Splits a tree into its top `depth + 1` levels and the subtrees hanging below them.
+///
+/// The first part of the result is a copy of the tree that keeps only the
+/// nodes at depth `depth` or less, with the root at depth 0; nodes at depth
+/// `depth` become leaves. The second part holds copies of the cut-off
+/// subtrees, each rooted at depth `depth + 1`, in depth-first order. This is
+/// useful for paging a large tree: show the top part, and load the
+/// subtrees on demand.
+///
+/// The tree is copied with an explicit stack, so deep trees are fine.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree to split
+/// * `depth` - The depth of the deepest nodes to keep in the top part
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{split_at_depth, NAryNode};
+///
+/// let root = NAryNode::with_children(1, vec![
+///     NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+///     NAryNode::with_children(3, vec![NAryNode::new(6)]),
+/// ]);
+///
+/// let (top, below) = split_at_depth(&root, 1);
+///
+/// assert_eq!(top, NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(3)]));
+/// assert_eq!(below, vec![NAryNode::new(4), NAryNode::new(5), NAryNode::new(6)]);
+/// ```
+pub fn split_at_depth<T>(root: &NAryNode<T>, depth: usize) -> (NAryNode<T>, Vec<NAryNode<T>>)
+where
+    T: Clone,
+{
+    let mut below = Vec::new();
+
+    // One frame per node on the current path: its value, the source children
+    // still to be copied, and the children copied so far
+    let mut stack = vec![(root.value.clone(), root.children.iter(), Vec::new())];
+
+    loop {
+        let frame_depth = stack.len() - 1;
+        let (_, remaining, _) = stack.last_mut().expect("the root frame is popped last");
+
+        match remaining.next() {
+            Some(child) if frame_depth < depth => {
+                stack.push((child.value.clone(), child.children.iter(), Vec::new()));
+            }
+            Some(child) => below.push(map_tree(child, T::clone)),
+            None => {
+                let (value, _, children) = stack.pop().expect("the stack is not empty");
+                let node = NAryNode::with_children(value, children);
+
+                match stack.last_mut() {
+                    Some((_, _, siblings)) => siblings.push(node),
+                    None => return (node, below),
+                }
+            }
+        }
+    }
+}
+
+/// Builds an [`NAryNode`] tree from the first `n` nodes of a tree, in depth-first order.
+///
+/// The nodes keep their parents and their order among siblings, so the
+/// result is the top-left corner of the tree as a depth-first traversal would
+/// reach it. A node whose children were all cut off becomes a leaf, and a node
+/// that was cut off partway through its children keeps the ones that were
+/// reached. The root is always included, so an `n` of 0 gives the same tree
+/// as 1. This is useful for previewing large or generated trees.
+///
+/// `branch_fn` is only called for nodes whose children may still fit, so a
+/// generated tree is expanded no further than needed. The tree is built with
+/// an explicit stack, so deep trees are fine.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `n` - The number of nodes to keep
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{truncate_tree, NAryNode};
+///
+/// // The implicit binary tree 1..16, cut to its first five nodes: 1, 2, 4, 8, 9
+/// let preview = truncate_tree(&1, 5, |n| if *n < 8 { vec![n * 2, n * 2 + 1] } else { vec![] });
+///
+/// assert_eq!(preview, NAryNode::with_children(1, vec![
+///     NAryNode::with_children(2, vec![
+///         NAryNode::with_children(4, vec![NAryNode::new(8), NAryNode::new(9)]),
+///     ]),
+/// ]));
+/// ```
+pub fn truncate_tree<T, B>(root: &T, n: usize, mut branch_fn: B) -> NAryNode<T>
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut kept = 1;
+    let mut expand = |node: &T, kept: usize| {
+        if kept < n {
+            branch_fn(node).into_iter()
+        } else {
+            Vec::new().into_iter()
+        }
+    };
+
+    // One frame per node on the current path: its value, its children still
+    // to be reached, and the children kept so far
+    let mut stack = vec![(root.clone(), expand(root, kept), Vec::new())];
+
+    loop {
+        let (_, remaining, _) = stack.last_mut().expect("the root frame is popped last");
+
+        match remaining.next() {
+            Some(child) if kept < n => {
+                kept += 1;
+                let grandchildren = expand(&child, kept);
+                stack.push((child, grandchildren, Vec::new()));
+            }
+            _ => {
+                let (value, _, children) = stack.pop().expect("the stack is not empty");
+                let node = NAryNode::with_children(value, children);
+
+                match stack.last_mut() {
+                    Some((_, _, siblings)) => siblings.push(node),
+                    None => return node,
+                }
+            }
+        }
+    }
+}
+
+/// Merges two [`NAryNode`] trees, pairing up children by key.
+///
+/// The two roots are always merged into the root of the result, whose value
+/// is `combine(&a.value, &b.value)`. At every merged node, each child of `a`
+/// is paired with the first child of `b` that has the same key and was not
+/// paired yet; such pairs are merged the same way, recursively. The result
+/// lists the children of `a` in their order, merged where they were paired,
+/// followed by the unpaired children of `b` in their order. Unpaired children
+/// are copied over with their whole subtrees.
+///
+/// This is how layered configuration trees are usually combined, with `b`
+/// overriding or extending `a`. Keys only need to be comparable for equality,
+/// so pairing takes time proportional to the product of the two child counts
+/// at each node. The result is built with an explicit stack, so deep trees are
+/// fine.
+///
+/// # Parameters
+///
+/// * `a` - The first tree
+/// * `b` - The second tree
+/// * `key_fn` - A function that returns the key used to pair up children
+/// * `combine` - A function that returns the value of a merged node from the
+///   values in `a` and `b`
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{merge_trees, NAryNode};
+///
+/// let defaults = NAryNode::with_children(("config", 0), vec![
+///     NAryNode::with_children(("server", 0), vec![NAryNode::new(("port", 80))]),
+///     NAryNode::new(("debug", 0)),
+/// ]);
+/// let overrides = NAryNode::with_children(("config", 0), vec![
+///     NAryNode::with_children(("server", 0), vec![
+///         NAryNode::new(("port", 8080)),
+///         NAryNode::new(("workers", 4)),
+///     ]),
+/// ]);
+///
+/// let merged = merge_trees(&defaults, &overrides, |(name, _)| *name, |_, b| *b);
+///
+/// assert_eq!(merged, NAryNode::with_children(("config", 0), vec![
+///     NAryNode::with_children(("server", 0), vec![
+///         NAryNode::new(("port", 8080)),
+///         NAryNode::new(("workers", 4)),
+///     ]),
+///     NAryNode::new(("debug", 0)),
+/// ]));
+/// ```
+pub fn merge_trees<T, K, KF, F>(
+    a: &NAryNode<T>,
+    b: &NAryNode<T>,
+    mut key_fn: KF,
+    mut combine: F,
+) -> NAryNode<T>
+where
+    T: Clone,
+    K: Eq,
+    KF: FnMut(&T) -> K,
+    F: FnMut(&T, &T) -> T,
+{
+    type Pairs<'a, T> = Vec<(&'a NAryNode<T>, Option<&'a NAryNode<T>>)>;
+
+    // The children of `a` with their partners in `b`, then the rest of `b`
+    fn pair_children<'a, T, K: Eq>(
+        a: &'a NAryNode<T>,
+        b: &'a NAryNode<T>,
+        key_fn: &mut impl FnMut(&T) -> K,
+    ) -> Pairs<'a, T> {
+        let mut unpaired: Vec<_> = b
+            .children
+            .iter()
+            .map(|child| Some((key_fn(&child.value), child)))
+            .collect();
+
+        let mut pairs: Pairs<'a, T> = a
+            .children
+            .iter()
+            .map(|child| {
+                let key = key_fn(&child.value);
+                let partner = unpaired
+                    .iter_mut()
+                    .find(|slot| slot.as_ref().is_some_and(|(k, _)| *k == key))
+                    .and_then(Option::take)
+                    .map(|(_, partner)| partner);
+                (child, partner)
+            })
+            .collect();
+
+        pairs.extend(
+            unpaired
+                .into_iter()
+                .flatten()
+                .map(|(_, child)| (child, None)),
+        );
+        pairs
+    }
+
+    // One frame per merged node on the current path: its value, the pairs of
+    // children still to be merged, and the children merged so far
+    let mut stack = vec![(
+        combine(&a.value, &b.value),
+        pair_children(a, b, &mut key_fn).into_iter(),
+        Vec::new(),
+    )];
+
+    loop {
+        let (_, remaining, merged) = stack.last_mut().expect("the root frame is popped last");
+
+        match remaining.next() {
+            Some((a, Some(b))) => {
+                stack.push((
+                    combine(&a.value, &b.value),
+                    pair_children(a, b, &mut key_fn).into_iter(),
+                    Vec::new(),
+                ));
+            }
+            Some((only, None)) => merged.push(map_tree(only, T::clone)),
+            None => {
+                let (value, _, children) = stack.pop().expect("the stack is not empty");
+                let node = NAryNode::with_children(value, children);
+
+                match stack.last_mut() {
+                    Some((_, _, siblings)) => siblings.push(node),
+                    None => return node,
+                }
+            }
+        }
+    }
+}
+
+/// Flattens a tree into `(depth, value)` pairs in depth-first pre-order.
+///
+/// The root has depth 0. This is the "indented list" form of a tree, handy
+/// for writing it out as indented text or storing it in a flat table;
+/// [`unflatten`] turns it back into a tree.
+///
+/// # Parameters
+///
+/// * `root` - The root of the tree
+/// * `branch_fn` - A function that returns a vector of branches from a given node
+///
+/// # Example
+///
+/// ```
+/// use arboriter::flatten;
+///
+/// let flat = flatten(&1, |n| if *n < 4 { vec![n * 2, n * 2 + 1] } else { vec![] });
+///
+/// assert_eq!(flat, vec![(0, 1), (1, 2), (2, 4), (2, 5), (1, 3), (2, 6), (2, 7)]);
+/// ```
+pub fn flatten<T, B>(root: &T, mut branch_fn: B) -> Vec<(usize, T)>
+where
+    T: Clone,
+    B: FnMut(&T) -> Vec<T>,
+{
+    let mut flat = Vec::new();
+
+    walk(
+        root.clone(),
+        |_, _| true,
+        |node, _| branch_fn(node),
+        |node, depth| {
+            flat.push((depth, node.clone()));
+            TreeControl::Continue
+        },
+    );
+
+    flat
+}
+
+/// Rebuilds a tree from `(depth, value)` pairs in depth-first pre-order.
+///
+/// This is the inverse of [`flatten`]. Each entry becomes a child of the
+/// closest preceding entry that is one level shallower. Returns `None` if the
+/// list doesn't describe a single tree: when it is empty, when the first entry
+/// isn't at depth 0, when a later entry is at depth 0, or when the depth
+/// increases by more than 1 from one entry to the next.
+///
+/// # Parameters
+///
+/// * `list` - The depth-tagged values, root first
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{unflatten, NAryNode};
+///
+/// // root
+/// //   docs
+/// //     guide.md
+/// //   README.md
+/// let lines = [(0, "root"), (1, "docs"), (2, "guide.md"), (1, "README.md")];
+///
+/// assert_eq!(
+///     unflatten(&lines),
+///     Some(NAryNode::with_children("root", vec![
+///         NAryNode::with_children("docs", vec![NAryNode::new("guide.md")]),
+///         NAryNode::new("README.md"),
+///     ]))
+/// );
+///
+/// // "guide.md" can't be two levels below "root"
+/// assert_eq!(unflatten(&[(0, "root"), (2, "guide.md")]), None);
+/// ```
+pub fn unflatten<T>(list: &[(usize, T)]) -> Option<NAryNode<T>>
+where
+    T: Clone,
+{
+    let ((0, root), rest) = list.split_first()? else {
+        return None;
+    };
+
+    // The nodes on the path to the latest entry, with the children collected so far
+    let mut stack = vec![(root.clone(), Vec::new())];
+
+    for (depth, value) in rest {
+        if *depth == 0 || *depth > stack.len() {
+            return None;
+        }
+
+        while stack.len() > *depth {
+            close_last(&mut stack);
+        }
+        stack.push((value.clone(), Vec::new()));
+    }
+
+    while stack.len() > 1 {
+        close_last(&mut stack);
+    }
+
+    let (value, children) = stack.pop()?;
+    Some(NAryNode::with_children(value, children))
+}
+
+/// Turns the last node on an [`unflatten`] path into a child of the one before it.
+fn close_last<T>(stack: &mut Vec<(T, Vec<NAryNode<T>>)>) {
+    if let Some((value, children)) = stack.pop()
+        && let Some((_, siblings)) = stack.last_mut()
+    {
+        siblings.push(NAryNode::with_children(value, children));
+    }
+}
+
+/// Demonstrates traversing a binary tree with the for_tree macro.
+///
+/// This function shows a common pattern for traversing a binary tree using
+/// the [`for_tree!`] macro. It prints the value of each node in the tree
+/// in depth-first order.
+///
+/// # Parameters
+///
+/// * `root` - The root node of the binary tree to traverse
+///
+/// # Type Parameters
+///
+/// * `T` - The type of value stored in each node, must implement Debug
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{BinaryNode, binary_tree_example};
+///
+/// // Create a simple binary tree
+/// let root = BinaryNode::with_children(
+///     1,
+///     Some(Box::new(BinaryNode::new(2))),
+///     Some(Box::new(BinaryNode::new(3)))
+/// );
+///
+/// // This will print:
+/// // Traversing binary tree:
+/// // Visiting node with value: 1
+/// // Visiting node with value: 2
+/// // Visiting node with value: 3
+/// binary_tree_example(&root);
+/// ```
+#[cfg(feature = "std")]
+pub fn binary_tree_example<T: std::fmt::Debug>(root: &BinaryNode<T>) {
+    println!("Traversing binary tree:");
+
+    for_tree!(node in root; |_| true; |node| node.children() => {
+        println!("Visiting node with value: {:?}", node.value);
+    });
+}
+
+/// Demonstrates using for_tree for string generation.
+///
+/// This function shows how [`for_tree!`] can be used for tasks other than
+/// traversing existing data structures. It generates all possible strings
+/// of "a", "b", and "c" with length <= 3, illustrating how tree traversal
+/// can be used for combinatorial generation.
+///
+/// The example also demonstrates the use of [`prune!`] to limit the depth
+/// of the traversal.
+///
+/// # Example
+///
+/// ```no_run
+/// use arboriter::generate_strings_example;
+///
+/// // This will print all strings of a, b, c with length <= 3:
+/// // ""
+/// // "a"
+/// // "aa"
+/// // "aaa"
+/// // "aab"
+/// // "aac"
+/// // "ab"
+/// // ...etc.
+/// generate_strings_example();
+/// ```
+#[cfg(feature = "std")]
+pub fn generate_strings_example() {
+    println!("Generating strings of a, b, c with length <= 3:");
+
+    for_tree!(s in String::new(); |s| s.len() <= 3; |s| {
+        let mut branches: Vec<String> = Vec::new();
+        branches.push(format!("{}a", s));
+        branches.push(format!("{}b", s));
+        branches.push(format!("{}c", s));
+        branches
+    } => {
+        println!("Generated string: {}", s);
+
+        if s.len() == 3 {
+            prune!(); // Don't generate longer strings
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    // The same tree as `create_sample_tree` in the examples:
+    //          1
+    //        /   \
+    //       2     3
+    //      / \   / \
+    //     4   5 -7  6
+    //    / \
+    //  -8   9
+    fn sample_tree() -> BinaryNode<i32> {
+        BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::with_children(
+                    4,
+                    Some(Box::new(BinaryNode::new(-8))),
+                    Some(Box::new(BinaryNode::new(9))),
+                ))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                Some(Box::new(BinaryNode::new(-7))),
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        )
+    }
+
+    fn binary_children<'a, T>(node: &&'a BinaryNode<T>) -> Vec<&'a BinaryNode<T>> {
+        node.children()
+    }
+
+    #[test]
+    fn test_binary_tree() {
+        // Create a simple binary tree
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                None,
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        );
+
+        // Collect values using for_tree
+        let mut values = Vec::new();
+
+        for_tree!(node in &root; |_| true; |node| {
+            let mut children = Vec::new();
+            if let Some(left) = &node.left {
+                children.push(left.as_ref());
+            }
+            if let Some(right) = &node.right {
+                children.push(right.as_ref());
+            }
+            children
+        } => {
+            values.push(node.value);
+        });
+
+        // Verify depth-first traversal order
+        assert_eq!(values, vec![1, 2, 4, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_string_generation() {
+        // Generate all strings of length <= 2
+        let mut strings = Vec::new();
+
+        for_tree!(s in String::new(); |s| s.len() <= 2; |s| {
+            let mut branches = Vec::new();
+            branches.push(format!("{}a", s));
+            branches.push(format!("{}b", s));
+            branches
+        } => {
+            strings.push(s.clone());
+
+            if s.len() == 2 {
+                prune!();
+            }
+        });
+
+        // Check that we got all possible strings
+        // The order is determined by the depth-first traversal:
+        // "" -> "a" -> "aa" -> "ab" -> "b" -> "ba" -> "bb"
+        let expected = vec!["", "a", "aa", "ab", "b", "ba", "bb"];
+
+        assert_eq!(strings, expected);
+    }
+
+    #[test]
+    fn test_break() {
+        // Test breaking out of traversal
+        let mut count = 0;
+
+        for_tree!(n in 0; |n| *n < 10; |n| vec![*n + 1] => {
+            count += 1;
+
+            if *n >= 5 {
+                break_tree!();
+            }
+        });
+
+        // Should only visit 0, 1, 2, 3, 4, 5
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn test_prune() {
+        // Create a binary tree with pruning
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2, // We'll prune this branch
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                None,
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        );
+
+        let mut values = Vec::new();
+
+        for_tree!(node in &root; |_| true; |node| {
+            let mut children = Vec::new();
+            if let Some(left) = &node.left {
+                children.push(left.as_ref());
+            }
+            if let Some(right) = &node.right {
+                children.push(right.as_ref());
+            }
+            children
+        } => {
+            values.push(node.value);
+
+            if node.value == 2 {
+                prune!();  // Don't visit children of node with value 2
+            }
+        });
+
+        // Should only visit 1, 2, 3, 6 (4 and 5 are pruned)
+        assert_eq!(values, vec![1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn test_collect_tree() {
+        let strings = collect_tree(
+            String::new(),
+            |s| s.len() <= 2,
+            |s| vec![format!("{}a", s), format!("{}b", s)],
+        );
+
+        assert_eq!(strings, vec!["", "a", "aa", "ab", "b", "ba", "bb"]);
+    }
+
+    #[test]
+    fn test_collect_tree_with() {
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::new(3))),
+        );
+
+        // Skip the subtree rooted at 2 through the condition
+        let values = collect_tree_with(
+            &root,
+            |node| node.value != 2,
+            |node| {
+                let mut children = Vec::new();
+                if let Some(left) = &node.left {
+                    children.push(left.as_ref());
+                }
+                if let Some(right) = &node.right {
+                    children.push(right.as_ref());
+                }
+                children
+            },
+            |node| node.value,
+        );
+
+        assert_eq!(values, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_try_traverse_tree() {
+        let mut visited = Vec::new();
+
+        let result: Result<(), String> = for_tree_try!(n in 0; |n| *n < 10; |n| vec![*n + 1] => {
+            visited.push(*n);
+
+            if *n == 4 {
+                Err(format!("stopped at {}", n))?;
+            }
+        });
+
+        assert_eq!(result, Err("stopped at 4".to_string()));
+        assert_eq!(visited, vec![0, 1, 2, 3, 4]);
+
+        // Without errors, pruning and breaking behave as in for_tree!
+        let mut visited = Vec::new();
+
+        let result: Result<(), String> = for_tree_try!(n in 1; |n| *n < 8; |n| vec![*n * 2, *n * 2 + 1] => {
+            visited.push(*n);
+
+            if *n == 2 {
+                prune!();
+            }
+            if *n == 6 {
+                break_tree!();
+            }
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(visited, vec![1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn test_traverse_tree_max_depth() {
+        // Depth 0 visits only the root
+        let mut values = Vec::new();
+        traverse_tree_max_depth(
+            1,
+            |_| true,
+            |n| vec![*n * 2, *n * 2 + 1],
+            0,
+            |n| {
+                values.push(*n);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(values, vec![1]);
+
+        // The depth limit and the condition both apply
+        let mut values = Vec::new();
+        traverse_tree_max_depth(
+            1,
+            |n| *n != 3,
+            |n| vec![*n * 2, *n * 2 + 1],
+            2,
+            |n| {
+                values.push(*n);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(values, vec![1, 2, 4, 5]);
+
+        // No control generates the children of a node at the limit
+        for control in [
+            TreeControl::PruneAfter(5),
+            TreeControl::BreakAfterSubtree,
+            TreeControl::Defer,
+        ] {
+            let mut expanded = Vec::new();
+            traverse_tree_max_depth(
+                1,
+                |_| true,
+                |n| {
+                    expanded.push(*n);
+                    vec![*n * 2, *n * 2 + 1]
+                },
+                1,
+                |_| control,
+            );
+            assert_eq!(expanded, vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_for_tree_depth() {
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                None,
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        );
+
+        let mut visited = Vec::new();
+
+        for_tree_depth!(node, depth in &root; |_| true; |node| {
+            let mut children = Vec::new();
+            if let Some(left) = &node.left {
+                children.push(left.as_ref());
+            }
+            if let Some(right) = &node.right {
+                children.push(right.as_ref());
+            }
+            children
+        } => {
+            visited.push((node.value, depth));
+
+            if node.value == 2 {
+                prune!();
+            }
+        });
+
+        assert_eq!(visited, vec![(1, 0), (2, 1), (3, 1), (6, 2)]);
+    }
+
+    #[test]
+    fn test_traverse_tree_path() {
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                None,
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        );
+
+        let mut paths = Vec::new();
+
+        traverse_tree_path(
+            &root,
+            |_| true,
+            |node| {
+                let mut children = Vec::new();
+                if let Some(left) = &node.left {
+                    children.push(left.as_ref());
+                }
+                if let Some(right) = &node.right {
+                    children.push(right.as_ref());
+                }
+                children
+            },
+            |path| {
+                paths.push(path.iter().map(|node| node.value).collect::<Vec<_>>());
+
+                if path.len() == 2 && path[1].value == 3 {
+                    return TreeControl::Break;
+                }
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(
+            paths,
+            vec![
+                vec![1],
+                vec![1, 2],
+                vec![1, 2, 4],
+                vec![1, 2, 5],
+                vec![1, 3]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_if_and_break_tree_if() {
+        let mut values = Vec::new();
+        let limit = 2;
+
+        let result: Result<(), core::num::ParseIntError> = for_tree_try!(s in String::from("1"); |_| true; |s| vec![format!("{}0", s), format!("{}1", s)] => {
+            values.push(s.clone());
+            break_tree_if!(s.parse::<u32>()? == 10);
+            prune_if!(s.len() >= limit);
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(values, vec!["1", "10"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_binary_node_serde_round_trip() {
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                None,
+            ))),
+            Some(Box::new(BinaryNode::new(3))),
+        );
+
+        let json = serde_json::to_string(&root).unwrap();
+        assert_eq!(
+            json,
+            r#"{"value":1,"left":{"value":2,"left":{"value":4,"left":null,"right":null},"right":null},"right":{"value":3,"left":null,"right":null}}"#
+        );
+
+        let decoded: BinaryNode<i32> = serde_json::from_str(&json).unwrap();
+
+        let shape = |root: &BinaryNode<i32>| {
+            collect_tree_with(
+                root,
+                |_| true,
+                |node| {
+                    let mut children = Vec::new();
+                    if let Some(left) = &node.left {
+                        children.push(left.as_ref());
+                    }
+                    if let Some(right) = &node.right {
+                        children.push(right.as_ref());
+                    }
+                    children
+                },
+                |node| (node.value, node.left.is_some(), node.right.is_some()),
+            )
+        };
+
+        assert_eq!(shape(&decoded), shape(&root));
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn test_fold_tree() {
+        // Find the maximum value, stopping as soon as something above 4 is seen
+        let max = fold_tree(
+            0,
+            |n| *n < 10,
+            |n| vec![*n + 1],
+            None,
+            |max: Option<i32>, n| {
+                let max = Some(max.map_or(*n, |m| m.max(*n)));
+                let control = if *n > 4 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                };
+                (max, control)
+            },
+        );
+        assert_eq!(max, Some(5));
+
+        // A root that fails the condition leaves the accumulator untouched
+        let count = fold_tree(
+            0,
+            |n| *n > 0,
+            |n| vec![*n + 1],
+            42,
+            |count, _| (count + 1, TreeControl::Continue),
+        );
+        assert_eq!(count, 42);
+    }
+
+    #[test]
+    fn test_find_tree() {
+        let generated = core::cell::Cell::new(0);
+        let branch_fn = |n: &i32| vec![*n * 2, *n * 2 + 1];
+
+        // Stops as soon as the match is found
+        let found = find_tree(
+            1,
+            |n| *n < 4,
+            |n| {
+                generated.set(generated.get() + 1);
+                branch_fn(n)
+            },
+            |n| *n == 3,
+        );
+        assert_eq!(found, Some(3));
+        assert_eq!(generated.get(), 2);
+
+        assert_eq!(find_tree(1, |n| *n < 64, branch_fn, |n| *n == 100), None);
+        assert_eq!(
+            find_map_tree(1, |n| *n < 64, branch_fn, |n| (*n > 40).then(|| n % 10)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_binary_node_orders() {
+        // A binary search tree:
+        //        8
+        //      /   \
+        //     3     10
+        //    / \      \
+        //   1   6      14
+        //      / \    /
+        //     4   7  13
+        let root = BinaryNode::with_children(
+            8,
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                Some(Box::new(BinaryNode::new(1))),
+                Some(Box::new(BinaryNode::with_children(
+                    6,
+                    Some(Box::new(BinaryNode::new(4))),
+                    Some(Box::new(BinaryNode::new(7))),
+                ))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                10,
+                None,
+                Some(Box::new(BinaryNode::with_children(
+                    14,
+                    Some(Box::new(BinaryNode::new(13))),
+                    None,
+                ))),
+            ))),
+        );
+
+        let mut in_order = Vec::new();
+        root.traverse_in_order(|value| in_order.push(*value));
+        assert_eq!(in_order, vec![1, 3, 4, 6, 7, 8, 10, 13, 14]);
+
+        let mut pre_order = Vec::new();
+        root.traverse_pre_order(|value| pre_order.push(*value));
+        assert_eq!(pre_order, vec![8, 3, 1, 6, 4, 7, 10, 14, 13]);
+
+        let mut post_order = Vec::new();
+        root.traverse_post_order(|value| post_order.push(*value));
+        assert_eq!(post_order, vec![1, 4, 7, 6, 3, 13, 14, 10, 8]);
+    }
+
+    #[test]
+    fn test_traverse_tree_ctx() {
+        let mut visited = Vec::new();
+
+        // Reject odd numbers, but only below the first level
+        traverse_tree_ctx(
+            1,
+            |n, depth| depth < 2 || *n % 2 == 0,
+            |n, depth| {
+                if depth < 3 {
+                    vec![*n * 2, *n * 2 + 1]
+                } else {
+                    Vec::new()
+                }
+            },
+            |n, depth| {
+                visited.push((*n, depth));
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(
+            visited,
+            vec![(1, 0), (2, 1), (4, 2), (8, 3), (3, 1), (6, 2), (12, 3)]
+        );
+    }
+
+    #[test]
+    fn test_count_nodes() {
+        let root = sample_tree();
+
+        assert_eq!(count_nodes(&root, |_| true, binary_children), 9);
+
+        // Negative nodes are filtered out by the condition
+        assert_eq!(
+            count_nodes(&root, |node| node.value >= 0, binary_children),
+            7
+        );
+
+        // Filtering a node also removes its whole subtree from the count
+        assert_eq!(
+            count_nodes(&root, |node| node.value != 2, binary_children),
+            4
+        );
+        assert_eq!(
+            count_nodes(&root, |node| node.value != 1, binary_children),
+            0
+        );
+    }
+
+    #[test]
+    fn test_traverse_tree_budget() {
+        let root = sample_tree();
+
+        // A budget equal to the tree size is not exhausted
+        let mut count = 0;
+        let exhausted = traverse_tree_budget(
+            &root,
+            |_| true,
+            binary_children,
+            9,
+            |_| {
+                count += 1;
+                TreeControl::Continue
+            },
+        );
+        assert!(!exhausted);
+        assert_eq!(count, 9);
+
+        // One less is
+        let mut values = Vec::new();
+        let exhausted = traverse_tree_budget(
+            &root,
+            |_| true,
+            binary_children,
+            8,
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+        assert!(exhausted);
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 3, -7]);
+
+        // Breaking from the visitor is not reported as exhaustion
+        let exhausted =
+            traverse_tree_budget(&root, |_| true, binary_children, 3, |_| TreeControl::Break);
+        assert!(!exhausted);
+
+        // Repeated and deferred visits of a node are not charged again
+        let mut calls = Vec::new();
+        let exhausted = traverse_tree_budget(
+            1,
+            |n| *n < 4,
+            |n| vec![*n * 2, *n * 2 + 1],
+            3,
+            |n| {
+                calls.push(*n);
+                match *n {
+                    1 if calls.len() == 1 => TreeControl::Defer,
+                    2 if calls.len() == 2 => TreeControl::Repeat,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+        assert!(!exhausted);
+        assert_eq!(calls, vec![1, 2, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_traverse_tree_stats() {
+        let root = sample_tree();
+
+        let stats = traverse_tree_stats(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                if node.value == 4 {
+                    TreeControl::Prune
+                } else if node.value == -7 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(
+            stats,
+            TraversalStats {
+                visited: 6,
+                pruned: 1,
+                max_depth: 2,
+                broke_early: true,
+                root_rejected: false,
+            }
+        );
+
+        // A rejected root produces empty statistics that say so
+        let stats =
+            traverse_tree_stats(&root, |_| false, binary_children, |_| TreeControl::Continue);
+        assert_eq!(
+            stats,
+            TraversalStats {
+                root_rejected: true,
+                ..TraversalStats::default()
+            }
+        );
+
+        // Rejected children don't count
+        let stats = traverse_tree_stats(
+            &root,
+            |n| n.value == 1,
+            binary_children,
+            |_| TreeControl::Continue,
+        );
+        assert!(!stats.root_rejected);
+        assert_eq!(stats.visited, 1);
+
+        // A repeated node is still one node
+        let mut repeated = false;
+        let stats = traverse_tree_stats(
+            1,
+            |n| *n < 4,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                if *n == 2 && !repeated {
+                    repeated = true;
+                    TreeControl::Repeat
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(stats.visited, 3);
+    }
+
+    #[test]
+    fn test_repeat() {
+        // Each node is revisited until it has been seen three times
+        let mut visits = Vec::new();
+        let mut seen = 0;
+
+        traverse_tree(
+            0,
+            |n| *n < 2,
+            |n| vec![*n + 1],
+            |n| {
+                visits.push(*n);
+                seen += 1;
+
+                if seen < 3 {
+                    TreeControl::Repeat
+                } else {
+                    seen = 0;
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(visits, vec![0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        // Far deeper than the call stack would allow with recursion
+        let count = count_nodes(0u32, |n| *n < 1_000_000, |n| vec![*n + 1]);
+        assert_eq!(count, 1_000_000);
+
+        let mut deepest = 0;
+        traverse_tree_path(
+            0u32,
+            |n| *n < 100_000,
+            |n| vec![*n + 1],
+            |path| {
+                deepest = deepest.max(path.len());
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(deepest, 100_000);
+    }
+
+    #[test]
+    fn test_for_tree_typed() {
+        let root = sample_tree();
+        let mut values = Vec::new();
+
+        for_tree!(node: &BinaryNode<i32> in &root; |node| node.value >= 0; |node| {
+            let mut children = Vec::new();
+            if let Some(left) = &node.left {
+                children.push(left.as_ref());
+            }
+            if let Some(right) = &node.right {
+                children.push(right.as_ref());
+            }
+            children
+        } => {
+            values.push(node.value);
+        });
+
+        assert_eq!(values, vec![1, 2, 4, 9, 5, 3, 6]);
+
+        // Generic types and the `;` form are accepted too
+        let mut lengths = Vec::new();
+
+        for_tree!(s: (String, usize) in (String::new(), 0); |s| s.1 <= 2; |s| {
+            vec![(format!("{}a", s.0), s.1 + 1)]
+        }; {
+            lengths.push(s.0.len());
+        });
+
+        assert_eq!(lengths, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_binary_node_children() {
+        let mut root = sample_tree();
+
+        let values: Vec<i32> = root.children().iter().map(|child| child.value).collect();
+        assert_eq!(values, vec![2, 3]);
+        assert!(BinaryNode::new(0).children().is_empty());
+
+        for child in root.children_mut() {
+            child.value = -child.value;
+        }
+        let values = collect_tree_with(&root, |_| true, |node| node.children(), |node| node.value);
+        assert_eq!(values, vec![1, -2, 4, -8, 9, 5, -3, -7, 6]);
+    }
+
+    #[test]
+    fn test_traverse_iddfs() {
+        let root = sample_tree();
+        let mut rounds: Vec<Vec<i32>> = Vec::new();
+        let mut last_depth_seen = 0;
+
+        traverse_tree_depth(
+            &root,
+            |_| true,
+            binary_children,
+            |_, depth| {
+                last_depth_seen = last_depth_seen.max(depth);
+                TreeControl::Continue
+            },
+        );
+
+        traverse_iddfs(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                if node.value == 1 {
+                    rounds.push(Vec::new());
+                }
+                if let Some(round) = rounds.last_mut() {
+                    round.push(node.value);
+                }
+                TreeControl::Continue
+            },
+        );
+
+        // One round per level, plus a final round that finds nothing new
+        assert_eq!(rounds.len(), last_depth_seen + 2);
+        assert_eq!(rounds[0], vec![1]);
+        assert_eq!(rounds[1], vec![1, 2, 3]);
+        assert_eq!(rounds[2], vec![1, 2, 4, 5, 3, -7, 6]);
+        assert_eq!(rounds[3], vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+        assert_eq!(rounds[4], rounds[3]);
+    }
+
+    #[test]
+    fn test_traverse_tree_rev() {
+        // Same structure as test_binary_tree
+        let root = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                Some(Box::new(BinaryNode::new(5))),
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                None,
+                Some(Box::new(BinaryNode::new(6))),
+            ))),
+        );
+
+        let mut values = Vec::new();
+        traverse_tree_rev(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(values, vec![1, 3, 6, 2, 5, 4]);
+    }
+
+    #[test]
+    fn test_traverse_tree_indexed() {
+        let root = sample_tree();
+        let mut values = Vec::new();
+
+        traverse_tree_indexed(
+            &root,
+            |_| true,
+            |node, index| node.children().get(index).copied(),
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+
+        // Children past a break are never generated
+        let mut requested = Vec::new();
+        traverse_tree_indexed(
+            0u32,
+            |_| true,
+            |n, index| {
+                requested.push((*n, index));
+                Some(*n * 10 + index as u32 + 1)
+            },
+            |n| {
+                if *n == 1 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(requested, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_format_tree_snapshot() {
+        // Prints just the value of each node
+        #[derive(Clone, Copy)]
+        struct Value<'a>(&'a BinaryNode<i32>);
+
+        impl Debug for Value<'_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, "{}", self.0.value)
+            }
+        }
+
+        let root = sample_tree();
+        let text = format_tree(&Value(&root), |node| {
+            node.0.children().into_iter().map(Value).collect()
+        });
+
+        let expected = "\
+1
+├── 2
+│   ├── 4
+│   │   ├── -8
+│   │   └── 9
+│   └── 5
+└── 3
+    ├── -7
+    └── 6
+";
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn test_for_tree_value() {
+        let root = sample_tree();
+
+        let first_negative = for_tree_value!(node in &root; |_| true; binary_children => {
+            if node.value < 0 {
+                break_tree!(node.value);
+            }
+        });
+        assert_eq!(first_negative, Some(-8));
+
+        // A pruned search that never breaks with a value
+        let missing: Option<i32> = for_tree_value!(node in &root; |_| true; binary_children => {
+            prune_if!(node.value == 4);
+            if node.value == -8 {
+                break_tree!(node.value);
+            }
+            if node.value == 3 {
+                break_tree!();
+            }
+        });
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_skip_body() {
+        let root = sample_tree();
+        let mut reached = Vec::new();
+        let mut processed = Vec::new();
+
+        for_tree!(node in &root; |_| true; binary_children => {
+            reached.push(node.value);
+            if node.value == 2 {
+                skip_body!();
+            }
+            prune_if!(node.value == 3);
+            processed.push(node.value);
+        });
+
+        // 2 is skipped but its children are not; 3's children are pruned
+        assert_eq!(reached, vec![1, 2, 4, -8, 9, 5, 3]);
+        assert_eq!(processed, vec![1, 4, -8, 9, 5]);
+    }
+
+    #[test]
+    fn test_zip_trees_different_shapes() {
+        let a = sample_tree();
+        let b = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                Some(Box::new(BinaryNode::new(4))),
+                None,
+            ))),
+            Some(Box::new(BinaryNode::with_children(
+                30,
+                Some(Box::new(BinaryNode::new(-7))),
+                Some(Box::new(BinaryNode::with_children(
+                    6,
+                    Some(Box::new(BinaryNode::new(12))),
+                    None,
+                ))),
+            ))),
+        );
+
+        let mut diffs = Vec::new();
+        let mut visited = 0;
+        zip_trees(&a, &b, binary_children, binary_children, |x, y| {
+            visited += 1;
+            let (x, y) = (x.map(|n| n.value), y.map(|n| n.value));
+            if x != y {
+                diffs.push((x, y));
+            }
+            TreeControl::Continue
+        });
+
+        assert_eq!(visited, 10);
+        assert_eq!(
+            diffs,
+            vec![
+                (Some(-8), None),
+                (Some(9), None),
+                (Some(5), None),
+                (Some(3), Some(30)),
+                (None, Some(12)),
+            ]
+        );
+
+        // Pruning a pair skips the children on both sides
+        let mut visited = 0;
+        zip_trees(&a, &b, binary_children, binary_children, |x, _| {
+            visited += 1;
+            prune_if_value(x, 2)
+        });
+        assert_eq!(visited, 6);
+    }
+
+    fn prune_if_value(node: Option<&&BinaryNode<i32>>, value: i32) -> TreeControl {
+        if node.is_some_and(|node| node.value == value) {
+            TreeControl::Prune
+        } else {
+            TreeControl::Continue
+        }
+    }
+
+    #[test]
+    fn test_traverse_graph_checked() {
+        // A diamond is a DAG: 3 is reached twice, but never from itself
+        let diamond = [vec![1, 2], vec![3], vec![3], vec![]];
+        let mut visited = Vec::new();
+        let result = traverse_graph_checked(
+            0,
+            |_| true,
+            |n| diamond[*n].clone(),
+            |n| {
+                visited.push(*n);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(visited, vec![0, 1, 3, 2, 3]);
+
+        // A self-loop is the shortest possible cycle
+        let self_loop = [vec![1], vec![2], vec![2]];
+        let result = traverse_graph_checked(
+            0,
+            |_| true,
+            |n| self_loop[*n].clone(),
+            |_| TreeControl::Continue,
+        );
+        assert_eq!(result, Err(vec![2, 2]));
+
+        // A back-edge that is pruned before it is followed is not reported
+        let result = traverse_graph_checked(
+            0,
+            |_| true,
+            |n| self_loop[*n].clone(),
+            |n| {
+                if *n == 2 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_traverse_leaves() {
+        let root = sample_tree();
+        let mut leaves = Vec::new();
+
+        traverse_leaves(
+            &root,
+            |node| node.value != 3,
+            binary_children,
+            |node| {
+                leaves.push(node.value);
+                if node.value == 9 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(leaves, vec![-8, 9]);
+
+        let mut leaves = Vec::new();
+        traverse_leaves(
+            &root,
+            |node| node.value != 3,
+            binary_children,
+            |node| {
+                leaves.push(node.value);
+                TreeControl::Continue
+            },
+        );
+
+        // 3 is rejected by the condition, so -7 and 6 are never reached
+        assert_eq!(leaves, vec![-8, 9, 5]);
+    }
+
+    #[test]
+    fn test_stateful_condition() {
+        let root = sample_tree();
+        let mut values = Vec::new();
+
+        // Only the first two subtrees rooted at a negative node or 5 are accepted
+        let mut accepted = 0;
+        for_tree!(node in &root; |node| {
+            if node.value < 0 || node.value == 5 {
+                accepted += 1;
+                accepted <= 2
+            } else {
+                true
+            }
+        }; binary_children => {
+            values.push(node.value);
+        });
+
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 3, 6]);
+        assert_eq!(accepted, 3);
+    }
+
+    #[test]
+    fn test_binary_node_map() {
+        let root = sample_tree();
+        let mut calls = Vec::new();
+
+        let mapped = root.map(|value| {
+            calls.push(*value);
+            value.to_string()
+        });
+
+        assert_eq!(calls, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+        let values = collect_tree_with(
+            &mapped,
+            |_| true,
+            binary_children,
+            |node| node.value.clone(),
+        );
+        assert_eq!(values, vec!["1", "2", "4", "-8", "9", "5", "3", "-7", "6"]);
+        assert!(
+            mapped
+                .left
+                .as_ref()
+                .unwrap()
+                .right
+                .as_ref()
+                .unwrap()
+                .left
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_map_tree_deep() {
+        // A chain deep enough to overflow the stack if mapped recursively
+        let mut root = NAryNode::new(0u32);
+        for value in 1..100_000 {
+            root = NAryNode::with_children(value, vec![root]);
+        }
+
+        let mapped = map_tree(&root, |value| u64::from(*value) * 2);
+        assert_eq!(
+            count_nodes(&mapped, |_| true, |node| node.children()),
+            100_000
+        );
+        assert_eq!(mapped.value, 199_998);
+
+        // Dropping such a chain recurses, so take both apart iteratively
+        let mut next = Some(root);
+        while let Some(mut node) = next {
+            next = node.children.pop();
+        }
+        let mut next = Some(mapped);
+        while let Some(mut node) = next {
+            next = node.children.pop();
+        }
+    }
+
+    #[test]
+    fn test_filter_tree() {
+        let root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(2, vec![NAryNode::new(-4), NAryNode::new(-5)]),
+                NAryNode::with_children(-3, vec![NAryNode::new(6)]),
+                NAryNode::new(7),
+            ],
+        );
+
+        let mut checked = Vec::new();
+        let filtered = filter_tree(&root, |n| {
+            checked.push(*n);
+            *n > 0
+        });
+        let filtered = filtered.unwrap();
+
+        // 6 is positive, but is removed along with its parent and never checked
+        assert_eq!(checked, vec![1, 2, -4, -5, -3, 7]);
+        let values = collect_tree_with(
+            &filtered,
+            |_| true,
+            |node| node.children(),
+            |node| node.value,
+        );
+        assert_eq!(values, vec![1, 2, 7]);
+        // 2 lost all of its children but survives as a leaf
+        assert!(filtered.children[0].children.is_empty());
+
+        assert!(filter_tree(&root, |n| *n > 1).is_none());
+
+        let binary = sample_tree().filter(|value| *value > 0).unwrap();
+        let values = collect_tree_with(&binary, |_| true, binary_children, |node| node.value);
+        assert_eq!(values, vec![1, 2, 4, 9, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_traverse_events_prune_and_break() {
+        let root = sample_tree();
+        let mut events = Vec::new();
+
+        traverse_events(
+            &root,
+            |_| true,
+            binary_children,
+            |event| match event {
+                Event::Enter(node) => {
+                    events.push(Event::Enter(node.value));
+                    match node.value {
+                        4 => TreeControl::Prune,
+                        -7 => TreeControl::Break,
+                        _ => TreeControl::Continue,
+                    }
+                }
+                Event::Leave(node) => {
+                    events.push(Event::Leave(node.value));
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Enter(1),
+                Event::Enter(2),
+                Event::Enter(4),
+                Event::Leave(4),
+                Event::Enter(5),
+                Event::Leave(5),
+                Event::Leave(2),
+                Event::Enter(3),
+                Event::Enter(-7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_tree_ordered() {
+        let root = sample_tree();
+
+        let mut values = Vec::new();
+        traverse_tree_ordered(
+            &root,
+            |_| true,
+            binary_children,
+            |node| node.value,
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+
+        let mut values = Vec::new();
+        traverse_tree_ordered(
+            &root,
+            |_| true,
+            binary_children,
+            |node| core::cmp::Reverse(node.value.abs()),
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(values, vec![1, 3, -7, 6, 2, 5, 4, 9, -8]);
+    }
+
+    #[test]
+    fn test_restart_subtree() {
+        // The children of the root depend on a generation counter that visiting
+        // node 12 bumps once
+        let generation = Cell::new(1);
+        let mut visited = Vec::new();
+
+        traverse_tree(
+            0,
+            |_| true,
+            |n| match *n {
+                0 => vec![generation.get() * 10 + 1, generation.get() * 10 + 2],
+                _ => Vec::new(),
+            },
+            |n| {
+                visited.push(*n);
+                if *n == 12 && generation.get() == 1 {
+                    generation.set(2);
+                    TreeControl::RestartSubtree
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(visited, vec![0, 11, 12, 21, 22]);
+    }
+
+    #[test]
+    fn test_restart_subtree_limit() {
+        let mut restarts = 0;
+        let mut root_visits = 0;
+
+        traverse_tree(
+            0,
+            |_| true,
+            |n| if *n == 0 { vec![1] } else { Vec::new() },
+            |n| {
+                if *n == 0 {
+                    root_visits += 1;
+                    return TreeControl::Continue;
+                }
+                restarts += 1;
+                TreeControl::RestartSubtree
+            },
+        );
+
+        // The first visit plus one per allowed restart; then it acts like `Prune`
+        assert_eq!(root_visits, 1);
+        assert_eq!(restarts, MAX_SUBTREE_RESTARTS + 1);
+
+        // On the root, it revisits the root like `Repeat`
+        let mut visits = 0;
+        traverse_tree(
+            0,
+            |_| true,
+            |_| Vec::new(),
+            |_| {
+                visits += 1;
+                TreeControl::RestartSubtree
+            },
+        );
+        assert_eq!(visits, MAX_SUBTREE_RESTARTS + 1);
+    }
+
+    #[test]
+    fn test_traverse_best_first() {
+        let root = sample_tree();
+        let mut values = Vec::new();
+
+        // Largest value first, pruning below 4
+        traverse_best_first(
+            &root,
+            |_| true,
+            binary_children,
+            |node| node.value,
+            |node| {
+                values.push(node.value);
+                if node.value == 4 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(values, vec![1, 3, 6, 2, 5, 4, -7]);
+
+        // Ties are visited in discovery order
+        let mut values = Vec::new();
+        traverse_best_first(
+            &root,
+            |_| true,
+            binary_children,
+            |_| 0,
+            |node| {
+                values.push(node.value);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(values, vec![1, 2, 3, 4, 5, -7, 6, -8, 9]);
+    }
+
+    #[test]
+    fn test_binary_node_constructors() {
+        assert!(BinaryNode::<i32>::from_sorted_slice(&[]).is_none());
+        assert!(BinaryNode::<i32>::from_bst_insert([]).is_none());
+
+        let values: Vec<i32> = (1..=10).collect();
+        let balanced = BinaryNode::from_sorted_slice(&values).unwrap();
+        let mut depth = 0;
+        traverse_tree_depth(
+            &balanced,
+            |_| true,
+            binary_children,
+            |_, d| {
+                depth = depth.max(d);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(depth, 3);
+
+        let bst = BinaryNode::from_bst_insert([4, 2, 6, 2, 5, 1]).unwrap();
+        let mut in_order = Vec::new();
+        bst.traverse_in_order(|value| in_order.push(*value));
+        assert_eq!(in_order, vec![1, 2, 2, 4, 5, 6]);
+        let pre_order = collect_tree_with(&bst, |_| true, binary_children, |node| node.value);
+        assert_eq!(pre_order, vec![4, 2, 1, 2, 6, 5]);
+    }
+
+    #[test]
+    fn test_for_tree_dynamic() {
+        let root = sample_tree();
+        let extra = BinaryNode::new(100);
+        let mut values = Vec::new();
+
+        // Graft an extra child under 5, and discard what is added to a pruned node
+        for_tree_dynamic!(node, branches in &root; |_| true; binary_children => {
+            values.push(node.value);
+            if node.value == 5 {
+                add_branch!(branches, &extra);
+            }
+            if node.value == 3 {
+                add_branch!(branches, &extra);
+                prune!();
+            }
+        });
+
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 100, 3]);
+    }
+
+    #[test]
+    fn test_continue_without() {
+        let mut values = Vec::new();
+
+        // Skip the first child of 1 and the first two children of 3
+        traverse_tree(
+            1,
+            |n| *n < 40,
+            |n| vec![*n * 3, *n * 3 + 1, *n * 3 + 2],
+            |n| {
+                values.push(*n);
+                match *n {
+                    1 => TreeControl::ContinueWithout(1),
+                    5 => TreeControl::ContinueWithout(5),
+                    4 => TreeControl::ContinueWithout(2),
+                    _ => TreeControl::Prune,
+                }
+            },
+        );
+
+        assert_eq!(values, vec![1, 4, 14, 5]);
+
+        let mut values = Vec::new();
+        traverse_tree_indexed(
+            1,
+            |_| true,
+            |n, index| (index < 3 && *n < 10).then(|| *n * 3 + index),
+            |n| {
+                values.push(*n);
+                TreeControl::ContinueWithout(1)
+            },
+        );
+        assert_eq!(values, vec![1, 4, 13, 14, 5, 16, 17]);
+    }
+
+    #[test]
+    fn test_traverse_tree_with_stack() {
+        let root = sample_tree();
+        let mut stack = Vec::with_capacity(16);
+
+        let mut values = Vec::new();
+        traverse_tree_with_stack(
+            &root,
+            |node| node.value != -7,
+            binary_children,
+            |node| {
+                values.push(node.value);
+                if node.value == 4 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+            &mut stack,
+        );
+        assert_eq!(values, vec![1, 2, 4, 5, 3, 6]);
+
+        // A break leaves the buffer empty but keeps its capacity
+        let mut values = Vec::new();
+        traverse_tree_with_stack(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                values.push(node.value);
+                if node.value == -8 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+            &mut stack,
+        );
+        assert_eq!(values, vec![1, 2, 4, -8]);
+        assert!(stack.is_empty());
+        assert!(stack.capacity() >= 16);
+    }
+
+    #[test]
+    fn test_level_order() {
+        let root = sample_tree();
+
+        let levels: Vec<Vec<i32>> = level_order(&root, |node| node.value != 5, binary_children)
+            .into_iter()
+            .map(|level| level.into_iter().map(|node| node.value).collect())
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![vec![1], vec![2, 3], vec![4, -7, 6], vec![-8, 9]]
+        );
+        assert!(level_order(&root, |_| false, binary_children).is_empty());
+    }
+
+    #[test]
+    fn test_node_equality() {
+        assert_eq!(sample_tree(), sample_tree());
+        assert_ne!(sample_tree(), sample_tree().map(|value| value + 1));
+        assert!(sample_tree().structural_eq(&sample_tree().map(|value| value + 1), |_, _| true));
+
+        let mut lopsided = sample_tree();
+        lopsided.right = None;
+        assert_ne!(sample_tree(), lopsided);
+        assert!(!sample_tree().structural_eq(&lopsided, |_, _| true));
+
+        let values: Vec<i32> = (1..=7).collect();
+        assert_eq!(
+            BinaryNode::from_sorted_slice(&values),
+            BinaryNode::from_bst_insert([4, 2, 6, 1, 3, 5, 7])
+        );
+
+        let nary = NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(3)]);
+        assert_eq!(map_tree(&nary, |n| n * 2), nary.map(|n| n * 2));
+        assert!(nary.structural_eq(&nary.map(|n| n.to_string()), |a, b| a.to_string() == *b));
+    }
+
+    #[test]
+    fn test_traverse_dyn() {
+        struct Leaf(i32);
+        struct Branch(i32, Vec<Box<dyn Tree>>);
+
+        impl Tree for Leaf {
+            fn children(&self) -> Vec<&dyn Tree> {
+                Vec::new()
+            }
+        }
+
+        impl Tree for Branch {
+            fn children(&self) -> Vec<&dyn Tree> {
+                self.1.iter().map(|child| child.as_ref()).collect()
+            }
+        }
+
+        fn value(node: &dyn Tree) -> i32 {
+            let node = node as &dyn Any;
+            match (node.downcast_ref::<Leaf>(), node.downcast_ref::<Branch>()) {
+                (Some(leaf), _) => leaf.0,
+                (_, Some(branch)) => branch.0,
+                _ => unreachable!(),
+            }
+        }
+
+        let root = Branch(
+            1,
+            vec![
+                Box::new(Branch(2, vec![Box::new(Leaf(4)), Box::new(Leaf(5))])),
+                Box::new(Leaf(3)),
+                Box::new(Branch(6, vec![Box::new(Leaf(7))])),
+            ],
+        );
+
+        let mut values = Vec::new();
+        traverse_dyn(&root, &mut |node| {
+            values.push(value(node));
+            match value(node) {
+                2 => TreeControl::Prune,
+                3 => TreeControl::Break,
+                _ => TreeControl::Continue,
+            }
+        });
+
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_traverse_tree_descend_if_ordering() {
+        let calls = core::cell::RefCell::new(Vec::new());
+
+        traverse_tree_descend_if(
+            1,
+            |n| {
+                calls.borrow_mut().push(format!("condition {}", n));
+                *n < 4
+            },
+            |n| {
+                calls.borrow_mut().push(format!("should_descend {}", n));
+                *n != 3
+            },
+            |n| {
+                calls.borrow_mut().push(format!("branch {}", n));
+                vec![*n * 2, *n * 2 + 1]
+            },
+            |n| {
+                calls.borrow_mut().push(format!("visit {}", n));
+                if *n == 2 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(
+            calls.into_inner(),
+            vec![
+                "condition 1",
+                "visit 1",
+                "should_descend 1",
+                "branch 1",
+                "condition 2",
+                "visit 2",
+                "condition 3",
+                "visit 3",
+                "should_descend 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tree_height_and_len() {
+        let root = sample_tree();
+
+        assert_eq!(tree_height(&&root, binary_children), 3);
+        assert_eq!(root.height(), 3);
+        assert_eq!(root.len(), 9);
+        assert_eq!(root.len(), count_nodes(&root, |_| true, binary_children));
+
+        let leaf = BinaryNode::new(1);
+        assert_eq!(tree_height(&&leaf, binary_children), 0);
+        assert_eq!(leaf.height(), 0);
+        assert_eq!(leaf.len(), 1);
+    }
+
+    #[test]
+    fn test_for_tree_body_loops() {
+        let root = sample_tree();
+        let mut visited = Vec::new();
+        let mut pairs = Vec::new();
+
+        for_tree!(node in &root; |_| true; binary_children => {
+            visited.push(node.value);
+
+            // An unlabeled `break` and `continue` only affect this loop
+            for i in 0..10 {
+                if i == 1 {
+                    continue;
+                }
+                if i == 3 {
+                    break;
+                }
+                pairs.push((node.value, i));
+            }
+
+            // A labeled `continue` targets a loop inside the body
+            let mut count = 0;
+            'outer: for _ in 0..2 {
+                for _ in 0..5 {
+                    count += 1;
+                    if count % 2 == 1 {
+                        continue 'outer;
+                    }
+                }
+            }
+            assert_eq!(count, 3);
+
+            if node.value == 4 {
+                prune!();
+            }
+        });
+
+        assert_eq!(visited, vec![1, 2, 4, 5, 3, -7, 6]);
+        assert_eq!(pairs.len(), visited.len() * 2);
+        assert!(pairs.iter().all(|&(_, i)| i == 0 || i == 2));
+    }
+
+    #[test]
+    fn test_traverse_tree_child_info() {
+        let root = sample_tree();
+        let mut visited = Vec::new();
+
+        traverse_tree_child_info(
+            &root,
+            |node| node.value != 5,
+            binary_children,
+            |node, info| {
+                visited.push((node.value, info.index, info.is_last, info.sibling_count));
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(
+            visited,
+            vec![
+                (1, 0, true, 1),
+                (2, 0, false, 2),
+                // 5 is filtered out, so 4 is the only visited child of 2
+                (4, 0, true, 1),
+                (-8, 0, false, 2),
+                (9, 1, true, 2),
+                (3, 1, true, 2),
+                (-7, 0, false, 2),
+                (6, 1, true, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_until() {
+        let root = sample_tree();
+        let mut visited = Vec::new();
+
+        let stopped = traverse_until(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                visited.push(node.value);
+                node.value < 0
+            },
+        );
+
+        assert!(stopped);
+        assert_eq!(visited, vec![1, 2, 4, -8]);
+
+        let mut count = 0;
+        let stopped = traverse_until(
+            &root,
+            |_| true,
+            binary_children,
+            |_| {
+                count += 1;
+                false
+            },
+        );
+
+        assert!(!stopped);
+        assert_eq!(count, 9);
+    }
+
+    #[test]
+    fn test_traverse_tree_never_clones_generated_nodes() {
+        static CLONES: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+        struct Counted(String);
+
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+                Counted(self.0.clone())
+            }
+        }
+
+        let mut visited = 0;
+
+        traverse_tree(
+            Counted(String::new()),
+            |s| s.0.len() <= 4,
+            |s| {
+                "abc"
+                    .chars()
+                    .map(|c| Counted(format!("{}{}", s.0, c)))
+                    .collect()
+            },
+            |_| {
+                visited += 1;
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, 1 + 3 + 9 + 27 + 81);
+        assert_eq!(CLONES.load(core::sync::atomic::Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_tree_control_and_precedence() {
+        use TreeControl::*;
+
+        let ordered = [
+            Continue,
+            ContinueWithout(1),
+            Defer,
+            Prune,
+            Repeat,
+            RestartSubtree,
+            Break,
+        ];
+
+        for (i, &weaker) in ordered.iter().enumerate() {
+            assert_eq!(weaker.and(weaker), weaker);
+            for &stronger in &ordered[i + 1..] {
+                assert_eq!(weaker.and(stronger), stronger);
+                assert_eq!(stronger.and(weaker), stronger);
+            }
+        }
+
+        assert_eq!(
+            ContinueWithout(2).and(ContinueWithout(5)),
+            ContinueWithout(5)
+        );
+        assert_eq!(Break.and(Prune).and(Continue), Break);
+        assert_eq!(TreeControl::from_prune(true), Prune);
+        assert_eq!(TreeControl::from_prune(false), Continue);
+    }
+
+    #[test]
+    fn test_control_macro() {
+        let classify = |n: i32| control!(break if n > 100, prune if n < 0, repeat if n == 7);
+
+        assert_eq!(classify(101), TreeControl::Break);
+        assert_eq!(classify(-1), TreeControl::Prune);
+        assert_eq!(classify(7), TreeControl::Repeat);
+        assert_eq!(classify(3), TreeControl::Continue);
+        assert_eq!(control!(), TreeControl::Continue);
+        assert_eq!(control!(restart if true,), TreeControl::RestartSubtree);
+        assert_eq!(control!(defer if true), TreeControl::Defer);
+        assert_eq!(
+            control!(prune if false, prune_after(2) if true),
+            TreeControl::PruneAfter(2)
+        );
+        assert_eq!(
+            control!(break_after_subtree if true, break if true),
+            TreeControl::BreakAfterSubtree
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_traverse_to_channel_stops_when_receiver_is_dropped() {
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        // The receiver is dropped after three nodes, which must end the
+        // otherwise infinite traversal
+        let consumer = std::thread::spawn(move || rx.iter().take(3).collect::<Vec<i32>>());
+
+        traverse_to_channel(0, |_| true, |n| vec![*n + 1], tx);
+
+        assert_eq!(consumer.join().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_binary_node_rotations() {
+        fn in_order(node: &BinaryNode<i32>) -> Vec<i32> {
+            let mut values = Vec::new();
+            node.traverse_in_order(|v| values.push(*v));
+            values
+        }
+
+        let mut root = sample_tree();
+        let expected = in_order(&root);
+        assert!(root.is_balanced());
+
+        assert!(root.rotate_right());
+        assert_eq!(root.value, 2);
+        assert_eq!(in_order(&root), expected);
+
+        assert!(root.rotate_left());
+        assert_eq!(root, sample_tree());
+
+        let mut leaf = BinaryNode::new(1);
+        assert!(!leaf.rotate_left());
+        assert!(!leaf.rotate_right());
+        assert_eq!(leaf, BinaryNode::new(1));
+
+        // Rotating a degenerate chain left at the root balances it
+        let mut chain = BinaryNode::from_bst_insert(1..=3).unwrap();
+        assert!(!chain.is_balanced());
+        chain.rotate_left();
+        assert!(chain.is_balanced());
+        assert_eq!(in_order(&chain), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_traverse_tree_progress() {
+        let root = sample_tree();
+        let mut reports = Vec::new();
+        let mut repeated = false;
+
+        traverse_tree_progress(
+            &root,
+            |_| true,
+            binary_children,
+            3,
+            |visits| reports.push(visits),
+            |node| {
+                if node.value == 4 && !repeated {
+                    repeated = true;
+                    return TreeControl::Repeat;
+                }
+                if node.value == 1 {
+                    TreeControl::Defer
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        // Nine nodes, whatever the repeated and deferred visits
+        assert_eq!(reports, vec![3, 6, 9]);
+
+        let mut reports = Vec::new();
+        let mut repeated = false;
+        traverse_tree_progress(
+            1,
+            |n| *n < 4,
+            |n| vec![*n * 2, *n * 2 + 1],
+            1,
+            |visits| reports.push(visits),
+            |n| {
+                if *n == 2 && !repeated {
+                    repeated = true;
+                    TreeControl::Repeat
+                } else {
+                    TreeControl::Defer
+                }
+            },
+        );
+        assert_eq!(reports, vec![1, 2, 3]);
+
+        let mut reports = Vec::new();
+        traverse_tree_progress(
+            &root,
+            |_| true,
+            binary_children,
+            0,
+            |visits| reports.push(visits),
+            |_| TreeControl::Continue,
+        );
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn test_collect_for_tree() {
+        let root = sample_tree();
+
+        let values =
+            collect_for_tree!(node in &root; |node| node.value >= 0; binary_children => node.value);
+        assert_eq!(values, vec![1, 2, 4, 9, 5, 3, 6]);
+
+        let labels = collect_for_tree!(s in String::new(); |s| s.len() <= 1; |s| {
+            vec![format!("{}x", s), format!("{}y", s)]
+        } => format!("[{}]", s));
+        assert_eq!(labels, vec!["[]", "[x]", "[y]"]);
+    }
+
+    #[test]
+    fn test_traverse_shared_allows_mutating_visited_nodes() {
+        struct Node {
+            value: i32,
+            children: Vec<Rc<RefCell<Node>>>,
+        }
+
+        let node = |value, children| Rc::new(RefCell::new(Node { value, children }));
+        let shared = node(4, Vec::new());
+        let root = node(
+            1,
+            vec![node(2, vec![shared.clone()]), node(3, vec![shared.clone()])],
+        );
+
+        let mut visited = Vec::new();
+        let handle = root.clone();
+
+        traverse_shared(
+            root.clone(),
+            |n| n.children.clone(),
+            |n| {
+                visited.push(n.value);
+                // No borrow is held between visits, so any node can be mutated
+                if n.value == 2 {
+                    handle.borrow_mut().value = 10;
+                }
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec![1, 2, 4, 3, 4]);
+        assert_eq!(root.borrow().value, 10);
+    }
+
+    #[test]
+    fn test_nary_sort_children_by_key() {
+        let mut root = NAryNode::with_children(
+            5,
+            vec![
+                NAryNode::with_children(3, vec![NAryNode::new(9), NAryNode::new(-1)]),
+                NAryNode::new(1),
+                NAryNode::with_children(-2, vec![NAryNode::new(4), NAryNode::new(2)]),
+            ],
+        );
+
+        // Sorting by absolute value orders the children differently from their values
+        root.sort_children_by_key(|v: &i32| v.abs());
+
+        let expected = NAryNode::with_children(
+            5,
+            vec![
+                NAryNode::new(1),
+                NAryNode::with_children(-2, vec![NAryNode::new(2), NAryNode::new(4)]),
+                NAryNode::with_children(3, vec![NAryNode::new(-1), NAryNode::new(9)]),
+            ],
+        );
+        assert_eq!(root, expected);
+    }
+
+    #[test]
+    fn test_traverse_tree_hinted_matches_traverse_tree() {
+        let root = sample_tree();
+
+        for expected_depth in [0, 2, 100] {
+            let mut visited = Vec::new();
+            traverse_tree_hinted(
+                &root,
+                |_| true,
+                binary_children,
+                expected_depth,
+                |node| {
+                    visited.push(node.value);
+                    TreeControl::from_prune(node.value == 4)
+                },
+            );
+            assert_eq!(visited, vec![1, 2, 4, 5, 3, -7, 6]);
+        }
+    }
+
+    #[test]
+    fn test_nary_from_parent_list_errors() {
+        let empty: [(Option<usize>, i32); 0] = [];
+        assert_eq!(
+            NAryNode::from_parent_list(&empty),
+            Err(TreeBuildError::NoRoot)
+        );
+
+        assert_eq!(
+            NAryNode::from_parent_list(&[(None, 1), (Some(0), 2), (None, 3)]),
+            Err(TreeBuildError::MultipleRoots {
+                first: 0,
+                second: 2
+            })
+        );
+        assert_eq!(
+            NAryNode::from_parent_list(&[(None, 1), (Some(3), 2)]),
+            Err(TreeBuildError::MissingParent { node: 1, parent: 3 })
+        );
+
+        // 2 and 3 are each other's parent, and 4 hangs off that cycle
+        assert_eq!(
+            NAryNode::from_parent_list(&[
+                (None, 0),
+                (Some(0), 1),
+                (Some(3), 2),
+                (Some(2), 3),
+                (Some(3), 4),
+            ]),
+            Err(TreeBuildError::Cycle { node: 2 })
+        );
+        assert_eq!(
+            NAryNode::from_parent_list(&[(None, 0), (Some(1), 1)]),
+            Err(TreeBuildError::Cycle { node: 1 })
+        );
+
+        let rows: &[(Option<usize>, i32)] = &[(None, 1), (Some(0), 2), (Some(0), 3), (Some(1), 4)];
+        let tree = NAryNode::try_from(rows).unwrap();
+        assert_eq!(
+            tree,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(2, vec![NAryNode::new(4)]),
+                    NAryNode::new(3),
+                ]
+            )
+        );
+        assert_eq!(
+            TreeBuildError::Cycle { node: 2 }.to_string(),
+            "node 2 is part of a parent cycle"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_traverse_tree_dedup_skips_repeated_subtrees() {
+        // Strings over {a, b} of length 2, keyed by their letters in sorted order,
+        // so "ba" repeats "ab" and is skipped along with its subtree
+        let mut visited = Vec::new();
+
+        traverse_tree_dedup(
+            String::new(),
+            |s| s.len() <= 2,
+            |s| vec![format!("{}a", s), format!("{}b", s)],
+            |s| {
+                let mut letters: Vec<char> = s.chars().collect();
+                letters.sort();
+                letters
+            },
+            |s| {
+                visited.push(s.clone());
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec!["", "a", "aa", "ab", "b", "bb"]);
+    }
+
+    #[test]
+    fn test_binary_node_sexpr_round_trip() {
+        let root = sample_tree();
+        let text = root.to_string();
+
+        assert_eq!(text, "(1 (2 (4 (-8) (9)) (5)) (3 (-7) (6)))");
+        assert_eq!(BinaryNode::from_sexpr(&text), Ok(sample_tree()));
+
+        let lopsided = BinaryNode::with_children(
+            1,
+            Some(Box::new(BinaryNode::with_children(
+                2,
+                None,
+                Some(Box::new(BinaryNode::new(3))),
+            ))),
+            None,
+        );
+        assert_eq!(lopsided.to_string(), "(1 (2 _ (3)) _)");
+        assert_eq!(
+            "  ( 1\n(2 _(3))\t_ ) ".parse::<BinaryNode<i32>>(),
+            Ok(lopsided)
+        );
+    }
+
+    #[test]
+    fn test_binary_node_sexpr_errors() {
+        let parse = BinaryNode::<i32>::from_sexpr;
+
+        assert_eq!(parse(""), Err(SexprError::UnexpectedEnd));
+        assert_eq!(parse("(1 (2) (3)"), Err(SexprError::UnexpectedEnd));
+        assert_eq!(
+            parse("(1 (2) (3)))"),
+            Err(SexprError::TrailingInput { position: 11 })
+        );
+        assert_eq!(
+            parse("(1 (2))"),
+            Err(SexprError::UnexpectedChar {
+                position: 6,
+                found: ')'
+            })
+        );
+        assert_eq!(
+            parse("(1 (2) (3) (4))"),
+            Err(SexprError::UnexpectedChar {
+                position: 11,
+                found: '('
+            })
+        );
+        assert_eq!(parse("(x)"), Err(SexprError::InvalidValue { position: 1 }));
+        assert_eq!(
+            parse("(_)"),
+            Err(SexprError::UnexpectedChar {
+                position: 1,
+                found: '_'
+            })
+        );
+        assert_eq!(
+            parse("(1 2 _)"),
+            Err(SexprError::UnexpectedChar {
+                position: 3,
+                found: '2'
+            })
+        );
+        assert_eq!(
+            parse("1"),
+            Err(SexprError::UnexpectedChar {
+                position: 0,
+                found: '1'
+            })
+        );
+        assert_eq!(
+            SexprError::TrailingInput { position: 3 }.to_string(),
+            "unexpected input after the tree at position 3"
+        );
+    }
+
+    #[test]
+    fn test_try_traverse_branches_stops_at_first_error() {
+        let root = sample_tree();
+        let mut visited = Vec::new();
+        let mut checked = Vec::new();
+
+        let result = try_traverse_branches(
+            &root,
+            |node| {
+                checked.push(node.value);
+                true
+            },
+            |node| {
+                if node.value == 4 {
+                    Err(node.value)
+                } else {
+                    Ok(node.children())
+                }
+            },
+            |node| {
+                visited.push(node.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(result, Err(4));
+        assert_eq!(visited, vec![1, 2, 4]);
+        assert_eq!(checked, vec![1, 2, 4]);
+
+        let result: Result<(), ()> = try_traverse_branches(
+            &root,
+            |_| true,
+            |node| Ok(node.children()),
+            |_| TreeControl::Continue,
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_traverse_tree_limit_paths() {
+        use core::sync::atomic::Ordering::Relaxed;
+
+        // A shallow tree stays on the recursive path
+        let root = sample_tree();
+        let before = HYBRID_FALLBACKS.load(Relaxed);
+        let mut visited = Vec::new();
+        traverse_tree_hybrid(
+            &root,
+            |_| true,
+            binary_children,
+            |node| {
+                visited.push(node.value);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(visited, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+        assert_eq!(HYBRID_FALLBACKS.load(Relaxed), before);
+
+        // A chain deep enough to overflow a recursive traversal switches once
+        let mut count = 0;
+        traverse_tree_hybrid(
+            0,
+            |n| *n < 1_000_000,
+            |n| vec![*n + 1],
+            |_| {
+                count += 1;
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(count, 1_000_000);
+        assert!(HYBRID_FALLBACKS.load(Relaxed) > before);
+
+        // Control flow matches `traverse_tree` at every limit, including
+        // restarts and repeats on both sides of the switch
+        let run = |limit: Option<usize>| {
+            let mut visited = Vec::new();
+            let mut restarted = false;
+            let mut repeated = false;
+            let visit = |n: &u32| {
+                visited.push(*n);
+                match *n {
+                    5 if !restarted => {
+                        restarted = true;
+                        TreeControl::RestartSubtree
+                    }
+                    6 if !repeated => {
+                        repeated = true;
+                        TreeControl::Repeat
+                    }
+                    3 => TreeControl::ContinueWithout(1),
+                    12 => TreeControl::Prune,
+                    14 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                }
+            };
+            let branches = |n: &u32| vec![n * 2, n * 2 + 1];
+            match limit {
+                Some(limit) => traverse_tree_limit(1, |n| *n < 32, branches, limit, visit),
+                None => traverse_tree(1, |n| *n < 32, branches, visit),
+            }
+            visited
+        };
+
+        let expected = run(None);
+        for limit in 0..6 {
+            assert_eq!(run(Some(limit)), expected, "recursion limit {}", limit);
+        }
+    }
+
+    #[test]
+    fn test_traverse_tree_filtered_combinations() {
+        // Node 2 gets each combination of filters in turn; everything else is
+        // visited and descended into
+        let run = |visit_two: bool, descend_two: bool| {
+            let mut visited = Vec::new();
+            traverse_tree_filtered(
+                1,
+                |n| *n != 2 || visit_two,
+                |n| *n < 8 && (*n != 2 || descend_two),
+                |n| vec![n * 2, n * 2 + 1],
+                |n| {
+                    visited.push(*n);
+                    TreeControl::Continue
+                },
+            );
+            visited
+        };
+
+        assert_eq!(
+            run(true, true),
+            vec![1, 2, 4, 8, 9, 5, 10, 11, 3, 6, 12, 13, 7, 14, 15]
+        );
+        assert_eq!(run(true, false), vec![1, 2, 3, 6, 12, 13, 7, 14, 15]);
+        assert_eq!(
+            run(false, true),
+            vec![1, 4, 8, 9, 5, 10, 11, 3, 6, 12, 13, 7, 14, 15]
+        );
+        assert_eq!(run(false, false), vec![1, 3, 6, 12, 13, 7, 14, 15]);
+    }
+
+    #[test]
+    fn test_reduce_up_evaluates_expression_tree() {
+        #[derive(Debug)]
+        enum Expr {
+            Num(i64),
+            Add,
+            Sub,
+            Mul,
+        }
+
+        fn leaf(n: i64) -> Option<Box<BinaryNode<Expr>>> {
+            Some(Box::new(BinaryNode::new(Expr::Num(n))))
+        }
+
+        fn op(
+            op: Expr,
+            left: Option<Box<BinaryNode<Expr>>>,
+            right: Option<Box<BinaryNode<Expr>>>,
+        ) -> Option<Box<BinaryNode<Expr>>> {
+            Some(Box::new(BinaryNode::with_children(op, left, right)))
+        }
+
+        // (2 + 3) * (10 - 4)
+        let tree = op(
+            Expr::Mul,
+            op(Expr::Add, leaf(2), leaf(3)),
+            op(Expr::Sub, leaf(10), leaf(4)),
+        )
+        .unwrap();
+
+        let value = reduce_up(
+            &&*tree,
+            binary_children,
+            |node, args: Vec<i64>| match node.value {
+                Expr::Num(n) => n,
+                Expr::Add => args[0] + args[1],
+                Expr::Sub => args[0] - args[1],
+                Expr::Mul => args[0] * args[1],
+            },
+        );
+
+        assert_eq!(value, 30);
+    }
+
+    #[test]
+    fn test_binary_node_iter_in_order() {
+        let values = [50, 30, 70, 20, 40, 60, 80, 35, 45, 65];
+        let tree = BinaryNode::from_bst_insert(values).unwrap();
+
+        let yielded: Vec<i32> = tree.iter().copied().collect();
+        assert!(yielded.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(yielded.len(), values.len());
+
+        let mut expected = Vec::new();
+        sample_tree().traverse_in_order(|v| expected.push(*v));
+        assert_eq!(sample_tree().iter().copied().collect::<Vec<_>>(), expected);
+
+        // The iterator is lazy, so it can stop early
+        assert_eq!(
+            tree.iter().take(2).copied().collect::<Vec<_>>(),
+            vec![20, 30]
+        );
+        assert_eq!(BinaryNode::new(7).iter().collect::<Vec<_>>(), vec![&7]);
+    }
+
+    #[test]
+    fn test_split_at_depth_boundaries() {
+        let root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(
+                    2,
+                    vec![
+                        NAryNode::with_children(4, vec![NAryNode::new(8)]),
+                        NAryNode::new(5),
+                    ],
+                ),
+                NAryNode::new(3),
+            ],
+        );
+
+        let (top, below) = split_at_depth(&root, 0);
+        assert_eq!(top, NAryNode::new(1));
+        assert_eq!(below, root.children);
+
+        let (top, below) = split_at_depth(&root, 2);
+        assert_eq!(
+            top,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+                    NAryNode::new(3),
+                ]
+            )
+        );
+        assert_eq!(below, vec![NAryNode::new(8)]);
+
+        // Deeper than the tree: nothing is cut off
+        let (top, below) = split_at_depth(&root, 10);
+        assert_eq!(top, root);
+        assert!(below.is_empty());
+    }
+
+    #[test]
+    fn test_traverse_tree_in_control_flow_per_order() {
+        let root = sample_tree();
+        let run = |order, prune_at: i32, break_at: i32| {
+            let mut visited = Vec::new();
+            let mut repeated = false;
+            traverse_tree_in(
+                order,
+                &root,
+                |_| true,
+                binary_children,
+                |node| {
+                    visited.push(node.value);
+                    match node.value {
+                        9 if !repeated => {
+                            repeated = true;
+                            TreeControl::Repeat
+                        }
+                        v if v == break_at => TreeControl::Break,
+                        v if v == prune_at => TreeControl::Prune,
+                        _ => TreeControl::Continue,
+                    }
+                },
+            );
+            visited
+        };
+
+        assert_eq!(run(Order::PreOrderDfs, 2, 0), vec![1, 2, 3, -7, 6]);
+        assert_eq!(run(Order::Bfs, 2, 0), vec![1, 2, 3, -7, 6]);
+        // Prune has nothing left to skip in post-order
+        assert_eq!(
+            run(Order::PostOrderDfs, 2, 0),
+            vec![-8, 9, 9, 4, 5, 2, -7, 6, 3, 1]
+        );
+
+        assert_eq!(run(Order::PreOrderDfs, 0, 5), vec![1, 2, 4, -8, 9, 9, 5]);
+        assert_eq!(run(Order::Bfs, 0, 5), vec![1, 2, 3, 4, 5]);
+        assert_eq!(run(Order::PostOrderDfs, 0, 5), vec![-8, 9, 9, 4, 5]);
+    }
+
+    #[test]
+    fn test_binary_node_contains_and_depth_of() {
+        let root = sample_tree();
+
+        assert_eq!(root.depth_of(&1), Some(0));
+        assert_eq!(root.depth_of(&5), Some(2));
+        assert_eq!(root.depth_of(&-8), Some(3));
+        assert_eq!(root.depth_of(&42), None);
+        assert!(root.contains(&-7));
+        assert!(!root.contains(&0));
+
+        // The first match in pre-order wins, even if another is shallower
+        let duplicate = BinaryNode::with_children(
+            0,
+            Some(Box::new(BinaryNode::with_children(
+                1,
+                Some(Box::new(BinaryNode::new(7))),
+                None,
+            ))),
+            Some(Box::new(BinaryNode::new(7))),
+        );
+        assert_eq!(duplicate.depth_of(&7), Some(2));
+    }
+
+    #[test]
+    fn test_traverse_tree_edges_passes_incoming_edge() {
+        // A trie of words, with the letter that extends each prefix on the edge
+        let words = ["an", "at", "be"];
+        let mut visited = Vec::new();
+
+        traverse_tree_edges(
+            String::new(),
+            |_| true,
+            |prefix| {
+                let mut letters: Vec<char> = words
+                    .iter()
+                    .filter(|w| w.starts_with(prefix.as_str()) && w.len() > prefix.len())
+                    .map(|w| w.as_bytes()[prefix.len()] as char)
+                    .collect();
+                letters.dedup();
+                letters
+                    .into_iter()
+                    .map(|c| (c, format!("{}{}", prefix, c)))
+                    .collect()
+            },
+            |prefix, edge| {
+                assert_eq!(edge.copied(), prefix.chars().last());
+                visited.push((edge.copied(), prefix.clone()));
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(
+            visited,
+            vec![
+                (None, "".to_string()),
+                (Some('a'), "a".to_string()),
+                (Some('n'), "an".to_string()),
+                (Some('t'), "at".to_string()),
+                (Some('b'), "b".to_string()),
+                (Some('e'), "be".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_tree_grouped_actions() {
+        let root = sample_tree();
+        let mut groups = Vec::new();
+        let mut visited = Vec::new();
+
+        traverse_tree_grouped(
+            &root,
+            |_| true,
+            binary_children,
+            |children| {
+                let values: Vec<i32> = children.iter().map(|child| child.value).collect();
+                groups.push(values.clone());
+                match values.as_slice() {
+                    // If any sibling is negative, explore none of them
+                    values if values.iter().any(|v| *v < 0) => SiblingAction::VisitNone,
+                    [2, 3] => SiblingAction::VisitOnly(vec![1, 5]),
+                    _ => SiblingAction::VisitAll,
+                }
+            },
+            |node| {
+                visited.push(node.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec![1, 3]);
+        assert_eq!(groups, vec![vec![2, 3], vec![-7, 6]]);
+    }
+
+    #[test]
+    fn test_traverse_tree_seeded_is_reproducible() {
+        let sample = |seed| {
+            let mut visited = Vec::new();
+            traverse_tree_seeded(
+                seed,
+                0u32,
+                |n| *n < 1000,
+                |n, rng| {
+                    let count = 1 + rng.below(3);
+                    (0..count).map(|i| n * 4 + 1 + i as u32).collect()
+                },
+                |n, rng| {
+                    visited.push(*n);
+                    TreeControl::from_prune(rng.next_f64() < 0.3)
+                },
+            );
+            visited
+        };
+
+        assert_eq!(sample(1), sample(1));
+        assert_eq!(sample(99), sample(99));
+        assert_ne!(sample(1), sample(2));
+    }
+
+    #[test]
+    fn test_flatten_unflatten_round_trip() {
+        let root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(2, vec![NAryNode::new(4)]),
+                NAryNode::new(3),
+                NAryNode::with_children(
+                    5,
+                    vec![NAryNode::with_children(6, vec![NAryNode::new(7)])],
+                ),
+            ],
+        );
+
+        let flat = flatten(&&root, |n| n.children.iter().collect());
+        let flat: Vec<_> = flat.into_iter().map(|(d, n)| (d, n.value)).collect();
+        assert_eq!(
+            flat,
+            vec![(0, 1), (1, 2), (2, 4), (1, 3), (1, 5), (2, 6), (3, 7)]
+        );
+        assert_eq!(unflatten(&flat), Some(root));
+
+        assert_eq!(unflatten::<i32>(&[]), None);
+        assert_eq!(unflatten(&[(1, 1)]), None);
+        assert_eq!(unflatten(&[(0, 1), (1, 2), (0, 3)]), None);
+        assert_eq!(unflatten(&[(0, 1), (1, 2), (3, 3)]), None);
+        assert_eq!(unflatten(&[(0, 1)]), Some(NAryNode::new(1)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_traverse_tree_timed_shape() {
+        let root = sample_tree();
+        let mut repeated = false;
+
+        let timings = traverse_tree_timed(
+            &root,
+            |_| true,
+            binary_children,
+            |n| {
+                if n.value == 5 && !repeated {
+                    repeated = true;
+                    return TreeControl::Repeat;
+                }
+                TreeControl::from_prune(n.value == 3)
+            },
+        )
+        .unwrap();
+
+        let shape = map_tree(&timings, |(n, _)| n.value);
+        assert_eq!(
+            shape,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(
+                        2,
+                        vec![
+                            NAryNode::with_children(4, vec![NAryNode::new(-8), NAryNode::new(9)]),
+                            NAryNode::new(5),
+                        ]
+                    ),
+                    NAryNode::new(3),
+                ]
+            )
+        );
+
+        // Break leaves the path to the current node timed
+        let partial = traverse_tree_timed(
+            &root,
+            |_| true,
+            binary_children,
+            |n| {
+                if n.value == 4 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            map_tree(&partial, |(n, _)| n.value),
+            NAryNode::with_children(1, vec![NAryNode::with_children(2, vec![NAryNode::new(4)])])
+        );
+
+        // A deferred node gets its second visit, as in traverse_tree
+        let mut visited = Vec::new();
+        traverse_tree_timed(
+            1,
+            |n| *n < 4,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                visited.push(*n);
+                if *n == 1 {
+                    TreeControl::Defer
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(visited, vec![1, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_binary_node_insert_remove() {
+        let mut set = BinaryNode::new(50);
+        for value in [30, 70, 20, 40, 60, 80, 35, 45, 65, 62] {
+            assert!(set.insert(value));
+        }
+        assert!(!set.insert(40));
+
+        // Leaf, one child, and two children, including the root whose
+        // successor sits deeper in the right subtree
+        assert!(set.remove(&20));
+        assert!(set.remove(&30));
+        assert!(set.remove(&50));
+        assert!(set.remove(&65));
+        assert!(!set.remove(&20));
+
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![35, 40, 45, 60, 62, 70, 80]
+        );
+        assert_eq!(set.value, 60);
+
+        let mut single = BinaryNode::new(1);
+        assert!(!single.remove(&1));
+        assert_eq!(single, BinaryNode::new(1));
+    }
+
+    #[test]
+    fn test_leaf_paths() {
+        let root = sample_tree();
+        let paths: Vec<Vec<i32>> = leaf_paths(&&root, binary_children)
+            .into_iter()
+            .map(|path| path.iter().map(|n| n.value).collect())
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                vec![1, 2, 4, -8],
+                vec![1, 2, 4, 9],
+                vec![1, 2, 5],
+                vec![1, 3, -7],
+                vec![1, 3, 6],
+            ]
+        );
+        assert_eq!(leaf_paths(&0, |_| vec![]), vec![vec![0]]);
+    }
+
+    #[test]
+    fn test_for_tree_without_condition() {
+        let root = sample_tree();
+
+        let mut elided = Vec::new();
+        for_tree!(node in &root; |node| node.children(); => {
+            elided.push(node.value);
+        });
+
+        let mut explicit = Vec::new();
+        for_tree!(node in &root; |_| true; |node| node.children() => {
+            explicit.push(node.value);
+        });
+
+        assert_eq!(elided, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+        assert_eq!(elided, explicit);
+
+        // The `;` form with a condition and the typed form still parse
+        let mut semicolon = Vec::new();
+        for_tree!(n in 1; |n| *n < 4; |n| vec![*n * 2, *n * 2 + 1]; {
+            semicolon.push(*n);
+        });
+        assert_eq!(semicolon, vec![1, 2, 3]);
+
+        let mut typed = Vec::new();
+        for_tree!(node: &BinaryNode<i32> in &root; |node| node.children(); => {
+            if node.value < 0 {
+                prune!();
+            }
+            typed.push(node.value);
+        });
+        assert_eq!(typed, vec![1, 2, 4, 9, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_defer_revisits_after_subtree() {
+        // Logs `n` for the first visit and `-n` for the second, deferring the
+        // inner nodes of the implicit binary tree 1..8
+        fn visitor(log: &mut Vec<i32>) -> impl FnMut(&i32) -> TreeControl + '_ {
+            |n| {
+                if log.contains(n) {
+                    log.push(-n);
+                    TreeControl::Continue
+                } else {
+                    log.push(*n);
+                    control!(defer if *n < 4)
+                }
+            }
+        }
+        let expected = vec![1, 2, 4, 5, -2, 3, 6, 7, -3, -1];
+        let children = |n: &i32| vec![n * 2, n * 2 + 1];
+
+        let mut log = Vec::new();
+        traverse_tree(1, |n| *n < 8, children, visitor(&mut log));
+        assert_eq!(log, expected);
+
+        let mut log = Vec::new();
+        traverse_tree_limit(1, |n| *n < 8, children, 1, visitor(&mut log));
+        assert_eq!(log, expected);
+
+        let mut log = Vec::new();
+        traverse_tree_indexed(
+            1,
+            |n| *n < 8,
+            |n, i| (i < 2).then(|| n * 2 + i as i32),
+            visitor(&mut log),
+        );
+        assert_eq!(log, expected);
+
+        let mut log = Vec::new();
+        {
+            let mut visit = visitor(&mut log);
+            traverse_tree_path(1, |n| *n < 8, children, |path| visit(path.last().unwrap()));
+        }
+        assert_eq!(log, expected);
+
+        let mut log = Vec::new();
+        assert_eq!(
+            traverse_graph_checked(1, |n| *n < 8, children, visitor(&mut log)),
+            Ok(())
+        );
+        assert_eq!(log, expected);
+
+        let mut log = Vec::new();
+        TreeWalker::new(1)
+            .condition(|n| *n < 8)
+            .branches(children)
+            .run(visitor(&mut log));
+        assert_eq!(log, expected);
+
+        // A deferred node at the depth limit still gets its second visit
+        let mut log = Vec::new();
+        traverse_tree_max_depth(1, |n| *n < 8, children, 1, visitor(&mut log));
+        assert_eq!(log, vec![1, 2, -2, 3, -3, -1]);
+
+        // Breaking on the second visit stops the traversal
+        let mut log = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 8,
+            children,
+            |n| {
+                let control = visitor(&mut log)(n);
+                if *n == 2 && log.contains(&-2) {
+                    TreeControl::Break
+                } else {
+                    control
+                }
+            },
+        );
+        assert_eq!(log, vec![1, 2, 4, 5, -2]);
+    }
+
+    #[test]
+    fn test_traverse_tree_deferred_events() {
+        let root = sample_tree();
+        let mut events = Vec::new();
+
+        traverse_tree_deferred(
+            &root,
+            |_| true,
+            binary_children,
+            |event| match event {
+                Event::Enter(node) => {
+                    events.push(format!("enter {}", node.value));
+                    control!(prune if node.value == 3, defer if node.value == 2 || node.value == 4)
+                }
+                Event::Leave(node) => {
+                    events.push(format!("leave {}", node.value));
+                    TreeControl::Prune
+                }
+            },
+        );
+
+        assert_eq!(
+            events,
+            vec![
+                "enter 1", "enter 2", "enter 4", "enter -8", "enter 9", "leave 4", "enter 5",
+                "leave 2", "enter 3"
+            ]
+        );
+
+        let stats = traverse_tree_stats(
+            1,
+            |n| *n < 8,
+            |n| vec![n * 2, n * 2 + 1],
+            |n| control!(defer if *n == 1),
+        );
+        assert_eq!(stats.visited, 7);
+    }
+
+    #[test]
+    fn test_binary_node_into_iter() {
+        let values: Vec<i32> = sample_tree().into_iter().collect();
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5, 3, -7, 6]);
+
+        let mut sum = 0;
+        for value in BinaryNode::new(5) {
+            sum += value;
+        }
+        assert_eq!(sum, 5);
+    }
+
+    #[test]
+    fn test_traverse_sampled_limits_breadth() {
+        let tree = sample_tree();
+        let mut visited = Vec::new();
+
+        // Prefer the right child, keeping only one per node
+        traverse_sampled(
+            &tree,
+            |_| true,
+            binary_children,
+            1,
+            |children| (0..children.len()).rev().collect(),
+            |n| {
+                visited.push(n.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec![1, 3, 6]);
+
+        // Repeated indices are only followed once
+        let mut visited = Vec::new();
+        traverse_sampled(
+            &tree,
+            |n| n.value > 0,
+            binary_children,
+            5,
+            |children| {
+                [1, 1, 0]
+                    .into_iter()
+                    .filter(|&i| i < children.len())
+                    .collect()
+            },
+            |n| {
+                visited.push(n.value);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec![1, 3, 6, 2, 5, 4, 9]);
+    }
+
+    #[test]
+    fn test_nary_node_retain() {
+        let mut root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(2, vec![NAryNode::new(-4), NAryNode::new(-5)]),
+                NAryNode::with_children(-3, vec![NAryNode::new(6)]),
+                NAryNode::new(7),
+            ],
+        );
+
+        let mut checked = Vec::new();
+        root.retain(|n| {
+            checked.push(*n);
+            *n > 0
+        });
+
+        // Neither the root nor 6, below the removed -3, is checked
+        checked.sort();
+        assert_eq!(checked, vec![-5, -4, -3, 2, 7]);
+        // 2 lost all of its children but survives as a leaf
+        assert_eq!(
+            root,
+            NAryNode::with_children(1, vec![NAryNode::new(2), NAryNode::new(7)])
+        );
+    }
+
+    #[test]
+    fn test_merge_trees() {
+        let a = NAryNode::with_children(
+            (0, 1),
+            vec![
+                NAryNode::with_children((1, 1), vec![NAryNode::new((3, 1))]),
+                NAryNode::new((2, 1)),
+                NAryNode::new((1, 1)),
+            ],
+        );
+        let b = NAryNode::with_children(
+            (0, 10),
+            vec![
+                NAryNode::new((4, 10)),
+                NAryNode::with_children(
+                    (1, 10),
+                    vec![NAryNode::new((3, 10)), NAryNode::new((5, 10))],
+                ),
+            ],
+        );
+
+        let merged = merge_trees(&a, &b, |(key, _)| *key, |x, y| (x.0, x.1 + y.1));
+
+        // Only the first child of `a` with key 1 is paired
+        assert_eq!(
+            merged,
+            NAryNode::with_children(
+                (0, 11),
+                vec![
+                    NAryNode::with_children(
+                        (1, 11),
+                        vec![NAryNode::new((3, 11)), NAryNode::new((5, 10))]
+                    ),
+                    NAryNode::new((2, 1)),
+                    NAryNode::new((1, 1)),
+                    NAryNode::new((4, 10)),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_traverse_bounded_prunes_on_path_cost() {
+        let tree = sample_tree();
+        let mut visited = Vec::new();
+
+        // Path sums: 1, 3, 7, -1, 16, 8, 4, -3, 10
+        traverse_bounded(
+            &tree,
+            binary_children,
+            |n| f64::from(n.value),
+            8.0,
+            |n, cost| {
+                visited.push((n.value, cost));
+                if n.value == 3 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(
+            visited,
+            vec![(1, 1.0), (2, 3.0), (4, 7.0), (-8, -1.0), (5, 8.0), (3, 4.0)]
+        );
+
+        let mut visited = Vec::new();
+        traverse_bounded(
+            &tree,
+            binary_children,
+            |_| 1.0,
+            0.5,
+            |n, _| {
+                visited.push(n.value);
+                TreeControl::Continue
+            },
+        );
+        assert!(visited.is_empty());
+    }
+
+    #[test]
+    fn test_tree_macro() {
+        let root = tree!(1 => {
+            2 => { 4, 5 },
+            3,
+            -1 + 7 => {},
+            8 => { 9 => { 10, }, },
+        });
+
+        assert_eq!(
+            root,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(2, vec![NAryNode::new(4), NAryNode::new(5)]),
+                    NAryNode::new(3),
+                    NAryNode::new(6),
+                    NAryNode::with_children(
+                        8,
+                        vec![NAryNode::with_children(9, vec![NAryNode::new(10)])]
+                    ),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_branching_histogram() {
+        let tree = sample_tree();
+        let histogram = branching_histogram(&&tree, binary_children);
+
+        assert_eq!(histogram, BTreeMap::from([(0, 5), (2, 4)]));
+        assert_eq!(
+            branching_histogram(&0, |_| Vec::new()),
+            BTreeMap::from([(0, 1)])
+        );
+    }
+
+    /// Branch function for the implicit binary tree 1..16 that counts its calls,
+    /// so it is only `FnMut`
+    fn counting_children(calls: &mut usize) -> impl FnMut(&u32) -> Vec<u32> + '_ {
+        move |n| {
+            *calls += 1;
+            if *n < 8 {
+                vec![n * 2, n * 2 + 1]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn test_callbacks_accept_mutable_captures() {
+        let mut calls = 0;
+        let mut other = 0;
+        let cont = |_: &u32| TreeControl::Continue;
+
+        traverse_tree(1, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_with_stack(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            cont,
+            &mut Vec::new(),
+        );
+        traverse_tree_hinted(1, |_| true, counting_children(&mut calls), 4, cont);
+        traverse_tree_hybrid(1, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_limit(1, |_| true, counting_children(&mut calls), 2, cont);
+        traverse_tree_in(Order::Bfs, 1, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_rev(1, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_ordered(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |n| {
+                other += 1;
+                *n
+            },
+            cont,
+        );
+        traverse_tree_ordered_by(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |a, b| {
+                other += 1;
+                a.cmp(b)
+            },
+            cont,
+        );
+        traverse_tree_indexed(
+            1u32,
+            |_| true,
+            |n, i| {
+                other += 1;
+                (*n < 8 && i < 2).then(|| n * 2 + i as u32)
+            },
+            cont,
+        );
+        traverse_tree_max_depth(1, |_| true, counting_children(&mut calls), 3, cont);
+        traverse_iddfs(1, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_budget(1, |_| true, counting_children(&mut calls), 100, cont);
+        traverse_tree_seeded(
+            0,
+            1,
+            |_| true,
+            |n, _| {
+                other += 1;
+                if *n < 8 { vec![n * 2] } else { vec![] }
+            },
+            |_, _| TreeControl::Continue,
+        );
+        traverse_sampled(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            2,
+            |c| {
+                other += 1;
+                (0..c.len()).collect()
+            },
+            cont,
+        );
+        traverse_bounded(
+            1,
+            counting_children(&mut calls),
+            |_| {
+                other += 1;
+                1.0
+            },
+            10.0,
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_progress(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            4,
+            |_| other += 1,
+            cont,
+        );
+        traverse_until(1, |_| true, counting_children(&mut calls), |_| false);
+        traverse_tree_descend_if(1, |_| true, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_filtered(1, |_| true, |_| true, counting_children(&mut calls), cont);
+        traverse_tree_stats(1, |_| true, counting_children(&mut calls), cont);
+        traverse_leaves(1, |_| true, counting_children(&mut calls), cont);
+        traverse_best_first(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |n| {
+                other += 1;
+                *n
+            },
+            cont,
+        );
+        traverse_tree_dynamic(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_child_info(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_edges(
+            1u32,
+            |_| true,
+            |n| {
+                other += 1;
+                if *n < 8 { vec![((), n * 2)] } else { vec![] }
+            },
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_grouped(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| SiblingAction::VisitAll,
+            cont,
+        );
+        traverse_tree_depth(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_ctx(
+            1u32,
+            |_, _| true,
+            |n, _| {
+                other += 1;
+                if *n < 8 { vec![n * 2] } else { vec![] }
+            },
+            |_, _| TreeControl::Continue,
+        );
+        traverse_tree_path(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| TreeControl::Continue,
+        );
+        traverse_events(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| TreeControl::Continue,
+        );
+        traverse_tree_deferred(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| TreeControl::Continue,
+        );
+        zip_trees(
+            1,
+            1,
+            counting_children(&mut calls),
+            |n: &u32| {
+                other += 1;
+                vec![n * 2; usize::from(*n < 8)]
+            },
+            |_, _| TreeControl::Continue,
+        );
+        level_order(1, |_| true, counting_children(&mut calls));
+        collect_tree(1, |_| true, counting_children(&mut calls));
+        collect_tree_with(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |n| {
+                other += 1;
+                *n
+            },
+        );
+        fold_tree(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            0,
+            |acc, n| (acc + n, TreeControl::Continue),
+        );
+        reduce_up(
+            &1,
+            counting_children(&mut calls),
+            |_, children: Vec<usize>| {
+                other += 1;
+                children.len()
+            },
+        );
+        count_nodes(1, |_| true, counting_children(&mut calls));
+        tree_height(&1, counting_children(&mut calls));
+        branching_histogram(&1, counting_children(&mut calls));
+        leaf_paths(&1, counting_children(&mut calls));
+        find_tree(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| {
+                other += 1;
+                false
+            },
+        );
+        find_map_tree(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| {
+                other += 1;
+                None::<u32>
+            },
+        );
+        let _ = try_traverse_tree(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| Ok::<_, ()>(TreeControl::Continue),
+        );
+        let _ = try_traverse_branches(
+            1u32,
+            |_| true,
+            |n| {
+                other += 1;
+                Ok::<_, ()>(vec![n * 2; usize::from(*n < 8)])
+            },
+            cont,
+        );
+        format_tree(&1, counting_children(&mut calls));
+        traverse_tree_value(
+            1,
+            |_| true,
+            counting_children(&mut calls),
+            |_| ControlFlow::<(), _>::Continue(TreeControl::Continue),
+        );
+        flatten(&1, counting_children(&mut calls));
+        let _ = traverse_graph_checked(1, |_| true, counting_children(&mut calls), cont);
+
+        #[cfg(feature = "std")]
+        {
+            traverse_tree_dedup(
+                1,
+                |_| true,
+                counting_children(&mut calls),
+                |n| {
+                    other += 1;
+                    *n
+                },
+                cont,
+            );
+            traverse_tree_timed(1, |_| true, counting_children(&mut calls), cont);
+            let (tx, _rx) = std::sync::mpsc::channel();
+            traverse_to_channel(1, |_| true, counting_children(&mut calls), tx);
+        }
+
+        let tree = NAryNode::with_children(1, vec![NAryNode::new(2)]);
+        merge_trees(
+            &tree,
+            &tree,
+            |n| {
+                other += 1;
+                *n
+            },
+            |a, _| *a,
+        );
+        TreeWalker::new(1)
+            .branches(counting_children(&mut calls))
+            .run(cont);
+
+        // 15 branch calls for each full traversal of the 15 node tree, and more
+        // for the ones that generate it several times
+        assert!(calls >= 15 * 40);
+        assert!(other > 0);
+    }
+
+    #[test]
+    fn test_prune_depth_with_for_tree_depth() {
+        let mut visited = Vec::new();
+        let max_depth = 2;
+
+        for_tree_depth!(n, depth in 1; |_| true; |n| vec![*n * 2, *n * 2 + 1] => {
+            visited.push((*n, depth));
+            prune_depth!(depth, max_depth);
+        });
+
+        assert_eq!(
+            visited,
+            vec![(1, 0), (2, 1), (4, 2), (5, 2), (3, 1), (6, 2), (7, 2)]
+        );
+    }
+
+    #[test]
+    fn test_traverse_tree_root_edge_cases() {
+        let mut visited = Vec::new();
+        let mut branch_calls = 0;
+
+        // A root that fails the condition is never visited
+        traverse_tree(
+            1,
+            |_| false,
+            |n| {
+                branch_calls += 1;
+                vec![*n * 2, *n * 2 + 1]
+            },
+            |n| {
+                visited.push(*n);
+                TreeControl::Continue
+            },
+        );
+        assert!(visited.is_empty());
+        assert_eq!(branch_calls, 0);
+
+        // A root without children is visited once
+        traverse_tree(
+            1,
+            |_| true,
+            |_| Vec::new(),
+            |n| {
+                visited.push(*n);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(visited, vec![1]);
+
+        // A pruned or breaking root is the only node visited, and its children
+        // are never generated
+        for control in [TreeControl::Prune, TreeControl::Break] {
+            let mut visited = Vec::new();
+            traverse_tree(
+                1,
+                |_| true,
+                |n| {
+                    branch_calls += 1;
+                    vec![*n * 2, *n * 2 + 1]
+                },
+                |n| {
+                    visited.push(*n);
+                    control
+                },
+            );
+            assert_eq!(visited, vec![1]);
+        }
+        assert_eq!(branch_calls, 0);
+    }
+
+    #[test]
+    fn test_traverse_sibling_pairs() {
+        let tree = sample_tree();
+        let mut visited = Vec::new();
+        let mut pairs = Vec::new();
+
+        traverse_sibling_pairs(
+            &tree,
+            |n| n.value > 0,
+            binary_children,
+            |n| {
+                visited.push(n.value);
+                if n.value == 2 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+            |a, b| pairs.push((a.value, b.value)),
+        );
+
+        // -7 is paired even though it is never visited, and the children of the
+        // pruned 2 are never paired
+        assert_eq!(visited, vec![1, 2, 3, 6]);
+        assert_eq!(pairs, vec![(2, 3), (-7, 6)]);
+    }
+
+    #[test]
+    fn test_map_tree_cow() {
+        let root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(2, vec![NAryNode::new(4)]),
+                NAryNode::with_children(3, vec![NAryNode::new(5), NAryNode::new(6)]),
+            ],
+        );
+
+        let mut seen = Vec::new();
+        let unchanged = map_tree_cow(&root, |n| {
+            seen.push(*n);
+            None
+        });
+        assert_eq!(seen, vec![1, 2, 4, 3, 5, 6]);
+        assert!(matches!(unchanged, Cow::Borrowed(r) if core::ptr::eq(r, &root)));
+
+        let changed = map_tree_cow(&root, |n| (*n == 5).then_some(50));
+        let Cow::Owned(changed) = changed else {
+            panic!("a changed tree is owned");
+        };
+        assert_eq!(
+            changed,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(2, vec![NAryNode::new(4)]),
+                    NAryNode::with_children(3, vec![NAryNode::new(50), NAryNode::new(6)]),
+                ]
+            )
+        );
+        assert_eq!(root.clone(), root);
+    }
+
+    #[test]
+    fn test_prune_siblings_skips_rest_of_level() {
+        let branch = |n: &u32| vec![n * 2, n * 2 + 1];
+        let expected = vec![1, 2, 4, 3, 6, 12, 13, 7, 14, 15];
+        let control = |n: u32| {
+            if n == 4 {
+                TreeControl::PruneSiblings
+            } else {
+                TreeControl::Continue
+            }
+        };
+
+        let mut visited = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 16,
+            branch,
+            |n| {
+                visited.push(*n);
+                control(*n)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        // Unlike `Break`, which stops everything
+        let mut visited = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 16,
+            branch,
+            |n| {
+                visited.push(*n);
+                if *n == 4 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(visited, vec![1, 2, 4]);
+
+        for limit in [1, 2, 3, 10] {
+            let mut visited = Vec::new();
+            traverse_tree_limit(
+                1,
+                |n| *n < 16,
+                branch,
+                limit,
+                |n| {
+                    visited.push(*n);
+                    control(*n)
+                },
+            );
+            assert_eq!(visited, expected, "recursion limit {}", limit);
+        }
+
+        let mut visited = Vec::new();
+        traverse_tree_indexed(
+            1,
+            |n| *n < 16,
+            |n, i| (i < 2).then(|| n * 2 + i as u32),
+            |n| {
+                visited.push(*n);
+                control(*n)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        traverse_tree_path(
+            1,
+            |n| *n < 16,
+            branch,
+            |path| {
+                let n = *path.last().unwrap();
+                visited.push(n);
+                control(n)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        let result = traverse_graph_checked(
+            1,
+            |n| *n < 16,
+            branch,
+            |n| {
+                visited.push(*n);
+                control(*n)
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        TreeWalker::new(1)
+            .condition(|n| *n < 16)
+            .branches(branch)
+            .run(|n| {
+                visited.push(*n);
+                control(*n)
+            });
+        assert_eq!(visited, expected);
+
+        // In post-order, the siblings after a node are skipped once it is left
+        let mut visited = Vec::new();
+        traverse_tree_in(
+            Order::PostOrderDfs,
+            1,
+            |n| *n < 16,
+            branch,
+            |n| {
+                visited.push(*n);
+                control(*n)
+            },
+        );
+        assert_eq!(visited, vec![8, 9, 4, 2, 12, 13, 6, 14, 15, 7, 3, 1]);
+
+        // A deferred parent still gets its second visit
+        let mut events = Vec::new();
+        traverse_tree_deferred(
+            1,
+            |n| *n < 8,
+            branch,
+            |event| match event {
+                Event::Enter(n) => {
+                    events.push(*n);
+                    match *n {
+                        2 => TreeControl::Defer,
+                        4 => TreeControl::PruneSiblings,
+                        _ => TreeControl::Continue,
+                    }
+                }
+                Event::Leave(n) => {
+                    events.push(n + 100);
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(events, vec![1, 2, 4, 102, 3, 6, 7]);
+
+        assert_eq!(
+            TreeControl::Prune.and(TreeControl::PruneSiblings),
+            TreeControl::PruneSiblings
+        );
+        assert_eq!(
+            TreeControl::PruneSiblings.and(TreeControl::Repeat),
+            TreeControl::Repeat
+        );
+    }
+
+    #[test]
+    fn test_traverse_tree_accepts_non_clone_nodes() {
+        // Owned nodes are moved through the traversal, never cloned
+        struct Node(u32);
+
+        let mut visited = Vec::new();
+        traverse_tree(
+            Node(1),
+            |n| n.0 < 8,
+            |n| vec![Node(n.0 * 2), Node(n.0 * 2 + 1)],
+            |n| {
+                visited.push(n.0);
+                TreeControl::Continue
+            },
+        );
+
+        assert_eq!(visited, vec![1, 2, 4, 5, 3, 6, 7]);
+
+        // And so are the wrappers that only borrow them
+        let children = |n: &Node| vec![Node(n.0 * 2), Node(n.0 * 2 + 1)];
+        assert_eq!(count_nodes(Node(1), |n| n.0 < 8, children), 7);
+        assert_eq!(
+            traverse_tree_stats(Node(1), |n| n.0 < 8, children, |_| TreeControl::Continue)
+                .max_depth,
+            2
+        );
+        let mut deepest = 0;
+        traverse_tree_path(
+            Node(1),
+            |n| n.0 < 8,
+            children,
+            |path| {
+                deepest = deepest.max(path.len());
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(deepest, 3);
+    }
+
+    #[test]
+    fn test_traverse_forest() {
+        let forest = [1u32, 2, 3];
+        let mut roots_taken = 0;
+        let mut visited = Vec::new();
+
+        traverse_forest(
+            forest.iter().inspect(|_| roots_taken += 1),
+            |n| **n != 2,
+            |_| Vec::new(),
+            |n| {
+                visited.push(**n);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(visited, vec![1, 3]);
+        assert_eq!(roots_taken, 3);
+
+        // Pruning and breaking inside one tree of the forest
+        let root = sample_tree();
+        let trees = [&root, root.right.as_deref().unwrap(), &root];
+        let mut visited = Vec::new();
+        traverse_forest(
+            trees,
+            |_| true,
+            binary_children,
+            |n| {
+                visited.push(n.value);
+                match n.value {
+                    2 => TreeControl::Prune,
+                    -7 if visited.len() > 5 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+        assert_eq!(visited, vec![1, 2, 3, -7, 6, 3, -7]);
+
+        // An empty forest visits nothing
+        let mut count = 0;
+        traverse_forest(
+            Vec::<u32>::new(),
+            |_| true,
+            |_| Vec::new(),
+            |_| {
+                count += 1;
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        // Nodes of the implicit tree below 8 that display only their parity,
+        // so the generated ids are all that tells them apart
+        struct Parity(u32);
+
+        impl Display for Parity {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str(if self.0.is_multiple_of(2) {
+                    "even"
+                } else {
+                    "odd"
+                })
+            }
+        }
+
+        let dot = to_dot(&Parity(1), |n| {
+            [n.0 * 2, n.0 * 2 + 1]
+                .into_iter()
+                .filter(|c| *c < 8)
+                .map(Parity)
+                .collect()
+        });
+
+        assert!(dot.starts_with("digraph {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches("[label=\"even\"]").count(), 3);
+        assert_eq!(dot.matches("[label=\"odd\"]").count(), 4);
+        assert_eq!(dot.matches(" -> ").count(), 6);
+        assert!(dot.contains("    n2 [label=\"even\"];\n    n1 -> n2;\n"));
+        assert!(dot.contains("    n5 [label=\"even\"];\n    n4 -> n5;\n"));
+
+        // Labels are escaped so the output stays valid DOT
+        let dot = to_dot(&"say \"hi\"\\\nbye", |_| Vec::new());
+        assert_eq!(
+            dot,
+            "digraph {\n    n0 [label=\"say \\\"hi\\\"\\\\\\nbye\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_traverse_enumerated() {
+        let root = sample_tree();
+
+        // Indices follow the actual visits, without gaps for pruned subtrees
+        // or skipped nodes, and stop at a break
+        let mut numbered = Vec::new();
+        traverse_enumerated(
+            &root,
+            |n| n.value != 5,
+            binary_children,
+            |index, n| {
+                numbered.push((index, n.value));
+                match n.value {
+                    4 => TreeControl::Prune,
+                    -7 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+        assert_eq!(numbered, vec![(0, 1), (1, 2), (2, 4), (3, 3), (4, -7)]);
+
+        // Repeated and deferred visits keep the node's index
+        let mut numbered = Vec::new();
+        let mut repeated = false;
+        traverse_enumerated(
+            &root,
+            |_| true,
+            binary_children,
+            |index, n| {
+                numbered.push((index, n.value));
+                match n.value {
+                    2 if !repeated => {
+                        repeated = true;
+                        TreeControl::Repeat
+                    }
+                    3 if numbered.iter().filter(|(_, v)| *v == 3).count() == 1 => {
+                        TreeControl::Defer
+                    }
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+        assert_eq!(
+            numbered,
+            vec![
+                (0, 1),
+                (1, 2),
+                (1, 2),
+                (2, 4),
+                (3, -8),
+                (4, 9),
+                (5, 5),
+                (6, 3),
+                (7, -7),
+                (8, 6),
+                (6, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binary_node_mirror() {
+        let root = sample_tree();
+        let mirror = root.mirror();
+
+        let mut values = Vec::new();
+        mirror.traverse_pre_order(|value| values.push(*value));
+        assert_eq!(values, vec![1, 3, 6, -7, 2, 5, 4, 9, -8]);
+
+        // Reverse traversal of a tree visits the same order as its mirror
+        let mut reversed = Vec::new();
+        traverse_tree_rev(
+            &root,
+            |_| true,
+            binary_children,
+            |n| {
+                reversed.push(n.value);
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(reversed, values);
+
+        let mut flipped = root.map(|value| *value);
+        flipped.mirror_mut();
+        assert_eq!(flipped, mirror);
+        flipped.mirror_mut();
+        assert_eq!(flipped, root);
+
+        assert!(!root.is_symmetric());
+        assert!(BinaryNode::new(1).is_symmetric());
+
+        // A tree joined with its own mirror is symmetric, unless a value differs
+        let left = root.map(|value| *value);
+        let mut joined = BinaryNode::with_children(0, Some(Box::new(left)), Some(Box::new(mirror)));
+        assert!(joined.is_symmetric());
+        joined.right.as_mut().unwrap().value = 2;
+        assert!(!joined.is_symmetric());
+    }
+
+    #[test]
+    fn test_collect_matching() {
+        let root = sample_tree();
+        let mut visited = 0;
+
+        // Every node whose value is at least 4 in size, nested or not
+        let all = collect_matching(
+            &root,
+            |_| true,
+            |n| {
+                visited += 1;
+                binary_children(n)
+            },
+            |n| n.value.abs() >= 4,
+        );
+        assert_eq!(
+            all.iter().map(|n| n.value).collect::<Vec<_>>(),
+            vec![4, -8, 9, 5, -7, 6]
+        );
+        assert_eq!(visited, 9);
+
+        // Only the topmost matches, without generating the branches below them
+        let mut visited = 0;
+        let topmost = collect_matching_prune(
+            &root,
+            |_| true,
+            |n| {
+                visited += 1;
+                binary_children(n)
+            },
+            |n| n.value.abs() >= 4,
+        );
+        assert_eq!(
+            topmost.iter().map(|n| n.value).collect::<Vec<_>>(),
+            vec![4, 5, -7, 6]
+        );
+        assert_eq!(visited, 3);
+
+        assert!(collect_matching(&root, |_| true, binary_children, |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_traverse_tree_ancestors() {
+        let root = sample_tree();
+        let mut seen = Vec::new();
+
+        traverse_tree_ancestors(
+            &root,
+            |_| true,
+            binary_children,
+            |ctx| {
+                seen.push((
+                    ctx.current().value,
+                    ctx.depth(),
+                    ctx.parent().map(|n| n.value),
+                    ctx.ancestor(2).map(|n| n.value),
+                ));
+                assert_eq!(ctx.ancestor(0).map(|n| n.value), Some(ctx.current().value));
+                assert!(ctx.ancestor(ctx.depth() + 1).is_none());
+                assert_eq!(ctx.ancestor(ctx.depth()).map(|n| n.value), Some(1));
+                TreeControl::from_prune(ctx.current().value == 3)
+            },
+        );
+
+        assert_eq!(
+            seen,
+            vec![
+                (1, 0, None, None),
+                (2, 1, Some(1), None),
+                (4, 2, Some(2), Some(1)),
+                (-8, 3, Some(4), Some(2)),
+                (9, 3, Some(4), Some(2)),
+                (5, 2, Some(2), Some(1)),
+                (3, 1, Some(1), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_lazy() {
+        // An infinite chain ends only when the visitor breaks
+        let mut visited = Vec::new();
+        traverse_lazy(
+            0u32,
+            |n| vec![n + 1],
+            |n| {
+                visited.push(*n);
+                if *n == 4 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(visited, vec![0, 1, 2, 3, 4]);
+
+        // A finite tree ends where the branches run out
+        let root = sample_tree();
+        let mut count = 0;
+        traverse_lazy(&root, binary_children, |_| {
+            count += 1;
+            TreeControl::Continue
+        });
+        assert_eq!(count, 9);
+    }
+
+    #[test]
+    fn test_traverse_by_level() {
+        let root = sample_tree();
+
+        let mut levels = Vec::new();
+        traverse_by_level(
+            &root,
+            |n| n.value != 5,
+            binary_children,
+            |depth, level| {
+                levels.push((depth, level.iter().map(|n| n.value).collect::<Vec<_>>()));
+                TreeControl::Continue
+            },
+        );
+        assert_eq!(
+            levels,
+            vec![
+                (0, vec![1]),
+                (1, vec![2, 3]),
+                (2, vec![4, -7, 6]),
+                (3, vec![-8, 9]),
+            ]
+        );
+
+        // Children of a level that breaks are never generated, and a repeated
+        // level is handed over again
+        let mut generated = 0;
+        let mut calls = Vec::new();
+        traverse_by_level(
+            &root,
+            |_| true,
+            |n| {
+                generated += 1;
+                binary_children(n)
+            },
+            |depth, level| {
+                calls.push((depth, level.len()));
+                match calls.len() {
+                    1 => TreeControl::Repeat,
+                    3 => TreeControl::Prune,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+        assert_eq!(calls, vec![(0, 1), (0, 1), (1, 2)]);
+        assert_eq!(generated, 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "as its child 1 (at depth 2)")]
+    fn test_traverse_tree_guarded_self_loop_panics() {
+        // 2 is its own second child
+        traverse_tree_guarded(
+            0u32,
+            |_| true,
+            |n| if *n == 2 { vec![3, 2] } else { vec![n + 1] },
+            |_| TreeControl::Continue,
+        );
+    }
+
+    #[test]
+    fn test_prune_after_caps_subtrees() {
+        let mut visited = Vec::new();
+
+        // The cap set at 1 can't be loosened by 2, only tightened by 3, and the
+        // deferred 8 still gets its second visit at the cap
+        traverse_tree(
+            1,
+            |n| *n < 64,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                let first = !visited.contains(n);
+                visited.push(*n);
+                match *n {
+                    1 => TreeControl::PruneAfter(3),
+                    2 => TreeControl::PruneAfter(5),
+                    3 => TreeControl::PruneAfter(1),
+                    8 if first => TreeControl::Defer,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+
+        assert_eq!(visited, vec![1, 2, 4, 8, 8, 9, 5, 10, 11, 3, 6, 7]);
+        assert_eq!(
+            TreeControl::PruneAfter(4).and(TreeControl::PruneAfter(2)),
+            TreeControl::PruneAfter(2)
+        );
+        assert_eq!(
+            TreeControl::PruneAfter(4).and(TreeControl::Prune),
+            TreeControl::Prune
+        );
+
+        let mut visited = Vec::new();
+        for_tree_depth!(n, depth in 1; |n| *n < 16; |n| vec![*n * 2, *n * 2 + 1] => {
+            visited.push((*n, depth));
+            if *n == 2 {
+                prune_after!(1);
+            }
+        });
+
+        assert_eq!(
+            visited,
+            vec![
+                (1, 0),
+                (2, 1),
+                (4, 2),
+                (5, 2),
+                (3, 1),
+                (6, 2),
+                (12, 3),
+                (13, 3),
+                (7, 2),
+                (14, 3),
+                (15, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_collect() {
+        let root = sample_tree();
+        let mut branched = Vec::new();
+
+        // Collect the positive values, pruning below 4 after collecting it
+        let values = traverse_collect(
+            &root,
+            |_| true,
+            |n| {
+                branched.push(n.value);
+                binary_children(n)
+            },
+            |n| {
+                let value = (n.value > 0).then_some(n.value);
+                (value, TreeControl::from_prune(n.value == 4))
+            },
+        );
+
+        assert_eq!(values, vec![1, 2, 4, 5, 3, 6]);
+        assert!(!branched.contains(&4));
+
+        // A value can be yielded on the visit that stops the traversal
+        let values = traverse_collect(
+            &root,
+            |_| true,
+            binary_children,
+            |n| {
+                let control = if n.value == 5 {
+                    TreeControl::Break
+                } else {
+                    TreeControl::Continue
+                };
+                (Some(n.value), control)
+            },
+        );
+
+        assert_eq!(values, vec![1, 2, 4, -8, 9, 5]);
+    }
+
+    #[test]
+    fn test_break_after_subtree() {
+        let control_at =
+            |n: &i32, stop: TreeControl| if *n == 2 { stop } else { TreeControl::Continue };
+        let children = |n: &i32| vec![*n * 2, *n * 2 + 1];
+
+        // `Break` stops right away, `BreakAfterSubtree` lets the subtree of 2 finish
+        let mut visited = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 16,
+            children,
+            |n| {
+                visited.push(*n);
+                control_at(n, TreeControl::Break)
+            },
+        );
+        assert_eq!(visited, vec![1, 2]);
+
+        let expected = vec![1, 2, 4, 8, 9, 5, 10, 11];
+        let mut visited = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 16,
+            children,
+            |n| {
+                visited.push(*n);
+                control_at(n, TreeControl::BreakAfterSubtree)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        // The traversals with their own stack handling agree
+        let mut visited = Vec::new();
+        traverse_tree_limit(
+            1,
+            |n| *n < 16,
+            children,
+            2,
+            |n| {
+                visited.push(*n);
+                control_at(n, TreeControl::BreakAfterSubtree)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        traverse_tree_with_stack(
+            1,
+            |n| *n < 16,
+            children,
+            |n| {
+                visited.push(*n);
+                control_at(n, TreeControl::BreakAfterSubtree)
+            },
+            &mut Vec::new(),
+        );
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        traverse_tree_indexed(
+            1,
+            |n| *n < 16,
+            |n, i| (i < 2).then(|| *n * 2 + i as i32),
+            |n| {
+                visited.push(*n);
+                control_at(n, TreeControl::BreakAfterSubtree)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        let mut visited = Vec::new();
+        traverse_tree_path(
+            1,
+            |n| *n < 16,
+            children,
+            |path| {
+                let n = path.last().unwrap();
+                visited.push(*n);
+                control_at(n, TreeControl::BreakAfterSubtree)
+            },
+        );
+        assert_eq!(visited, expected);
+
+        // Deferred nodes inside the subtree get their second visit, the
+        // deferred root outside it doesn't, and the subtree's own node is left
+        let mut events = Vec::new();
+        traverse_events(
+            1,
+            |n| *n < 8,
+            children,
+            |event| match event {
+                Event::Enter(n) => {
+                    events.push(*n);
+                    control_at(n, TreeControl::BreakAfterSubtree)
+                }
+                Event::Leave(n) => {
+                    events.push(-*n);
+                    TreeControl::Continue
+                }
+            },
+        );
+        assert_eq!(events, vec![1, 2, 4, -4, 5, -5, -2]);
+
+        let mut visited = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 8,
+            children,
+            |n| {
+                let first = !visited.contains(n);
+                visited.push(*n);
+                match *n {
+                    1 | 4 if first => TreeControl::Defer,
+                    _ => control_at(n, TreeControl::BreakAfterSubtree),
+                }
+            },
+        );
+        assert_eq!(visited, vec![1, 2, 4, 4, 5]);
+    }
+
+    #[test]
+    fn test_traverse_binary() {
+        type Child<'a> = Option<&'a BinaryNode<i32>>;
+        fn pair<'a>(n: &&'a BinaryNode<i32>) -> (Child<'a>, Child<'a>) {
+            (n.left.as_deref(), n.right.as_deref())
+        }
+
+        let root = sample_tree();
+
+        let mut expected = Vec::new();
+        traverse_tree(
+            &root,
+            |_| true,
+            binary_children,
+            |n| {
+                expected.push(n.value);
+                TreeControl::from_prune(n.value == 4)
+            },
+        );
+
+        let mut values = Vec::new();
+        traverse_binary(
+            &root,
+            |_| true,
+            pair,
+            |n| {
+                values.push(n.value);
+                TreeControl::from_prune(n.value == 4)
+            },
+        );
+        assert_eq!(values, expected);
+        assert_eq!(values, vec![1, 2, 4, 5, 3, -7, 6]);
+
+        // Skipping counts only the children that are present
+        let lopsided = BinaryNode::with_children(
+            1,
+            None,
+            Some(Box::new(BinaryNode::with_children(
+                3,
+                Some(Box::new(BinaryNode::new(6))),
+                Some(Box::new(BinaryNode::new(7))),
+            ))),
+        );
+        let mut values = Vec::new();
+        traverse_binary(
+            &lopsided,
+            |_| true,
+            pair,
+            |n| {
+                values.push(n.value);
+                match n.value {
+                    1 => TreeControl::Continue,
+                    _ => TreeControl::ContinueWithout(1),
+                }
+            },
+        );
+        assert_eq!(values, vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn test_binary_nary_conversion() {
+        let root = sample_tree();
+        let nary = NAryNode::from(&root);
+
+        assert_eq!(nary.children.len(), 2);
+        assert_eq!(
+            collect_tree(&nary, |_| true, |n| n.children())
+                .iter()
+                .map(|n| n.value)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4, -8, 9, 5, 3, -7, 6]
+        );
+        assert_eq!(nary.to_binary().unwrap().to_string(), root.to_string());
+
+        // A lone right child becomes the only child, and then a left child
+        let lopsided = BinaryNode::with_children(1, None, Some(Box::new(BinaryNode::new(2))));
+        let nary = NAryNode::from(&lopsided);
+        assert_eq!(nary, NAryNode::with_children(1, vec![NAryNode::new(2)]));
+        let binary = nary.to_binary().unwrap();
+        assert_eq!(binary.left.map(|n| n.value), Some(2));
+        assert!(binary.right.is_none());
+
+        // Wide nodes anywhere in the tree are rejected
+        let wide = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::new(2),
+                NAryNode::with_children(3, vec![NAryNode::new(4); 4]),
+            ],
+        );
+        assert_eq!(wide.to_binary(), Err(TooManyChildren { children: 4 }));
+    }
+
+    #[test]
+    fn test_traverse_tree_iter_matches_traverse_tree() {
+        let control = |n: &u32| match *n {
+            2 => TreeControl::Prune,
+            6 => TreeControl::Break,
+            _ => TreeControl::Continue,
+        };
+
+        let mut expected = Vec::new();
+        traverse_tree(
+            1,
+            |n| *n < 16,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                expected.push(*n);
+                control(n)
+            },
+        );
+
+        // Arrays, boxed slices and iterators all work as children
+        let mut from_array = Vec::new();
+        traverse_tree_iter(
+            1,
+            |n| *n < 16,
+            |n| [*n * 2, *n * 2 + 1],
+            |n| {
+                from_array.push(*n);
+                control(n)
+            },
+        );
+
+        let mut from_boxed = Vec::new();
+        traverse_tree_iter(
+            1,
+            |n| *n < 16,
+            |n| -> Box<[u32]> { Box::new([*n * 2, *n * 2 + 1]) },
+            |n| {
+                from_boxed.push(*n);
+                control(n)
+            },
+        );
+
+        let mut from_range = Vec::new();
+        traverse_tree_iter(
+            1,
+            |n| *n < 16,
+            |n| *n * 2..*n * 2 + 2,
+            |n| {
+                from_range.push(*n);
+                control(n)
+            },
+        );
+
+        assert_eq!(expected, vec![1, 2, 3, 6]);
+        assert_eq!(from_array, expected);
+        assert_eq!(from_boxed, expected);
+        assert_eq!(from_range, expected);
     }
-}
 
-/// Demonstrates traversing a binary tree with the for_tree macro.
-///
-/// This function shows a common pattern for traversing a binary tree using
-/// the [`for_tree!`] macro. It prints the value of each node in the tree
-/// in depth-first order.
-///
-/// # Parameters
-///
-/// * `root` - The root node of the binary tree to traverse
-///
-/// # Type Parameters
-///
-/// * `T` - The type of value stored in each node, must implement Debug
-///
-/// # Example
-///
-/// ```
-/// use arboriter::{BinaryNode, binary_tree_example};
-///
-/// // Create a simple binary tree
-/// let root = BinaryNode::with_children(
-///     1,
-///     Some(Box::new(BinaryNode::new(2))),
-///     Some(Box::new(BinaryNode::new(3)))
-/// );
-///
-/// // This will print:
-/// // Traversing binary tree:
-/// // Visiting node with value: 1
-/// // Visiting node with value: 2
-/// // Visiting node with value: 3
-/// binary_tree_example(&root);
-/// ```
-pub fn binary_tree_example<T: std::fmt::Debug>(root: &BinaryNode<T>) {
-    println!("Traversing binary tree:");
+    #[test]
+    fn test_replace_subtree_first_match_and_root() {
+        let mut root = NAryNode::with_children(
+            1,
+            vec![
+                NAryNode::with_children(2, vec![NAryNode::new(7)]),
+                NAryNode::new(7),
+            ],
+        );
 
-    for_tree!(node in root; |_| true; |node| {
-        let mut children: Vec<&BinaryNode<T>> = Vec::new();
-        if let Some(left) = &node.left {
-            children.push(left.as_ref());
+        // Only the first match in pre-order is replaced
+        assert!(root.replace_subtree(|n| *n == 7, NAryNode::new(8)));
+        assert_eq!(
+            root,
+            NAryNode::with_children(
+                1,
+                vec![
+                    NAryNode::with_children(2, vec![NAryNode::new(8)]),
+                    NAryNode::new(7),
+                ],
+            )
+        );
+
+        assert!(!root.replace_subtree(|n| *n == 9, NAryNode::new(0)));
+
+        assert!(root.replace_subtree(|n| *n == 1, NAryNode::new(0)));
+        assert_eq!(root, NAryNode::new(0));
+    }
+
+    #[test]
+    fn test_lowest_common_ancestor() {
+        let root = NAryNode::from(&sample_tree());
+
+        assert_eq!(lowest_common_ancestor(&root, &-8, &5), Some(&2));
+        assert_eq!(lowest_common_ancestor(&root, &9, &6), Some(&1));
+        assert_eq!(lowest_common_ancestor(&root, &3, &-7), Some(&3));
+        assert_eq!(lowest_common_ancestor(&root, &6, &3), Some(&3));
+        assert_eq!(lowest_common_ancestor(&root, &9, &9), Some(&9));
+        assert_eq!(lowest_common_ancestor(&root, &1, &1), Some(&1));
+        assert_eq!(lowest_common_ancestor(&root, &10, &1), None);
+        assert_eq!(lowest_common_ancestor(&root, &10, &11), None);
+
+        // The 10 under 3 is closer to the 20 than the one found first
+        let root = tree!(1 => { 2 => { 10 }, 3 => { 10, 20 } });
+        assert_eq!(lowest_common_ancestor(&root, &10, &20), Some(&3));
+        let root = tree!(1 => { 2 => { 10, 20 }, 3 => { 10, 20 } });
+        assert_eq!(lowest_common_ancestor(&root, &20, &10), Some(&2));
+    }
+
+    #[test]
+    fn test_binary_preorder_round_trip() {
+        let trees = [
+            sample_tree(),
+            BinaryNode::new(0),
+            "(1 _ (2 (3) _))".parse().unwrap(),
+        ];
+
+        for tree in trees {
+            let sequence = tree.serialize_preorder();
+            // One marker per missing child: a tree of n nodes has n + 1 of them
+            assert_eq!(sequence.len(), 2 * tree.len() + 1);
+            assert_eq!(
+                BinaryNode::deserialize_preorder(&sequence),
+                Some((tree, sequence.len()))
+            );
         }
-        if let Some(right) = &node.right {
-            children.push(right.as_ref());
+
+        // Empty, starting with a missing root, and truncated input
+        let sequence = sample_tree().serialize_preorder();
+        assert_eq!(BinaryNode::<i32>::deserialize_preorder(&[]), None);
+        assert_eq!(
+            BinaryNode::<i32>::deserialize_preorder(&[None, Some(1)]),
+            None
+        );
+        for end in 0..sequence.len() {
+            assert_eq!(BinaryNode::deserialize_preorder(&sequence[..end]), None);
         }
-        children
-    } => {
-        println!("Visiting node with value: {:?}", node.value);
-    });
-}
+    }
 
-/// Demonstrates using for_tree for string generation.
-///
-/// This function shows how [`for_tree!`] can be used for tasks other than
-/// traversing existing data structures. It generates all possible strings
-/// of "a", "b", and "c" with length <= 3, illustrating how tree traversal
-/// can be used for combinatorial generation.
-///
-/// The example also demonstrates the use of [`prune!`] to limit the depth
-/// of the traversal.
-///
-/// # Example
-///
-/// ```no_run
-/// use arboriter::generate_strings_example;
-///
-/// // This will print all strings of a, b, c with length <= 3:
-/// // ""
-/// // "a"
-/// // "aa"
-/// // "aaa"
-/// // "aab"
-/// // "aac"
-/// // "ab"
-/// // ...etc.
-/// generate_strings_example();
-/// ```
-pub fn generate_strings_example() {
-    println!("Generating strings of a, b, c with length <= 3:");
+    #[test]
+    fn test_traverse_with_subtree_size() {
+        let root = sample_tree();
+        let mut sizes = Vec::new();
 
-    for_tree!(s in String::new(); |s| s.len() <= 3; |s| {
-        let mut branches: Vec<String> = Vec::new();
-        branches.push(format!("{}a", s));
-        branches.push(format!("{}b", s));
-        branches.push(format!("{}c", s));
-        branches
-    } => {
-        println!("Generated string: {}", s);
+        traverse_with_subtree_size(&&root, binary_children, |node, size| {
+            sizes.push((node.value, size));
+        });
 
-        if s.len() == 3 {
-            prune!(); // Don't generate longer strings
-        }
-    });
-}
+        assert_eq!(
+            sizes,
+            vec![
+                (-8, 1),
+                (9, 1),
+                (4, 3),
+                (5, 1),
+                (2, 5),
+                (-7, 1),
+                (6, 1),
+                (3, 3),
+                (1, 9)
+            ]
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_product_counts_and_edge_cases() {
+        // 1 + 3 + 9 + 27 sequences over three elements
+        let sequences: Vec<Vec<u8>> = product(&[0, 1, 2], 3).collect();
+        assert_eq!(sequences.len(), 40);
+        assert!(sequences.iter().all(|s| s.len() <= 3));
+        assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(
+            product(&[1, 2], 0).collect::<Vec<_>>(),
+            vec![Vec::<i32>::new()]
+        );
+        assert_eq!(
+            product::<i32>(&[], 5).collect::<Vec<_>>(),
+            vec![Vec::<i32>::new()]
+        );
+    }
 
     #[test]
-    fn test_binary_tree() {
-        // Create a simple binary tree
-        let root = BinaryNode::with_children(
+    fn test_try_fold_tree_prune_break_and_error() {
+        let branches = |n: &u32| vec![*n * 2, *n * 2 + 1];
+
+        // Prune skips 2's subtree, Break at 6 keeps the sum so far
+        let sum: Result<u32, String> = try_fold_tree(
             1,
-            Some(Box::new(BinaryNode::with_children(
-                2,
-                Some(Box::new(BinaryNode::new(4))),
-                Some(Box::new(BinaryNode::new(5))),
-            ))),
-            Some(Box::new(BinaryNode::with_children(
-                3,
-                None,
-                Some(Box::new(BinaryNode::new(6))),
-            ))),
+            |n| *n < 16,
+            branches,
+            0,
+            |sum, n| {
+                let control = match *n {
+                    2 => TreeControl::Prune,
+                    6 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                };
+                Ok((sum + n, control))
+            },
         );
+        assert_eq!(sum, Ok(1 + 2 + 3 + 6));
 
-        // Collect values using for_tree
-        let mut values = Vec::new();
+        // The first error wins, and nothing after it is folded
+        let mut folded = Vec::new();
+        let sum = try_fold_tree(
+            1,
+            |n| *n < 16,
+            branches,
+            0,
+            |sum, n| {
+                folded.push(*n);
+                if *n % 5 == 0 {
+                    return Err(*n);
+                }
+                Ok((sum + n, TreeControl::Continue))
+            },
+        );
+        assert_eq!(sum, Err(5));
+        assert_eq!(folded, vec![1, 2, 4, 8, 9, 5]);
+    }
 
-        for_tree!(node in &root; |_| true; |node| {
-            let mut children = Vec::new();
-            if let Some(left) = &node.left {
-                children.push(left.as_ref());
-            }
-            if let Some(right) = &node.right {
-                children.push(right.as_ref());
+    #[test]
+    fn test_collect_post_order_children_before_parents() {
+        let root = sample_tree();
+        let nodes = collect_post_order(&root, |_| true, binary_children);
+        let values: Vec<i32> = nodes.iter().map(|node| node.value).collect();
+
+        assert_eq!(values, vec![-8, 9, 4, 5, 2, -7, 6, 3, 1]);
+        for (position, node) in nodes.iter().enumerate() {
+            for child in node.children() {
+                let child_position = values.iter().position(|v| *v == child.value).unwrap();
+                assert!(child_position < position);
             }
-            children
-        } => {
-            values.push(node.value);
-        });
+        }
 
-        // Verify depth-first traversal order
-        assert_eq!(values, vec![1, 2, 4, 5, 3, 6]);
+        // A filtered out node takes its subtree with it
+        let values: Vec<i32> = collect_post_order(&root, |n| n.value != 4, binary_children)
+            .iter()
+            .map(|node| node.value)
+            .collect();
+        assert_eq!(values, vec![5, 2, -7, 6, 3, 1]);
     }
 
+    #[cfg(feature = "std")]
     #[test]
-    fn test_string_generation() {
-        // Generate all strings of length <= 2
-        let mut strings = Vec::new();
+    fn test_traverse_memoized_visits_but_expands_once() {
+        // Lattice paths to (2, 2): every point is reached along several paths
+        let mut expansions = 0;
+        let mut visits = BTreeMap::new();
 
-        for_tree!(s in String::new(); |s| s.len() <= 2; |s| {
-            let mut branches = Vec::new();
-            branches.push(format!("{}a", s));
-            branches.push(format!("{}b", s));
-            branches
-        } => {
-            strings.push(s.clone());
+        traverse_memoized(
+            (0, 0),
+            |&(x, y)| x <= 2 && y <= 2,
+            |&(x, y)| {
+                expansions += 1;
+                vec![(x + 1, y), (x, y + 1)]
+            },
+            |point| {
+                *visits.entry(*point).or_insert(0) += 1;
+                // A pruned node is not expanded, so its next occurrence is
+                if *point == (1, 1) && visits[point] == 1 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
 
-            if s.len() == 2 {
-                prune!();
+        assert_eq!(expansions, 9);
+        assert_eq!(visits[&(0, 0)], 1);
+        assert_eq!(visits[&(1, 1)], 2);
+        // Each point is expanded once, so each of the 12 edges of the grid
+        // leads to exactly one visit, plus the one of the root
+        assert_eq!(visits.values().sum::<i32>(), 12 + 1);
+    }
+
+    #[test]
+    fn test_traverse_tree_push_matches_traverse_tree() {
+        // Every control variant, with restarts and repeats limited by visit counts
+        fn control(n: u32, visits: &mut BTreeMap<u32, usize>) -> TreeControl {
+            let count = visits.entry(n).or_insert(0);
+            *count += 1;
+            match (n, *count) {
+                (1, 1) => TreeControl::Defer,
+                (2, 1) => TreeControl::ContinueWithout(1),
+                (5, 1) => TreeControl::PruneAfter(1),
+                (10, 1) => TreeControl::Repeat,
+                (11, 1) => TreeControl::RestartSubtree,
+                (6, _) => TreeControl::Prune,
+                (7, _) => TreeControl::BreakAfterSubtree,
+                (28, _) => TreeControl::PruneSiblings,
+                (30, _) => TreeControl::Break,
+                _ => TreeControl::Continue,
             }
-        });
+        }
 
-        // Check that we got all possible strings
-        // The order is determined by the depth-first traversal:
-        // "" -> "a" -> "aa" -> "ab" -> "b" -> "ba" -> "bb"
-        let expected = vec!["", "a", "aa", "ab", "b", "ba", "bb"];
+        let mut expected = Vec::new();
+        let mut visits = BTreeMap::new();
+        traverse_tree(
+            1,
+            |n| *n < 64,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                expected.push(*n);
+                control(*n, &mut visits)
+            },
+        );
 
-        assert_eq!(strings, expected);
+        let mut pushed = Vec::new();
+        let mut visits = BTreeMap::new();
+        traverse_tree_push(
+            1,
+            |n| *n < 64,
+            |n, push| {
+                push(*n * 2);
+                push(*n * 2 + 1);
+            },
+            |n| {
+                pushed.push(*n);
+                control(*n, &mut visits)
+            },
+        );
+
+        assert_eq!(pushed, expected);
+        assert_eq!(
+            expected,
+            vec![1, 2, 5, 10, 10, 11, 10, 11, 3, 6, 7, 14, 28, 15, 30]
+        );
     }
 
     #[test]
-    fn test_break() {
-        // Test breaking out of traversal
-        let mut count = 0;
+    fn test_truncate_tree_keeps_a_depth_first_prefix() {
+        let root = sample_tree();
+        let full = collect_tree_with(&root, |_| true, binary_children, |node| node.value);
 
-        for_tree!(n in 0; |n| *n < 10; |n| vec![*n + 1] => {
-            count += 1;
+        for n in 0..12 {
+            let preview = truncate_tree(&&root, n, binary_children);
+            let values = collect_tree_with(
+                &preview,
+                |_| true,
+                |node| node.children(),
+                |node| node.value.value,
+            );
+            assert_eq!(values, full[..n.clamp(1, full.len())]);
+        }
 
-            if *n >= 5 {
-                break_tree!();
-            }
+        // Branches are only generated while there is room for the children
+        let mut expanded = Vec::new();
+        truncate_tree(&1, 3, |n| {
+            expanded.push(*n);
+            vec![n * 2, n * 2 + 1]
         });
+        assert_eq!(expanded, vec![1, 2]);
+    }
 
-        // Should only visit 0, 1, 2, 3, 4, 5
-        assert_eq!(count, 6);
+    #[test]
+    fn test_traverse_with_parent() {
+        let root = sample_tree();
+        let mut edges = Vec::new();
+
+        traverse_with_parent(
+            &root,
+            |n| n.value != 4,
+            binary_children,
+            |parent, node| {
+                edges.push((parent.map(|p| p.value), node.value));
+                if node.value == 3 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        assert_eq!(
+            edges,
+            vec![(None, 1), (Some(1), 2), (Some(2), 5), (Some(1), 3)]
+        );
     }
 
     #[test]
-    fn test_prune() {
-        // Create a binary tree with pruning
-        let root = BinaryNode::with_children(
+    fn test_traverse_tree_dynamic_retain() {
+        let mut visited = Vec::new();
+
+        traverse_tree_dynamic(
             1,
-            Some(Box::new(BinaryNode::with_children(
-                2, // We'll prune this branch
-                Some(Box::new(BinaryNode::new(4))),
-                Some(Box::new(BinaryNode::new(5))),
-            ))),
-            Some(Box::new(BinaryNode::with_children(
-                3,
-                None,
-                Some(Box::new(BinaryNode::new(6))),
-            ))),
+            |n| *n < 32,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n, branches| {
+                visited.push(*n);
+                match *n {
+                    // Only the odd child, which also filters an added one
+                    1 => {
+                        branches.add(6);
+                        branches.add(7);
+                        branches.retain(|child| child % 2 == 1);
+                    }
+                    // Filters compose, and apply to replaced children too
+                    3 => {
+                        let bound = *n * 4;
+                        branches.set(vec![10, 12, 13, 15]);
+                        branches.retain(move |child| *child < bound);
+                        branches.retain(|child| child % 3 != 0);
+                    }
+                    _ => {}
+                }
+                TreeControl::Continue
+            },
         );
 
-        let mut values = Vec::new();
+        assert_eq!(visited, vec![1, 3, 10, 20, 21, 7, 14, 28, 29, 15, 30, 31]);
+    }
 
-        for_tree!(node in &root; |_| true; |node| {
-            let mut children = Vec::new();
-            if let Some(left) = &node.left {
-                children.push(left.as_ref());
-            }
-            if let Some(right) = &node.right {
-                children.push(right.as_ref());
-            }
-            children
-        } => {
-            values.push(node.value);
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_collect_rev_post_order_is_topological_on_a_dag() {
+        // Edges listed so that a plain pre-order would put 4 before 3
+        let edges: [&[usize]; 7] = [&[4, 1, 2], &[3], &[3, 5], &[4, 6], &[], &[6], &[]];
+        let successors = |n: &usize| edges[*n].to_vec();
 
-            if node.value == 2 {
-                prune!();  // Don't visit children of node with value 2
+        assert_eq!(
+            traverse_graph_checked(0, |_| true, successors, |_| TreeControl::Continue),
+            Ok(())
+        );
+
+        let mut seen = std::collections::HashSet::new();
+        let order = collect_rev_post_order(0, |n| seen.insert(*n), successors);
+
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..7).collect::<Vec<_>>());
+
+        let position = |n: usize| order.iter().position(|m| *m == n).unwrap();
+        for (from, targets) in edges.iter().enumerate() {
+            for &to in *targets {
+                assert!(position(from) < position(to), "{} -> {}", from, to);
             }
-        });
+        }
+    }
 
-        // Should only visit 1, 2, 3, 6 (4 and 5 are pruned)
-        assert_eq!(values, vec![1, 2, 3, 6]);
+    #[test]
+    fn test_random_path_is_uniform_over_children() {
+        let children = |n: &u32| if *n == 0 { vec![1, 2, 3] } else { vec![] };
+        let mut rng = SeededRng::new(11);
+        let mut counts = [0; 4];
+
+        for _ in 0..3000 {
+            let path = random_path(&0, children, &mut rng);
+            assert_eq!(path.len(), 2);
+            assert_eq!(path[0], 0);
+            counts[path[1] as usize] += 1;
+        }
+
+        assert_eq!(counts[0], 0);
+        assert!(counts[1..].iter().all(|&c| (900..1100).contains(&c)));
+        assert_eq!(random_path(&5, children, &mut rng), vec![5]);
+    }
+
+    #[test]
+    fn test_traverse_tree_dynamic_drops_branches_at_depth_cap() {
+        for cap in [TreeControl::Continue, TreeControl::PruneAfter(0)] {
+            let mut visited = Vec::new();
+
+            traverse_tree_dynamic(
+                1,
+                |n| *n < 8 || *n == 100,
+                |n| vec![*n * 2, *n * 2 + 1],
+                |n, branches| {
+                    visited.push(*n);
+                    match *n {
+                        2 => TreeControl::PruneAfter(1),
+                        // At the cap, so the added child has nowhere to go
+                        4 => {
+                            branches.add(100);
+                            cap
+                        }
+                        _ => TreeControl::Continue,
+                    }
+                },
+            );
+
+            assert_eq!(visited, vec![1, 2, 4, 5, 3, 6, 7]);
+        }
     }
 }
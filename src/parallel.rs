@@ -0,0 +1,324 @@
+//! Parallel tree traversal backed by rayon
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::TreeControl;
+
+/// Parallel depth-first traversal that forks subtrees onto the rayon thread pool.
+///
+/// Each node is visited before any of its children, exactly as in
+/// [`traverse_tree`](crate::traverse_tree), but the subtrees rooted at a node's
+/// children are traversed concurrently. This pays off when trees are large and
+/// the work done in `visit_fn` is independent and expensive.
+///
+/// # Ordering
+///
+/// A node is always visited before its descendants. The order in which siblings
+/// and their subtrees are visited relative to each other is unspecified and may
+/// change from run to run.
+///
+/// # Control Flow
+///
+/// * `TreeControl::Continue` - Continue into this node's children
+/// * `TreeControl::Prune` - Skip this node's children
+/// * `TreeControl::Break` - Cooperatively cancel the traversal. No new nodes are
+///   visited once the break is observed, but visits already running on other
+///   threads are allowed to finish.
+/// * `TreeControl::Repeat` - Visit this node again
+/// * `TreeControl::ContinueWithout(n)` - Continue into this node's children, skipping the first `n`
+/// * `TreeControl::RestartSubtree` - Treated like `Prune`, since siblings may
+///   already be running
+/// * `TreeControl::Defer` - Continue into this node's children, then visit this
+///   node again once its whole subtree is done
+/// * `TreeControl::PruneSiblings` - Treated like `Prune`, since siblings may
+///   already be running
+/// * `TreeControl::PruneAfter(n)` - Continue into this node's children, but no
+///   further than `n` levels below this node
+/// * `TreeControl::BreakAfterSubtree` - Continue into this node's children, then
+///   cancel the traversal like `Break` once they are done. Subtrees already
+///   running on other threads keep going until the break is observed.
+///
+/// # Returns
+///
+/// The number of nodes that were visited, counted with an atomic counter that
+/// the threads share, so the most common metric needs no synchronization of
+/// its own. Nodes are counted like
+/// [`TraversalStats::visited`](crate::TraversalStats::visited): repeated visits
+/// from `Repeat` and the second visit of a node that returned `Defer` don't
+/// add to it.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Mutex;
+/// use arboriter::{par_traverse_tree, TreeControl};
+///
+/// let visited = Mutex::new(Vec::new());
+///
+/// let count = par_traverse_tree(1, |n| *n < 16, |n| vec![*n * 2, *n * 2 + 1], |n| {
+///     visited.lock().unwrap().push(*n);
+///     TreeControl::Continue
+/// });
+///
+/// let mut visited = visited.into_inner().unwrap();
+/// visited.sort();
+/// assert_eq!(visited, (1..16).collect::<Vec<_>>());
+/// assert_eq!(count, 15);
+/// ```
+pub fn par_traverse_tree<T, C, B, F>(initial: T, condition: C, branch_fn: B, visit_fn: F) -> usize
+where
+    T: Send + Sync,
+    C: Fn(&T) -> bool + Sync,
+    B: Fn(&T) -> Vec<T> + Sync,
+    F: Fn(&T) -> TreeControl + Sync,
+{
+    // `depth_left` is how many levels below `node` may still be visited,
+    // after the `PruneAfter` caps of its ancestors
+    fn traverse_internal<T, C, B, F>(
+        node: &T,
+        depth_left: usize,
+        condition: &C,
+        branch_fn: &B,
+        visit_fn: &F,
+        stop: &AtomicBool,
+        visited: &AtomicUsize,
+    ) where
+        T: Send + Sync,
+        C: Fn(&T) -> bool + Sync,
+        B: Fn(&T) -> Vec<T> + Sync,
+        F: Fn(&T) -> TreeControl + Sync,
+    {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        visited.fetch_add(1, Ordering::Relaxed);
+
+        let (skip, deferred, depth_left, break_after) = loop {
+            match visit_fn(node) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => {
+                    stop.store(true, Ordering::Relaxed);
+                    return;
+                }
+                // Siblings may already be running, so there is nothing to restart
+                TreeControl::Prune | TreeControl::PruneSiblings | TreeControl::RestartSubtree => {
+                    return;
+                }
+                TreeControl::Continue => break (0, false, depth_left, false),
+                TreeControl::ContinueWithout(skip) => break (skip, false, depth_left, false),
+                TreeControl::Defer => break (0, true, depth_left, false),
+                TreeControl::PruneAfter(levels) => {
+                    break (0, false, depth_left.min(levels), false);
+                }
+                TreeControl::BreakAfterSubtree => break (0, false, depth_left, true),
+            }
+        };
+
+        let children: Vec<T> = if depth_left == 0 {
+            Vec::new()
+        } else {
+            branch_fn(node)
+                .into_iter()
+                .skip(skip)
+                .filter(|child| condition(child))
+                .collect()
+        };
+
+        rayon::scope(|scope| {
+            for child in children {
+                scope.spawn(move |_| {
+                    traverse_internal(
+                        &child,
+                        depth_left - 1,
+                        condition,
+                        branch_fn,
+                        visit_fn,
+                        stop,
+                        visited,
+                    );
+                });
+            }
+        });
+
+        // The scope only ends once every spawned subtree is done
+        if break_after
+            || deferred
+                && !stop.load(Ordering::Relaxed)
+                && matches!(
+                    visit_fn(node),
+                    TreeControl::Break | TreeControl::BreakAfterSubtree
+                )
+        {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let stop = AtomicBool::new(false);
+    let visited = AtomicUsize::new(0);
+
+    if condition(&initial) {
+        traverse_internal(
+            &initial,
+            usize::MAX,
+            &condition,
+            &branch_fn,
+            &visit_fn,
+            &stop,
+            &visited,
+        );
+    }
+
+    visited.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_par_traverse_tree_prune() {
+        let visited = Mutex::new(Vec::new());
+
+        par_traverse_tree(
+            1,
+            |n| *n < 32,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                visited.lock().unwrap().push(*n);
+                if *n == 2 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        let mut visited = visited.into_inner().unwrap();
+        visited.sort();
+        assert_eq!(
+            visited,
+            vec![
+                1, 2, 3, 6, 7, 12, 13, 14, 15, 24, 25, 26, 27, 28, 29, 30, 31
+            ]
+        );
+    }
+
+    #[test]
+    fn test_par_traverse_tree_break() {
+        let count = AtomicUsize::new(0);
+
+        let visited = par_traverse_tree(
+            1,
+            |n| *n < 1024,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |_| {
+                count.fetch_add(1, Ordering::Relaxed);
+                TreeControl::Break
+            },
+        );
+
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn test_par_traverse_tree_prune_after() {
+        let visited = Mutex::new(Vec::new());
+
+        par_traverse_tree(
+            1,
+            |n| *n < 32,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                visited.lock().unwrap().push(*n);
+                match *n {
+                    1 => TreeControl::PruneAfter(3),
+                    3 => TreeControl::PruneAfter(1),
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+
+        let mut visited = visited.into_inner().unwrap();
+        visited.sort();
+        assert_eq!(visited, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_par_traverse_tree_break_after_subtree() {
+        let visited = Mutex::new(Vec::new());
+
+        par_traverse_tree(
+            1,
+            |n| *n < 32,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                visited.lock().unwrap().push(*n);
+                if *n == 2 {
+                    TreeControl::BreakAfterSubtree
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        // The subtree of 2 always completes, whatever ran next to it
+        let visited = visited.into_inner().unwrap();
+        for n in [2, 4, 5, 8, 9, 10, 11, 16, 17, 18, 19, 20, 21, 22, 23] {
+            assert!(visited.contains(&n));
+        }
+    }
+
+    #[test]
+    fn test_par_traverse_tree_counts_nodes_not_visits() {
+        let calls = AtomicUsize::new(0);
+        let repeated = AtomicBool::new(false);
+
+        let visited = par_traverse_tree(
+            1,
+            |n| *n < 64,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                match *n {
+                    1 if calls.load(Ordering::Relaxed) == 1 => TreeControl::Defer,
+                    5 if !repeated.swap(true, Ordering::Relaxed) => TreeControl::Repeat,
+                    3 => TreeControl::Prune,
+                    _ => TreeControl::Continue,
+                }
+            },
+        );
+
+        // The children of 3 are pruned, leaving 1, 3 and the 31 nodes from 2 down
+        assert_eq!(visited, 33);
+        assert_eq!(calls.load(Ordering::Relaxed), 33 + 2);
+    }
+
+    #[test]
+    fn test_par_traverse_tree_defer() {
+        let visited = Mutex::new(Vec::new());
+
+        par_traverse_tree(
+            1,
+            |n| *n < 32,
+            |n| vec![*n * 2, *n * 2 + 1],
+            |n| {
+                let mut visited = visited.lock().unwrap();
+                let first = !visited.contains(n);
+                visited.push(*n);
+                if first && *n == 1 {
+                    TreeControl::Defer
+                } else {
+                    TreeControl::Continue
+                }
+            },
+        );
+
+        // The root's second visit comes after its whole subtree
+        let visited = visited.into_inner().unwrap();
+        assert_eq!(visited.len(), 32);
+        assert_eq!(visited.first(), Some(&1));
+        assert_eq!(visited.last(), Some(&1));
+    }
+}
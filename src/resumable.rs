@@ -0,0 +1,338 @@
+//! Depth-first traversal that can be paused and resumed
+
+use alloc::vec::{self, Vec};
+
+use crate::{Event, MAX_SUBTREE_RESTARTS, Pending, TreeControl};
+
+/// Whether a call to [`TraversalState::step`] finished the traversal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepResult {
+    /// The budget ran out with nodes left to visit
+    Paused,
+    /// Every node has been visited, or the visitor stopped the traversal
+    Done,
+}
+
+/// A depth-first traversal whose progress is kept in a value, so that it can
+/// be run a few nodes at a time.
+///
+/// The traversal functions keep their explicit stack to themselves and only
+/// return once the whole tree is done. A `TraversalState` owns that stack
+/// instead: each call to [`step`](TraversalState::step) visits at most
+/// `budget` nodes and returns, and the next call picks up where the last one
+/// stopped. This lets a long traversal be interleaved with other work, such as
+/// the other tasks of an event loop, which a `for_tree!` body can't do.
+///
+/// Nodes are visited in the same order as [`traverse_tree`], and every
+/// [`TreeControl`] variant means the same. There is no separate condition:
+/// children that shouldn't be visited are left out by `branch_fn`. The visitor
+/// is passed to each step rather than stored, so it can borrow whatever is at
+/// hand at the time.
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{StepResult, TraversalState, TreeControl};
+///
+/// let mut state = TraversalState::new(1, |n: &u32| {
+///     if *n < 8 { vec![n * 2, n * 2 + 1] } else { vec![] }
+/// });
+///
+/// let mut visited = Vec::new();
+/// let mut slices = 0;
+/// let mut visit = |n: &u32| {
+///     visited.push(*n);
+///     TreeControl::Continue
+/// };
+///
+/// // Three nodes at a time, with room for other work in between
+/// while state.step(3, &mut visit) == StepResult::Paused {
+///     slices += 1;
+/// }
+///
+/// assert_eq!(visited, vec![1, 2, 4, 8, 9, 5, 10, 11, 3, 6, 12, 13, 7, 14, 15]);
+/// assert_eq!(slices, 4);
+/// ```
+///
+/// [`traverse_tree`]: crate::traverse_tree
+pub struct TraversalState<T, B> {
+    branch_fn: B,
+    walk: WalkState<T, vec::IntoIter<T>>,
+}
+
+impl<T, B> TraversalState<T, B>
+where
+    B: FnMut(&T) -> Vec<T>,
+{
+    /// Creates a traversal of the tree rooted at `initial` that hasn't visited any node yet.
+    pub fn new(initial: T, branch_fn: B) -> Self {
+        TraversalState {
+            branch_fn,
+            walk: WalkState::new(initial, 0, usize::MAX),
+        }
+    }
+
+    /// Visits up to `budget` nodes, then returns whether the traversal is done.
+    ///
+    /// Repeated visits of a node, from `TreeControl::Repeat` or `Defer`, don't
+    /// count against the budget. Once done, every further step returns
+    /// `StepResult::Done` without visiting anything.
+    pub fn step<F>(&mut self, budget: usize, visit: &mut F) -> StepResult
+    where
+        F: FnMut(&T) -> TreeControl,
+    {
+        let mut visits = 0;
+
+        while !self.walk.is_done() {
+            if visits == budget {
+                return StepResult::Paused;
+            }
+            visits += 1;
+
+            self.walk.step(
+                &mut |_, _| true,
+                &mut |node, _| (self.branch_fn)(node),
+                &mut |event, _| match event {
+                    Event::Enter(node) | Event::Leave(node) => visit(node),
+                },
+                &mut |_| {},
+            );
+        }
+
+        StepResult::Done
+    }
+}
+
+/// The explicit stack of a depth-first traversal, which `TraversalState`,
+/// `walk_with_capacity` and `TreeWalker` drive one node at a time. `I`
+/// iterates over the children of a node.
+///
+/// This is the one place that decides what each [`TreeControl`] variant does
+/// to the rest of the traversal; the callers only supply the callbacks.
+pub(crate) struct WalkState<T, I> {
+    // The node to visit next, or `None` once the traversal is over
+    next: Option<T>,
+    // One frame per node on the current path, holding the node, its children
+    // that are still waiting to be visited, and whether it asked to be visited
+    // again once they are done. The depth of a node is the number of frames above it.
+    stack: Vec<(T, Pending<I>, bool)>,
+    // The depth limit of the root, as if its ancestors had returned `PruneAfter`
+    depth_limit: usize,
+    root_restarts: usize,
+    // The depth of the node whose subtree ends the traversal once it is done
+    break_after: Option<usize>,
+    // Whether the traversal was stopped by `Break` or `BreakAfterSubtree`
+    broke: bool,
+}
+
+impl<T, I: Iterator<Item = T>> WalkState<T, I> {
+    /// A traversal that visits `initial` next, with room for `expected_depth`
+    /// levels reserved on the stack, which never generates the children of
+    /// nodes at `depth_limit` or deeper.
+    pub(crate) fn new(initial: T, expected_depth: usize, depth_limit: usize) -> Self {
+        WalkState {
+            next: Some(initial),
+            stack: Vec::with_capacity(expected_depth),
+            depth_limit,
+            root_restarts: 0,
+            break_after: None,
+            broke: false,
+        }
+    }
+
+    /// Whether every node has been visited, or the traversal was stopped.
+    pub(crate) fn is_done(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Whether the traversal was stopped by `Break` or `BreakAfterSubtree`
+    /// rather than running out of nodes.
+    pub(crate) fn broke(&self) -> bool {
+        self.broke
+    }
+
+    /// Visits the next node, then moves on to the node after it.
+    ///
+    /// All callbacks receive the depth of the node they are called with; for
+    /// `condition` that is the depth the node will have once visited. The first
+    /// visit of every node is an `Event::Enter`, and the second visit of a node
+    /// that returned `TreeControl::Defer` an `Event::Leave`. `exit` is called
+    /// once the traversal is done with a node: after its subtree, or right after
+    /// the visit if its children are skipped, but not once the traversal stops.
+    pub(crate) fn step<C, B, G, F, X>(
+        &mut self,
+        condition: &mut C,
+        branch_fn: &mut B,
+        visit: &mut F,
+        exit: &mut X,
+    ) where
+        C: FnMut(&T, usize) -> bool,
+        B: FnMut(&T, usize) -> G,
+        G: IntoIterator<IntoIter = I>,
+        F: FnMut(Event<&T>, usize) -> TreeControl,
+        X: FnMut(&T),
+    {
+        let Some(node) = self.next.take() else {
+            return;
+        };
+        let depth = self.stack.len();
+
+        loop {
+            match visit(Event::Enter(&node), depth) {
+                TreeControl::Repeat => continue,
+                TreeControl::Break => return self.stop(),
+                TreeControl::Prune => {
+                    exit(&node);
+                    break;
+                }
+                TreeControl::PruneSiblings => {
+                    exit(&node);
+                    if let Some((_, siblings, _)) = self.stack.last_mut() {
+                        siblings.skip_rest();
+                    }
+                    break;
+                }
+                control @ (TreeControl::Continue
+                | TreeControl::ContinueWithout(_)
+                | TreeControl::Defer
+                | TreeControl::PruneAfter(_)
+                | TreeControl::BreakAfterSubtree) => {
+                    // Any node visited from now on is inside this node's
+                    // subtree, so a later one only moves the end closer
+                    if control == TreeControl::BreakAfterSubtree {
+                        self.break_after = Some(depth);
+                    }
+                    let inherited = self
+                        .stack
+                        .last()
+                        .map_or(self.depth_limit, |(_, siblings, _)| siblings.depth_limit());
+                    let children =
+                        Pending::descend(control, depth, inherited, || branch_fn(&node, depth));
+                    self.stack
+                        .push((node, children, control == TreeControl::Defer));
+                    break;
+                }
+                TreeControl::RestartSubtree => {
+                    exit(&node);
+                    match self.stack.last_mut() {
+                        Some((parent, children, _)) => {
+                            children.restart(|| branch_fn(parent, depth - 1));
+                            break;
+                        }
+                        None if self.root_restarts < MAX_SUBTREE_RESTARTS => {
+                            self.root_restarts += 1;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        self.advance(condition, visit, exit);
+    }
+
+    /// Moves on to the next accepted child of the deepest unfinished node,
+    /// giving finished deferred nodes their second visit on the way.
+    fn advance<C, F, X>(&mut self, condition: &mut C, visit: &mut F, exit: &mut X)
+    where
+        C: FnMut(&T, usize) -> bool,
+        F: FnMut(Event<&T>, usize) -> TreeControl,
+        X: FnMut(&T),
+    {
+        loop {
+            let depth = self.stack.len();
+            let Some((_, children, _)) = self.stack.last_mut() else {
+                return;
+            };
+
+            match children.next() {
+                Some(child) if condition(&child, depth) => {
+                    self.next = Some(child);
+                    return;
+                }
+                Some(_) => {}
+                None => {
+                    let (finished, _, deferred) = self.stack.pop().expect("the stack is not empty");
+                    if deferred
+                        && matches!(
+                            visit(Event::Leave(&finished), depth - 1),
+                            TreeControl::Break | TreeControl::BreakAfterSubtree
+                        )
+                    {
+                        return self.stop();
+                    }
+                    exit(&finished);
+                    if self.break_after == Some(self.stack.len()) {
+                        return self.stop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.next = None;
+        self.stack.clear();
+        self.broke = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traverse_tree;
+    use alloc::vec;
+
+    #[test]
+    fn test_traversal_state_matches_traverse_tree() {
+        let branches = |n: &u32| {
+            if *n < 32 {
+                vec![n * 2, n * 2 + 1]
+            } else {
+                vec![]
+            }
+        };
+        let control = |n: &u32| match *n {
+            1 => TreeControl::Defer,
+            2 => TreeControl::PruneAfter(1),
+            6 => TreeControl::Prune,
+            7 => TreeControl::ContinueWithout(1),
+            15 => TreeControl::Break,
+            _ => TreeControl::Continue,
+        };
+
+        let mut expected = Vec::new();
+        traverse_tree(
+            1,
+            |_| true,
+            branches,
+            |n| {
+                expected.push(*n);
+                control(n)
+            },
+        );
+
+        // Every budget, including one node at a time, visits the same nodes
+        for budget in 1..6 {
+            let mut state = TraversalState::new(1, branches);
+            let mut visited = Vec::new();
+            let mut visit = |n: &u32| {
+                visited.push(*n);
+                control(n)
+            };
+
+            let mut steps = 1;
+            while state.step(budget, &mut visit) == StepResult::Paused {
+                steps += 1;
+            }
+
+            assert_eq!(visited, expected);
+            assert!(steps >= expected.len() / budget);
+            assert_eq!(
+                state.step(budget, &mut |_| TreeControl::Continue),
+                StepResult::Done
+            );
+        }
+    }
+}
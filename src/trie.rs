@@ -0,0 +1,126 @@
+//! Prefix tree of strings
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{TreeControl, traverse_tree};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TrieNode {
+    children: BTreeMap<char, TrieNode>,
+    // Whether a word ends at this node
+    terminal: bool,
+}
+
+/// A set of strings stored as a prefix tree.
+///
+/// Each node stands for a prefix and has one child per character that extends
+/// it, so words sharing a prefix share the nodes for it. This is the data
+/// structure behind the string generators in the examples: instead of
+/// generating every string up to some length, only the inserted ones are
+/// stored, and [`words`](Trie::words) generates them back with
+/// [`traverse_tree`].
+///
+/// # Example
+///
+/// ```
+/// use arboriter::Trie;
+///
+/// let mut trie = Trie::new();
+/// trie.insert("tree");
+/// trie.insert("trie");
+/// trie.insert("tree");
+/// trie.insert("try");
+///
+/// assert!(trie.contains("trie"));
+/// assert!(!trie.contains("tr"));
+/// assert_eq!(trie.words(), vec!["tree", "trie", "try"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trie {
+    root: TrieNode,
+}
+
+impl Trie {
+    /// Creates an empty trie.
+    pub fn new() -> Self {
+        Trie::default()
+    }
+
+    /// Adds a word to the trie. Adding a word that is already present has no effect.
+    pub fn insert(&mut self, word: &str) {
+        let mut node = &mut self.root;
+        for c in word.chars() {
+            node = node.children.entry(c).or_default();
+        }
+        node.terminal = true;
+    }
+
+    /// Returns `true` if `word` was inserted, not just a longer word starting with it.
+    pub fn contains(&self, word: &str) -> bool {
+        let mut node = &self.root;
+        for c in word.chars() {
+            match node.children.get(&c) {
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+        node.terminal
+    }
+
+    /// Returns every word in the trie, in lexicographic order.
+    ///
+    /// The words are rebuilt by a depth-first traversal that carries the prefix
+    /// of each node along with it, so each word is collected when the traversal
+    /// reaches the node it ends at.
+    pub fn words(&self) -> Vec<String> {
+        let mut words = Vec::new();
+
+        traverse_tree(
+            (String::new(), &self.root),
+            |_| true,
+            |(prefix, node)| {
+                node.children
+                    .iter()
+                    .map(|(c, child)| {
+                        let mut word = prefix.clone();
+                        word.push(*c);
+                        (word, child)
+                    })
+                    .collect()
+            },
+            |(prefix, node)| {
+                if node.terminal {
+                    words.push(prefix.clone());
+                }
+                TreeControl::Continue
+            },
+        );
+
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_trie_prefixes_and_empty_word() {
+        let mut trie = Trie::new();
+        assert!(trie.words().is_empty());
+        assert!(!trie.contains(""));
+
+        for word in ["banana", "band", "ban", "apple", ""] {
+            trie.insert(word);
+        }
+
+        assert!(trie.contains("ban") && trie.contains("band") && trie.contains(""));
+        assert!(!trie.contains("bana") && !trie.contains("bandana"));
+        assert_eq!(trie.words(), vec!["", "apple", "ban", "banana", "band"]);
+    }
+}
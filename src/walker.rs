@@ -0,0 +1,387 @@
+//! Builder for composing traversal options
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::resumable::WalkState;
+use crate::{Event, Pending, TreeControl};
+
+type ConditionFn<'a, T> = Box<dyn FnMut(&T) -> bool + 'a>;
+type BranchFn<'a, T> = Box<dyn FnMut(&T) -> Vec<T> + 'a>;
+type HookFn<'a, T> = Box<dyn FnMut(&T) + 'a>;
+
+/// Builder that combines traversal options behind a single entry point.
+///
+/// Instead of picking between a dozen free functions, a `TreeWalker` is created
+/// from a root value, configured with chainable methods, and finished with
+/// [`run`](TreeWalker::run) or [`collect`](TreeWalker::collect). [`traverse_tree`]
+/// remains the simple shortcut for plain depth-first traversal.
+///
+/// The only required option is [`branches`](TreeWalker::branches); every other
+/// option has a default:
+///
+/// * `condition` - every node is visited
+/// * `bfs` - depth-first order
+/// * `max_depth` - unlimited
+/// * `budget` - unlimited
+/// * `on_exit` - no exit hook
+/// * `on_prune`, `on_break` - no observers
+///
+/// # Example
+///
+/// ```
+/// use arboriter::{TreeControl, TreeWalker};
+///
+/// let values = TreeWalker::new(1)
+///     .condition(|n| *n < 16)
+///     .branches(|n| vec![*n * 2, *n * 2 + 1])
+///     .bfs()
+///     .max_depth(2)
+///     .collect();
+///
+/// assert_eq!(values, vec![1, 2, 3, 4, 5, 6, 7]);
+/// ```
+///
+/// [`traverse_tree`]: crate::traverse_tree
+pub struct TreeWalker<'a, T> {
+    root: T,
+    condition: Option<ConditionFn<'a, T>>,
+    branches: Option<BranchFn<'a, T>>,
+    bfs: bool,
+    max_depth: Option<usize>,
+    budget: Option<usize>,
+    on_exit: Option<HookFn<'a, T>>,
+    on_prune: Option<HookFn<'a, T>>,
+    on_break: Option<HookFn<'a, T>>,
+}
+
+impl<'a, T> TreeWalker<'a, T> {
+    /// Creates a walker that starts from `root`.
+    pub fn new(root: T) -> Self {
+        TreeWalker {
+            root,
+            condition: None,
+            branches: None,
+            bfs: false,
+            max_depth: None,
+            budget: None,
+            on_exit: None,
+            on_prune: None,
+            on_break: None,
+        }
+    }
+
+    /// Sets the function that determines whether a node should be visited.
+    pub fn condition<C>(mut self, condition: C) -> Self
+    where
+        C: FnMut(&T) -> bool + 'a,
+    {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    /// Sets the function that returns the branches of a node. Required.
+    pub fn branches<B>(mut self, branch_fn: B) -> Self
+    where
+        B: FnMut(&T) -> Vec<T> + 'a,
+    {
+        self.branches = Some(Box::new(branch_fn));
+        self
+    }
+
+    /// Visits nodes in breadth-first (level) order instead of depth-first order.
+    pub fn bfs(mut self) -> Self {
+        self.bfs = true;
+        self
+    }
+
+    /// Never visits nodes deeper than `max_depth`, with the root at depth 0.
+    ///
+    /// Nodes at `max_depth` are still visited, but their branches are not generated.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Stops the traversal after at most `max_visits` nodes have been visited.
+    ///
+    /// Nodes are counted like [`TraversalStats::visited`]: a node visited again
+    /// after returning `Repeat` or `Defer` still counts once.
+    ///
+    /// [`TraversalStats::visited`]: crate::TraversalStats::visited
+    pub fn budget(mut self, max_visits: usize) -> Self {
+        self.budget = Some(max_visits);
+        self
+    }
+
+    /// Sets a hook that is called when the traversal is done with a node.
+    ///
+    /// In depth-first order the hook runs once the node's whole subtree has been
+    /// traversed, or right after the visit if the node was pruned, so it pairs with
+    /// the visitor like entering and leaving the node. In breadth-first order
+    /// subtrees are not finished one at a time, so the hook runs right after the
+    /// node is visited and its branches are queued. The hook is not called for the
+    /// node that returned `TreeControl::Break`, nor for any node still open when the
+    /// traversal stops. After `TreeControl::BreakAfterSubtree` in depth-first
+    /// order, it is called for the node once its subtree is done.
+    pub fn on_exit<X>(mut self, on_exit: X) -> Self
+    where
+        X: FnMut(&T) + 'a,
+    {
+        self.on_exit = Some(Box::new(on_exit));
+        self
+    }
+
+    /// Sets an observer that is called with every node for which the visitor
+    /// returns `TreeControl::Prune`.
+    ///
+    /// The observer runs right after the visit and doesn't change what the
+    /// traversal does, so counting or logging pruned nodes can stay out of the
+    /// visitor. Other variants that skip nodes, such as `PruneSiblings` or
+    /// `PruneAfter`, don't call it.
+    pub fn on_prune<O>(mut self, on_prune: O) -> Self
+    where
+        O: FnMut(&T) + 'a,
+    {
+        self.on_prune = Some(Box::new(on_prune));
+        self
+    }
+
+    /// Sets an observer that is called with the node for which the visitor
+    /// returns `TreeControl::Break`, including on the second visit of a
+    /// deferred node.
+    ///
+    /// Like [`on_prune`](TreeWalker::on_prune), it only observes the
+    /// traversal. It is not called when the traversal stops because the
+    /// [`budget`](TreeWalker::budget) ran out, nor for
+    /// `TreeControl::BreakAfterSubtree`.
+    pub fn on_break<O>(mut self, on_break: O) -> Self
+    where
+        O: FnMut(&T) + 'a,
+    {
+        self.on_break = Some(Box::new(on_break));
+        self
+    }
+
+    /// Runs the traversal, calling `visit_fn` for every visited node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`branches`](TreeWalker::branches) was never called.
+    pub fn run<F>(self, mut visit_fn: F)
+    where
+        F: FnMut(&T) -> TreeControl,
+    {
+        let TreeWalker {
+            root,
+            mut condition,
+            branches,
+            bfs,
+            max_depth,
+            budget,
+            mut on_exit,
+            mut on_prune,
+            mut on_break,
+        } = self;
+
+        let mut branch_fn = branches.expect("TreeWalker::branches must be set before calling run");
+        let mut condition = |node: &T| condition.as_mut().is_none_or(|condition| condition(node));
+        let mut exit = |node: &T| {
+            if let Some(on_exit) = on_exit.as_mut() {
+                on_exit(node);
+            }
+        };
+        let mut observe = |node: &T, control: TreeControl| {
+            let observer = match control {
+                TreeControl::Prune => on_prune.as_mut(),
+                TreeControl::Break => on_break.as_mut(),
+                _ => None,
+            };
+            if let Some(observer) = observer {
+                observer(node);
+            }
+            control
+        };
+
+        // Applies the budget around the user's visitor. Never returns `Repeat`,
+        // since repeats are resolved here. The second visit of a deferred node,
+        // on `Event::Leave`, doesn't count against the budget.
+        let mut visits = 0;
+        let mut visit = |event: Event<&T>| {
+            let node = match event {
+                Event::Enter(node) => node,
+                Event::Leave(node) => return observe(node, visit_fn(node)),
+            };
+
+            if budget == Some(visits) {
+                return TreeControl::Break;
+            }
+            visits += 1;
+
+            loop {
+                match visit_fn(node) {
+                    TreeControl::Repeat => continue,
+                    control => return observe(node, control),
+                }
+            }
+        };
+
+        // The depth cap works like a `PruneAfter` set above the root
+        let root_limit = max_depth.unwrap_or(usize::MAX);
+
+        if !condition(&root) {
+            return;
+        }
+
+        if bfs {
+            let mut queue = VecDeque::new();
+            queue.push_back((root, 0, root_limit));
+
+            while let Some((node, depth, depth_limit)) = queue.pop_front() {
+                match visit(Event::Enter(&node)) {
+                    // Subtrees are not finished one at a time, so there is
+                    // nothing to wait for
+                    TreeControl::Break | TreeControl::BreakAfterSubtree => return,
+                    // Siblings are already queued, so there is nothing to restart
+                    // or skip
+                    TreeControl::Prune
+                    | TreeControl::PruneSiblings
+                    | TreeControl::RestartSubtree => {}
+                    // There is no subtree to wait for, so `Defer` acts like `Continue`
+                    control @ (TreeControl::Continue
+                    | TreeControl::ContinueWithout(_)
+                    | TreeControl::Defer
+                    | TreeControl::PruneAfter(_)
+                    | TreeControl::Repeat) => {
+                        let mut children =
+                            Pending::descend(control, depth, depth_limit, || branch_fn(&node));
+                        while let Some(child) = children.next() {
+                            if condition(&child) {
+                                queue.push_back((child, depth + 1, children.depth_limit()));
+                            }
+                        }
+                    }
+                }
+                exit(&node);
+            }
+            return;
+        }
+
+        let mut walk = WalkState::new(root, 0, root_limit);
+        while !walk.is_done() {
+            walk.step(
+                &mut |child, _| condition(child),
+                &mut |node, _| branch_fn(node),
+                &mut |event, _| visit(event),
+                &mut exit,
+            );
+        }
+    }
+}
+
+impl<T: Clone> TreeWalker<'_, T> {
+    /// Runs the traversal and collects every visited node into a `Vec`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`branches`](TreeWalker::branches) was never called.
+    pub fn collect(self) -> Vec<T> {
+        let mut nodes = Vec::new();
+        self.run(|node| {
+            nodes.push(node.clone());
+            TreeControl::Continue
+        });
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_tree_walker_dfs_options() {
+        let values = TreeWalker::new(1)
+            .condition(|n| *n < 16)
+            .branches(|n| vec![*n * 2, *n * 2 + 1])
+            .max_depth(2)
+            .budget(5)
+            .collect();
+
+        assert_eq!(values, vec![1, 2, 4, 5, 3]);
+    }
+
+    #[test]
+    fn test_tree_walker_on_exit() {
+        let events = RefCell::new(Vec::new());
+
+        TreeWalker::new(1)
+            .condition(|n| *n < 8)
+            .branches(|n| vec![*n * 2, *n * 2 + 1])
+            .on_exit(|n| events.borrow_mut().push(format!("exit {}", n)))
+            .run(|n| {
+                events.borrow_mut().push(format!("enter {}", n));
+                if *n == 2 {
+                    TreeControl::Prune
+                } else {
+                    TreeControl::Continue
+                }
+            });
+
+        let expected: Vec<String> = [
+            "enter 1", "enter 2", "exit 2", "enter 3", "enter 6", "exit 6", "enter 7", "exit 7",
+            "exit 3", "exit 1",
+        ]
+        .iter()
+        .map(|s| String::from(*s))
+        .collect();
+        assert_eq!(events.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_tree_walker_on_prune_and_on_break() {
+        let pruned = RefCell::new(Vec::new());
+        let broken = RefCell::new(Vec::new());
+        let mut visited = Vec::new();
+
+        TreeWalker::new(1)
+            .condition(|n| *n < 16)
+            .branches(|n| vec![*n * 2, *n * 2 + 1])
+            .on_prune(|n| pruned.borrow_mut().push(*n))
+            .on_break(|n| broken.borrow_mut().push(*n))
+            .run(|n| {
+                visited.push(*n);
+                match *n {
+                    2 | 6 => TreeControl::Prune,
+                    7 => TreeControl::Break,
+                    _ => TreeControl::Continue,
+                }
+            });
+
+        assert_eq!(visited, vec![1, 2, 3, 6, 7]);
+        assert_eq!(pruned.into_inner(), vec![2, 6]);
+        assert_eq!(broken.into_inner(), vec![7]);
+
+        // Running out of budget is not a break returned by the visitor
+        let broken = RefCell::new(Vec::new());
+        let values = TreeWalker::new(1)
+            .branches(|n| vec![*n * 2, *n * 2 + 1])
+            .on_break(|n| broken.borrow_mut().push(*n))
+            .budget(3)
+            .collect();
+
+        assert_eq!(values, vec![1, 2, 4]);
+        assert!(broken.into_inner().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "TreeWalker::branches must be set before calling run")]
+    fn test_tree_walker_requires_branches() {
+        TreeWalker::new(1).run(|_: &i32| TreeControl::Continue);
+    }
+}
@@ -0,0 +1,146 @@
+//! Behaviour of the traversals and helpers on empty and single-node trees
+
+use arboriter::{
+    BinaryNode, NAryNode, TreeControl, collect_tree, count_nodes, find_tree, fold_tree, leaf_paths,
+    level_order, traverse_forest, traverse_tree, tree_height,
+};
+
+fn binary_children<'a>(node: &&'a BinaryNode<i32>) -> Vec<&'a BinaryNode<i32>> {
+    node.children()
+}
+
+fn nary_children<'a>(node: &&'a NAryNode<i32>) -> Vec<&'a NAryNode<i32>> {
+    node.children()
+}
+
+fn no_children(_: &u32) -> Vec<u32> {
+    Vec::new()
+}
+
+#[test]
+fn lone_binary_node() {
+    let root = BinaryNode::new(1);
+
+    let mut visits = 0;
+    traverse_tree(
+        &root,
+        |_| true,
+        binary_children,
+        |_| {
+            visits += 1;
+            TreeControl::Continue
+        },
+    );
+    assert_eq!(visits, 1);
+
+    assert_eq!(collect_tree(&root, |_| true, binary_children), vec![&root]);
+    assert_eq!(count_nodes(&root, |_| true, binary_children), 1);
+    assert_eq!(tree_height(&&root, binary_children), 0);
+    assert_eq!(
+        find_tree(&root, |_| true, binary_children, |n| n.value == 1),
+        Some(&root)
+    );
+    assert_eq!(
+        find_tree(&root, |_| true, binary_children, |n| n.value == 2),
+        None
+    );
+
+    assert_eq!(root.len(), 1);
+    assert_eq!(root.height(), 0);
+    assert!(root.children().is_empty());
+    assert_eq!(root.iter().copied().collect::<Vec<_>>(), vec![1]);
+}
+
+#[test]
+fn lone_nary_node() {
+    let root = NAryNode::with_children(1, Vec::new());
+
+    assert_eq!(collect_tree(&root, |_| true, nary_children), vec![&root]);
+    assert_eq!(count_nodes(&root, |_| true, nary_children), 1);
+    assert_eq!(tree_height(&&root, nary_children), 0);
+    assert_eq!(
+        find_tree(&root, |_| true, nary_children, |n| n.value == 2),
+        None
+    );
+    assert_eq!(
+        level_order(&root, |_| true, nary_children),
+        vec![vec![&root]]
+    );
+    assert_eq!(leaf_paths(&&root, nary_children), vec![vec![&root]]);
+}
+
+#[test]
+fn generator_without_branches() {
+    let mut visited = Vec::new();
+    traverse_tree(
+        7,
+        |_| true,
+        no_children,
+        |n| {
+            visited.push(*n);
+            TreeControl::Continue
+        },
+    );
+    assert_eq!(visited, vec![7]);
+
+    assert_eq!(collect_tree(7, |_| true, no_children), vec![7]);
+    assert_eq!(count_nodes(7, |_| true, no_children), 1);
+    assert_eq!(tree_height(&7, no_children), 0);
+    assert_eq!(find_tree(7, |_| true, no_children, |n| *n == 7), Some(7));
+    assert_eq!(find_tree(7, |_| true, no_children, |n| *n == 8), None);
+    assert_eq!(
+        fold_tree(
+            7,
+            |_| true,
+            no_children,
+            0,
+            |sum, n| (sum + n, TreeControl::Continue)
+        ),
+        7
+    );
+}
+
+#[test]
+fn rejected_root() {
+    let mut visits = 0;
+    traverse_tree(
+        7,
+        |_| false,
+        no_children,
+        |_| {
+            visits += 1;
+            TreeControl::Continue
+        },
+    );
+    assert_eq!(visits, 0);
+
+    assert!(collect_tree(7, |_| false, no_children).is_empty());
+    assert_eq!(count_nodes(7, |_| false, no_children), 0);
+    assert_eq!(find_tree(7, |_| false, no_children, |_| true), None);
+    assert!(level_order(7, |_| false, no_children).is_empty());
+    assert_eq!(
+        fold_tree(
+            7,
+            |_| false,
+            no_children,
+            0,
+            |sum, n| (sum + n, TreeControl::Continue)
+        ),
+        0
+    );
+}
+
+#[test]
+fn forest_without_roots() {
+    let mut visits = 0;
+    traverse_forest(
+        Vec::<u32>::new(),
+        |_| true,
+        no_children,
+        |_| {
+            visits += 1;
+            TreeControl::Continue
+        },
+    );
+    assert_eq!(visits, 0);
+}
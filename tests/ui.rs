@@ -0,0 +1,15 @@
+//! Compile-fail tests for the error messages of the body macros
+//!
+//! The expected output is rustc's exact diagnostics, which change between
+//! compiler releases, so CI only checks them on stable. Setting
+//! `ARBORITER_UI_TESTS=false` skips them.
+
+#[test]
+fn ui() {
+    if std::env::var("ARBORITER_UI_TESTS").is_ok_and(|run| run == "false") {
+        return;
+    }
+
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
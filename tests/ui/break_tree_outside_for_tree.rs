@@ -0,0 +1,10 @@
+// `break_tree!` outside a body used to fail with a type error about `From`
+use arboriter::break_tree;
+
+fn main() {
+    for n in 0..10 {
+        if n == 5 {
+            break_tree!();
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// `prune!` in a plain visitor would silently return from `visit` itself
+use arboriter::{TreeControl, prune, traverse_tree};
+
+fn visit(n: &i32) -> TreeControl {
+    if *n == 2 {
+        prune!();
+    }
+    TreeControl::Continue
+}
+
+fn main() {
+    traverse_tree(1, |n| *n < 8, |n| vec![*n * 2, *n * 2 + 1], visit);
+}